@@ -0,0 +1,41 @@
+//! Builds a pack of the tiles of a region, to be served by a `PackCache`.
+//!
+//! ```sh
+//! cargo run --release --example pack -- 45 5 48 11 alps.hgtpack
+//! ```
+
+use earthel::{BoundingBox, EarthEl};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: pack <south> <west> <north> <east> <output>";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [south, west, north, east, output] = args.as_slice() else {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    };
+    let edges: Result<Vec<f64>, _> = [south, west, north, east]
+        .iter()
+        .map(|edge| edge.parse())
+        .collect();
+    let Ok(&[south, west, north, east]) = edges.as_deref() else {
+        eprintln!("{USAGE}: edges are degrees");
+        return ExitCode::from(2);
+    };
+    let bbox = BoundingBox::new(south, west, north, east);
+    match pack(bbox, output).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn pack(bbox: BoundingBox, output: &str) -> earthel::Result<()> {
+    EarthEl::default().pack_region(bbox).await?.save(output)?;
+    println!("{output} written");
+    Ok(())
+}
@@ -0,0 +1,289 @@
+//! Atmospheric quantities derived from the terrain elevation.
+//!
+//! ```
+//! use earthel::atmosphere::LapseRate;
+//!
+//! // 20 °C in the valley at 400 m, on a clear day at the standard lapse rate.
+//! let summit = LapseRate::STANDARD.temperature(20.0, 400.0, 2400.0);
+//! assert!((summit - 7.0).abs() < 1e-9);
+//! ```
+//!
+//! Pressures are in hectopascals and follow the barometric formula of the
+//! International Standard Atmosphere (ISA) below the tropopause, the one
+//! altimeters are calibrated with:
+//!
+//! ```
+//! use earthel::atmosphere;
+//!
+//! // 850 hPa measured at 1500 m.
+//! let qnh = atmosphere::qnh(850.0, 1500.0);
+//! assert!((atmosphere::altitude(850.0, qnh) - 1500.0).abs() < 1e-6);
+//! ```
+//!
+//! Elevations are above mean sea level, as those of weather stations, and
+//! the terrain is taken above the EGM96 geoid whatever the vertical datum of
+//! the instance.
+
+use crate::{geodesy, EarthEl, HgtError, Result};
+
+/// Decrease of the air temperature with the elevation, in °C per kilometer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LapseRate(f64);
+
+impl LapseRate {
+    /// Environmental lapse rate of the International Standard Atmosphere.
+    pub const STANDARD: LapseRate = LapseRate(6.5);
+    /// Rate at which dry air cools when it rises, e.g. on the lee side of a
+    /// range under föhn.
+    pub const DRY_ADIABATIC: LapseRate = LapseRate(9.8);
+    /// Typical rate for saturated air, e.g. inside clouds.
+    pub const SATURATED: LapseRate = LapseRate(5.0);
+
+    /// A custom rate in °C per kilometer, negative for temperature
+    /// inversions.
+    ///
+    /// Fails with [`HgtError::InvalidParameter`] if the rate is not finite.
+    pub fn new(per_kilometer: f64) -> Result<Self> {
+        if !per_kilometer.is_finite() {
+            return Err(HgtError::InvalidParameter(format!(
+                "lapse rate must be finite, got {per_kilometer}"
+            )));
+        }
+        Ok(Self(per_kilometer))
+    }
+
+    /// The rate in °C per kilometer.
+    pub fn per_kilometer(&self) -> f64 {
+        self.0
+    }
+
+    /// Temperature at `elevation` of the air that is `base_temperature` at
+    /// `base_elevation`, elevations in meters.
+    pub fn temperature(&self, base_temperature: f64, base_elevation: f64, elevation: f64) -> f64 {
+        base_temperature - self.0 * (elevation - base_elevation) / 1000.0
+    }
+}
+
+impl Default for LapseRate {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+/// Mean sea-level pressure of the standard atmosphere, in hPa.
+pub const STANDARD_PRESSURE: f64 = 1013.25;
+
+/// Temperature at sea level of the standard atmosphere, in kelvins.
+const STANDARD_TEMPERATURE: f64 = 288.15;
+
+/// Exponent of the barometric formula, g·M / (R·L) with the lapse rate L of
+/// the standard atmosphere.
+const BAROMETRIC_EXPONENT: f64 = 5.255_88;
+
+/// Pressure at `elevation` meters where the sea-level pressure is `qnh`.
+pub fn station_pressure(qnh: f64, elevation: f64) -> f64 {
+    qnh * standard_ratio(elevation)
+}
+
+/// Sea-level pressure (QNH) of a `pressure` measured at `elevation` meters.
+pub fn qnh(pressure: f64, elevation: f64) -> f64 {
+    pressure / standard_ratio(elevation)
+}
+
+/// Altitude in meters at which the pressure is `pressure`, as shown by an
+/// altimeter set to `qnh`.
+pub fn altitude(pressure: f64, qnh: f64) -> f64 {
+    let lapse_rate = LapseRate::STANDARD.0 / 1000.0;
+    STANDARD_TEMPERATURE / lapse_rate * (1.0 - (pressure / qnh).powf(1.0 / BAROMETRIC_EXPONENT))
+}
+
+/// Ratio of the pressure at `elevation` to the pressure at sea level.
+fn standard_ratio(elevation: f64) -> f64 {
+    let lapse_rate = LapseRate::STANDARD.0 / 1000.0;
+    (1.0 - lapse_rate * elevation / STANDARD_TEMPERATURE).powf(BAROMETRIC_EXPONENT)
+}
+
+fn check_pressure(pressure: f64) -> Result<()> {
+    if !(pressure.is_finite() && pressure > 0.0) {
+        return Err(HgtError::InvalidParameter(format!(
+            "pressure must be positive, got {pressure}"
+        )));
+    }
+    Ok(())
+}
+
+impl EarthEl {
+    /// Estimates the temperature on the ground at a location from a
+    /// `base_temperature` measured at `base_elevation` meters, e.g. at a
+    /// nearby weather station, `None` where there is no elevation data.
+    ///
+    /// ```no_run
+    /// use earthel::atmosphere::LapseRate;
+    /// use earthel::EarthEl;
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// // 18 °C at the Sion station (482 m); on the Matterhorn?
+    /// let temperature = EarthEl::default()
+    ///     .temperature_at(45.9763, 7.6586, 18.0, 482.0, LapseRate::STANDARD)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn temperature_at(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        base_temperature: f64,
+        base_elevation: f64,
+        lapse_rate: LapseRate,
+    ) -> Result<Option<f64>> {
+        let elevation = self.sea_level_elevation(latitude, longitude).await?;
+        Ok(elevation
+            .map(|elevation| lapse_rate.temperature(base_temperature, base_elevation, elevation)))
+    }
+
+    /// Calibrates a barometric altimeter on the ground: the sea-level
+    /// pressure (QNH) at a location where `pressure` hPa is measured, from
+    /// the elevation of the terrain, `None` where there is no elevation data.
+    ///
+    /// Fails with [`HgtError::InvalidParameter`] if the pressure isn't
+    /// positive.
+    ///
+    /// ```no_run
+    /// use earthel::{atmosphere, EarthEl};
+    ///
+    /// # async fn run(gps: (f64, f64), measured: f64, later: f64) -> earthel::Result<()> {
+    /// if let Some(qnh) = EarthEl::default().estimate_qnh(gps.0, gps.1, measured).await? {
+    ///     println!("Now at {:.0} m", atmosphere::altitude(later, qnh));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn estimate_qnh(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        pressure: f64,
+    ) -> Result<Option<f64>> {
+        check_pressure(pressure)?;
+        let elevation = self.sea_level_elevation(latitude, longitude).await?;
+        Ok(elevation.map(|elevation| qnh(pressure, elevation)))
+    }
+
+    /// The other way around: the pressure expected on the ground at a
+    /// location where the sea-level pressure is `qnh` hPa, e.g. to check a
+    /// barometer, `None` where there is no elevation data.
+    ///
+    /// Fails with [`HgtError::InvalidParameter`] if the pressure isn't
+    /// positive.
+    pub async fn ground_pressure(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        qnh: f64,
+    ) -> Result<Option<f64>> {
+        check_pressure(qnh)?;
+        let elevation = self.sea_level_elevation(latitude, longitude).await?;
+        Ok(elevation.map(|elevation| station_pressure(qnh, elevation)))
+    }
+
+    /// Elevation of the terrain above mean sea level, which the barometric
+    /// formula is defined against, `None` where there is no data.
+    async fn sea_level_elevation(&self, latitude: f64, longitude: f64) -> Result<Option<f64>> {
+        let elevation = self.try_get_elevation(latitude, longitude).await?;
+        let (latitude, longitude) = geodesy::normalize(latitude, longitude)?;
+        Ok(elevation.map(|elevation| self.to_egm96(latitude, longitude, elevation)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::{synthetic_tile, VOID};
+    use crate::{BoundingBox, MissingPolicy, TileCache, TileId};
+
+    /// 2000 m in the west half of N45E006, voids in the east half.
+    fn earthel() -> EarthEl {
+        let cache = MemoryCache::new();
+        let data = synthetic_tile(1201, |_, col| if col < 600 { 2000 } else { VOID });
+        cache.store(&TileId::new(45, 6), &data).unwrap();
+        EarthEl::builder()
+            .cache(cache)
+            .coverage(BoundingBox::new(45.0, 6.0, 46.0, 7.0))
+            .missing(MissingPolicy::None)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn estimates_temperatures_on_the_ground() {
+        let earthel = earthel();
+        let standard = earthel
+            .temperature_at(45.5, 6.25, 15.0, 0.0, LapseRate::STANDARD)
+            .await
+            .unwrap();
+        assert!((standard.unwrap() - 2.0).abs() < 1e-9);
+        let inversion = LapseRate::new(-2.0).unwrap();
+        let below = earthel
+            .temperature_at(45.5, 6.25, -5.0, 2500.0, inversion)
+            .await
+            .unwrap();
+        assert!((below.unwrap() + 6.0).abs() < 1e-9);
+        let void = earthel
+            .temperature_at(45.5, 6.75, 15.0, 0.0, LapseRate::default())
+            .await
+            .unwrap();
+        assert_eq!(void, None);
+
+        assert!(matches!(
+            LapseRate::new(f64::NAN),
+            Err(HgtError::InvalidParameter(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn calibrates_altimeters() {
+        // The standard atmosphere is 795 hPa at 2000 m.
+        assert!((station_pressure(STANDARD_PRESSURE, 2000.0) - 794.95).abs() < 0.05);
+        assert!((qnh(794.95, 2000.0) - STANDARD_PRESSURE).abs() < 0.1);
+        assert!(altitude(STANDARD_PRESSURE, STANDARD_PRESSURE).abs() < 1e-9);
+
+        let earthel = earthel();
+        let qnh = earthel
+            .estimate_qnh(45.5, 6.25, 790.0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!((altitude(790.0, qnh) - 2000.0).abs() < 1e-6);
+        let pressure = earthel.ground_pressure(45.5, 6.25, qnh).await.unwrap();
+        assert!((pressure.unwrap() - 790.0).abs() < 1e-9);
+        assert_eq!(earthel.estimate_qnh(45.5, 6.75, 790.0).await.unwrap(), None);
+        assert!(matches!(
+            earthel.estimate_qnh(45.5, 6.25, 0.0).await,
+            Err(HgtError::InvalidParameter(_))
+        ));
+    }
+
+    #[cfg(feature = "geoid")]
+    #[tokio::test]
+    async fn calibrates_with_sea_level_elevations() {
+        let cache = MemoryCache::new();
+        let data = synthetic_tile(1201, |_, _| 2000);
+        cache.store(&TileId::new(45, 6), &data).unwrap();
+        let earthel = EarthEl::builder()
+            .cache(cache)
+            .vertical_datum(crate::VerticalDatum::Wgs84Ellipsoid)
+            .build();
+        // The ellipsoid is about 50 m below the geoid here, but pressures go
+        // with the height above the sea.
+        let ellipsoidal = earthel.get_elevation(45.5, 6.25).await.unwrap();
+        assert!(ellipsoidal > 2040);
+        let qnh = earthel
+            .estimate_qnh(45.5, 6.25, 790.0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!((altitude(790.0, qnh) - 2000.0).abs() < 1.0);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::tile::wrap_lon;
+use crate::{HgtError, Result, TileId};
+
+/// A latitude/longitude rectangle, in degrees.
+///
+/// Boxes crossing the antimeridian have a `west` edge east of their `east`
+/// edge, as in GeoJSON: `BoundingBox::new(-20.0, 177.0, -15.0, -178.0)` spans
+/// the 5 degrees around Fiji. Rasters of such boxes extend east of 180°.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BoundingBox {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl BoundingBox {
+    pub fn new(south: f64, west: f64, north: f64, east: f64) -> Self {
+        Self {
+            south,
+            west,
+            north,
+            east,
+        }
+    }
+
+    /// The part of the SRTM coverage most tile sources have data for, from
+    /// 56°S to 60°N, e.g. for
+    /// [`EarthElBuilder::coverage`](crate::EarthElBuilder::coverage).
+    pub const SRTM: BoundingBox = BoundingBox {
+        south: -56.0,
+        west: -180.0,
+        north: 60.0,
+        east: 180.0,
+    };
+
+    /// Whether the box crosses the antimeridian, its west edge being east of
+    /// its east edge.
+    pub fn crosses_antimeridian(&self) -> bool {
+        self.west > self.east
+    }
+
+    /// The box with its east edge moved past 180° when it crosses the
+    /// antimeridian, so that `west < east`.
+    pub(crate) fn unwrapped(&self) -> Self {
+        let mut bbox = *self;
+        if bbox.crosses_antimeridian() {
+            bbox.east += 360.0;
+        }
+        bbox
+    }
+
+    /// Whether a point lies inside the box, edges included.
+    pub(crate) fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        let bbox = self.unwrapped();
+        (bbox.south..=bbox.north).contains(&latitude)
+            && [0.0, 360.0, -360.0]
+                .iter()
+                .any(|turn| (bbox.west..=bbox.east).contains(&(longitude + turn)))
+    }
+
+    /// Checks that the box is well formed.
+    pub(crate) fn validate(&self) -> Result<()> {
+        let finite = [self.south, self.west, self.north, self.east]
+            .iter()
+            .all(|v| v.is_finite());
+        let crossing = self.crosses_antimeridian() && self.west <= 180.0 && self.east >= -180.0;
+        if !finite
+            || self.south >= self.north
+            || (self.west >= self.east && !crossing)
+            || self.south < -90.0
+            || self.north > 90.0
+        {
+            return Err(HgtError::InvalidParameter(format!(
+                "invalid bounding box {self:?}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether some part of `tile` lies inside the box.
+    pub(crate) fn intersects(&self, tile: &TileId) -> bool {
+        let bbox = self.unwrapped();
+        let (lat, lon) = (f64::from(tile.lat), f64::from(tile.lon));
+        lat < bbox.north
+            && lat + 1.0 > bbox.south
+            && [0.0, 360.0, -360.0]
+                .iter()
+                .any(|turn| lon + turn < bbox.east && lon + turn + 1.0 > bbox.west)
+    }
+
+    /// The tiles intersecting the box, with longitudes wrapped into
+    /// `[-180, 180)`.
+    pub fn tiles(&self) -> Vec<TileId> {
+        let bbox = self.unwrapped();
+        let (lat_from, lat_to) = (bbox.south.floor() as i32, bbox.north.ceil() as i32);
+        let (lon_from, lon_to) = (bbox.west.floor() as i32, bbox.east.ceil() as i32);
+        (lat_from..lat_to)
+            .flat_map(|lat| {
+                (lon_from..lon_to)
+                    .take(360)
+                    .map(move |lon| TileId::new(lat, wrap_lon(lon)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_intersecting_tiles() {
+        let bbox = BoundingBox::new(46.5, 5.5, 47.5, 6.0);
+        assert_eq!(bbox.tiles(), vec![TileId::new(46, 5), TileId::new(47, 5)]);
+        assert!(bbox.validate().is_ok());
+        assert!(BoundingBox::new(47.0, 5.0, 46.0, 6.0).validate().is_err());
+    }
+
+    #[test]
+    fn wraps_around_the_antimeridian() {
+        // Fiji.
+        let fiji = BoundingBox::new(-19.5, 177.5, -16.0, -179.5);
+        assert!(fiji.validate().is_ok() && fiji.crosses_antimeridian());
+        assert_eq!(
+            fiji.tiles(),
+            [-20, -19, -18, -17]
+                .into_iter()
+                .flat_map(|lat| [177, 178, 179, -180].map(|lon| TileId::new(lat, lon)))
+                .collect::<Vec<_>>()
+        );
+        assert!(fiji.intersects(&TileId::new(-18, -180)));
+        assert!(!fiji.intersects(&TileId::new(-18, -179)));
+        assert!(!fiji.intersects(&TileId::new(-18, 176)));
+        assert!(fiji.contains(-18.0, 178.4) && fiji.contains(-18.0, -179.9));
+        assert!(!fiji.contains(-18.0, -179.0));
+        assert_eq!(fiji.unwrapped().east, 180.5);
+
+        // The Bering Strait, given with an east edge past 180°.
+        let bering = BoundingBox::new(64.0, 179.0, 67.0, 191.0);
+        assert!(bering.tiles().contains(&TileId::new(65, -170)));
+        assert!(!bering.tiles().contains(&TileId::new(65, -169)));
+        assert!(bering.intersects(&TileId::new(66, -170)));
+        assert!(BoundingBox::new(64.0, 190.0, 67.0, -170.0)
+            .validate()
+            .is_err());
+        assert!(BoundingBox::SRTM.intersects(&TileId::new(59, 179)));
+        assert!(!BoundingBox::SRTM.intersects(&TileId::new(65, -169)));
+    }
+
+    #[test]
+    fn intersects_tiles() {
+        let bbox = BoundingBox::new(-56.0, -180.0, 60.0, 180.0);
+        assert!(bbox.intersects(&TileId::new(59, 179)));
+        assert!(bbox.intersects(&TileId::new(-56, -180)));
+        assert!(!bbox.intersects(&TileId::new(60, 0)));
+        assert!(!bbox.intersects(&TileId::new(-57, 0)));
+    }
+}
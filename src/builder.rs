@@ -0,0 +1,278 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::cache::FsCache;
+#[cfg(target_arch = "wasm32")]
+use crate::cache::MemoryCache;
+use crate::cache::TileCache;
+#[cfg(feature = "manifest")]
+use crate::manifest::{Manifest, PinnedSource};
+use crate::overview::Overviews;
+use crate::point_cache::{PointCache, PointCacheConfig};
+use crate::profile_cache::ProfileCache;
+use crate::raster::Resampling;
+use crate::source::{SkadiSource, TileSource};
+#[cfg(feature = "geoid")]
+use crate::VerticalDatum;
+use crate::{Body, BoundingBox, DownloadCallback, DownloadEvent, EarthEl, MissingPolicy, TileId};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Configures an [`EarthEl`] instance.
+///
+/// ```
+/// use earthel::{cache::MemoryCache, EarthEl};
+///
+/// let earthel = EarthEl::builder().cache(MemoryCache::new()).build();
+/// ```
+#[derive(Default)]
+pub struct EarthElBuilder {
+    cache: Option<Arc<dyn TileCache>>,
+    source: Option<Arc<dyn TileSource>>,
+    point_cache: Option<PointCacheConfig>,
+    profile_cache: Option<usize>,
+    overviews: Option<usize>,
+    coverage: Option<BoundingBox>,
+    body: Body,
+    #[cfg(feature = "geoid")]
+    datum: VerticalDatum,
+    on_download: Option<DownloadCallback>,
+    missing: MissingPolicy,
+    max_age: Option<Duration>,
+    deadline: Option<Duration>,
+    resampling: Resampling,
+    #[cfg(feature = "manifest")]
+    manifest: Option<Arc<Manifest>>,
+    pins: Option<HashMap<TileId, String>>,
+    max_allocation: Option<usize>,
+}
+
+impl EarthElBuilder {
+    /// Stores downloaded tiles in `cache` instead of the default: a
+    /// [`FsCache`](crate::cache::FsCache) natively, a
+    /// [`MemoryCache`](crate::cache::MemoryCache) on WebAssembly.
+    pub fn cache(mut self, cache: impl TileCache + 'static) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Downloads missing tiles from `source` instead of the Skadi bucket.
+    pub fn source(mut self, source: impl TileSource + 'static) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    /// Memoizes point lookups so that repeated queries of the same (quantized)
+    /// coordinates skip tile access entirely. Disabled by default.
+    pub fn point_cache(mut self, config: PointCacheConfig) -> Self {
+        self.point_cache = Some(config);
+        self
+    }
+
+    /// Keeps the last `capacity` computed profiles in memory, keyed by a hash
+    /// of the route and the sampling spacing, so that repeated requests for
+    /// the same route skip sampling. Disabled by default.
+    pub fn profile_cache(mut self, capacity: usize) -> Self {
+        self.profile_cache = Some(capacity);
+        self
+    }
+
+    /// Keeps up to `capacity` downsampled tiles in memory for rasters and
+    /// profiles whose cells or spacing are coarser than the tiles (see
+    /// [`EarthEl::extract_dem`]). 0 disables overviews, always sampling
+    /// tiles at full resolution. Defaults to 32 tiles.
+    pub fn overviews(mut self, capacity: usize) -> Self {
+        self.overviews = Some(capacity);
+        self
+    }
+
+    /// Restricts downloads to tiles intersecting `coverage`; lookups of other
+    /// tiles that are not cached yet fail with
+    /// [`HgtError::OutsideCoverage`](crate::HgtError::OutsideCoverage),
+    /// except in rasters and anomaly scans, which leave them without data.
+    /// Prefetches list them among the failed tiles. Defaults to the whole
+    /// globe; [`BoundingBox::SRTM`] restricts it to the SRTM coverage.
+    pub fn coverage(mut self, coverage: BoundingBox) -> Self {
+        self.coverage = Some(coverage);
+        self
+    }
+
+    /// Measures distances and areas on `body` instead of the earth, for DEMs
+    /// of other planets served by a custom [`source`](Self::source). Tiles
+    /// keep the 1° HGT layout.
+    pub fn body(mut self, body: Body) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Returns elevations relative to `datum` instead of the EGM96 geoid of the
+    /// SRTM data. Applies to point lookups and profiles; rasters stay in EGM96.
+    #[cfg(feature = "geoid")]
+    pub fn vertical_datum(mut self, datum: VerticalDatum) -> Self {
+        self.datum = datum;
+        self
+    }
+
+    /// Calls `callback` as tiles are queued, downloaded or fail to download,
+    /// e.g. to drive a progress bar.
+    pub fn on_download(
+        mut self,
+        callback: impl Fn(&DownloadEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_download = Some(Arc::new(callback));
+        self
+    }
+
+    /// Sets what lookups return where there is no data: over the ocean,
+    /// where no tiles exist, and over voids. Defaults to
+    /// [`MissingPolicy::Error`].
+    pub fn missing(mut self, policy: MissingPolicy) -> Self {
+        self.missing = policy;
+        self
+    }
+
+    /// Revalidates cached tiles against the source when they are accessed
+    /// more than `max_age` after they were downloaded or last checked, so
+    /// that long-lived caches pick up corrected tiles. Unchanged tiles are not
+    /// downloaded again by sources supporting conditional requests, and stale
+    /// tiles keep being served while the source is unreachable. Only caches
+    /// keeping [`TileMetadata`](crate::cache::TileMetadata) know the age of
+    /// their tiles. Disabled by default.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Bounds the time each query may take as a whole, all downloads, retries
+    /// and waits for other tasks included, so that latency stays bounded
+    /// while sources are failing or slow: point lookups, profiles, rasters,
+    /// exports and every other query loading tiles. Streamed lookups bound
+    /// each batch. Queries running past it fail with
+    /// [`HgtError::DeadlineExceeded`](crate::HgtError::DeadlineExceeded) and
+    /// cancel the downloads they started, which later queries start again.
+    /// Disabled by default.
+    ///
+    /// Bulk downloads, i.e. [`EarthEl::prefetch`], `EarthEl::pack_region`
+    /// and the downloads of manifests, are not bounded.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Selects how raster operations such as [`EarthEl::extract_dem`] sample
+    /// cells from the tiles, see [`Resampling`]. Defaults to area-weighted
+    /// averages, which keep coarse rasters free from aliasing.
+    pub fn resampling(mut self, method: Resampling) -> Self {
+        self.resampling = method;
+        self
+    }
+
+    /// Limits the instance to the tiles pinned in `manifest`, downloaded from
+    /// their pinned URLs instead of the [`source`](Self::source) and rejected
+    /// with [`HgtError::ChecksumMismatch`](crate::HgtError::ChecksumMismatch)
+    /// unless they match their checksums. Other tiles are missing.
+    #[cfg(feature = "manifest")]
+    pub fn manifest(mut self, manifest: Manifest) -> Self {
+        self.manifest = Some(Arc::new(manifest));
+        self
+    }
+
+    /// Strict mode, for reproducible results: tiles are pinned to the version
+    /// (entity tag) they were first downloaded with, and downloads and
+    /// revalidations serving another version fail with
+    /// [`HgtError::VersionMismatch`](crate::HgtError::VersionMismatch),
+    /// keeping the pinned tile. Only caches keeping
+    /// [`TileMetadata`](crate::cache::TileMetadata) remember versions, and
+    /// sources that don't tag tiles can't be pinned. Disabled by default.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.pins = strict.then(|| self.pins.unwrap_or_default());
+        self
+    }
+
+    /// Pins `tile` to `version`, e.g. recorded in an
+    /// [`Elevation`](crate::Elevation) of an earlier run, and enables
+    /// [strict mode](Self::strict). Lookups of a cached tile at another
+    /// version fail too.
+    pub fn pin_version(mut self, tile: TileId, version: impl Into<String>) -> Self {
+        self.pins
+            .get_or_insert_with(HashMap::new)
+            .insert(tile, version.into());
+        self
+    }
+
+    /// Profile for embedded devices such as a Raspberry Pi: point and profile
+    /// caches and overviews are disabled, even if configured, profiles read
+    /// the samples they interpolate from the cache instead of decoding whole
+    /// tiles, and operations that would allocate more than `max_allocation`
+    /// bytes at once, such as decoding a tile for a raster, fail with
+    /// [`HgtError::AllocationLimit`](crate::HgtError::AllocationLimit).
+    ///
+    /// Point lookups never decode tiles. Keep an
+    /// [`FsCache`](crate::cache::FsCache) in its default raw storage mode,
+    /// which reads samples without decompressing whole tiles. Downloads still
+    /// hold the tile they store in memory, and instances never run background
+    /// tasks.
+    pub fn low_memory(mut self, max_allocation: usize) -> Self {
+        self.max_allocation = Some(max_allocation);
+        self
+    }
+
+    pub fn build(self) -> EarthEl {
+        #[cfg(feature = "manifest")]
+        let source = match &self.manifest {
+            Some(manifest) => Some(Arc::new(PinnedSource::new(Arc::clone(manifest))) as _),
+            None => self.source,
+        };
+        #[cfg(not(feature = "manifest"))]
+        let source = self.source;
+        // The low memory profile keeps nothing in memory.
+        let low_memory = self.max_allocation.is_some();
+        EarthEl {
+            cache: self.cache.unwrap_or_else(default_cache),
+            source: source.unwrap_or_else(|| Arc::new(SkadiSource::default())),
+            point_cache: self
+                .point_cache
+                .filter(|_| !low_memory)
+                .map(|config| Arc::new(PointCache::new(config))),
+            profile_cache: self
+                .profile_cache
+                .filter(|_| !low_memory)
+                .map(|capacity| Arc::new(ProfileCache::new(capacity))),
+            overviews: match self.overviews.unwrap_or(DEFAULT_OVERVIEWS) {
+                _ if low_memory => None,
+                0 => None,
+                capacity => Some(Arc::new(Overviews::new(capacity))),
+            },
+            coverage: self
+                .coverage
+                .unwrap_or(BoundingBox::new(-90.0, -180.0, 90.0, 180.0)),
+            body: self.body,
+            #[cfg(feature = "geoid")]
+            datum: self.datum,
+            on_download: self.on_download,
+            missing: self.missing,
+            max_age: self.max_age,
+            deadline: self.deadline,
+            resampling: self.resampling,
+            #[cfg(feature = "manifest")]
+            manifest: self.manifest,
+            pins: self.pins.map(Arc::new),
+            max_allocation: self.max_allocation.map(|bytes| bytes as u64),
+            in_flight: Default::default(),
+            counters: Default::default(),
+            subscribers: Default::default(),
+        }
+    }
+}
+
+/// Overview tiles kept in memory by default.
+const DEFAULT_OVERVIEWS: usize = 32;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_cache() -> Arc<dyn TileCache> {
+    Arc::new(FsCache::default())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn default_cache() -> Arc<dyn TileCache> {
+    Arc::new(MemoryCache::new())
+}
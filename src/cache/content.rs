@@ -0,0 +1,254 @@
+use super::files::{lock_file, remove_file, try_lock_file, write_atomically};
+use super::{sha256_hex, CacheLock, TileCache};
+use crate::{Result, TileId};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Stores tiles under the SHA-256 of their content, with an index mapping
+/// tile names to hashes.
+///
+/// Identical tiles (for instance the same ocean tile served under several
+/// names, or by several sources) are stored once, and [`verify`] can detect
+/// corrupted files by re-hashing them.
+///
+/// Layout: `<root>/index` holds one `<tile> <sha256>` line per tile and
+/// `<root>/objects/<sha256>.hgt` holds the data.
+///
+/// Several processes can share the directory: writers update the index
+/// under an advisory lock of `<root>/index.lock`, and readers reload it
+/// when missing a tile that another process may have added.
+///
+/// [`verify`]: ContentAddressedCache::verify
+pub struct ContentAddressedCache {
+    root: PathBuf,
+    index: RwLock<HashMap<TileId, String>>,
+}
+
+impl ContentAddressedCache {
+    /// Opens the cache rooted at `root`, loading its index if there is one.
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(root.join("objects"))?;
+        let index = read_index(&root)?;
+        Ok(Self {
+            root,
+            index: RwLock::new(index),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// SHA-256 (lowercase hex) of a cached tile.
+    pub fn hash_of(&self, tile: &TileId) -> Option<String> {
+        if let Some(hash) = self.index.read().unwrap().get(tile) {
+            return Some(hash.clone());
+        }
+        // Another process may have cached it since.
+        self.reload().ok()?.get(tile).cloned()
+    }
+
+    /// Replaces the index in memory by the one on disk.
+    fn reload(&self) -> Result<HashMap<TileId, String>> {
+        let index = read_index(&self.root)?;
+        *self.index.write().unwrap() = index.clone();
+        Ok(index)
+    }
+
+    /// Applies `update` to the index on disk, under the lock of the index so
+    /// that concurrent updates by other processes aren't lost.
+    fn update_index<T>(
+        &self,
+        update: impl FnOnce(&mut HashMap<TileId, String>) -> Result<T>,
+    ) -> Result<T> {
+        let _lock = lock_file(&self.root.join("index.lock"))?;
+        let mut index = self.index.write().unwrap();
+        *index = read_index(&self.root)?;
+        update(&mut index)
+    }
+
+    /// Re-hashes every indexed tile and returns the ones whose stored data is
+    /// missing or does not match its hash.
+    pub fn verify(&self) -> Result<Vec<TileId>> {
+        let index = self.index.read().unwrap().clone();
+        let mut corrupted = Vec::new();
+        for (tile, hash) in index {
+            let intact = match fs::read(self.object_path(&hash)) {
+                Ok(data) => sha256_hex(&data) == hash,
+                Err(e) if e.kind() == ErrorKind::NotFound => false,
+                Err(e) => return Err(e.into()),
+            };
+            if !intact {
+                corrupted.push(tile);
+            }
+        }
+        corrupted.sort();
+        Ok(corrupted)
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        self.root.join("objects").join(format!("{hash}.hgt"))
+    }
+
+    /// Rewrites the index file; the rename makes the update atomic.
+    fn write_index(&self, index: &HashMap<TileId, String>) -> Result<()> {
+        let mut lines: Vec<String> = index
+            .iter()
+            .map(|(tile, hash)| format!("{tile} {hash}\n"))
+            .collect();
+        lines.sort();
+        write_atomically(&self.root.join("index"), lines.concat().as_bytes())
+    }
+
+    fn remove_unreferenced(&self, index: &HashMap<TileId, String>, hash: &str) -> Result<()> {
+        if index.values().any(|h| h == hash) {
+            return Ok(());
+        }
+        remove_file(&self.object_path(hash))
+    }
+}
+
+fn read_index(root: &Path) -> Result<HashMap<TileId, String>> {
+    let mut index = HashMap::new();
+    match fs::read_to_string(root.join("index")) {
+        Ok(content) => {
+            for line in content.lines() {
+                if let Some((name, hash)) = line.split_once(' ') {
+                    index.extend(TileId::from_name(name).map(|tile| (tile, hash.to_owned())));
+                }
+            }
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(index)
+}
+
+impl TileCache for ContentAddressedCache {
+    fn load(&self, tile: &TileId) -> Result<Option<Vec<u8>>> {
+        let Some(hash) = self.hash_of(tile) else {
+            return Ok(None);
+        };
+        match fs::read(self.object_path(&hash)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn store(&self, tile: &TileId, data: &[u8]) -> Result<()> {
+        let hash = sha256_hex(data);
+        self.update_index(|index| {
+            // Under the lock, so that no other process removes the object as
+            // unreferenced before the index refers to it.
+            let path = self.object_path(&hash);
+            if !path.exists() {
+                write_atomically(&path, data)?;
+            }
+            let previous = index.insert(*tile, hash);
+            self.write_index(index)?;
+            if let Some(previous) = previous {
+                self.remove_unreferenced(index, &previous)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn remove(&self, tile: &TileId) -> Result<()> {
+        self.update_index(|index| {
+            if let Some(hash) = index.remove(tile) {
+                self.write_index(index)?;
+                self.remove_unreferenced(index, &hash)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn tiles(&self) -> Result<Vec<TileId>> {
+        Ok(self.reload()?.into_keys().collect())
+    }
+
+    fn size(&self, tile: &TileId) -> Result<Option<u64>> {
+        let Some(hash) = self.hash_of(tile) else {
+            return Ok(None);
+        };
+        match fs::metadata(self.object_path(&hash)) {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read_at(&self, tile: &TileId, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let hash = self.hash_of(tile).ok_or_else(|| super::not_cached(tile))?;
+        let mut file = File::open(self.object_path(&hash))?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf)?;
+        Ok(())
+    }
+
+    fn try_lock(&self, tile: &TileId) -> Result<Option<CacheLock>> {
+        let path = self.root.join("locks").join(format!("{tile}.lock"));
+        Ok(try_lock_file(&path)?.map(CacheLock::new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deduplicates_identical_tiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ContentAddressedCache::open(dir.path()).unwrap();
+        let (a, b) = (TileId::new(10, 10), TileId::new(10, 11));
+        cache.store(&a, &[0; 8]).unwrap();
+        cache.store(&b, &[0; 8]).unwrap();
+        assert_eq!(cache.hash_of(&a), cache.hash_of(&b));
+        assert_eq!(fs::read_dir(dir.path().join("objects")).unwrap().count(), 1);
+
+        cache.remove(&a).unwrap();
+        assert_eq!(cache.load(&b).unwrap(), Some(vec![0; 8]));
+
+        let reopened = ContentAddressedCache::open(dir.path()).unwrap();
+        assert_eq!(reopened.tiles().unwrap(), vec![b]);
+    }
+
+    #[test]
+    fn shares_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = ContentAddressedCache::open(dir.path()).unwrap();
+        let b = ContentAddressedCache::open(dir.path()).unwrap();
+        let (x, y) = (TileId::new(10, 10), TileId::new(10, 11));
+        a.store(&x, &[1; 8]).unwrap();
+        // Found without reopening, and kept by updates of the other instance.
+        assert_eq!(b.load(&x).unwrap(), Some(vec![1; 8]));
+        b.store(&y, &[2; 8]).unwrap();
+        let mut tiles = a.tiles().unwrap();
+        tiles.sort();
+        assert_eq!(tiles, vec![x, y]);
+        let reopened = ContentAddressedCache::open(dir.path()).unwrap();
+        assert_eq!(reopened.load(&x).unwrap(), Some(vec![1; 8]));
+
+        let lock = a.try_lock(&x).unwrap();
+        assert!(b.try_lock(&x).unwrap().is_none());
+        drop(lock);
+        assert!(b.try_lock(&x).unwrap().is_some());
+    }
+
+    #[test]
+    fn verify_reports_corrupted_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ContentAddressedCache::open(dir.path()).unwrap();
+        let tile = TileId::new(1, 2);
+        cache.store(&tile, &[1, 2, 3]).unwrap();
+        assert!(cache.verify().unwrap().is_empty());
+        let hash = cache.hash_of(&tile).unwrap();
+        fs::write(cache.object_path(&hash), [3, 2, 1]).unwrap();
+        assert_eq!(cache.verify().unwrap(), vec![tile]);
+    }
+}
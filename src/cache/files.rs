@@ -0,0 +1,67 @@
+//! File operations of the on-disk caches, safe with several processes
+//! sharing a cache directory.
+
+use crate::Result;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Writes a file through a temporary file renamed over it, so that readers
+/// see either the previous content or the new one, never part of it.
+pub(super) fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let n = TEMP_FILES.fetch_add(1, Ordering::Relaxed);
+    // Hidden and unique to the process and the write, so concurrent writes of
+    // the same file don't share a temporary file and listings skip them.
+    let tmp = path.with_file_name(format!(".{name}.{}-{n}.tmp", std::process::id()));
+    fs::write(&tmp, data)?;
+    if let Err(e) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Removes a file, if it exists.
+pub(super) fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Waits for an exclusive advisory lock on the file at `path`, created if
+/// needed, held until the returned file is closed.
+///
+/// Locks are held by the open file, so they also exclude other instances of
+/// a cache within the same process. The operating system releases them
+/// when a process dies.
+pub(super) fn lock_file(path: &Path) -> Result<File> {
+    let file = open_lock(path)?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Like [`lock_file`], but `None` instead of waiting while another open file
+/// holds the lock.
+pub(super) fn try_lock_file(path: &Path) -> Result<Option<File>> {
+    let file = open_lock(path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+fn open_lock(path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?)
+}
@@ -0,0 +1,602 @@
+use super::files::{lock_file, remove_file, try_lock_file, write_atomically};
+use super::{copy_range, CacheLock, TileCache, TileMetadata};
+use crate::lru::LruCache;
+use crate::source::Validators;
+use crate::{Result, TileId};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of decompressed tiles [`FsCache`] keeps in memory for lookups.
+const DECODED_TILES: usize = 4;
+
+/// How [`FsCache`] stores tiles on disk.
+///
+/// Compressed tiles take 3 to 4 times less space; they are decompressed on
+/// access, and the last few decompressed tiles are kept in memory so that
+/// point lookups don't pay for decompression every time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StorageMode {
+    /// Plain `.hgt` files.
+    #[default]
+    Raw,
+    /// Gzip-compressed `.hgt.gz` files.
+    Gzip,
+    /// Zstandard-compressed `.hgt.zst` files.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl StorageMode {
+    const ALL: &[StorageMode] = &[
+        Self::Raw,
+        Self::Gzip,
+        #[cfg(feature = "zstd")]
+        Self::Zstd,
+    ];
+
+    fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|mode| mode.extension() == extension)
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Raw => ".hgt",
+            Self::Gzip => ".hgt.gz",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => ".hgt.zst",
+        }
+    }
+
+    fn encode(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Raw => Ok(data.to_vec()),
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(zstd::encode_all(data, 0)?),
+        }
+    }
+
+    fn decode(self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Self::Raw => Ok(data),
+            Self::Gzip => {
+                let mut decoded = Vec::new();
+                GzDecoder::new(&data[..]).read_to_end(&mut decoded)?;
+                Ok(decoded)
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(zstd::decode_all(&data[..])?),
+        }
+    }
+}
+
+/// Stores tiles as `.hgt` files in a directory, grouped by latitude band
+/// (`<root>/N47/N47E005.hgt`), optionally compressed (see [`StorageMode`]).
+///
+/// Tiles written in another storage mode, e.g. before the mode of an existing
+/// cache was changed, are still found and read.
+///
+/// Several processes can share the directory: files are replaced atomically
+/// through temporary files unique to each write, tiles are locked with
+/// advisory file locks (`N47/.N47E005.lock`) while being downloaded, and
+/// `<root>/index` records the decompressed size of compressed tiles so that
+/// telling it doesn't take decompressing them. Its `<tile> <extension>
+/// <stored length> <size>` lines are only trusted while the file they
+/// describe is unchanged, so that the index needs no repair when tiles are
+/// written without it. Likewise, decompressed tiles kept in memory are only
+/// served while their file is unchanged.
+#[derive(Clone)]
+pub struct FsCache {
+    root: PathBuf,
+    storage: StorageMode,
+    decoded: Arc<Mutex<LruCache<TileId, Decoded>>>,
+    index: Arc<Mutex<HashMap<TileId, IndexEntry>>>,
+}
+
+/// Identifies the file a tile was decompressed from, so that tiles replaced
+/// by other processes are decompressed again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    storage: StorageMode,
+    stored: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    fn of(storage: StorageMode, metadata: &fs::Metadata) -> Self {
+        Self {
+            storage,
+            stored: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+}
+
+/// A decompressed tile and the file it was decompressed from.
+type Decoded = (FileStamp, Arc<Vec<u8>>);
+
+/// How a compressed tile is stored, and its decompressed size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IndexEntry {
+    storage: StorageMode,
+    stored: u64,
+    size: u64,
+}
+
+impl FsCache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            storage: StorageMode::Raw,
+            decoded: Arc::new(Mutex::new(LruCache::new(DECODED_TILES))),
+            index: Default::default(),
+        }
+    }
+
+    /// Stores new tiles in `storage` mode.
+    pub fn with_storage(mut self, storage: StorageMode) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn storage(&self) -> StorageMode {
+        self.storage
+    }
+
+    fn path(&self, tile: &TileId, storage: StorageMode) -> PathBuf {
+        self.root
+            .join(tile.folder())
+            .join(format!("{}{}", tile.name(), storage.extension()))
+    }
+
+    /// Side file holding the [`TileMetadata`] of a tile, e.g. `N47/N47E005.meta`.
+    fn metadata_path(&self, tile: &TileId) -> PathBuf {
+        self.root
+            .join(tile.folder())
+            .join(format!("{}.meta", tile.name()))
+    }
+
+    /// Storage modes in lookup order, the configured one first.
+    fn lookup_order(&self) -> impl Iterator<Item = StorageMode> + '_ {
+        std::iter::once(self.storage).chain(
+            StorageMode::ALL
+                .iter()
+                .copied()
+                .filter(move |&mode| mode != self.storage),
+        )
+    }
+
+    /// Finds the file holding a tile, and how it is stored.
+    fn find(&self, tile: &TileId) -> Result<Option<(StorageMode, PathBuf, u64)>> {
+        for storage in self.lookup_order() {
+            let path = self.path(tile, storage);
+            match fs::metadata(&path) {
+                Ok(metadata) => return Ok(Some((storage, path, metadata.len()))),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Decompressed size of a compressed tile, if the index has it for this
+    /// very file. Misses reload the index, which other processes may have
+    /// updated.
+    fn indexed_size(
+        &self,
+        tile: &TileId,
+        storage: StorageMode,
+        stored: u64,
+    ) -> Result<Option<u64>> {
+        let matches = |index: &HashMap<TileId, IndexEntry>| {
+            index
+                .get(tile)
+                .filter(|entry| entry.storage == storage && entry.stored == stored)
+                .map(|entry| entry.size)
+        };
+        if let Some(size) = matches(&self.index.lock().unwrap()) {
+            return Ok(Some(size));
+        }
+        let index = self.read_index()?;
+        let size = matches(&index);
+        *self.index.lock().unwrap() = index;
+        Ok(size)
+    }
+
+    fn read_index(&self) -> Result<HashMap<TileId, IndexEntry>> {
+        let text = match fs::read_to_string(self.root.join("index")) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let entry = |line: &str| {
+            let mut fields = line.split(' ');
+            let tile = TileId::from_name(fields.next()?)?;
+            let entry = IndexEntry {
+                storage: StorageMode::from_extension(fields.next()?)?,
+                stored: fields.next()?.parse().ok()?,
+                size: fields.next()?.parse().ok()?,
+            };
+            Some((tile, entry))
+        };
+        Ok(text.lines().filter_map(entry).collect())
+    }
+
+    /// Sets or removes the index entry of a tile. The index is re-read under
+    /// a lock, so that concurrent updates by other processes aren't lost.
+    fn update_index(&self, tile: &TileId, entry: Option<IndexEntry>) -> Result<()> {
+        let _lock = lock_file(&self.root.join(".index.lock"))?;
+        let mut index = self.read_index()?;
+        let changed = match entry {
+            Some(entry) => index.insert(*tile, entry) != Some(entry),
+            None => index.remove(tile).is_some(),
+        };
+        if changed {
+            let mut lines: Vec<String> = index
+                .iter()
+                .map(|(tile, entry)| {
+                    let extension = entry.storage.extension();
+                    format!("{tile} {extension} {} {}\n", entry.stored, entry.size)
+                })
+                .collect();
+            lines.sort();
+            write_atomically(&self.root.join("index"), lines.concat().as_bytes())?;
+        }
+        *self.index.lock().unwrap() = index;
+        Ok(())
+    }
+
+    /// The decompressed content of a compressed tile, from memory if it was
+    /// decompressed from the very same file.
+    fn decoded(&self, tile: &TileId, storage: StorageMode, path: &Path) -> Result<Arc<Vec<u8>>> {
+        let mut file = File::open(path)?;
+        let stamp = FileStamp::of(storage, &file.metadata()?);
+        if let Some((cached, data)) = self.decoded.lock().unwrap().get(tile) {
+            if cached == stamp {
+                return Ok(data);
+            }
+        }
+        let mut stored = Vec::new();
+        file.read_to_end(&mut stored)?;
+        let data = Arc::new(storage.decode(stored)?);
+        self.decoded
+            .lock()
+            .unwrap()
+            .insert(*tile, (stamp, data.clone()));
+        Ok(data)
+    }
+}
+
+impl Default for FsCache {
+    /// Caches tiles in `hgt` under the system temporary directory.
+    fn default() -> Self {
+        Self::new(std::env::temp_dir().join("hgt"))
+    }
+}
+
+impl fmt::Debug for FsCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FsCache")
+            .field("root", &self.root)
+            .field("storage", &self.storage)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TileCache for FsCache {
+    fn load(&self, tile: &TileId) -> Result<Option<Vec<u8>>> {
+        match self.find(tile)? {
+            None => Ok(None),
+            Some((StorageMode::Raw, path, _)) => match fs::read(path) {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+            Some((storage, path, _)) => {
+                Ok(Some(self.decoded(tile, storage, &path)?.as_ref().clone()))
+            }
+        }
+    }
+
+    fn store(&self, tile: &TileId, data: &[u8]) -> Result<()> {
+        let path = self.path(tile, self.storage);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let encoded = self.storage.encode(data)?;
+        write_atomically(&path, &encoded)?;
+        self.decoded.lock().unwrap().remove(tile);
+        let entry = (self.storage != StorageMode::Raw).then_some(IndexEntry {
+            storage: self.storage,
+            stored: encoded.len() as u64,
+            size: data.len() as u64,
+        });
+        if entry.is_some() || self.read_index()?.contains_key(tile) {
+            self.update_index(tile, entry)?;
+        }
+        for &storage in StorageMode::ALL {
+            if storage != self.storage {
+                remove_file(&self.path(tile, storage))?;
+            }
+        }
+        // The validators of the previous version don't apply to new content.
+        remove_file(&self.metadata_path(tile))
+    }
+
+    fn remove(&self, tile: &TileId) -> Result<()> {
+        self.decoded.lock().unwrap().remove(tile);
+        for &storage in StorageMode::ALL {
+            remove_file(&self.path(tile, storage))?;
+        }
+        if self.read_index()?.contains_key(tile) {
+            self.update_index(tile, None)?;
+        }
+        remove_file(&self.metadata_path(tile))
+    }
+
+    fn tiles(&self) -> Result<Vec<TileId>> {
+        let mut tiles = Vec::new();
+        let folders = match fs::read_dir(&self.root) {
+            Ok(folders) => folders,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(tiles),
+            Err(e) => return Err(e.into()),
+        };
+        for folder in folders {
+            let folder = folder?;
+            if !folder.file_type()?.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(folder.path())? {
+                let name = file?.file_name();
+                let Some(name) = name.to_str() else {
+                    continue;
+                };
+                let name = name
+                    .strip_suffix(".gz")
+                    .or_else(|| name.strip_suffix(".zst"))
+                    .unwrap_or(name);
+                tiles.extend(TileId::from_name(name));
+            }
+        }
+        tiles.sort();
+        tiles.dedup();
+        Ok(tiles)
+    }
+
+    fn size(&self, tile: &TileId) -> Result<Option<u64>> {
+        match self.find(tile)? {
+            None => Ok(None),
+            Some((StorageMode::Raw, _, len)) => Ok(Some(len)),
+            Some((storage, path, stored)) => {
+                if let Some(size) = self.indexed_size(tile, storage, stored)? {
+                    return Ok(Some(size));
+                }
+                let size = self.decoded(tile, storage, &path)?.len() as u64;
+                let entry = IndexEntry {
+                    storage,
+                    stored,
+                    size,
+                };
+                self.update_index(tile, Some(entry))?;
+                Ok(Some(size))
+            }
+        }
+    }
+
+    fn read_at(&self, tile: &TileId, offset: u64, buf: &mut [u8]) -> Result<()> {
+        match self.find(tile)? {
+            None => Err(super::not_cached(tile)),
+            Some((StorageMode::Raw, path, _)) => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_exact(buf)?;
+                Ok(())
+            }
+            Some((storage, path, _)) => {
+                copy_range(&self.decoded(tile, storage, &path)?, offset, buf)
+            }
+        }
+    }
+
+    /// Metadata is kept as `key value` lines, `fetched` being in Unix seconds.
+    fn metadata(&self, tile: &TileId) -> Result<Option<TileMetadata>> {
+        let text = match fs::read_to_string(self.metadata_path(tile)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut validators = Validators::default();
+        let mut fetched_at = None;
+        for line in text.lines() {
+            match line.split_once(' ') {
+                Some(("etag", value)) => validators.etag = Some(value.to_owned()),
+                Some(("last-modified", value)) => validators.last_modified = Some(value.to_owned()),
+                Some(("fetched", value)) => {
+                    fetched_at = value
+                        .parse()
+                        .ok()
+                        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+                }
+                _ => {}
+            }
+        }
+        Ok(fetched_at.map(|fetched_at| TileMetadata {
+            validators,
+            fetched_at,
+        }))
+    }
+
+    fn store_metadata(&self, tile: &TileId, metadata: &TileMetadata) -> Result<()> {
+        let mut text = String::new();
+        if let Some(etag) = &metadata.validators.etag {
+            text.push_str(&format!("etag {etag}\n"));
+        }
+        if let Some(date) = &metadata.validators.last_modified {
+            text.push_str(&format!("last-modified {date}\n"));
+        }
+        let fetched = metadata
+            .fetched_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        text.push_str(&format!("fetched {}\n", fetched.as_secs()));
+        let path = self.metadata_path(tile);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomically(&path, text.as_bytes())
+    }
+
+    fn try_lock(&self, tile: &TileId) -> Result<Option<CacheLock>> {
+        let path = self
+            .root
+            .join(tile.folder())
+            .join(format!(".{}.lock", tile.name()));
+        Ok(try_lock_file(&path)?.map(CacheLock::new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_tiles_in_latitude_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FsCache::new(dir.path());
+        let tile = TileId::new(-12, 130);
+        cache.store(&tile, &[0, 1, 0, 2]).unwrap();
+        assert!(dir.path().join("S12/S12E130.hgt").exists());
+        assert_eq!(cache.size(&tile).unwrap(), Some(4));
+        let mut buf = [0; 2];
+        cache.read_at(&tile, 2, &mut buf).unwrap();
+        assert_eq!(buf, [0, 2]);
+        assert_eq!(cache.tiles().unwrap(), vec![tile]);
+        cache.remove(&tile).unwrap();
+        cache.remove(&tile).unwrap();
+        assert_eq!(cache.load(&tile).unwrap(), None);
+    }
+
+    #[test]
+    fn compresses_tiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let tile = TileId::new(47, 5);
+        let data: Vec<u8> = (0..20_000).map(|i| (i / 100) as u8).collect();
+        FsCache::new(dir.path()).store(&tile, &data).unwrap();
+        let cache = FsCache::new(dir.path()).with_storage(StorageMode::Gzip);
+        // Tiles stored before switching modes are still there.
+        assert_eq!(cache.size(&tile).unwrap(), Some(20_000));
+        cache.store(&tile, &data).unwrap();
+        let path = dir.path().join("N47/N47E005.hgt.gz");
+        assert!(fs::metadata(&path).unwrap().len() < 2_000);
+        assert!(!dir.path().join("N47/N47E005.hgt").exists());
+        assert_eq!(cache.size(&tile).unwrap(), Some(20_000));
+        let mut buf = [0; 3];
+        cache.read_at(&tile, 10_099, &mut buf).unwrap();
+        assert_eq!(buf, [100, 101, 101]);
+        assert_eq!(cache.load(&tile).unwrap(), Some(data));
+        assert_eq!(cache.tiles().unwrap(), vec![tile]);
+        cache.remove(&tile).unwrap();
+        assert_eq!(cache.size(&tile).unwrap(), None);
+        assert!(!path.exists());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compresses_tiles_with_zstd() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FsCache::new(dir.path()).with_storage(StorageMode::Zstd);
+        let tile = TileId::new(-1, -1);
+        let data = vec![7; 10_000];
+        cache.store(&tile, &data).unwrap();
+        assert!(dir.path().join("S01/S01W001.hgt.zst").exists());
+        assert_eq!(FsCache::new(dir.path()).load(&tile).unwrap(), Some(data));
+    }
+
+    #[test]
+    fn shares_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = FsCache::new(dir.path()).with_storage(StorageMode::Gzip);
+        let b = FsCache::new(dir.path());
+        let tile = TileId::new(47, 5);
+
+        let lock = a.try_lock(&tile).unwrap();
+        assert!(lock.is_some());
+        assert!(b.try_lock(&tile).unwrap().is_none());
+        assert!(b.try_lock(&TileId::new(47, 6)).unwrap().is_some());
+        drop(lock);
+        assert!(b.try_lock(&tile).unwrap().is_some());
+
+        a.store(&tile, &[1; 10_000]).unwrap();
+        let index = fs::read_to_string(dir.path().join("index")).unwrap();
+        assert!(index.starts_with("N47E005 .hgt.gz "), "{index}");
+        assert!(index.ends_with(" 10000\n"), "{index}");
+        assert_eq!(b.size(&tile).unwrap(), Some(10_000));
+        // Entries don't outlive the file they describe.
+        let path = dir.path().join("N47/N47E005.hgt.gz");
+        fs::write(&path, StorageMode::Gzip.encode(&[2; 20_000]).unwrap()).unwrap();
+        assert_eq!(b.size(&tile).unwrap(), Some(20_000));
+        assert!(fs::read_to_string(dir.path().join("index"))
+            .unwrap()
+            .ends_with(" 20000\n"));
+
+        // Tiles decompressed by a process are read again once another
+        // replaces them.
+        assert_eq!(a.load(&tile).unwrap().unwrap(), [2; 20_000]);
+        let c = FsCache::new(dir.path()).with_storage(StorageMode::Gzip);
+        let data: Vec<u8> = (0..20_000).map(|i| (i % 251) as u8).collect();
+        c.store(&tile, &data).unwrap();
+        assert_eq!(a.load(&tile).unwrap().unwrap(), data);
+        let mut sample = [0; 2];
+        a.read_at(&tile, 251, &mut sample).unwrap();
+        assert_eq!(sample, [0, 1]);
+
+        b.store(&tile, &[3; 4]).unwrap();
+        assert_eq!(a.size(&tile).unwrap(), Some(4));
+        assert_eq!(fs::read_to_string(dir.path().join("index")).unwrap(), "");
+        // Neither temporary nor lock files are mistaken for tiles.
+        assert_eq!(a.tiles().unwrap(), vec![tile]);
+    }
+
+    #[test]
+    fn keeps_metadata_next_to_tiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FsCache::new(dir.path());
+        let tile = TileId::new(47, 5);
+        cache.store(&tile, &[0, 1, 0, 2]).unwrap();
+        assert_eq!(cache.metadata(&tile).unwrap(), None);
+        let metadata = TileMetadata {
+            validators: Validators {
+                etag: Some("\"5d41402a\"".into()),
+                last_modified: Some("Sun, 06 Nov 1994 08:49:37 GMT".into()),
+            },
+            fetched_at: UNIX_EPOCH + Duration::from_secs(784111777),
+        };
+        cache.store_metadata(&tile, &metadata).unwrap();
+        assert_eq!(cache.metadata(&tile).unwrap(), Some(metadata));
+        // The side file is not mistaken for a tile.
+        assert_eq!(cache.tiles().unwrap(), vec![tile]);
+        cache.remove(&tile).unwrap();
+        assert_eq!(cache.metadata(&tile).unwrap(), None);
+        assert!(!dir.path().join("N47/N47E005.meta").exists());
+    }
+}
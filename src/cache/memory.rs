@@ -0,0 +1,87 @@
+use super::{copy_range, not_cached, TileCache, TileMetadata};
+use crate::{Result, TileId};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Keeps tiles in memory for the lifetime of the cache.
+#[derive(Debug, Default)]
+pub struct MemoryCache {
+    tiles: RwLock<HashMap<TileId, Vec<u8>>>,
+    metadata: RwLock<HashMap<TileId, TileMetadata>>,
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TileCache for MemoryCache {
+    fn load(&self, tile: &TileId) -> Result<Option<Vec<u8>>> {
+        Ok(self.tiles.read().unwrap().get(tile).cloned())
+    }
+
+    fn store(&self, tile: &TileId, data: &[u8]) -> Result<()> {
+        self.tiles.write().unwrap().insert(*tile, data.to_vec());
+        self.metadata.write().unwrap().remove(tile);
+        Ok(())
+    }
+
+    fn remove(&self, tile: &TileId) -> Result<()> {
+        self.tiles.write().unwrap().remove(tile);
+        self.metadata.write().unwrap().remove(tile);
+        Ok(())
+    }
+
+    fn tiles(&self) -> Result<Vec<TileId>> {
+        Ok(self.tiles.read().unwrap().keys().copied().collect())
+    }
+
+    fn size(&self, tile: &TileId) -> Result<Option<u64>> {
+        Ok(self
+            .tiles
+            .read()
+            .unwrap()
+            .get(tile)
+            .map(|data| data.len() as u64))
+    }
+
+    fn read_at(&self, tile: &TileId, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let tiles = self.tiles.read().unwrap();
+        let data = tiles.get(tile).ok_or_else(|| not_cached(tile))?;
+        copy_range(data, offset, buf)
+    }
+
+    fn metadata(&self, tile: &TileId) -> Result<Option<TileMetadata>> {
+        Ok(self.metadata.read().unwrap().get(tile).cloned())
+    }
+
+    fn store_metadata(&self, tile: &TileId, metadata: &TileMetadata) -> Result<()> {
+        self.metadata
+            .write()
+            .unwrap()
+            .insert(*tile, metadata.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_and_reads_tiles() {
+        let cache = MemoryCache::new();
+        let tile = TileId::new(47, 5);
+        assert_eq!(cache.size(&tile).unwrap(), None);
+        cache.store(&tile, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(cache.size(&tile).unwrap(), Some(4));
+        let mut buf = [0; 2];
+        cache.read_at(&tile, 1, &mut buf).unwrap();
+        assert_eq!(buf, [2, 3]);
+        assert!(cache.read_at(&tile, 3, &mut buf).is_err());
+        assert_eq!(cache.tiles().unwrap(), vec![tile]);
+        cache.remove(&tile).unwrap();
+        assert_eq!(cache.load(&tile).unwrap(), None);
+    }
+}
@@ -0,0 +1,190 @@
+//! Storage for downloaded tiles.
+//!
+//! [`EarthEl`](crate::EarthEl) keeps the raw HGT data of every tile it
+//! downloads in a [`TileCache`]. The crate ships a filesystem cache (the
+//! default), a content-addressed cache that deduplicates identical tiles, an
+//! in-memory cache, a read-only cache of compact tile packs for app assets
+//! and, behind the `sqlite` feature, an SQLite cache.
+//! Embedders with their own storage layer can implement the trait.
+//!
+//! On WebAssembly, where there is no filesystem, only [`MemoryCache`],
+//! [`PackCache`] and custom caches (e.g. backed by IndexedDB) are available.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod content;
+#[cfg(not(target_arch = "wasm32"))]
+mod files;
+#[cfg(not(target_arch = "wasm32"))]
+mod fs;
+mod memory;
+mod pack;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use content::ContentAddressedCache;
+#[cfg(not(target_arch = "wasm32"))]
+pub use fs::{FsCache, StorageMode};
+pub use memory::MemoryCache;
+pub use pack::{PackBuilder, PackCache};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteCache;
+
+use crate::metrics::SystemTime;
+use crate::source::Validators;
+use crate::{Result, TileId};
+use sha2::{Digest, Sha256};
+use std::any::Any;
+use std::fmt;
+
+/// What is known about the freshness of a cached tile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileMetadata {
+    /// Validators the source sent with the tile.
+    pub validators: Validators,
+    /// When the tile was downloaded or last confirmed current by the source.
+    pub fetched_at: SystemTime,
+}
+
+/// A tile locked against downloads by other processes sharing a cache,
+/// released on drop. See [`TileCache::try_lock`].
+#[derive(Default)]
+pub struct CacheLock {
+    _guard: Option<Box<dyn Any + Send + Sync>>,
+}
+
+impl CacheLock {
+    /// A lock held as long as `guard` lives, e.g. a locked file.
+    pub fn new(guard: impl Any + Send + Sync) -> Self {
+        Self {
+            _guard: Some(Box::new(guard)),
+        }
+    }
+}
+
+impl fmt::Debug for CacheLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CacheLock").finish_non_exhaustive()
+    }
+}
+
+/// Persistence for raw (uncompressed) HGT tiles.
+///
+/// Implementations must be safe to share between threads. Only `load`,
+/// `store`, `remove` and `tiles` are required; backends that can answer size
+/// and partial reads without loading a whole tile should override `size` and
+/// `read_at`, which are used for every point lookup. Backends that keep
+/// [`TileMetadata`] let tiles be revalidated against their source, see
+/// [`EarthEl::refresh_tile`](crate::EarthEl::refresh_tile). Backends that
+/// several processes can share should override `try_lock`.
+pub trait TileCache: Send + Sync {
+    /// Returns the content of a tile, or `None` if it is not cached.
+    fn load(&self, tile: &TileId) -> Result<Option<Vec<u8>>>;
+
+    /// Stores the content of a tile, replacing any previous version.
+    fn store(&self, tile: &TileId, data: &[u8]) -> Result<()>;
+
+    /// Removes a tile. Removing a tile that is not cached is not an error.
+    fn remove(&self, tile: &TileId) -> Result<()>;
+
+    /// Lists the cached tiles.
+    fn tiles(&self) -> Result<Vec<TileId>>;
+
+    /// Returns the size in bytes of a cached tile, or `None` if it is not cached.
+    fn size(&self, tile: &TileId) -> Result<Option<u64>> {
+        Ok(self.load(tile)?.map(|data| data.len() as u64))
+    }
+
+    /// Fills `buf` with the bytes of a cached tile starting at `offset`.
+    fn read_at(&self, tile: &TileId, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let data = self.load(tile)?.ok_or_else(|| not_cached(tile))?;
+        copy_range(&data, offset, buf)
+    }
+
+    /// Returns the metadata of a cached tile, `None` if unknown. Not kept by
+    /// default.
+    fn metadata(&self, tile: &TileId) -> Result<Option<TileMetadata>> {
+        let _ = tile;
+        Ok(None)
+    }
+
+    /// Records the metadata of a cached tile. Backends keeping metadata
+    /// should drop it when the tile is stored again. Ignored by default.
+    fn store_metadata(&self, tile: &TileId, metadata: &TileMetadata) -> Result<()> {
+        let _ = (tile, metadata);
+        Ok(())
+    }
+
+    /// Tries to lock `tile` so that other processes sharing the cache don't
+    /// download it at the same time, `None` while another process holds the
+    /// lock. [`EarthEl`](crate::EarthEl) holds it while downloading the
+    /// tile. Caches private to a process need not lock anything, as by
+    /// default.
+    fn try_lock(&self, tile: &TileId) -> Result<Option<CacheLock>> {
+        let _ = tile;
+        Ok(Some(CacheLock::default()))
+    }
+}
+
+impl<C: TileCache + ?Sized> TileCache for std::sync::Arc<C> {
+    fn load(&self, tile: &TileId) -> Result<Option<Vec<u8>>> {
+        (**self).load(tile)
+    }
+
+    fn store(&self, tile: &TileId, data: &[u8]) -> Result<()> {
+        (**self).store(tile, data)
+    }
+
+    fn remove(&self, tile: &TileId) -> Result<()> {
+        (**self).remove(tile)
+    }
+
+    fn tiles(&self) -> Result<Vec<TileId>> {
+        (**self).tiles()
+    }
+
+    fn size(&self, tile: &TileId) -> Result<Option<u64>> {
+        (**self).size(tile)
+    }
+
+    fn read_at(&self, tile: &TileId, offset: u64, buf: &mut [u8]) -> Result<()> {
+        (**self).read_at(tile, offset, buf)
+    }
+
+    fn metadata(&self, tile: &TileId) -> Result<Option<TileMetadata>> {
+        (**self).metadata(tile)
+    }
+
+    fn store_metadata(&self, tile: &TileId, metadata: &TileMetadata) -> Result<()> {
+        (**self).store_metadata(tile, metadata)
+    }
+
+    fn try_lock(&self, tile: &TileId) -> Result<Option<CacheLock>> {
+        (**self).try_lock(tile)
+    }
+}
+
+pub(crate) fn not_cached(tile: &TileId) -> crate::HgtError {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("tile {tile} is not cached"),
+    )
+    .into()
+}
+
+pub(crate) fn copy_range(data: &[u8], offset: u64, buf: &mut [u8]) -> Result<()> {
+    let start = usize::try_from(offset).unwrap_or(usize::MAX);
+    let range = data
+        .get(start..start.saturating_add(buf.len()))
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+    buf.copy_from_slice(range);
+    Ok(())
+}
+
+/// SHA-256 of `data`, in lowercase hex.
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
@@ -0,0 +1,463 @@
+//! Read-only tile packs: many tiles delta-encoded and compressed into a
+//! single file with an index, e.g. shipped as an asset of a mobile app.
+//!
+//! A pack holds, all integers being big-endian:
+//!
+//! - the magic bytes `HGTPACK1` and the number of tiles as a `u32`,
+//! - an entry per tile, sorted by tile: latitude and longitude as `i16`,
+//!   grid size as a `u16`, offset of the tile data from the start of the
+//!   pack as a `u64` and its length as a `u32`,
+//! - the data of each tile: its samples row by row, each as the difference
+//!   to the previous sample of the row (the sample above for the first of a
+//!   row, 0 for the first of the tile), zigzag-encoded as LEB128 varints
+//!   and compressed with deflate.
+//!
+//! Neighbouring samples differ little, so packs are typically smaller than
+//! gzipped HGT tiles.
+
+use super::{copy_range, not_cached, TileCache};
+use crate::lru::LruCache;
+use crate::tile;
+use crate::{BoundingBox, EarthEl, HgtError, Result, TileId};
+use byteorder::{BigEndian, ByteOrder};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::collections::BTreeMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::File;
+use std::io::{Read, Write};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const MAGIC: &[u8] = b"HGTPACK1";
+const HEADER_LEN: usize = MAGIC.len() + 4;
+const ENTRY_LEN: usize = 2 + 2 + 2 + 8 + 4;
+/// Decoded tiles kept in memory for point lookups.
+const DECODED_TILES: usize = 2;
+
+/// Builds a tile pack for a [`PackCache`].
+///
+/// ```
+/// use earthel::cache::{PackBuilder, PackCache, TileCache};
+/// use earthel::TileId;
+///
+/// let mut builder = PackBuilder::new();
+/// builder.add(TileId::new(45, 6), &vec![0; 2 * 1201 * 1201])?;
+/// let cache = PackCache::new(builder.finish())?;
+/// assert_eq!(cache.tiles()?, [TileId::new(45, 6)]);
+/// # Ok::<(), earthel::HgtError>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct PackBuilder {
+    tiles: BTreeMap<TileId, (u16, Vec<u8>)>,
+}
+
+impl PackBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a tile from its raw HGT samples, replacing any previous version.
+    /// Fails with [`HgtError::InvalidResolution`] if `data` is not a square
+    /// grid of samples, and with [`HgtError::InvalidParameter`] for tiles
+    /// that don't fit the layout of packs.
+    pub fn add(&mut self, tile: TileId, data: &[u8]) -> Result<()> {
+        let size = data.len() as u64;
+        let grid_size = tile::grid_size(size)
+            .filter(|&grid_size| (grid_size * grid_size * 2) as u64 == size)
+            .ok_or(HgtError::InvalidResolution(size))?;
+        let grid_size = u16::try_from(grid_size).map_err(|_| HgtError::InvalidResolution(size))?;
+        if i16::try_from(tile.lat).is_err() || i16::try_from(tile.lon).is_err() {
+            return Err(HgtError::InvalidParameter(format!(
+                "tile {tile} does not fit in a pack"
+            )));
+        }
+        self.tiles
+            .insert(tile, (grid_size, encode(data, grid_size.into())?));
+        Ok(())
+    }
+
+    /// Tiles added so far.
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// The content of the pack.
+    pub fn finish(self) -> Vec<u8> {
+        let index_len = HEADER_LEN + ENTRY_LEN * self.tiles.len();
+        let data_len: usize = self.tiles.values().map(|(_, data)| data.len()).sum();
+        let mut pack = Vec::with_capacity(index_len + data_len);
+        pack.extend_from_slice(MAGIC);
+        pack.extend_from_slice(&(self.tiles.len() as u32).to_be_bytes());
+        let mut offset = index_len as u64;
+        for (tile, (grid_size, data)) in &self.tiles {
+            pack.extend_from_slice(&(tile.lat as i16).to_be_bytes());
+            pack.extend_from_slice(&(tile.lon as i16).to_be_bytes());
+            pack.extend_from_slice(&grid_size.to_be_bytes());
+            pack.extend_from_slice(&offset.to_be_bytes());
+            pack.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            offset += data.len() as u64;
+        }
+        for (_, data) in self.tiles.into_values() {
+            pack.extend_from_slice(&data);
+        }
+        pack
+    }
+
+    /// Writes the pack to a file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(self, path: impl AsRef<Path>) -> Result<()> {
+        Ok(std::fs::write(path, self.finish())?)
+    }
+}
+
+/// Serves the tiles of a pack built with a [`PackBuilder`], read-only.
+///
+/// Point lookups decode the tile they read, keeping the last two decoded
+/// tiles in memory. Tiles can't be stored or removed: combine the cache with
+/// a [`coverage`](crate::EarthElBuilder::coverage) or a
+/// [`source`](crate::EarthElBuilder::source) without other tiles, so that
+/// lookups elsewhere don't download tiles they can't store.
+///
+/// ```no_run
+/// use earthel::cache::PackCache;
+/// use earthel::{EarthEl, MissingPolicy};
+///
+/// # fn run() -> earthel::Result<()> {
+/// let earthel = EarthEl::builder()
+///     .cache(PackCache::open("assets/alps.hgtpack")?)
+///     .missing(MissingPolicy::None)
+///     .build();
+/// # Ok(())
+/// # }
+/// ```
+pub struct PackCache {
+    data: PackData,
+    index: BTreeMap<TileId, Entry>,
+    decoded: Mutex<LruCache<TileId, Arc<Vec<u8>>>>,
+}
+
+enum PackData {
+    Bytes(Vec<u8>),
+    #[cfg(not(target_arch = "wasm32"))]
+    File(Mutex<File>),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    grid_size: usize,
+    offset: u64,
+    len: usize,
+}
+
+impl PackCache {
+    /// Serves the tiles of the pack `data`, e.g. an embedded asset.
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        let index = parse_index(&data, data.len() as u64)?;
+        Ok(Self::with_index(PackData::Bytes(data), index))
+    }
+
+    /// Serves the tiles of the pack at `path`, reading only the index up
+    /// front and tiles as they are accessed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut header = [0; HEADER_LEN];
+        file.read_exact(&mut header)
+            .map_err(|_| invalid("truncated header"))?;
+        let count = check_header(&header)?;
+        let mut index = vec![0; HEADER_LEN + ENTRY_LEN * count];
+        index[..HEADER_LEN].copy_from_slice(&header);
+        file.read_exact(&mut index[HEADER_LEN..])
+            .map_err(|_| invalid("truncated index"))?;
+        let index = parse_index(&index, len)?;
+        Ok(Self::with_index(PackData::File(Mutex::new(file)), index))
+    }
+
+    fn with_index(data: PackData, index: BTreeMap<TileId, Entry>) -> Self {
+        Self {
+            data,
+            index,
+            decoded: Mutex::new(LruCache::new(DECODED_TILES)),
+        }
+    }
+
+    fn decoded(&self, tile: &TileId) -> Result<Option<Arc<Vec<u8>>>> {
+        let Some(entry) = self.index.get(tile) else {
+            return Ok(None);
+        };
+        if let Some(data) = self.decoded.lock().unwrap().get(tile) {
+            return Ok(Some(data));
+        }
+        let encoded = match &self.data {
+            PackData::Bytes(data) => {
+                let start = entry.offset as usize;
+                data[start..start + entry.len].to_vec()
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            PackData::File(file) => {
+                use std::io::{Seek, SeekFrom};
+                let mut file = file.lock().unwrap();
+                file.seek(SeekFrom::Start(entry.offset))?;
+                let mut encoded = vec![0; entry.len];
+                file.read_exact(&mut encoded)?;
+                encoded
+            }
+        };
+        let data = Arc::new(decode(&encoded, entry.grid_size)?);
+        self.decoded.lock().unwrap().insert(*tile, data.clone());
+        Ok(Some(data))
+    }
+}
+
+impl TileCache for PackCache {
+    fn load(&self, tile: &TileId) -> Result<Option<Vec<u8>>> {
+        Ok(self.decoded(tile)?.map(|data| data.as_ref().clone()))
+    }
+
+    /// Fails: packs are read-only.
+    fn store(&self, tile: &TileId, _data: &[u8]) -> Result<()> {
+        Err(read_only(tile))
+    }
+
+    /// Fails for the tiles of the pack, which is read-only.
+    fn remove(&self, tile: &TileId) -> Result<()> {
+        match self.index.contains_key(tile) {
+            true => Err(read_only(tile)),
+            false => Ok(()),
+        }
+    }
+
+    fn tiles(&self) -> Result<Vec<TileId>> {
+        Ok(self.index.keys().copied().collect())
+    }
+
+    fn size(&self, tile: &TileId) -> Result<Option<u64>> {
+        Ok(self
+            .index
+            .get(tile)
+            .map(|entry| (entry.grid_size * entry.grid_size * 2) as u64))
+    }
+
+    fn read_at(&self, tile: &TileId, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let data = self.decoded(tile)?.ok_or_else(|| not_cached(tile))?;
+        copy_range(&data, offset, buf)
+    }
+}
+
+impl EarthEl {
+    /// A pack of the tiles intersecting `bbox`, downloaded first if needed,
+    /// to be [saved](PackBuilder::save) and served by a [`PackCache`]. Tiles
+    /// outside of the coverage or missing at the source (e.g. over the
+    /// ocean) are left out.
+    ///
+    /// ```no_run
+    /// use earthel::{BoundingBox, EarthEl};
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let alps = BoundingBox::new(45.0, 5.0, 48.0, 11.0);
+    /// EarthEl::default().pack_region(alps).await?.save("alps.hgtpack")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn pack_region(&self, bbox: BoundingBox) -> Result<PackBuilder> {
+        bbox.validate()?;
+        let mut builder = PackBuilder::new();
+        for tile in bbox.tiles() {
+            if !self.coverage.intersects(&tile) {
+                continue;
+            }
+            match self.ensure_tile(&tile).await {
+                Ok(_) => {}
+                Err(HgtError::TileNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            }
+            let data = self.cache.load(&tile)?.ok_or_else(|| not_cached(&tile))?;
+            builder.add(tile, &data)?;
+        }
+        Ok(builder)
+    }
+}
+
+fn read_only(tile: &TileId) -> HgtError {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("tile packs are read-only, can't change {tile}"),
+    )
+    .into()
+}
+
+fn invalid(reason: &str) -> HgtError {
+    HgtError::UnsupportedFormat(format!("invalid tile pack: {reason}"))
+}
+
+/// Number of tiles announced by a pack header.
+fn check_header(header: &[u8]) -> Result<usize> {
+    if header.len() < HEADER_LEN || !header.starts_with(MAGIC) {
+        return Err(invalid("not a tile pack"));
+    }
+    Ok(BigEndian::read_u32(&header[MAGIC.len()..]) as usize)
+}
+
+/// Parses the index at the start of a pack of `len` bytes.
+fn parse_index(pack: &[u8], len: u64) -> Result<BTreeMap<TileId, Entry>> {
+    let count = check_header(pack)?;
+    let entries = pack
+        .get(HEADER_LEN..HEADER_LEN + ENTRY_LEN * count)
+        .ok_or_else(|| invalid("truncated index"))?;
+    entries
+        .chunks_exact(ENTRY_LEN)
+        .map(|entry| {
+            let tile = TileId::new(
+                BigEndian::read_i16(entry).into(),
+                BigEndian::read_i16(&entry[2..]).into(),
+            );
+            let entry = Entry {
+                grid_size: BigEndian::read_u16(&entry[4..]).into(),
+                offset: BigEndian::read_u64(&entry[6..]),
+                len: BigEndian::read_u32(&entry[14..]) as usize,
+            };
+            if entry.grid_size < 2 || entry.offset.saturating_add(entry.len as u64) > len {
+                return Err(invalid(&format!("bad entry for {tile}")));
+            }
+            Ok((tile, entry))
+        })
+        .collect()
+}
+
+fn encode(data: &[u8], grid_size: usize) -> Result<Vec<u8>> {
+    let sample = |i: usize| i32::from(BigEndian::read_i16(&data[2 * i..]));
+    let mut varints = Vec::with_capacity(data.len() / 2);
+    for i in 0..grid_size * grid_size {
+        let delta = sample(i) - predicted(i, grid_size, sample);
+        let mut zigzag = ((delta << 1) ^ (delta >> 31)) as u32;
+        while zigzag >= 0x80 {
+            varints.push(zigzag as u8 | 0x80);
+            zigzag >>= 7;
+        }
+        varints.push(zigzag as u8);
+    }
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&varints)?;
+    Ok(encoder.finish()?)
+}
+
+fn decode(encoded: &[u8], grid_size: usize) -> Result<Vec<u8>> {
+    let mut varints = Vec::new();
+    DeflateDecoder::new(encoded).read_to_end(&mut varints)?;
+    let mut samples = vec![0i16; grid_size * grid_size];
+    let mut bytes = varints.into_iter();
+    for i in 0..samples.len() {
+        let (mut zigzag, mut shift) = (0u32, 0);
+        loop {
+            let byte = bytes.next().ok_or_else(|| invalid("truncated tile data"))?;
+            if shift > 28 {
+                return Err(invalid("bad sample"));
+            }
+            zigzag |= u32::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte < 0x80 {
+                break;
+            }
+        }
+        let delta = (zigzag >> 1) as i32 ^ -((zigzag & 1) as i32);
+        let value = predicted(i, grid_size, |i| i32::from(samples[i])) + delta;
+        samples[i] = i16::try_from(value).map_err(|_| invalid("bad sample"))?;
+    }
+    let mut data = vec![0; samples.len() * 2];
+    BigEndian::write_i16_into(&samples, &mut data);
+    Ok(data)
+}
+
+/// The value sample `i` is encoded relative to: the previous sample of its
+/// row, or the one above for the first sample of a row.
+fn predicted(i: usize, grid_size: usize, sample: impl Fn(usize) -> i32) -> i32 {
+    match (i / grid_size, i % grid_size) {
+        (0, 0) => 0,
+        (_, 0) => sample(i - grid_size),
+        _ => sample(i - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::{synthetic_tile, VOID};
+
+    #[tokio::test]
+    async fn packs_tiles() {
+        let alps = synthetic_tile(1201, |row, col| {
+            if (row, col) == (600, 600) {
+                VOID
+            } else {
+                (row * 3 + col % 7) as i16 - 500
+            }
+        });
+        let flat = synthetic_tile(3601, |_, _| 0);
+        let mut builder = PackBuilder::new();
+        builder.add(TileId::new(45, 6), &alps).unwrap();
+        builder.add(TileId::new(-33, -71), &flat).unwrap();
+        assert!(matches!(
+            builder.add(TileId::new(0, 0), &[0; 5]),
+            Err(HgtError::InvalidResolution(5))
+        ));
+        assert_eq!(builder.len(), 2);
+        let pack = builder.finish();
+        assert!(pack.len() < alps.len() / 10, "{} bytes", pack.len());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tiles.hgtpack");
+        std::fs::write(&path, &pack).unwrap();
+        for cache in [
+            PackCache::new(pack.clone()).unwrap(),
+            PackCache::open(&path).unwrap(),
+        ] {
+            assert_eq!(
+                cache.tiles().unwrap(),
+                [TileId::new(-33, -71), TileId::new(45, 6)]
+            );
+            let tile = TileId::new(45, 6);
+            assert_eq!(cache.size(&tile).unwrap(), Some(alps.len() as u64));
+            assert_eq!(cache.load(&tile).unwrap().unwrap(), alps);
+            assert_eq!(cache.load(&TileId::new(-33, -71)).unwrap().unwrap(), flat);
+            let mut buf = [0; 4];
+            cache
+                .read_at(&tile, 2 * (600 * 1201 + 599), &mut buf)
+                .unwrap();
+            assert_eq!(buf, [0x05, 0x18, 0x80, 0x00]);
+            assert_eq!(cache.load(&TileId::new(0, 0)).unwrap(), None);
+            assert!(cache.store(&tile, &alps).is_err());
+            assert!(cache.remove(&tile).is_err());
+            cache.remove(&TileId::new(0, 0)).unwrap();
+        }
+
+        // Packs of regions, from the tiles of another cache.
+        let cache = Arc::new(MemoryCache::new());
+        cache.store(&TileId::new(45, 6), &alps).unwrap();
+        let earthel = EarthEl::builder()
+            .cache(Arc::clone(&cache))
+            .coverage(BoundingBox::new(45.0, 6.0, 46.0, 7.0))
+            .build();
+        let builder = earthel
+            .pack_region(BoundingBox::new(44.5, 5.5, 45.5, 6.5))
+            .await
+            .unwrap();
+        let packed = PackCache::new(builder.finish()).unwrap();
+        assert_eq!(packed.tiles().unwrap(), [TileId::new(45, 6)]);
+        let expected = earthel.get_elevation(45.25, 6.5).await.unwrap();
+        let earthel = EarthEl::builder().cache(packed).build();
+        assert_eq!(earthel.get_elevation(45.25, 6.5).await.unwrap(), expected);
+
+        let error = PackCache::new(pack[..40].to_vec()).err().unwrap();
+        assert!(matches!(error, HgtError::UnsupportedFormat(_)));
+        assert!(PackCache::new(b"HGTPACK0".to_vec()).is_err());
+    }
+}
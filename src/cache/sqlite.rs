@@ -0,0 +1,122 @@
+use super::{not_cached, TileCache};
+use crate::{Result, TileId};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Stores tiles as blobs in a single SQLite database file.
+pub struct SqliteCache {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteCache {
+    /// Opens (or creates) the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Opens a private in-memory database.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self> {
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS tiles (name TEXT PRIMARY KEY, data BLOB NOT NULL)",
+            [],
+        )?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+impl TileCache for SqliteCache {
+    fn load(&self, tile: &TileId) -> Result<Option<Vec<u8>>> {
+        let connection = self.connection.lock().unwrap();
+        let data = connection
+            .query_row(
+                "SELECT data FROM tiles WHERE name = ?1",
+                params![tile.name()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(data)
+    }
+
+    fn store(&self, tile: &TileId, data: &[u8]) -> Result<()> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT OR REPLACE INTO tiles (name, data) VALUES (?1, ?2)",
+            params![tile.name(), data],
+        )?;
+        Ok(())
+    }
+
+    fn remove(&self, tile: &TileId) -> Result<()> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute("DELETE FROM tiles WHERE name = ?1", params![tile.name()])?;
+        Ok(())
+    }
+
+    fn tiles(&self) -> Result<Vec<TileId>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT name FROM tiles")?;
+        let names = statement.query_map([], |row| row.get::<_, String>(0))?;
+        let mut tiles = Vec::new();
+        for name in names {
+            tiles.extend(TileId::from_name(&name?));
+        }
+        Ok(tiles)
+    }
+
+    fn size(&self, tile: &TileId) -> Result<Option<u64>> {
+        let connection = self.connection.lock().unwrap();
+        let size: Option<i64> = connection
+            .query_row(
+                "SELECT length(data) FROM tiles WHERE name = ?1",
+                params![tile.name()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(size.map(|size| size as u64))
+    }
+
+    fn read_at(&self, tile: &TileId, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let connection = self.connection.lock().unwrap();
+        // substr() on a blob counts bytes and starts at 1.
+        let bytes: Option<Vec<u8>> = connection
+            .query_row(
+                "SELECT substr(data, ?2, ?3) FROM tiles WHERE name = ?1",
+                params![tile.name(), offset as i64 + 1, buf.len() as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let bytes = bytes.ok_or_else(|| not_cached(tile))?;
+        if bytes.len() != buf.len() {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        buf.copy_from_slice(&bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_and_reads_tiles() {
+        let cache = SqliteCache::open_in_memory().unwrap();
+        let tile = TileId::new(45, 6);
+        cache.store(&tile, &[9, 8, 7, 6]).unwrap();
+        assert_eq!(cache.size(&tile).unwrap(), Some(4));
+        let mut buf = [0; 2];
+        cache.read_at(&tile, 2, &mut buf).unwrap();
+        assert_eq!(buf, [7, 6]);
+        assert!(cache.read_at(&tile, 3, &mut buf).is_err());
+        assert_eq!(cache.tiles().unwrap(), vec![tile]);
+        cache.remove(&tile).unwrap();
+        assert_eq!(cache.size(&tile).unwrap(), None);
+    }
+}
@@ -0,0 +1,358 @@
+//! Detection and categorization of climbs and descents along elevation
+//! profiles.
+//!
+//! Climbs are rated the way most cycling platforms do, from the product of
+//! their length in meters and their average grade in percent.
+
+use crate::profile::{Profile, ProfileSample};
+use std::fmt;
+
+/// Settings of [`Profile::climb_analysis`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct ClimbOptions {
+    /// Length in meters of the moving average smoothing the elevations first,
+    /// 0 to use them as they are. SRTM samples are noisy by several meters,
+    /// which adds up to large fake gains along long profiles. Defaults to
+    /// 200 m.
+    pub smoothing: f64,
+    /// Shortest climb or descent reported, in meters.
+    pub min_length: f64,
+    /// Lowest average grade of a climb or descent, in percent.
+    pub min_grade: f64,
+    /// Drop below the highest point reached that ends a climb (or rise above
+    /// the lowest point that ends a descent), in meters, so that short dips
+    /// don't split climbs.
+    pub tolerance: f64,
+}
+
+impl Default for ClimbOptions {
+    fn default() -> Self {
+        Self {
+            smoothing: 200.0,
+            min_length: 500.0,
+            min_grade: 3.0,
+            tolerance: 10.0,
+        }
+    }
+}
+
+/// Category of a climb, from the hardest to the easiest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ClimbCategory {
+    /// Hors catégorie.
+    Hc,
+    Cat1,
+    Cat2,
+    Cat3,
+    Cat4,
+}
+
+impl ClimbCategory {
+    /// Rates a climb of `length` meters at `average_grade` percent, `None`
+    /// for climbs too easy to be categorized.
+    pub fn rate(length: f64, average_grade: f64) -> Option<Self> {
+        let score = length * average_grade;
+        [
+            (80_000.0, Self::Hc),
+            (64_000.0, Self::Cat1),
+            (32_000.0, Self::Cat2),
+            (16_000.0, Self::Cat3),
+            (8_000.0, Self::Cat4),
+        ]
+        .into_iter()
+        .find(|&(threshold, _)| score >= threshold)
+        .map(|(_, category)| category)
+    }
+}
+
+impl fmt::Display for ClimbCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Hc => "HC",
+            Self::Cat1 => "1",
+            Self::Cat2 => "2",
+            Self::Cat3 => "3",
+            Self::Cat4 => "4",
+        })
+    }
+}
+
+/// A climb, or a descent, found along a profile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Climb {
+    /// Distance along the profile of the foot of the climb, in meters.
+    pub start: f64,
+    /// Distance along the profile of the top of the climb, in meters.
+    pub end: f64,
+    /// Elevation difference between the foot and the top, in meters.
+    /// Descents run from their top to their foot, and have negative
+    /// differences and grades.
+    pub elevation_gain: f64,
+    /// Average grade in percent.
+    pub average_grade: f64,
+    /// Steepest grade between two consecutive samples, in percent.
+    pub max_grade: f64,
+    /// Category of climbs hard enough, always `None` for descents.
+    pub category: Option<ClimbCategory>,
+}
+
+impl Climb {
+    /// Horizontal length of the climb in meters.
+    pub fn length(&self) -> f64 {
+        self.end - self.start
+    }
+
+    /// Whether this is a descent found by [`Profile::climb_analysis`].
+    pub fn is_descent(&self) -> bool {
+        self.elevation_gain < 0.0
+    }
+}
+
+/// Outcome of [`Profile::climb_analysis`].
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClimbAnalysis {
+    /// Climbs, in order.
+    pub climbs: Vec<Climb>,
+    /// Descents, in order.
+    pub descents: Vec<Climb>,
+    /// Total elevation gained along the smoothed profile, in meters.
+    pub elevation_gain: f64,
+    /// Total elevation lost along the smoothed profile, in meters (positive).
+    pub elevation_loss: f64,
+}
+
+impl Profile {
+    /// Climbs of at least 500 m at 3% or more along the profile, in order.
+    ///
+    /// A climb runs from a low point to the highest point reached before the
+    /// road drops by more than 10 m, so short dips don't split it. Samples
+    /// over data voids are ignored.
+    pub fn climbs(&self) -> Vec<Climb> {
+        let options = ClimbOptions {
+            smoothing: 0.0,
+            ..Default::default()
+        };
+        let points = points(&self.samples);
+        find_climbs(&points, &options, 1.0)
+    }
+
+    /// Climbs and descents along the profile and the total elevation gain and
+    /// loss, after smoothing the elevations as configured in `options`.
+    /// Climbs and descents are detected like [`climbs`](Self::climbs), with
+    /// the lengths, grades and tolerance of `options`.
+    ///
+    /// ```no_run
+    /// use earthel::{ClimbOptions, EarthEl};
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let route = [(45.0, 6.03), (45.09, 6.07)];
+    /// let profile = EarthEl::default().profile(&route, 30.0).await?;
+    /// let analysis = profile.climb_analysis(&ClimbOptions::default());
+    /// println!("+{:.0} m / -{:.0} m", analysis.elevation_gain, analysis.elevation_loss);
+    /// for climb in &analysis.climbs {
+    ///     println!("{:.1} km at {:.1}%", climb.length() / 1000.0, climb.average_grade);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn climb_analysis(&self, options: &ClimbOptions) -> ClimbAnalysis {
+        let points = smooth(&points(&self.samples), options.smoothing);
+        let (mut elevation_gain, mut elevation_loss) = (0.0, 0.0);
+        for pair in points.windows(2) {
+            let rise = pair[1].1 - pair[0].1;
+            if rise > 0.0 {
+                elevation_gain += rise;
+            } else {
+                elevation_loss -= rise;
+            }
+        }
+        ClimbAnalysis {
+            climbs: find_climbs(&points, options, 1.0),
+            descents: find_climbs(&points, options, -1.0),
+            elevation_gain,
+            elevation_loss,
+        }
+    }
+}
+
+/// Distances and elevations of the samples with data.
+pub(crate) fn points(samples: &[ProfileSample]) -> Vec<(f64, f64)> {
+    samples
+        .iter()
+        .filter_map(|s| Some((s.distance, s.elevation?)))
+        .collect()
+}
+
+/// Moving average of the elevations over `window` meters centered on each
+/// point.
+pub(crate) fn smooth(points: &[(f64, f64)], window: f64) -> Vec<(f64, f64)> {
+    if window <= 0.0 {
+        return points.to_vec();
+    }
+    let half = window / 2.0;
+    let (mut from, mut to, mut sum) = (0, 0, 0.0);
+    points
+        .iter()
+        .map(|&(distance, _)| {
+            while to < points.len() && points[to].0 <= distance + half {
+                sum += points[to].1;
+                to += 1;
+            }
+            while points[from].0 < distance - half {
+                sum -= points[from].1;
+                from += 1;
+            }
+            (distance, sum / (to - from) as f64)
+        })
+        .collect()
+}
+
+/// Climbs along `points`, or descents with a `direction` of -1, found as
+/// climbs of the negated elevations.
+fn find_climbs(points: &[(f64, f64)], options: &ClimbOptions, direction: f64) -> Vec<Climb> {
+    let points: Vec<(f64, f64)> = points.iter().map(|&(d, e)| (d, e * direction)).collect();
+    if points.is_empty() {
+        return Vec::new();
+    }
+    let mut climbs = Vec::new();
+    let (mut foot, mut top) = (0, 0);
+    for (i, point) in points.iter().enumerate().skip(1) {
+        if point.1 > points[top].1 {
+            top = i;
+        } else if points[top].1 - point.1 > options.tolerance || point.1 < points[foot].1 {
+            climbs.extend(climb_between(&points[foot..=top], options, direction));
+            (foot, top) = (i, i);
+        }
+    }
+    climbs.extend(climb_between(&points[foot..=top], options, direction));
+    climbs
+}
+
+/// The climb from the first to the last of `points`, if long and steep
+/// enough.
+fn climb_between(points: &[(f64, f64)], options: &ClimbOptions, direction: f64) -> Option<Climb> {
+    let (foot, top) = (points.first()?, points.last()?);
+    let length = top.0 - foot.0;
+    if length < options.min_length {
+        return None;
+    }
+    let elevation_gain = top.1 - foot.1;
+    let average_grade = elevation_gain / length * 100.0;
+    if average_grade < options.min_grade {
+        return None;
+    }
+    let max_grade = points
+        .windows(2)
+        .filter(|pair| pair[1].0 > pair[0].0)
+        .map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0) * 100.0)
+        .fold(average_grade, f64::max);
+    Some(Climb {
+        start: foot.0,
+        end: top.0,
+        elevation_gain: elevation_gain * direction,
+        average_grade: average_grade * direction,
+        max_grade: max_grade * direction,
+        category: if direction > 0.0 {
+            ClimbCategory::rate(length, average_grade)
+        } else {
+            None
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::synthetic_profile as profile;
+
+    #[test]
+    fn rates_climbs() {
+        // Alpe d'Huez: 13.8 km at 8.1%.
+        assert_eq!(ClimbCategory::rate(13_800.0, 8.1), Some(ClimbCategory::Hc));
+        assert_eq!(ClimbCategory::rate(5_000.0, 5.0), Some(ClimbCategory::Cat3));
+        assert_eq!(ClimbCategory::rate(1_000.0, 4.0), None);
+        assert_eq!(ClimbCategory::Hc.to_string(), "HC");
+    }
+
+    #[test]
+    fn finds_climbs_across_small_dips() {
+        // 2 km at 6%, a 5 m dip, 1 km at 6%, a descent, then a false flat.
+        let mut elevations: Vec<f64> = (0..=20).map(|i| i as f64 * 6.0).collect();
+        elevations.push(115.0);
+        elevations.extend((1..=10).map(|i| 115.0 + i as f64 * 6.5));
+        elevations.extend((1..=10).map(|i| 180.0 - i as f64 * 10.0));
+        elevations.extend((1..=10).map(|i| 80.0 + i as f64));
+        let climbs = profile(elevations).climbs();
+        assert_eq!(climbs.len(), 1);
+        let climb = climbs[0];
+        assert_eq!((climb.start, climb.end), (0.0, 3_100.0));
+        assert!((climb.elevation_gain - 180.0).abs() < 1e-9);
+        assert!((climb.average_grade - 5.806).abs() < 1e-3);
+        assert_eq!(climb.category, Some(ClimbCategory::Cat3));
+    }
+
+    #[test]
+    fn analyzes_noisy_profiles() {
+        // 3 km at 5% then 2 km down at 7.5%, with ±4 m of noise on every
+        // sample.
+        let elevations = (0..=50).map(|i| {
+            let trend = if i <= 30 {
+                i as f64 * 5.0
+            } else {
+                150.0 - (i - 30) as f64 * 7.5
+            };
+            trend + if i % 2 == 0 { 4.0 } else { -4.0 }
+        });
+        let profile = profile(elevations);
+        let raw = profile.climb_analysis(&ClimbOptions {
+            smoothing: 0.0,
+            ..Default::default()
+        });
+        assert!(raw.elevation_gain > 190.0, "{raw:?}");
+
+        let analysis = profile.climb_analysis(&ClimbOptions::default());
+        assert!(
+            (analysis.elevation_gain - 150.0).abs() < 10.0,
+            "{analysis:?}"
+        );
+        assert!(
+            (analysis.elevation_loss - 150.0).abs() < 15.0,
+            "{analysis:?}"
+        );
+        assert_eq!(analysis.climbs.len(), 1);
+        let climb = analysis.climbs[0];
+        assert!(!climb.is_descent());
+        assert!(climb.start <= 100.0 && (2_900.0..=3_100.0).contains(&climb.end));
+        assert!((climb.average_grade - 5.0).abs() < 0.5, "{climb:?}");
+        assert!(climb.max_grade >= climb.average_grade);
+        assert_eq!(climb.category, Some(ClimbCategory::Cat4));
+
+        assert_eq!(analysis.descents.len(), 1);
+        let descent = analysis.descents[0];
+        assert!(descent.is_descent());
+        assert!((2_900.0..=3_100.0).contains(&descent.start) && descent.end >= 4_900.0);
+        assert!((descent.average_grade + 7.5).abs() < 1.0, "{descent:?}");
+        assert!(descent.max_grade <= descent.average_grade);
+        assert_eq!(descent.category, None);
+    }
+
+    #[test]
+    fn ignores_flat_profiles() {
+        assert!(profile([100.0; 50]).climbs().is_empty());
+        assert!(Profile::default().climbs().is_empty());
+        let analysis = profile([100.0; 50]).climb_analysis(&ClimbOptions::default());
+        assert!(analysis.climbs.is_empty() && analysis.descents.is_empty());
+        assert_eq!(
+            (analysis.elevation_gain, analysis.elevation_loss),
+            (0.0, 0.0)
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Difficulty ratings of routes from their elevation profiles.
+//!
+//! A route scores points for its length, its total elevation gain, its
+//! steepest sustained grade and how high it climbs, with the weights of a
+//! [`DifficultyScheme`]; the score is then rated against the thresholds of the
+//! scheme.
+
+use crate::climbs::{points, smooth};
+use crate::profile::Profile;
+use std::fmt;
+
+/// Weights and thresholds of [`Profile::difficulty`]. The defaults rate a
+/// 10 km walk on the flat as easy and the climb to Alpe d'Huez as hard.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct DifficultyScheme {
+    /// Length in meters of the moving average smoothing the elevations
+    /// first, see [`ClimbOptions::smoothing`](crate::ClimbOptions::smoothing).
+    /// Defaults to 200 m.
+    pub smoothing: f64,
+    /// Shortest stretch in meters whose average grade counts as sustained.
+    /// Defaults to 1 km.
+    pub sustained_length: f64,
+    /// Points per kilometer of length.
+    pub length_weight: f64,
+    /// Points per 100 m of elevation gain.
+    pub gain_weight: f64,
+    /// Points per percent of the steepest sustained grade.
+    pub grade_weight: f64,
+    /// Points per 100 m of the highest elevation above `altitude_threshold`,
+    /// for the effort of thin air.
+    pub altitude_weight: f64,
+    /// Elevation in meters above which altitude adds points.
+    pub altitude_threshold: f64,
+    /// Lowest scores of the [`Moderate`](DifficultyRating::Moderate),
+    /// [`Hard`](DifficultyRating::Hard),
+    /// [`VeryHard`](DifficultyRating::VeryHard) and
+    /// [`Extreme`](DifficultyRating::Extreme) ratings.
+    pub thresholds: [f64; 4],
+}
+
+impl Default for DifficultyScheme {
+    fn default() -> Self {
+        Self {
+            smoothing: 200.0,
+            sustained_length: 1_000.0,
+            length_weight: 1.0,
+            gain_weight: 1.0,
+            grade_weight: 2.0,
+            altitude_weight: 1.0,
+            altitude_threshold: 2_000.0,
+            thresholds: [15.0, 30.0, 60.0, 100.0],
+        }
+    }
+}
+
+impl DifficultyScheme {
+    /// Rates a score against the thresholds of the scheme.
+    pub fn rate(&self, score: f64) -> DifficultyRating {
+        [
+            DifficultyRating::Moderate,
+            DifficultyRating::Hard,
+            DifficultyRating::VeryHard,
+            DifficultyRating::Extreme,
+        ]
+        .into_iter()
+        .zip(self.thresholds)
+        .rev()
+        .find(|&(_, threshold)| score >= threshold)
+        .map_or(DifficultyRating::Easy, |(rating, _)| rating)
+    }
+}
+
+/// Difficulty rating of a route, from the easiest to the hardest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DifficultyRating {
+    Easy,
+    Moderate,
+    Hard,
+    VeryHard,
+    Extreme,
+}
+
+impl fmt::Display for DifficultyRating {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Easy => "easy",
+            Self::Moderate => "moderate",
+            Self::Hard => "hard",
+            Self::VeryHard => "very hard",
+            Self::Extreme => "extreme",
+        })
+    }
+}
+
+/// Outcome of [`Profile::difficulty`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Difficulty {
+    pub score: f64,
+    pub rating: DifficultyRating,
+    /// Total elevation gained along the smoothed profile, in meters.
+    pub elevation_gain: f64,
+    /// Steepest average climbing grade over the sustained length, in percent;
+    /// the average grade of the whole profile if it is shorter.
+    pub max_sustained_grade: f64,
+    /// Highest elevation of the smoothed profile in meters, `None` if the
+    /// profile has no data.
+    pub max_elevation: Option<f64>,
+}
+
+impl Profile {
+    /// Rates the difficulty of the route along the profile with `scheme`.
+    /// Samples over data voids are ignored.
+    ///
+    /// ```no_run
+    /// use earthel::{DifficultyScheme, EarthEl};
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let route = [(45.0, 6.03), (45.09, 6.07)];
+    /// let profile = EarthEl::default().profile(&route, 30.0).await?;
+    /// let difficulty = profile.difficulty(&DifficultyScheme::default());
+    /// println!("{} ({:.0} points)", difficulty.rating, difficulty.score);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn difficulty(&self, scheme: &DifficultyScheme) -> Difficulty {
+        let points = smooth(&points(&self.samples), scheme.smoothing);
+        let elevation_gain: f64 = points
+            .windows(2)
+            .map(|pair| (pair[1].1 - pair[0].1).max(0.0))
+            .sum();
+        let max_sustained_grade = max_sustained_grade(&points, scheme.sustained_length);
+        let max_elevation = points.iter().map(|point| point.1).reduce(f64::max);
+        let thin_air = max_elevation.map_or(0.0, |max| (max - scheme.altitude_threshold).max(0.0));
+        let score = scheme.length_weight * self.length() / 1000.0
+            + scheme.gain_weight * elevation_gain / 100.0
+            + scheme.grade_weight * max_sustained_grade
+            + scheme.altitude_weight * thin_air / 100.0;
+        Difficulty {
+            score,
+            rating: scheme.rate(score),
+            elevation_gain,
+            max_sustained_grade,
+            max_elevation,
+        }
+    }
+}
+
+/// Steepest average climbing grade in percent between two points at least
+/// `length` meters apart, or between the ends of shorter profiles; 0 on
+/// the flat or downhill.
+fn max_sustained_grade(points: &[(f64, f64)], length: f64) -> f64 {
+    let grade = |from: (f64, f64), to: (f64, f64)| {
+        let run = to.0 - from.0;
+        if run > 0.0 {
+            (to.1 - from.1) / run * 100.0
+        } else {
+            0.0
+        }
+    };
+    let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+        return 0.0;
+    };
+    if last.0 - first.0 < length {
+        return grade(first, last).max(0.0);
+    }
+    let mut max: f64 = 0.0;
+    let mut to = 0;
+    for &from in points {
+        while to < points.len() && points[to].0 - from.0 < length {
+            to += 1;
+        }
+        let Some(&end) = points.get(to) else {
+            break;
+        };
+        max = max.max(grade(from, end));
+    }
+    max
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::synthetic_profile as profile;
+
+    /// Alpe d'Huez: 13.8 km from `base` meters up 1130 m, 10% over its first
+    /// 3 km.
+    fn alpe(base: f64) -> Profile {
+        profile((0..=138).map(|i| {
+            let distance = i as f64 * 100.0;
+            if distance <= 3_000.0 {
+                base + distance * 0.1
+            } else {
+                base + 300.0 + (distance - 3_000.0) * 830.0 / 10_800.0
+            }
+        }))
+    }
+
+    #[test]
+    fn rates_routes() {
+        let scheme = DifficultyScheme::default();
+        // 10 km on the flat.
+        let flat = profile((0..=100).map(|_| 300.0)).difficulty(&scheme);
+        assert_eq!(flat.rating, DifficultyRating::Easy);
+        assert!((flat.score - 10.0).abs() < 1e-9);
+        assert_eq!(flat.max_sustained_grade, 0.0);
+
+        // Smoothing rounds off the ends of the climb by a few meters.
+        let low = alpe(720.0).difficulty(&scheme);
+        assert_eq!(low.rating, DifficultyRating::Hard);
+        assert!((low.elevation_gain - 1_130.0).abs() < 15.0, "{low:?}");
+        assert!((low.max_sustained_grade - 10.0).abs() < 1e-6, "{low:?}");
+        assert!((low.max_elevation.unwrap() - 1_850.0).abs() < 5.0);
+
+        // The same climb 2000 m higher, rated harder for the thin air.
+        let high = alpe(2_720.0).difficulty(&scheme);
+        assert!(high.score > low.score + 15.0);
+        assert_eq!(high.rating, DifficultyRating::VeryHard);
+
+        // Custom schemes, e.g. ignoring the length.
+        let climbing = DifficultyScheme {
+            smoothing: 0.0,
+            length_weight: 0.0,
+            ..scheme
+        };
+        assert!((profile([0.0, 50.0]).difficulty(&climbing).score - 100.5).abs() < 1e-9);
+        assert_eq!(climbing.rate(100.5), DifficultyRating::Extreme);
+        assert_eq!(DifficultyRating::VeryHard.to_string(), "very hard");
+        assert_eq!(Profile::default().difficulty(&scheme).max_elevation, None);
+    }
+}
@@ -0,0 +1,258 @@
+//! Elevation enrichment for GPX tracks and GeoJSON geometries.
+//!
+//! Both helpers keep the input geometry untouched and only fill in the
+//! elevation of every position, replacing any value already present.
+//! Positions without data, over voids or over missing tiles the
+//! [`MissingPolicy`](crate::MissingPolicy) tolerates, are left without
+//! elevation.
+
+use crate::{EarthEl, Result};
+
+#[cfg(feature = "geojson")]
+use crate::HgtError;
+#[cfg(feature = "geojson")]
+use serde_json::Value;
+#[cfg(feature = "gpx")]
+use std::io::{Read, Write};
+
+impl EarthEl {
+    /// Reads a GPX document, fills in the elevation of every waypoint, route
+    /// point and track point, and writes the result to `writer`.
+    ///
+    /// GPX 1.0 and 1.1 documents are supported. The writer is handed back once
+    /// the enriched document has been written.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use earthel::EarthEl;
+    /// use std::fs::File;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let input = File::open("track.gpx").unwrap();
+    ///     let output = File::create("track_with_elevation.gpx").unwrap();
+    ///     EarthEl::default().enrich_gpx(input, output).await.unwrap();
+    /// }
+    /// ```
+    #[cfg(feature = "gpx")]
+    pub async fn enrich_gpx<R: Read, W: Write>(&self, reader: R, mut writer: W) -> Result<W> {
+        self.within_deadline(async {
+            let mut gpx = gpx::read(reader)?;
+            for waypoint in gpx_waypoints_mut(&mut gpx) {
+                let point = waypoint.point();
+                waypoint.elevation = self.try_get_elevation_or_void(point.y(), point.x()).await?;
+            }
+            gpx::write(&gpx, &mut writer)?;
+            Ok(writer)
+        })
+        .await
+    }
+
+    /// Fills in the elevation of every position of a GeoJSON object.
+    ///
+    /// Accepts any geometry type as well as `Feature`, `FeatureCollection` and
+    /// `GeometryCollection` objects. Each position becomes
+    /// `[longitude, latitude, elevation]`, or `[longitude, latitude]` without
+    /// data.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use earthel::EarthEl;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let point = json!({ "type": "Point", "coordinates": [5.7181, 47.0592] });
+    ///     let enriched = EarthEl::default().enrich_geojson(point).await.unwrap();
+    ///     println!("{enriched}");
+    /// }
+    /// ```
+    #[cfg(feature = "geojson")]
+    pub async fn enrich_geojson(&self, mut value: Value) -> Result<Value> {
+        self.within_deadline(async {
+            for position in geojson_positions_mut(&mut value)? {
+                let (longitude, latitude) = match (position[0].as_f64(), position[1].as_f64()) {
+                    (Some(longitude), Some(latitude)) => (longitude, latitude),
+                    _ => return Err(HgtError::InvalidGeoJson("non-numeric position".into())),
+                };
+                let elevation = self.try_get_elevation_or_void(latitude, longitude).await?;
+                position.truncate(2);
+                position.extend(elevation.map(Value::from));
+            }
+            Ok(value)
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "gpx")]
+fn gpx_waypoints_mut(gpx: &mut gpx::Gpx) -> Vec<&mut gpx::Waypoint> {
+    let mut waypoints: Vec<&mut gpx::Waypoint> = gpx.waypoints.iter_mut().collect();
+    for route in gpx.routes.iter_mut() {
+        waypoints.extend(route.points.iter_mut());
+    }
+    for track in gpx.tracks.iter_mut() {
+        for segment in track.segments.iter_mut() {
+            waypoints.extend(segment.points.iter_mut());
+        }
+    }
+    waypoints
+}
+
+#[cfg(feature = "geojson")]
+fn geojson_positions_mut(value: &mut Value) -> Result<Vec<&mut Vec<Value>>> {
+    let mut positions = Vec::new();
+    collect_object(value, &mut positions)?;
+    Ok(positions)
+}
+
+#[cfg(feature = "geojson")]
+fn collect_object<'a>(value: &'a mut Value, out: &mut Vec<&'a mut Vec<Value>>) -> Result<()> {
+    let kind = value
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or_else(|| HgtError::InvalidGeoJson("missing \"type\" member".into()))?
+        .to_owned();
+    let member = match kind.as_str() {
+        "FeatureCollection" => "features",
+        "GeometryCollection" => "geometries",
+        "Feature" => "geometry",
+        _ => "coordinates",
+    };
+    match (kind.as_str(), value.get_mut(member)) {
+        // A feature without geometry is valid GeoJSON, there is just nothing to enrich.
+        ("Feature", Some(Value::Null)) => Ok(()),
+        ("Feature", Some(geometry)) => collect_object(geometry, out),
+        ("FeatureCollection" | "GeometryCollection", Some(Value::Array(children))) => children
+            .iter_mut()
+            .try_for_each(|child| collect_object(child, out)),
+        (_, Some(coordinates)) if member == "coordinates" => collect_coordinates(coordinates, out),
+        _ => Err(HgtError::InvalidGeoJson(format!(
+            "\"{kind}\" object without a valid \"{member}\" member"
+        ))),
+    }
+}
+
+#[cfg(feature = "geojson")]
+fn collect_coordinates<'a>(value: &'a mut Value, out: &mut Vec<&'a mut Vec<Value>>) -> Result<()> {
+    let Value::Array(items) = value else {
+        return Err(HgtError::InvalidGeoJson(
+            "coordinates must be arrays".into(),
+        ));
+    };
+    if !items.first().is_some_and(Value::is_number) {
+        return items
+            .iter_mut()
+            .try_for_each(|item| collect_coordinates(item, out));
+    }
+    if items.len() < 2 {
+        return Err(HgtError::InvalidGeoJson(
+            "position with less than two values".into(),
+        ));
+    }
+    out.push(items);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "gpx", feature = "geojson"))]
+    use crate::{cache::MemoryCache, tile, EarthEl, TileCache, TileId};
+
+    /// 40 m in the west half of N45E006, voids in the east half.
+    #[cfg(any(feature = "gpx", feature = "geojson"))]
+    fn earthel() -> EarthEl {
+        let cache = MemoryCache::new();
+        let data = tile::synthetic_tile(1201, |_, col| if col < 600 { 40 } else { tile::VOID });
+        cache.store(&TileId::new(45, 6), &data).unwrap();
+        EarthEl::builder().cache(cache).build()
+    }
+
+    #[cfg(feature = "gpx")]
+    #[tokio::test]
+    async fn gpx_leaves_voids_without_elevation() {
+        let document = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+              <trk><trkseg>
+                <trkpt lat="45.5" lon="6.25"/>
+                <trkpt lat="45.5" lon="6.75"><ele>12</ele></trkpt>
+              </trkseg></trk>
+            </gpx>"#;
+        let output = earthel()
+            .enrich_gpx(document.as_bytes(), Vec::new())
+            .await
+            .unwrap();
+        let gpx = gpx::read(output.as_slice()).unwrap();
+        let elevations: Vec<_> = gpx.tracks[0].segments[0]
+            .points
+            .iter()
+            .map(|point| point.elevation)
+            .collect();
+        assert_eq!(elevations, [Some(40.0), None]);
+    }
+
+    #[cfg(feature = "geojson")]
+    #[tokio::test]
+    async fn geojson_leaves_voids_without_elevation() {
+        let line = serde_json::json!({
+            "type": "LineString",
+            "coordinates": [[6.25, 45.5], [6.75, 45.5, 12.0]]
+        });
+        let enriched = earthel().enrich_geojson(line).await.unwrap();
+        assert_eq!(
+            enriched["coordinates"],
+            serde_json::json!([[6.25, 45.5, 40.0], [6.75, 45.5]])
+        );
+    }
+
+    #[cfg(feature = "gpx")]
+    #[test]
+    fn gpx_collects_every_point() {
+        let document = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+              <wpt lat="47.0" lon="5.7"/>
+              <rte><rtept lat="47.1" lon="5.8"/></rte>
+              <trk><trkseg>
+                <trkpt lat="47.2" lon="5.9"><ele>12</ele></trkpt>
+                <trkpt lat="47.3" lon="6.0"/>
+              </trkseg></trk>
+            </gpx>"#;
+        let mut gpx = gpx::read(document.as_bytes()).expect("valid gpx");
+        let latitudes: Vec<f64> = super::gpx_waypoints_mut(&mut gpx)
+            .iter()
+            .map(|w| w.point().y())
+            .collect();
+        assert_eq!(latitudes, vec![47.0, 47.1, 47.2, 47.3]);
+    }
+
+    #[cfg(feature = "geojson")]
+    #[test]
+    fn geojson_collects_nested_positions() {
+        let mut value = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [
+                { "type": "Feature", "properties": {}, "geometry": { "type": "Point", "coordinates": [5.7, 47.0] } },
+                { "type": "Feature", "properties": {}, "geometry": null },
+                { "type": "Feature", "properties": {}, "geometry": {
+                    "type": "LineString", "coordinates": [[5.7, 47.0, 100.0], [5.8, 47.1]]
+                } },
+                { "type": "Feature", "properties": {}, "geometry": {
+                    "type": "Polygon", "coordinates": [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0]]]
+                } }
+            ]
+        });
+        let positions = super::geojson_positions_mut(&mut value).expect("valid geojson");
+        assert_eq!(positions.len(), 7);
+    }
+
+    #[cfg(feature = "geojson")]
+    #[test]
+    fn geojson_rejects_malformed_objects() {
+        let mut missing_type = serde_json::json!({ "coordinates": [5.7, 47.0] });
+        assert!(super::geojson_positions_mut(&mut missing_type).is_err());
+        let mut bad_position = serde_json::json!({ "type": "Point", "coordinates": [5.7] });
+        assert!(super::geojson_positions_mut(&mut bad_position).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{Date, TileId};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum HgtError {
+    #[error("File operation failed: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Failed to decode gzip file: {0}")]
+    DecodeError(#[from] flate2::DecompressError),
+
+    #[error("Network error: {0}")]
+    ReqwestError(#[from] reqwest::Error),
+
+    #[error("Unexpected HGT resolution: {0}")]
+    InvalidResolution(u64),
+
+    #[error("Invalid coordinate: lat {lat}, lon {lon}")]
+    InvalidCoordinate { lat: f64, lon: f64 },
+
+    #[error("Tile {0} does not exist at the source")]
+    TileNotFound(TileId),
+
+    #[error("Tile {0} is outside of the source coverage")]
+    OutsideCoverage(TileId),
+
+    #[error("No elevation data at lat {lat}, lon {lon}")]
+    NoData { lat: f64, lon: f64 },
+
+    #[error("No DEM version dated on or before {0}")]
+    NoVersion(Date),
+
+    #[error("Invalid parameter: {0}")]
+    InvalidParameter(String),
+
+    #[error("Unsupported or malformed tile file: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Tile {tile} is unavailable: {reason}")]
+    TileUnavailable { tile: TileId, reason: String },
+
+    #[error("Tile {tile} does not match its pinned checksum (expected SHA-256 {expected}, got {actual})")]
+    ChecksumMismatch {
+        tile: TileId,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("Tile source is rate limiting requests (retry after {retry_after:?})")]
+    RateLimited { retry_after: Option<Duration> },
+
+    #[error("Query did not complete within its deadline of {0:?}")]
+    DeadlineExceeded(Duration),
+
+    #[error(
+        "Tile {tile} is not at its pinned version {expected} (got {})",
+        actual.as_deref().unwrap_or("an unknown version")
+    )]
+    VersionMismatch {
+        tile: TileId,
+        expected: String,
+        actual: Option<String>,
+    },
+
+    #[error("Allocation of {bytes} bytes exceeds the limit of {limit} bytes")]
+    AllocationLimit { bytes: u64, limit: u64 },
+
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite cache error: {0}")]
+    SqliteError(#[from] rusqlite::Error),
+
+    #[cfg(feature = "gpx")]
+    #[error("Failed to process GPX data: {0}")]
+    GpxError(#[from] gpx::errors::GpxError),
+
+    #[cfg(feature = "geojson")]
+    #[error("Invalid GeoJSON: {0}")]
+    InvalidGeoJson(String),
+
+    #[cfg(feature = "manifest")]
+    #[error("Invalid tile manifest: {0}")]
+    InvalidManifest(String),
+}
+
+impl HgtError {
+    /// Stable, machine-readable identifier of the kind of error, e.g.
+    /// `"outside_coverage"`, for services reporting errors to their clients.
+    /// Unlike messages, codes don't change between releases.
+    ///
+    /// Codes fall in a few groups, which services can map to HTTP statuses:
+    /// invalid requests (`invalid_coordinate`, `invalid_parameter`), requests
+    /// without data (`tile_not_found`, `outside_coverage`, `no_data`,
+    /// `no_version`), changed data (`checksum_mismatch`, `version_mismatch`),
+    /// requests too large for the memory budget (`allocation_limit`),
+    /// source outages (`network`, `tile_unavailable`,
+    /// `rate_limited`, `deadline_exceeded`) and internal errors (the others).
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::IoError(_) => "io",
+            Self::DecodeError(_) => "decode",
+            Self::ReqwestError(_) => "network",
+            Self::InvalidResolution(_) => "invalid_resolution",
+            Self::InvalidCoordinate { .. } => "invalid_coordinate",
+            Self::TileNotFound(_) => "tile_not_found",
+            Self::OutsideCoverage(_) => "outside_coverage",
+            Self::NoData { .. } => "no_data",
+            Self::NoVersion(_) => "no_version",
+            Self::InvalidParameter(_) => "invalid_parameter",
+            Self::UnsupportedFormat(_) => "unsupported_format",
+            Self::TileUnavailable { .. } => "tile_unavailable",
+            Self::ChecksumMismatch { .. } => "checksum_mismatch",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::DeadlineExceeded(_) => "deadline_exceeded",
+            Self::VersionMismatch { .. } => "version_mismatch",
+            Self::AllocationLimit { .. } => "allocation_limit",
+            #[cfg(feature = "sqlite")]
+            Self::SqliteError(_) => "sqlite",
+            #[cfg(feature = "gpx")]
+            Self::GpxError(_) => "gpx",
+            #[cfg(feature = "geojson")]
+            Self::InvalidGeoJson(_) => "invalid_geojson",
+            #[cfg(feature = "manifest")]
+            Self::InvalidManifest(_) => "invalid_manifest",
+        }
+    }
+}
+
+impl HgtError {
+    /// A copy of the error, of the same variant and with the same message,
+    /// `None` for the errors of other libraries that can't be copied.
+    pub(crate) fn duplicate(&self) -> Option<HgtError> {
+        Some(match self {
+            Self::IoError(e) => Self::IoError(std::io::Error::new(e.kind(), e.to_string())),
+            Self::InvalidResolution(size) => Self::InvalidResolution(*size),
+            Self::InvalidCoordinate { lat, lon } => Self::InvalidCoordinate {
+                lat: *lat,
+                lon: *lon,
+            },
+            Self::TileNotFound(tile) => Self::TileNotFound(*tile),
+            Self::OutsideCoverage(tile) => Self::OutsideCoverage(*tile),
+            Self::NoData { lat, lon } => Self::NoData {
+                lat: *lat,
+                lon: *lon,
+            },
+            Self::NoVersion(date) => Self::NoVersion(*date),
+            Self::InvalidParameter(message) => Self::InvalidParameter(message.clone()),
+            Self::UnsupportedFormat(message) => Self::UnsupportedFormat(message.clone()),
+            Self::TileUnavailable { tile, reason } => Self::TileUnavailable {
+                tile: *tile,
+                reason: reason.clone(),
+            },
+            Self::ChecksumMismatch {
+                tile,
+                expected,
+                actual,
+            } => Self::ChecksumMismatch {
+                tile: *tile,
+                expected: expected.clone(),
+                actual: actual.clone(),
+            },
+            Self::RateLimited { retry_after } => Self::RateLimited {
+                retry_after: *retry_after,
+            },
+            Self::DeadlineExceeded(deadline) => Self::DeadlineExceeded(*deadline),
+            Self::VersionMismatch {
+                tile,
+                expected,
+                actual,
+            } => Self::VersionMismatch {
+                tile: *tile,
+                expected: expected.clone(),
+                actual: actual.clone(),
+            },
+            Self::AllocationLimit { bytes, limit } => Self::AllocationLimit {
+                bytes: *bytes,
+                limit: *limit,
+            },
+            #[cfg(feature = "geojson")]
+            Self::InvalidGeoJson(message) => Self::InvalidGeoJson(message.clone()),
+            #[cfg(feature = "manifest")]
+            Self::InvalidManifest(message) => Self::InvalidManifest(message.clone()),
+            _ => return None,
+        })
+    }
+}
+
+pub type Result<T> = std::result::Result<T, HgtError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_identify_kinds() {
+        let tile = TileId::new(47, 5);
+        assert_eq!(HgtError::OutsideCoverage(tile).code(), "outside_coverage");
+        let error = HgtError::TileUnavailable {
+            tile,
+            reason: "overloaded".into(),
+        };
+        assert_eq!(error.code(), "tile_unavailable");
+        let error = HgtError::RateLimited { retry_after: None };
+        assert_eq!(error.code(), "rate_limited");
+    }
+}
@@ -0,0 +1,450 @@
+//! Decoding of the file formats tiles are distributed in.
+//!
+//! Tile sources hand raw HGT samples to [`EarthEl`](crate::EarthEl): big-endian
+//! 16-bit integers, row by row from the north-west corner, with -32768 for
+//! voids. Besides plain and gzipped `.hgt` files, SRTM archives come as `.zip`
+//! files holding a `.hgt` file (old mirrors, NASA Earthdata), or as BIL rasters
+//! with an ESRI `.hdr` header (USGS EarthExplorer), zipped or not.
+
+use crate::tile::VOID;
+use crate::{HgtError, Result};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use flate2::read::{DeflateDecoder, GzDecoder};
+use std::io::Read;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Raw HGT samples of a tile file: a plain `.hgt` file, a gzipped one, or a
+/// `.zip` archive holding either a `.hgt` file or a `.bil` raster and its
+/// `.hdr` header. The format is told from the first bytes.
+///
+/// ```
+/// let hgt = vec![0; 2 * 1201 * 1201];
+/// assert_eq!(earthel::source::decode_tile(&hgt).unwrap(), hgt);
+/// ```
+pub fn decode_tile(data: &[u8]) -> Result<Vec<u8>> {
+    if data.starts_with(GZIP_MAGIC) {
+        let mut decoded = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut decoded)?;
+        Ok(decoded)
+    } else if data.starts_with(ZIP_MAGIC) {
+        let entries = zip_entries(data)?;
+        let find = |extension: &str| {
+            entries
+                .iter()
+                .find(|entry| entry.name.to_ascii_lowercase().ends_with(extension))
+        };
+        if let Some(hgt) = find(".hgt") {
+            return hgt.extract(data);
+        }
+        match (find(".bil"), find(".hdr")) {
+            (Some(bil), Some(hdr)) => {
+                let header = String::from_utf8_lossy(&hdr.extract(data)?).into_owned();
+                decode_bil(&bil.extract(data)?, &header)
+            }
+            _ => Err(unsupported(
+                "zip archive holds neither a .hgt file nor a .bil raster with its .hdr header",
+            )),
+        }
+    } else {
+        Ok(data.to_vec())
+    }
+}
+
+/// Raw HGT samples of each tile of a zip archive, by file name: `.hgt` files
+/// and `.bil` rasters with the `.hdr` header next to them, wherever they are
+/// in the archive. Archives of several tiles, such as the `M32.zip` archives
+/// of viewfinderpanoramas.org, hold a `.hgt` file per tile.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn archive_tiles(archive: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let entries = zip_entries(archive)?;
+    let mut tiles = Vec::new();
+    for entry in &entries {
+        let path = entry.name.to_ascii_lowercase();
+        let name = entry.name.rsplit('/').next().unwrap_or_default().to_owned();
+        if path.ends_with(".hgt") {
+            tiles.push((name, entry.extract(archive)?));
+        } else if let Some(stem) = path.strip_suffix(".bil") {
+            let header = entries
+                .iter()
+                .find(|header| header.name.to_ascii_lowercase() == format!("{stem}.hdr"))
+                .ok_or_else(|| unsupported(format!("{} has no .hdr header", entry.name)))?;
+            let header = String::from_utf8_lossy(&header.extract(archive)?).into_owned();
+            tiles.push((name, decode_bil(&entry.extract(archive)?, &header)?));
+        }
+    }
+    Ok(tiles)
+}
+
+/// Raw HGT samples of a single-band 16-bit BIL raster described by the text
+/// of its `.hdr` header, e.g. `n47_e005_1arc_v3.bil` from USGS EarthExplorer.
+/// Samples equal to the `NODATA` value of the header become voids.
+pub fn decode_bil(data: &[u8], header: &str) -> Result<Vec<u8>> {
+    let header = BilHeader::parse(header)?;
+    let too_large = || unsupported("BIL raster sizes overflow");
+    let row_bytes = header.cols.checked_mul(2).ok_or_else(too_large)?;
+    let stride = header.total_row_bytes.unwrap_or(row_bytes);
+    if stride < row_bytes {
+        return Err(unsupported("BIL rows are shorter than their samples"));
+    }
+    // Headers parse with at least 2 rows.
+    let end = stride
+        .checked_mul(header.rows - 1)
+        .and_then(|end| end.checked_add(header.skip_bytes))
+        .and_then(|end| end.checked_add(row_bytes))
+        .ok_or_else(too_large)?;
+    if data.len() < end {
+        return Err(unsupported(format!(
+            "BIL raster of {} bytes is too short for {} rows of {} samples",
+            data.len(),
+            header.rows,
+            header.cols
+        )));
+    }
+    let mut hgt = vec![0; header.rows * row_bytes];
+    for (row, out) in hgt.chunks_exact_mut(row_bytes).enumerate() {
+        let start = header.skip_bytes + row * stride;
+        for (sample, out) in data[start..start + row_bytes]
+            .chunks_exact(2)
+            .zip(out.chunks_exact_mut(2))
+        {
+            let value = if header.little_endian {
+                LittleEndian::read_i16(sample)
+            } else {
+                BigEndian::read_i16(sample)
+            };
+            let value = if Some(value) == header.nodata {
+                VOID
+            } else {
+                value
+            };
+            BigEndian::write_i16(out, value);
+        }
+    }
+    Ok(hgt)
+}
+
+/// The fields of an ESRI BIL header that matter to elevation tiles.
+#[derive(Debug)]
+struct BilHeader {
+    rows: usize,
+    cols: usize,
+    little_endian: bool,
+    skip_bytes: usize,
+    total_row_bytes: Option<usize>,
+    nodata: Option<i16>,
+}
+
+impl BilHeader {
+    fn parse(text: &str) -> Result<Self> {
+        let fields: Vec<(String, &str)> = text
+            .lines()
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                Some((
+                    words.next()?.to_ascii_uppercase(),
+                    words.next().unwrap_or(""),
+                ))
+            })
+            .collect();
+        let field = |key: &str| {
+            fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|&(_, value)| value)
+        };
+        let number = |key: &str| -> Result<Option<usize>> {
+            field(key)
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| unsupported(format!("invalid {key} {value:?} in BIL header")))
+                })
+                .transpose()
+        };
+        let rows = number("NROWS")?.ok_or_else(|| unsupported("BIL header lacks NROWS"))?;
+        let cols = number("NCOLS")?.ok_or_else(|| unsupported("BIL header lacks NCOLS"))?;
+        if rows != cols || rows < 2 {
+            return Err(unsupported(format!(
+                "BIL raster of {rows} x {cols} samples is not a tile grid"
+            )));
+        }
+        if number("NBANDS")?.unwrap_or(1) != 1 {
+            return Err(unsupported("BIL rasters must have a single band"));
+        }
+        if number("NBITS")?.unwrap_or(8) != 16 {
+            return Err(unsupported("BIL rasters must have 16-bit samples"));
+        }
+        if field("PIXELTYPE").is_some_and(|kind| !kind.eq_ignore_ascii_case("SIGNEDINT")) {
+            return Err(unsupported("BIL samples must be signed integers"));
+        }
+        let little_endian = match field("BYTEORDER").map(str::to_ascii_uppercase).as_deref() {
+            None | Some("I") => true,
+            Some("M") => false,
+            Some(order) => {
+                return Err(unsupported(format!(
+                    "invalid BYTEORDER {order:?} in BIL header"
+                )))
+            }
+        };
+        let nodata = field("NODATA")
+            .map(|value| {
+                // Written as a float by some tools, e.g. -9999.0.
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|v| v.fract() == 0.0 && (-32768.0..=32767.0).contains(v))
+                    .map(|v| v as i16)
+                    .ok_or_else(|| unsupported(format!("invalid NODATA {value:?} in BIL header")))
+            })
+            .transpose()?;
+        Ok(Self {
+            rows,
+            cols,
+            little_endian,
+            skip_bytes: number("SKIPBYTES")?.unwrap_or(0),
+            total_row_bytes: number("TOTALROWBYTES")?,
+            nodata,
+        })
+    }
+}
+
+/// A file of a zip archive.
+struct ZipEntry {
+    name: String,
+    method: u16,
+    compressed_size: usize,
+    size: usize,
+    header_offset: usize,
+}
+
+impl ZipEntry {
+    fn extract(&self, archive: &[u8]) -> Result<Vec<u8>> {
+        let truncated = || unsupported("truncated zip archive");
+        let header = self
+            .header_offset
+            .checked_add(30)
+            .and_then(|end| archive.get(self.header_offset..end))
+            .filter(|header| header.starts_with(ZIP_MAGIC))
+            .ok_or_else(truncated)?;
+        let start = self.header_offset
+            + 30
+            + usize::from(LittleEndian::read_u16(&header[26..]))
+            + usize::from(LittleEndian::read_u16(&header[28..]));
+        let compressed = start
+            .checked_add(self.compressed_size)
+            .and_then(|end| archive.get(start..end))
+            .ok_or_else(truncated)?;
+        let data = match self.method {
+            0 => compressed.to_vec(),
+            8 => {
+                let mut data = Vec::with_capacity(self.size);
+                DeflateDecoder::new(compressed).read_to_end(&mut data)?;
+                data
+            }
+            method => {
+                return Err(unsupported(format!(
+                    "{} is compressed with unsupported zip method {method}",
+                    self.name
+                )))
+            }
+        };
+        if data.len() != self.size {
+            return Err(truncated());
+        }
+        Ok(data)
+    }
+}
+
+/// Files listed in the central directory of a zip archive.
+fn zip_entries(archive: &[u8]) -> Result<Vec<ZipEntry>> {
+    let invalid = || unsupported("invalid zip archive");
+    // The end of central directory record, followed by up to 64 KiB of
+    // comment.
+    let end = (0..=archive.len().saturating_sub(22))
+        .rev()
+        .take(22 + 0xffff)
+        .find(|&i| archive.len() >= i + 22 && archive[i..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(invalid)?;
+    let count = LittleEndian::read_u16(&archive[end + 10..]);
+    let mut offset = LittleEndian::read_u32(&archive[end + 16..]) as usize;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let header = archive
+            .get(offset..offset + 46)
+            .filter(|header| header.starts_with(b"PK\x01\x02"))
+            .ok_or_else(invalid)?;
+        if LittleEndian::read_u16(&header[8..]) & 1 != 0 {
+            return Err(unsupported("encrypted zip archives are not supported"));
+        }
+        let name_length = usize::from(LittleEndian::read_u16(&header[28..]));
+        let extra_length = usize::from(LittleEndian::read_u16(&header[30..]));
+        let comment_length = usize::from(LittleEndian::read_u16(&header[32..]));
+        let name = archive
+            .get(offset + 46..offset + 46 + name_length)
+            .ok_or_else(invalid)?;
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: LittleEndian::read_u16(&header[10..]),
+            compressed_size: LittleEndian::read_u32(&header[20..]) as usize,
+            size: LittleEndian::read_u32(&header[24..]) as usize,
+            header_offset: LittleEndian::read_u32(&header[42..]) as usize,
+        });
+        offset += 46 + name_length + extra_length + comment_length;
+    }
+    Ok(entries)
+}
+
+fn unsupported(reason: impl Into<String>) -> HgtError {
+    HgtError::UnsupportedFormat(reason.into())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::tile::synthetic_tile;
+    use byteorder::WriteBytesExt;
+    use flate2::write::{DeflateEncoder, GzEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    /// A zip archive of `files`, deflated.
+    pub(crate) fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+        for &(name, data) in files {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).unwrap();
+            let compressed = encoder.finish().unwrap();
+            let offset = archive.len() as u32;
+            // Local header then central directory entry, without CRCs, which
+            // the decoder doesn't check.
+            for (out, signature) in [(&mut archive, 0x04034b50), (&mut directory, 0x02014b50)] {
+                let central = signature == 0x02014b50;
+                out.write_u32::<LittleEndian>(signature).unwrap();
+                if central {
+                    out.write_u16::<LittleEndian>(20).unwrap();
+                }
+                for field in [20, 0, 8, 0, 0] {
+                    out.write_u16::<LittleEndian>(field).unwrap();
+                }
+                out.write_u32::<LittleEndian>(0).unwrap();
+                out.write_u32::<LittleEndian>(compressed.len() as u32)
+                    .unwrap();
+                out.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+                out.write_u16::<LittleEndian>(name.len() as u16).unwrap();
+                out.write_u16::<LittleEndian>(0).unwrap();
+                if central {
+                    for field in [0, 0, 0] {
+                        out.write_u16::<LittleEndian>(field).unwrap();
+                    }
+                    out.write_u32::<LittleEndian>(0).unwrap();
+                    out.write_u32::<LittleEndian>(offset).unwrap();
+                }
+                out.extend_from_slice(name.as_bytes());
+            }
+            archive.extend_from_slice(&compressed);
+        }
+        let directory_offset = archive.len() as u32;
+        let directory_size = directory.len() as u32;
+        archive.append(&mut directory);
+        archive.write_u32::<LittleEndian>(0x06054b50).unwrap();
+        for field in [0, 0, files.len() as u16, files.len() as u16] {
+            archive.write_u16::<LittleEndian>(field).unwrap();
+        }
+        archive.write_u32::<LittleEndian>(directory_size).unwrap();
+        archive.write_u32::<LittleEndian>(directory_offset).unwrap();
+        archive.write_u16::<LittleEndian>(0).unwrap();
+        archive
+    }
+
+    /// `hgt` as a little-endian BIL raster with -9999 for voids, and its
+    /// header.
+    pub(crate) fn bil(hgt: &[u8]) -> (Vec<u8>, String) {
+        let size = ((hgt.len() / 2) as f64).sqrt() as usize;
+        let data = hgt
+            .chunks_exact(2)
+            .flat_map(|sample| match BigEndian::read_i16(sample) {
+                VOID => (-9999i16).to_le_bytes(),
+                value => value.to_le_bytes(),
+            })
+            .collect();
+        let header = format!(
+            "BYTEORDER      I\nLAYOUT         BIL\nNROWS          {size}\nNCOLS          {size}\n\
+             NBANDS         1\nNBITS          16\nBANDROWBYTES   {0}\nTOTALROWBYTES  {0}\n\
+             PIXELTYPE      SIGNEDINT\nULXMAP         5.0\nULYMAP         48.0\n\
+             XDIM           0.000833333333333\nYDIM           0.000833333333333\n\
+             NODATA         -9999\n",
+            size * 2
+        );
+        (data, header)
+    }
+
+    fn tile() -> Vec<u8> {
+        synthetic_tile(1201, |row, col| match (row, col) {
+            (7, 9) => VOID,
+            _ => (row as i16 - col as i16) * 3,
+        })
+    }
+
+    #[test]
+    fn decodes_compressed_and_zipped_tiles() {
+        let hgt = tile();
+        assert_eq!(decode_tile(&hgt).unwrap(), hgt);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&hgt).unwrap();
+        assert_eq!(decode_tile(&encoder.finish().unwrap()).unwrap(), hgt);
+        let archive = zip(&[("readme.txt", &b"SRTM"[..]), ("N47E005.hgt", &hgt)]);
+        assert_eq!(decode_tile(&archive).unwrap(), hgt);
+        let empty = zip(&[("readme.txt", &b"SRTM"[..])]);
+        assert!(matches!(
+            decode_tile(&empty),
+            Err(HgtError::UnsupportedFormat(_))
+        ));
+        assert!(decode_tile(&archive[..archive.len() / 2]).is_err());
+
+        let entry = |header_offset, compressed_size| ZipEntry {
+            name: "N47E005.hgt".into(),
+            method: 0,
+            compressed_size,
+            size: 0,
+            header_offset,
+        };
+        assert!(entry(usize::MAX - 10, 0).extract(&archive).is_err());
+        assert!(entry(0, usize::MAX).extract(&archive).is_err());
+    }
+
+    #[test]
+    fn decodes_bil_rasters() {
+        let hgt = tile();
+        let (data, header) = bil(&hgt);
+        assert_eq!(decode_bil(&data, &header).unwrap(), hgt);
+        let archive = zip(&[
+            ("n47_e005_3arc_v2.bil", &data[..]),
+            ("n47_e005_3arc_v2.hdr", header.as_bytes()),
+        ]);
+        assert_eq!(decode_tile(&archive).unwrap(), hgt);
+
+        // Big-endian samples without voids.
+        let header = "NROWS 2\nNCOLS 2\nNBITS 16\nBYTEORDER M\n";
+        let data = [0, 1, 0, 2, 0xff, 0xff, 0, 4];
+        assert_eq!(decode_bil(&data, header).unwrap(), data);
+        assert!(decode_bil(&data[..6], header).is_err());
+        assert!(decode_bil(&data, "NROWS 2\nNCOLS 2\nNBITS 32\n").is_err());
+        assert!(decode_bil(&data, "NROWS 1\nNCOLS 4\nNBITS 16\n").is_err());
+
+        // Sizes overflowing rather than wrapping around.
+        let huge = usize::MAX / 2 + 1;
+        for header in [
+            format!("NROWS {huge}\nNCOLS {huge}\nNBITS 16\n"),
+            format!("NROWS 2\nNCOLS 2\nNBITS 16\nSKIPBYTES {}\n", usize::MAX),
+            format!("NROWS 3\nNCOLS 3\nNBITS 16\nTOTALROWBYTES {huge}\n"),
+        ] {
+            assert!(matches!(
+                decode_bil(&data, &header),
+                Err(HgtError::UnsupportedFormat(_))
+            ));
+        }
+    }
+}
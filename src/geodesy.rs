@@ -0,0 +1,202 @@
+//! Great-circle helpers on a spherical planet.
+//!
+//! Coordinates are `(latitude, longitude)` pairs in degrees and distances are
+//! in meters. These are the helpers profiles and line-of-sight queries use,
+//! for custom analyses along the same lines.
+//!
+//! ```
+//! use earthel::geodesy::Body;
+//!
+//! let (chamonix, zermatt) = ((45.92, 6.87), (46.02, 7.75));
+//! let distance = Body::EARTH.distance(chamonix, zermatt);
+//! let bearing = Body::EARTH.bearing(chamonix, zermatt);
+//! let halfway = Body::EARTH.destination(chamonix, bearing, distance / 2.0);
+//! let midpoint = Body::EARTH.intermediate(chamonix, zermatt, 0.5);
+//! assert!(Body::EARTH.distance(halfway, midpoint) < 1.0);
+//! // A point every 100 m or less, the vertices included.
+//! let points = Body::EARTH.densify(&[chamonix, zermatt], 100.0);
+//! assert!(points.windows(2).all(|pair| Body::EARTH.distance(pair[0], pair[1]) <= 100.0));
+//! ```
+
+use crate::{HgtError, Result};
+
+/// Mean earth radius in meters (IUGG).
+pub(crate) const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Validates a coordinate and wraps its longitude into `[-180, 180)`, so that
+/// e.g. 190° becomes -170°.
+pub fn normalize(latitude: f64, longitude: f64) -> Result<(f64, f64)> {
+    if !latitude.is_finite() || !longitude.is_finite() || latitude.abs() > 90.0 {
+        return Err(HgtError::InvalidCoordinate {
+            lat: latitude,
+            lon: longitude,
+        });
+    }
+    Ok((latitude, (longitude + 180.0).rem_euclid(360.0) - 180.0))
+}
+
+/// A planetary body, modelled as a sphere. Distances and areas are measured
+/// on it, so that DEMs of other bodies (e.g. Moon LOLA or Mars MOLA data cut
+/// into 1° HGT tiles) can be served through the same API with a custom
+/// [`TileSource`](crate::TileSource).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Body {
+    /// Mean radius in meters.
+    pub radius: f64,
+    /// Height of the highest terrain in meters, above which rays can stop
+    /// looking for the ground.
+    pub max_elevation: f64,
+}
+
+impl Body {
+    pub const EARTH: Body = Body {
+        radius: EARTH_RADIUS,
+        max_elevation: 8_900.0,
+    };
+    pub const MOON: Body = Body {
+        radius: 1_737_400.0,
+        max_elevation: 10_800.0,
+    };
+    pub const MARS: Body = Body {
+        radius: 3_389_500.0,
+        max_elevation: 21_300.0,
+    };
+
+    /// Meters per degree along a great circle.
+    pub fn meters_per_degree(&self) -> f64 {
+        self.radius.to_radians()
+    }
+
+    /// Haversine distance between two coordinates.
+    pub fn distance(&self, from: (f64, f64), to: (f64, f64)) -> f64 {
+        let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+        let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+        let h = ((lat2 - lat1) / 2.0).sin().powi(2)
+            + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+        2.0 * self.radius * h.sqrt().min(1.0).asin()
+    }
+
+    /// Initial bearing of the great circle from one coordinate to another, in
+    /// degrees clockwise from north in `[0, 360)`.
+    pub fn bearing(&self, from: (f64, f64), to: (f64, f64)) -> f64 {
+        let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+        let dlon = (to.1 - from.1).to_radians();
+        let y = dlon.sin() * lat2.cos();
+        let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+        y.atan2(x).to_degrees().rem_euclid(360.0)
+    }
+
+    /// Point at `fraction` (0 to 1) of the great circle between two coordinates.
+    pub fn intermediate(&self, from: (f64, f64), to: (f64, f64), fraction: f64) -> (f64, f64) {
+        let angle = self.distance(from, to) / self.radius;
+        if angle == 0.0 {
+            return from;
+        }
+        let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+        let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+        let a = ((1.0 - fraction) * angle).sin() / angle.sin();
+        let b = (fraction * angle).sin() / angle.sin();
+        let x = a * lat1.cos() * lon1.cos() + b * lat2.cos() * lon2.cos();
+        let y = a * lat1.cos() * lon1.sin() + b * lat2.cos() * lon2.sin();
+        let z = a * lat1.sin() + b * lat2.sin();
+        (
+            z.atan2((x * x + y * y).sqrt()).to_degrees(),
+            y.atan2(x).to_degrees(),
+        )
+    }
+
+    /// Point reached after `distance` meters on the great circle leaving
+    /// `from` with the given `bearing`, in degrees clockwise from north.
+    pub fn destination(&self, from: (f64, f64), bearing: f64, distance: f64) -> (f64, f64) {
+        let angle = distance / self.radius;
+        let (lat, lon, bearing) = (
+            from.0.to_radians(),
+            from.1.to_radians(),
+            bearing.to_radians(),
+        );
+        let lat2 = (lat.sin() * angle.cos() + lat.cos() * angle.sin() * bearing.cos()).asin();
+        let lon2 = lon
+            + (bearing.sin() * angle.sin() * lat.cos()).atan2(angle.cos() - lat.sin() * lat2.sin());
+        (lat2.to_degrees(), lon2.to_degrees())
+    }
+
+    /// Inserts points along each segment of a polyline so that consecutive
+    /// points are at most `spacing` meters apart. The original vertices are
+    /// kept.
+    pub fn densify(&self, points: &[(f64, f64)], spacing: f64) -> Vec<(f64, f64)> {
+        let mut dense = Vec::with_capacity(points.len());
+        for pair in points.windows(2) {
+            let steps = (self.distance(pair[0], pair[1]) / spacing).ceil().max(1.0) as usize;
+            dense.extend(
+                (0..steps).map(|i| self.intermediate(pair[0], pair[1], i as f64 / steps as f64)),
+            );
+        }
+        dense.extend(points.last());
+        dense
+    }
+}
+
+impl Default for Body {
+    fn default() -> Self {
+        Self::EARTH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_coordinates() {
+        assert_eq!(normalize(10.0, 190.0).unwrap(), (10.0, -170.0));
+        assert_eq!(normalize(10.0, -180.0).unwrap(), (10.0, -180.0));
+        assert_eq!(normalize(10.0, 180.0).unwrap(), (10.0, -180.0));
+        assert_eq!(normalize(-90.0, -541.0).unwrap(), (-90.0, 179.0));
+        assert!(normalize(90.5, 0.0).is_err());
+        assert!(normalize(f64::NAN, 0.0).is_err());
+        assert!(normalize(0.0, f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn measures_great_circle_distances() {
+        let paris = (48.8566, 2.3522);
+        let london = (51.5074, -0.1278);
+        assert!((Body::EARTH.distance(paris, london) - 343_560.0).abs() < 500.0);
+        assert_eq!(Body::EARTH.distance(paris, paris), 0.0);
+        // A degree of latitude is about 59 km on Mars.
+        assert!((Body::MARS.distance((0.0, 0.0), (1.0, 0.0)) - 59_158.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn travels_along_bearings() {
+        let (lat, lon) = Body::EARTH.destination((45.0, 6.0), 90.0, 10_000.0);
+        assert!(lat < 45.0 && (lat - 45.0).abs() < 0.001);
+        assert!((Body::EARTH.distance((45.0, 6.0), (lat, lon)) - 10_000.0).abs() < 1e-6);
+        let (lat, lon) = Body::EARTH.destination((0.0, 0.0), 0.0, 111_195.08);
+        assert!((lat - 1.0).abs() < 1e-6 && lon.abs() < 1e-12);
+
+        assert_eq!(Body::EARTH.bearing((0.0, 0.0), (0.0, 1.0)), 90.0);
+        assert_eq!(Body::EARTH.bearing((0.0, 0.0), (-1.0, 0.0)), 180.0);
+        assert_eq!(Body::EARTH.bearing((0.0, 1.0), (0.0, 0.0)), 270.0);
+        let (from, bearing) = ((45.0, 6.0), 37.0);
+        let to = Body::EARTH.destination(from, bearing, 50_000.0);
+        assert!((Body::EARTH.bearing(from, to) - bearing).abs() < 1e-9);
+    }
+
+    #[test]
+    fn densifies_segments() {
+        let start = (0.0, 0.0);
+        let end = (0.0, 1.0);
+        let (lat, lon) = Body::EARTH.intermediate(start, end, 0.5);
+        assert!(lat.abs() < 1e-9 && (lon - 0.5).abs() < 1e-9);
+
+        let dense = Body::EARTH.densify(&[start, end], 10_000.0);
+        assert_eq!(dense.len(), 13);
+        assert_eq!(dense.first(), Some(&start));
+        assert_eq!(dense.last(), Some(&end));
+        assert!(dense
+            .windows(2)
+            .all(|pair| Body::EARTH.distance(pair[0], pair[1]) <= 10_000.0));
+    }
+}
@@ -0,0 +1,152 @@
+//! Terrain-aware geofences, for UAV geo-awareness: inside a polygon, flights
+//! must keep a buffer above the ground.
+
+use crate::geometry::polygon_contains;
+use crate::{EarthEl, HgtError, Result};
+use geo_types::{Point, Polygon};
+
+/// Position of an aircraft relative to a terrain-aware geofence, from
+/// [`EarthEl::check_geofence`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GeofenceCheck {
+    /// Whether the position is inside the polygon, holes excluded.
+    pub inside: bool,
+    /// Elevation of the terrain under the position, in meters.
+    pub terrain: f64,
+    /// Altitude above the terrain plus the buffer in meters, negative below
+    /// it.
+    pub margin: f64,
+}
+
+impl GeofenceCheck {
+    /// Whether the position is inside the polygon and below the terrain plus
+    /// the buffer.
+    pub fn breached(&self) -> bool {
+        self.inside && self.margin < 0.0
+    }
+}
+
+impl EarthEl {
+    /// Checks a position (`x = longitude`, `y = latitude`) at `altitude`
+    /// meters in the configured vertical datum against a geofence: inside
+    /// `fence`, the terrain raised by `buffer` meters.
+    ///
+    /// The terrain is the sample under the position, which a DEM may
+    /// underestimate between samples or over obstacles: the buffer should
+    /// cover that and the accuracy of the dataset, see
+    /// [`Dataset::vertical_accuracy`](crate::source::Dataset::vertical_accuracy).
+    /// Fails with [`HgtError::NoData`] where the terrain is unknown.
+    ///
+    /// ```no_run
+    /// use earthel::EarthEl;
+    /// use geo_types::{polygon, Point};
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let fence = polygon![
+    ///     (x: 6.8, y: 45.8), (x: 6.9, y: 45.8), (x: 6.9, y: 45.9), (x: 6.8, y: 45.9), (x: 6.8, y: 45.8),
+    /// ];
+    /// let drone = Point::new(6.86, 45.83);
+    /// let check = EarthEl::default().check_geofence(&fence, &drone, 4_850.0, 50.0).await?;
+    /// if check.breached() {
+    ///     println!("{:.0} m too low", -check.margin);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn check_geofence(
+        &self,
+        fence: &Polygon<f64>,
+        position: &Point<f64>,
+        altitude: f64,
+        buffer: f64,
+    ) -> Result<GeofenceCheck> {
+        if !altitude.is_finite() {
+            return Err(HgtError::InvalidParameter(format!(
+                "altitude must be finite, got {altitude}"
+            )));
+        }
+        if !(buffer.is_finite() && buffer >= 0.0) {
+            return Err(HgtError::InvalidParameter(format!(
+                "buffer must be a non-negative number of meters, got {buffer}"
+            )));
+        }
+        let (lat, lon) = (position.y(), position.x());
+        let terrain = self
+            .try_get_elevation(lat, lon)
+            .await?
+            .ok_or(HgtError::NoData { lat, lon })?;
+        Ok(GeofenceCheck {
+            inside: polygon_contains(fence, position),
+            terrain,
+            margin: altitude - (terrain + buffer),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::{synthetic_tile, VOID};
+    use crate::{MissingPolicy, TileCache, TileId};
+    use geo_types::polygon;
+
+    #[tokio::test]
+    async fn checks_the_margin_above_the_terrain() {
+        let cache = MemoryCache::new();
+        // Flat at 100 m, with a 300 m plateau over the eastern half and a void
+        // along the western edge.
+        let data = synthetic_tile(1201, |_, col| match col {
+            0 => VOID,
+            600.. => 300,
+            _ => 100,
+        });
+        cache.store(&TileId::new(0, 0), &data).unwrap();
+        let earthel = EarthEl::builder()
+            .cache(cache)
+            .missing(MissingPolicy::None)
+            .build();
+        let fence = polygon![
+            (x: 0.2, y: 0.2), (x: 0.8, y: 0.2), (x: 0.8, y: 0.8), (x: 0.2, y: 0.8), (x: 0.2, y: 0.2),
+        ];
+
+        let check = earthel
+            .check_geofence(&fence, &Point::new(0.3, 0.5), 250.0, 50.0)
+            .await
+            .unwrap();
+        assert_eq!(
+            check,
+            GeofenceCheck {
+                inside: true,
+                terrain: 100.0,
+                margin: 100.0,
+            }
+        );
+        assert!(!check.breached());
+        // The same altitude over the plateau breaches the fence...
+        let check = earthel
+            .check_geofence(&fence, &Point::new(0.7, 0.5), 250.0, 50.0)
+            .await
+            .unwrap();
+        assert_eq!((check.terrain, check.margin), (300.0, -100.0));
+        assert!(check.breached());
+        // ...but not outside of it.
+        let check = earthel
+            .check_geofence(&fence, &Point::new(0.9, 0.5), 250.0, 50.0)
+            .await
+            .unwrap();
+        assert!(!check.inside && !check.breached());
+
+        assert!(matches!(
+            earthel
+                .check_geofence(&fence, &Point::new(0.0, 0.5), 250.0, 50.0)
+                .await,
+            Err(HgtError::NoData { .. })
+        ));
+        assert!(earthel
+            .check_geofence(&fence, &Point::new(0.3, 0.5), 250.0, -1.0)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Conversion between the EGM96 orthometric heights of SRTM tiles and
+//! heights above the WGS84 ellipsoid, as reported by GPS receivers.
+//!
+//! The geoid undulation is evaluated from the EGM96 spherical harmonic model
+//! embedded in the library, so no extra data is downloaded.
+
+use crate::{geodesy, Result};
+
+/// Reference surface of the elevations returned by an [`EarthEl`](crate::EarthEl).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VerticalDatum {
+    /// Heights above the EGM96 geoid (mean sea level), as stored in SRTM tiles.
+    #[default]
+    Egm96,
+    /// Heights above the WGS84 ellipsoid.
+    Wgs84Ellipsoid,
+}
+
+/// Height of the EGM96 geoid above the WGS84 ellipsoid at a location, in
+/// meters. Adding it to an SRTM elevation gives the ellipsoidal height.
+pub fn geoid_undulation(latitude: f64, longitude: f64) -> Result<f64> {
+    let (latitude, longitude) = geodesy::normalize(latitude, longitude)?;
+    Ok(egm96::egm96_compute_altitude_offset(latitude, longitude))
+}
+
+impl VerticalDatum {
+    /// Converts an EGM96 height at a location to this datum.
+    pub(crate) fn convert(self, latitude: f64, longitude: f64, height: f64) -> f64 {
+        match self {
+            Self::Egm96 => height,
+            Self::Wgs84Ellipsoid => {
+                height + egm96::egm96_compute_altitude_offset(latitude, longitude)
+            }
+        }
+    }
+
+    /// Converts a height at a location in this datum back to EGM96.
+    pub(crate) fn revert(self, latitude: f64, longitude: f64, height: f64) -> f64 {
+        match self {
+            Self::Egm96 => height,
+            Self::Wgs84Ellipsoid => {
+                height - egm96::egm96_compute_altitude_offset(latitude, longitude)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::{EarthEl, TileCache, TileId};
+
+    #[test]
+    fn computes_undulations() {
+        // Paris sits about 44.6 m above the ellipsoid at sea level.
+        assert!((geoid_undulation(48.8566, 2.3522).unwrap() - 44.61).abs() < 0.5);
+        assert!((geoid_undulation(40.05, -75.45).unwrap() + 34.32).abs() < 0.5);
+        assert!(geoid_undulation(f64::NAN, 0.0).is_err());
+    }
+
+    #[tokio::test]
+    async fn converts_elevations_to_the_ellipsoid() {
+        let cache = MemoryCache::new();
+        cache
+            .store(&TileId::new(48, 2), &synthetic_tile(1201, |_, _| 100))
+            .unwrap();
+        let earthel = EarthEl::builder()
+            .cache(cache)
+            .vertical_datum(VerticalDatum::Wgs84Ellipsoid)
+            .build();
+        assert_eq!(earthel.get_elevation(48.8566, 2.3522).await.unwrap(), 145);
+        let profile = earthel
+            .profile(&[(48.85, 2.35), (48.86, 2.35)], 500.0)
+            .await
+            .unwrap();
+        let elevation = profile.samples[0].elevation.unwrap();
+        assert!((elevation - 144.6).abs() < 0.5, "{elevation}");
+    }
+}
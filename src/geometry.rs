@@ -0,0 +1,397 @@
+//! Elevation queries for [`geo_types`] geometries.
+//!
+//! `geo_types` stores coordinates as `x = longitude`, `y = latitude`, and so
+//! do the points returned here.
+
+use crate::{Body, BoundingBox, EarthEl, HgtError, Result};
+use geo_types::{LineString, Point, Polygon};
+
+/// Elevation statistics of the terrain inside a polygon, from
+/// [`EarthEl::area_stats`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AreaStats {
+    /// Number of cells with data inside the polygon.
+    pub cells: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Population standard deviation of the elevation.
+    pub stddev: f64,
+    /// Mean terrain ruggedness index (Riley et al., 1999): the square root of
+    /// the summed squared elevation differences between a cell and its eight
+    /// neighbours, in meters.
+    pub tri: f64,
+}
+
+impl EarthEl {
+    /// Retrieves the elevation at a [`Point`], see
+    /// [`try_get_elevation`](Self::try_get_elevation).
+    pub async fn get_elevation_at(&self, point: &Point<f64>) -> Result<Option<f64>> {
+        self.try_get_elevation(point.y(), point.x()).await
+    }
+
+    /// Samples the elevation along a [`LineString`] every `spacing` meters.
+    ///
+    /// Every vertex of the line is part of the result, with extra points
+    /// inserted along the great circle between vertices that are further
+    /// apart than `spacing`. Elevations are `None` over voids and over
+    /// missing tiles the [`MissingPolicy`](crate::MissingPolicy) tolerates.
+    pub async fn sample_linestring(
+        &self,
+        line: &LineString<f64>,
+        spacing: f64,
+    ) -> Result<Vec<(Point<f64>, Option<f64>)>> {
+        check_spacing(spacing)?;
+        self.within_deadline(async {
+            let vertices: Vec<(f64, f64)> = line.coords().map(|c| (c.y, c.x)).collect();
+            let mut samples = Vec::new();
+            for (lat, lon) in self.body.densify(&vertices, spacing) {
+                let elevation = self.try_get_elevation_or_void(lat, lon).await?;
+                samples.push((Point::new(lon, lat), elevation));
+            }
+            Ok(samples)
+        })
+        .await
+    }
+
+    /// Samples the elevation on a regular grid of roughly `spacing` meters
+    /// covering the inside of a [`Polygon`]. Holes are excluded. Elevations
+    /// are `None` without data, as along
+    /// [`sample_linestring`](Self::sample_linestring).
+    pub async fn sample_polygon_grid(
+        &self,
+        polygon: &Polygon<f64>,
+        spacing: f64,
+    ) -> Result<Vec<(Point<f64>, Option<f64>)>> {
+        check_spacing(spacing)?;
+        self.within_deadline(async {
+            let mut samples = Vec::new();
+            for point in polygon_grid(polygon, spacing, self.body) {
+                let elevation = self.try_get_elevation_or_void(point.y(), point.x()).await?;
+                samples.push((point, elevation));
+            }
+            Ok(samples)
+        })
+        .await
+    }
+
+    /// Aggregates the elevation of the terrain inside a [`Polygon`] (holes
+    /// excluded), rasterized at the resolution of the tile under its first
+    /// vertex so that every sample of the underlying tiles counts once.
+    /// Polygons spanning more than 4 million samples are rasterized with
+    /// cells 2, 4, 8... times larger, from tile overviews.
+    ///
+    /// Fails with [`HgtError::NoData`] if no cell inside the polygon has data.
+    pub async fn area_stats(&self, polygon: &Polygon<f64>) -> Result<AreaStats> {
+        self.within_deadline(self.compute_area_stats(polygon)).await
+    }
+
+    async fn compute_area_stats(&self, polygon: &Polygon<f64>) -> Result<AreaStats> {
+        let exterior = polygon.exterior();
+        let first = exterior
+            .0
+            .first()
+            .ok_or_else(|| HgtError::InvalidParameter("polygon has no vertices".to_owned()))?;
+        let mut cell = match self.resolution_at(first.y, first.x).await {
+            Ok(resolution) => resolution.arc_seconds() / 3600.0,
+            Err(HgtError::TileNotFound(_)) => 3.0 / 3600.0,
+            Err(e) => return Err(e),
+        };
+        let (mut west, mut south) = (f64::INFINITY, f64::INFINITY);
+        let (mut east, mut north) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for c in exterior.coords() {
+            west = west.min(c.x);
+            east = east.max(c.x);
+            south = south.min(c.y);
+            north = north.max(c.y);
+        }
+        while (east - west) * (north - south) / (cell * cell) > MAX_STATS_CELLS as f64 {
+            cell *= 2.0;
+        }
+        // One more cell around the polygon gives border cells all their neighbours.
+        let bbox = BoundingBox::new(
+            (south - cell).max(-90.0),
+            west - cell,
+            (north + cell).min(90.0),
+            east + cell,
+        );
+        let dem = self.extract_dem(bbox, cell).await?;
+        let (mut cells, mut sum, mut squares, mut tri) = (0usize, 0.0, 0.0, 0.0);
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for row in 0..dem.height {
+            for col in 0..dem.width {
+                let Some(value) = dem.get(row, col).map(f64::from) else {
+                    continue;
+                };
+                let (lat, lon) = dem.cell_center(row, col);
+                if !polygon_contains(polygon, &Point::new(lon, lat)) {
+                    continue;
+                }
+                cells += 1;
+                sum += value;
+                squares += value * value;
+                min = min.min(value);
+                max = max.max(value);
+                let mut differences = 0.0;
+                for (dr, dc) in NEIGHBOURS {
+                    let (r, c) = (row.wrapping_add_signed(dr), col.wrapping_add_signed(dc));
+                    if r < dem.height && c < dem.width {
+                        if let Some(neighbour) = dem.get(r, c) {
+                            differences += (f64::from(neighbour) - value).powi(2);
+                        }
+                    }
+                }
+                tri += differences.sqrt();
+            }
+        }
+        if cells == 0 {
+            return Err(HgtError::NoData {
+                lat: first.y,
+                lon: first.x,
+            });
+        }
+        let n = cells as f64;
+        let mean = sum / n;
+        Ok(AreaStats {
+            cells,
+            min,
+            max,
+            mean,
+            stddev: (squares / n - mean * mean).max(0.0).sqrt(),
+            tri: tri / n,
+        })
+    }
+}
+
+/// Most cells [`EarthEl::area_stats`] aggregates before coarsening them.
+const MAX_STATS_CELLS: usize = 4_000_000;
+
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+fn check_spacing(spacing: f64) -> Result<()> {
+    if spacing.is_finite() && spacing > 0.0 {
+        Ok(())
+    } else {
+        Err(HgtError::InvalidParameter(format!(
+            "spacing must be a positive number of meters, got {spacing}"
+        )))
+    }
+}
+
+/// Grid points inside `polygon`. Rows are `spacing` meters apart on `body` and
+/// points in a row are spaced by `spacing` meters at that row's latitude.
+fn polygon_grid(polygon: &Polygon<f64>, spacing: f64, body: Body) -> Vec<Point<f64>> {
+    let exterior = polygon.exterior();
+    let (mut min_x, mut min_y) = (f64::INFINITY, f64::INFINITY);
+    let (mut max_x, mut max_y) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for c in exterior.coords() {
+        min_x = min_x.min(c.x);
+        max_x = max_x.max(c.x);
+        min_y = min_y.min(c.y);
+        max_y = max_y.max(c.y);
+    }
+    let lat_step = spacing / body.meters_per_degree();
+    let mut points = Vec::new();
+    let mut lat = min_y;
+    while lat <= max_y {
+        let lon_step = lat_step / lat.to_radians().cos().max(1e-6);
+        let mut lon = min_x;
+        while lon <= max_x {
+            let point = Point::new(lon, lat);
+            if polygon_contains(polygon, &point) {
+                points.push(point);
+            }
+            lon += lon_step;
+        }
+        lat += lat_step;
+    }
+    points
+}
+
+/// Tiles overlapping the exterior of `polygon`: those the exterior keeps a
+/// non-zero area of when clipped to the tile, so that tiles only touching its
+/// edges don't count.
+#[cfg(feature = "manifest")]
+pub(crate) fn polygon_tiles(polygon: &Polygon<f64>) -> Vec<crate::TileId> {
+    let ring: Vec<(f64, f64)> = polygon.exterior().coords().map(|c| (c.x, c.y)).collect();
+    let (mut west, mut south) = (f64::INFINITY, f64::INFINITY);
+    let (mut east, mut north) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for &(x, y) in &ring {
+        west = west.min(x);
+        east = east.max(x);
+        south = south.min(y);
+        north = north.max(y);
+    }
+    if ring.is_empty() {
+        return Vec::new();
+    }
+    let mut tiles = Vec::new();
+    for lat in (south.max(-90.0).floor() as i32)..(north.min(90.0).ceil() as i32) {
+        for lon in (west.floor() as i32)..(east.ceil() as i32) {
+            let (x, y) = (f64::from(lon), f64::from(lat));
+            let mut clipped = ring.clone();
+            clipped = clip(&clipped, |p| p.0 - x);
+            clipped = clip(&clipped, |p| x + 1.0 - p.0);
+            clipped = clip(&clipped, |p| p.1 - y);
+            clipped = clip(&clipped, |p| y + 1.0 - p.1);
+            if area(&clipped) > 1e-12 {
+                tiles.push(crate::TileId::new(lat, crate::tile::wrap_lon(lon)));
+            }
+        }
+    }
+    tiles
+}
+
+/// Clips a ring to the half-plane where `distance` is non-negative
+/// (Sutherland–Hodgman).
+#[cfg(feature = "manifest")]
+fn clip(ring: &[(f64, f64)], distance: impl Fn((f64, f64)) -> f64) -> Vec<(f64, f64)> {
+    let mut clipped = Vec::new();
+    for (i, &a) in ring.iter().enumerate() {
+        let b = ring[(i + 1) % ring.len()];
+        let (da, db) = (distance(a), distance(b));
+        if da >= 0.0 {
+            clipped.push(a);
+        }
+        if (da >= 0.0) != (db >= 0.0) {
+            let t = da / (da - db);
+            clipped.push((a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1)));
+        }
+    }
+    clipped
+}
+
+/// Unsigned area of a ring (shoelace formula).
+#[cfg(feature = "manifest")]
+fn area(ring: &[(f64, f64)]) -> f64 {
+    let mut twice = 0.0;
+    for (i, &(x1, y1)) in ring.iter().enumerate() {
+        let (x2, y2) = ring[(i + 1) % ring.len()];
+        twice += x1 * y2 - x2 * y1;
+    }
+    twice.abs() / 2.0
+}
+
+/// Whether `point` is inside the exterior of `polygon` and outside its holes.
+pub(crate) fn polygon_contains(polygon: &Polygon<f64>, point: &Point<f64>) -> bool {
+    ring_contains(polygon.exterior(), point)
+        && !polygon
+            .interiors()
+            .iter()
+            .any(|ring| ring_contains(ring, point))
+}
+
+/// Even-odd ray casting test; points on the boundary may go either way.
+fn ring_contains(ring: &LineString<f64>, point: &Point<f64>) -> bool {
+    let (x, y) = (point.x(), point.y());
+    let mut inside = false;
+    for segment in ring.0.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        if (a.y > y) != (b.y > y) && x < (b.x - a.x) * (y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo_types::polygon;
+
+    #[test]
+    fn grid_excludes_holes() {
+        let square = polygon!(
+            exterior: [
+                (x: 0.0, y: 0.0), (x: 0.1, y: 0.0), (x: 0.1, y: 0.1), (x: 0.0, y: 0.1), (x: 0.0, y: 0.0),
+            ],
+            interiors: [[
+                (x: 0.04, y: 0.04), (x: 0.06, y: 0.04), (x: 0.06, y: 0.06), (x: 0.04, y: 0.06), (x: 0.04, y: 0.04),
+            ]],
+        );
+        let grid = polygon_grid(&square, 1_000.0, Body::EARTH);
+        assert!(!grid.is_empty());
+        assert!(grid.iter().all(|p| p.x() >= 0.0 && p.x() <= 0.1));
+        let hole = &square.interiors()[0];
+        assert!(grid.iter().all(|p| !ring_contains(hole, p)));
+        let filled = Polygon::new(square.exterior().clone(), vec![]);
+        assert!(polygon_grid(&filled, 1_000.0, Body::EARTH).len() > grid.len());
+    }
+
+    #[tokio::test]
+    async fn aggregates_the_terrain_inside_polygons() {
+        use crate::cache::MemoryCache;
+        use crate::tile::synthetic_tile;
+        use crate::{TileCache, TileId};
+
+        let cache = MemoryCache::new();
+        // Flat at 100 m, with a 300 m plateau over the eastern half.
+        cache
+            .store(
+                &TileId::new(0, 0),
+                &synthetic_tile(1201, |_, col| if col >= 600 { 300 } else { 100 }),
+            )
+            .unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        let flat = polygon![
+            (x: 0.1, y: 0.1), (x: 0.2, y: 0.1), (x: 0.2, y: 0.2), (x: 0.1, y: 0.2), (x: 0.1, y: 0.1),
+        ];
+        let stats = earthel.area_stats(&flat).await.unwrap();
+        // 0.1° holds 120 samples each way.
+        assert!(stats.cells.abs_diff(120 * 120) < 500, "{stats:?}");
+        assert_eq!((stats.min, stats.max, stats.mean), (100.0, 100.0, 100.0));
+        assert_eq!((stats.stddev, stats.tri), (0.0, 0.0));
+
+        let straddling = polygon![
+            (x: 0.4, y: 0.1), (x: 0.6, y: 0.1), (x: 0.6, y: 0.2), (x: 0.4, y: 0.2), (x: 0.4, y: 0.1),
+        ];
+        let stats = earthel.area_stats(&straddling).await.unwrap();
+        assert_eq!((stats.min, stats.max), (100.0, 300.0));
+        assert!((stats.mean - 200.0).abs() < 5.0, "{stats:?}");
+        assert!((stats.stddev - 100.0).abs() < 1.0, "{stats:?}");
+        // Only the cells along the step are rugged.
+        assert!(stats.tri > 0.0 && stats.tri < 10.0, "{stats:?}");
+    }
+
+    #[cfg(feature = "manifest")]
+    #[test]
+    fn lists_overlapped_tiles() {
+        use crate::TileId;
+
+        let tile = polygon![
+            (x: 5.0, y: 47.0), (x: 6.0, y: 47.0), (x: 6.0, y: 48.0), (x: 5.0, y: 48.0), (x: 5.0, y: 47.0),
+        ];
+        assert_eq!(polygon_tiles(&tile), [TileId::new(47, 5)]);
+        // A thin diagonal sliver misses the corners of its bounding box.
+        let sliver = polygon![
+            (x: 5.1, y: 47.1), (x: 7.9, y: 49.9), (x: 7.8, y: 49.9), (x: 5.1, y: 47.2), (x: 5.1, y: 47.1),
+        ];
+        assert_eq!(
+            polygon_tiles(&sliver),
+            [
+                TileId::new(47, 5),
+                TileId::new(48, 5),
+                TileId::new(48, 6),
+                TileId::new(49, 6),
+                TileId::new(49, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_spacing() {
+        assert!(check_spacing(0.0).is_err());
+        assert!(check_spacing(f64::NAN).is_err());
+        assert!(check_spacing(30.0).is_ok());
+    }
+}
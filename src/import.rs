@@ -0,0 +1,181 @@
+//! Import of existing SRTM collections into the tile cache.
+
+use crate::formats::archive_tiles;
+use crate::source::{file_rank, file_tile, read_tile_file, ZIP};
+use crate::{tile, EarthEl, HgtError, Result, TileCache, TileId};
+use std::path::{Path, PathBuf};
+
+/// Outcome of [`EarthEl::import_directory`].
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// Tiles stored in the cache, in import order.
+    pub imported: Vec<TileId>,
+    /// Tiles found that the cache already had.
+    pub cached: Vec<TileId>,
+    /// Files that could not be read, with the reason.
+    pub failed: Vec<(PathBuf, HgtError)>,
+}
+
+impl ImportReport {
+    /// Whether `tile` was already found, or is in `cache` and is recorded as
+    /// such.
+    fn skips(&mut self, cache: &dyn TileCache, tile: &TileId) -> Result<bool> {
+        if self.imported.contains(tile) || self.cached.contains(tile) {
+            return Ok(true);
+        }
+        if cache.size(tile)?.is_some() {
+            self.cached.push(*tile);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+impl EarthEl {
+    /// Stores the tiles of an existing SRTM collection under `root` in the
+    /// cache, so that they are not downloaded again.
+    ///
+    /// Files are found in subdirectories too, by the tile name they start
+    /// with, in the formats a [`DirectorySource`](crate::source::DirectorySource)
+    /// reads. This covers the common layouts: `.hgt` and `.hgt.gz` trees,
+    /// `N47E005.SRTMGL1.hgt.zip` archives from USGS EarthExplorer, NASA
+    /// Earthdata or the QGIS SRTM Downloader plugin, and the `M32.zip`
+    /// archives of viewfinderpanoramas.org that hold a `.hgt` file per tile.
+    ///
+    /// Tiles already in the cache are left alone, and so are further copies of
+    /// a tile, the preferred format coming first as with `DirectorySource`.
+    /// Unreadable files don't stop the import; they are listed in the report.
+    ///
+    /// ```no_run
+    /// use earthel::EarthEl;
+    ///
+    /// # fn run() -> earthel::Result<()> {
+    /// let report = EarthEl::default().import_directory("/data/viewfinderpanoramas")?;
+    /// println!("imported {} tiles", report.imported.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn import_directory(&self, root: impl AsRef<Path>) -> Result<ImportReport> {
+        let mut files = Vec::new();
+        let mut folders = vec![root.as_ref().to_path_buf()];
+        while let Some(folder) = folders.pop() {
+            for entry in std::fs::read_dir(&folder)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    folders.push(entry.path());
+                    continue;
+                }
+                let name = entry.file_name().to_string_lossy().to_ascii_lowercase();
+                if let Some(rank) = file_rank(&name) {
+                    files.push((rank, entry.path(), name));
+                }
+            }
+        }
+        files.sort();
+
+        let mut report = ImportReport::default();
+        for (rank, path, name) in files {
+            let named = file_tile(&name);
+            let tiles = if rank == ZIP {
+                read_archive(&path, named)
+            } else {
+                let Some(tile) = named else {
+                    continue;
+                };
+                if report.skips(self.cache.as_ref(), &tile)? {
+                    continue;
+                }
+                read_tile_file(&path, rank).map(|data| vec![(tile, data)])
+            };
+            let tiles = match tiles {
+                Ok(tiles) => tiles,
+                Err(e) => {
+                    report.failed.push((path, e));
+                    continue;
+                }
+            };
+            for (tile, data) in tiles {
+                if report.skips(self.cache.as_ref(), &tile)? {
+                    continue;
+                }
+                if tile::grid_size(data.len() as u64).is_none() {
+                    let error = HgtError::InvalidResolution(data.len() as u64);
+                    report.failed.push((path.clone(), error));
+                    continue;
+                }
+                self.cache.store(&tile, &data)?;
+                report.imported.push(tile);
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// The tiles of a zip archive named after them, or the lone tile of an
+/// archive named after it.
+fn read_archive(path: &Path, named: Option<TileId>) -> Result<Vec<(TileId, Vec<u8>)>> {
+    let tiles = archive_tiles(&std::fs::read(path)?)?;
+    let lone = tiles.len() == 1;
+    Ok(tiles
+        .into_iter()
+        .filter_map(|(name, data)| {
+            let tile = file_tile(&name.to_ascii_lowercase()).or(named.filter(|_| lone))?;
+            Some((tile, data))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::formats::tests::zip;
+    use crate::tile::synthetic_tile;
+    use std::fs;
+
+    #[test]
+    fn imports_common_layouts() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let tile = |value| synthetic_tile(101, move |_, _| value);
+        // viewfinderpanoramas.org: several tiles per archive.
+        let m32 = zip(&[
+            ("M32/N47E005.hgt", &tile(1)[..]),
+            ("M32/N47E006.hgt", &tile(2)[..]),
+            ("M32/readme.txt", &b"tiles"[..]),
+        ]);
+        fs::write(root.join("M32.zip"), m32).unwrap();
+        // USGS EarthExplorer, in a QGIS plugin cache.
+        fs::create_dir(root.join("qgis")).unwrap();
+        let usgs = zip(&[("N46E005.hgt", &tile(3)[..])]);
+        fs::write(root.join("qgis/N46E005.SRTMGL1.hgt.zip"), usgs).unwrap();
+        // Plain files win over archives.
+        fs::write(root.join("N47E005.hgt"), tile(4)).unwrap();
+        fs::write(root.join("N45E005.hgt.zip"), b"not a zip").unwrap();
+        fs::write(root.join("N44E005.hgt"), [0; 5]).unwrap();
+        fs::write(root.join("notes.txt"), b"").unwrap();
+
+        let cache = MemoryCache::new();
+        cache.store(&TileId::new(47, 6), &tile(5)).unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        let report = earthel.import_directory(root).unwrap();
+        let mut imported = report.imported.clone();
+        imported.sort();
+        assert_eq!(imported, [TileId::new(46, 5), TileId::new(47, 5)]);
+        assert_eq!(report.cached, [TileId::new(47, 6)]);
+        let mut failed: Vec<_> = report.failed.iter().map(|(path, _)| path.clone()).collect();
+        failed.sort();
+        assert_eq!(
+            failed,
+            [root.join("N44E005.hgt"), root.join("N45E005.hgt.zip")]
+        );
+        let cache = earthel.cache();
+        assert_eq!(cache.load(&TileId::new(47, 5)).unwrap(), Some(tile(4)));
+        assert_eq!(cache.load(&TileId::new(47, 6)).unwrap(), Some(tile(5)));
+
+        // Importing again finds everything cached.
+        let again = earthel.import_directory(root).unwrap();
+        assert!(again.imported.is_empty());
+        assert_eq!(again.cached.len(), 3);
+    }
+}
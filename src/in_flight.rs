@@ -0,0 +1,214 @@
+//! Coalescing of concurrent downloads of the same tile.
+//!
+//! Tasks missing the same tile queue on a lock of that tile: the first one
+//! downloads it while the others wait, then find it in the cache. Processes
+//! sharing a cache do the same through the lock of the cache.
+
+use crate::cache::CacheLock;
+use crate::download::sleep;
+use crate::{EarthEl, Result, TileId};
+use futures::lock::{Mutex as AsyncMutex, OwnedMutexGuard};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often to retry taking a tile locked by another process.
+const CACHE_LOCK_POLL: Duration = Duration::from_millis(50);
+
+/// Locks of the tiles being downloaded.
+#[derive(Default)]
+pub(crate) struct InFlight {
+    tiles: Mutex<HashMap<TileId, Arc<AsyncMutex<()>>>>,
+}
+
+impl InFlight {
+    /// Waits until no other task holds the lock of `tile`, and takes it.
+    pub async fn lock(&self, tile: &TileId) -> TileLock<'_> {
+        let lock = Arc::clone(self.tiles.lock().unwrap().entry(*tile).or_default());
+        TileLock {
+            in_flight: self,
+            tile: *tile,
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+
+    /// Number of tiles locked or waited for.
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.tiles.lock().unwrap().len()
+    }
+}
+
+/// Lock of a tile, released on drop.
+pub(crate) struct TileLock<'a> {
+    in_flight: &'a InFlight,
+    tile: TileId,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for TileLock<'_> {
+    fn drop(&mut self) {
+        let mut tiles = self.in_flight.tiles.lock().unwrap();
+        self.guard = None;
+        // Without waiters, only the map refers to the lock. New waiters clone
+        // it while holding the map, so they can't show up meanwhile.
+        if tiles
+            .get(&self.tile)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            tiles.remove(&self.tile);
+        }
+    }
+}
+
+impl EarthEl {
+    /// Locks `tile` against other tasks, then against other processes sharing
+    /// the cache, see [`TileCache::try_lock`](crate::TileCache::try_lock).
+    pub(crate) async fn lock_tile(&self, tile: &TileId) -> Result<(TileLock<'_>, CacheLock)> {
+        let lock = self.in_flight.lock(tile).await;
+        loop {
+            if let Some(cache_lock) = self.cache.try_lock(tile)? {
+                return Ok((lock, cache_lock));
+            }
+            sleep(CACHE_LOCK_POLL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{FsCache, MemoryCache};
+    use crate::source::SourceFuture;
+    use crate::tile::synthetic_tile;
+    use crate::{EarthEl, HgtError, TileSource};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// A slow source serving tiles filled with their latitude, failing the
+    /// first `failures` requests.
+    #[derive(Default)]
+    struct Slow {
+        requests: AtomicUsize,
+        failures: usize,
+    }
+
+    impl TileSource for Slow {
+        fn fetch<'a>(&'a self, tile: &'a TileId) -> SourceFuture<'a> {
+            Box::pin(async move {
+                let request = self.requests.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                if request < self.failures {
+                    return Err(HgtError::TileUnavailable {
+                        tile: *tile,
+                        reason: "overloaded".into(),
+                    });
+                }
+                Ok(synthetic_tile(1201, |_, _| tile.lat as i16))
+            })
+        }
+    }
+
+    fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+
+    /// Queries the first `tiles` tiles north of the equator from `tasks`
+    /// tasks, each through its own clone of `earthel`.
+    async fn query_concurrently(earthel: &EarthEl, tasks: usize, tiles: i32) -> Vec<Result<i16>> {
+        let handles: Vec<_> = (0..tasks)
+            .map(|i| {
+                let earthel = earthel.clone();
+                let lat = i as i32 % tiles;
+                let offset = (i / tiles as usize) as f64 / tasks as f64;
+                tokio::spawn(async move {
+                    earthel
+                        .get_elevation(f64::from(lat) + 0.1 + offset * 0.8, 7.5)
+                        .await
+                })
+            })
+            .collect();
+        let mut results = Vec::new();
+        for handle in handles {
+            results.push(handle.await.unwrap());
+        }
+        results
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn downloads_each_tile_once() {
+        assert_shareable::<EarthEl>();
+        let source = Arc::new(Slow::default());
+        let earthel = EarthEl::builder()
+            .cache(MemoryCache::new())
+            .source(Arc::clone(&source))
+            .build();
+        let results = query_concurrently(&earthel, 64, 1).await;
+        assert!(results.iter().all(|r| matches!(r, Ok(0))), "{results:?}");
+        assert_eq!(source.requests.load(Ordering::SeqCst), 1);
+        let metrics = earthel.metrics();
+        assert_eq!(metrics.downloads, 1);
+        assert_eq!(metrics.tile_cache_misses, 64);
+        assert_eq!(metrics.coalesced_downloads, 63);
+        assert_eq!(earthel.in_flight.len(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn waiters_retry_failed_downloads() {
+        let source = Arc::new(Slow {
+            failures: 1,
+            ..Default::default()
+        });
+        let earthel = EarthEl::builder()
+            .cache(MemoryCache::new())
+            .source(Arc::clone(&source))
+            .build();
+        let results = query_concurrently(&earthel, 16, 1).await;
+        assert_eq!(results.iter().filter(|r| r.is_err()).count(), 1);
+        assert_eq!(source.requests.load(Ordering::SeqCst), 2);
+        assert_eq!(earthel.metrics().coalesced_downloads, 14);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn shares_a_disk_cache_under_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = Arc::new(Slow::default());
+        let earthel = EarthEl::builder()
+            .cache(FsCache::new(dir.path()))
+            .source(Arc::clone(&source))
+            .point_cache(Default::default())
+            .build();
+        for _ in 0..3 {
+            let results = query_concurrently(&earthel, 256, 8).await;
+            for (i, result) in results.into_iter().enumerate() {
+                assert_eq!(result.unwrap(), (i % 8) as i16);
+            }
+        }
+        assert_eq!(source.requests.load(Ordering::SeqCst), 8);
+        assert_eq!(earthel.metrics().downloads, 8);
+        assert_eq!(earthel.cache().tiles().unwrap().len(), 8);
+        assert_eq!(earthel.in_flight.len(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn shares_a_disk_cache_between_instances() {
+        // Instances don't share their locks, like separate processes.
+        let dir = tempfile::tempdir().unwrap();
+        let source = Arc::new(Slow::default());
+        let instances: Vec<_> = (0..4)
+            .map(|_| {
+                EarthEl::builder()
+                    .cache(FsCache::new(dir.path()))
+                    .source(Arc::clone(&source))
+                    .build()
+            })
+            .collect();
+        let queries = instances
+            .iter()
+            .map(|earthel| query_concurrently(earthel, 16, 2));
+        for results in futures::future::join_all(queries).await {
+            for (i, result) in results.into_iter().enumerate() {
+                assert_eq!(result.unwrap(), (i % 2) as i16);
+            }
+        }
+        assert_eq!(source.requests.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,98 +1,198 @@
 use byteorder::{BigEndian, ReadBytesExt};
-use flate2::read::GzDecoder;
-use std::fs;
-use std::fs::File;
-use std::io::{Cursor, Seek, SeekFrom};
-use std::path::PathBuf;
-use thiserror::Error;
+use cache::TileMetadata;
+use source::{Revalidation, Validators};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
-pub struct EarthEl;
+pub mod atmosphere;
+mod bbox;
+mod builder;
+pub mod cache;
+mod climbs;
+mod difficulty;
+mod download;
+#[cfg(any(feature = "gpx", feature = "geojson"))]
+mod enrich;
+mod error;
+mod formats;
+pub mod geodesy;
+#[cfg(feature = "geo")]
+mod geofence;
+#[cfg(feature = "geoid")]
+pub mod geoid;
+#[cfg(feature = "geo")]
+mod geometry;
+#[cfg(not(target_arch = "wasm32"))]
+mod import;
+mod in_flight;
+mod low_memory;
+mod lru;
+#[cfg(feature = "manifest")]
+pub mod manifest;
+mod metrics;
+mod missing;
+mod overview;
+mod point_cache;
+mod prefetch;
+mod profile;
+mod profile_cache;
+mod provenance;
+mod qa;
+pub mod raster;
+mod ray;
+#[cfg(feature = "serde")]
+mod serialization;
+pub mod slippy;
+mod snapshot;
+pub mod source;
+mod stream;
+mod subscription;
+mod tile;
+#[cfg(feature = "gpx")]
+pub mod track_export;
+mod versions;
+mod visibility;
+#[cfg(feature = "geo")]
+mod zonal;
 
-#[derive(Debug, Error)]
-pub enum HgtError {
-    #[error("File operation failed: {0}")]
-    IoError(#[from] std::io::Error),
+pub use bbox::BoundingBox;
+pub use builder::EarthElBuilder;
+pub use cache::{CacheLock, TileCache};
+pub use climbs::{Climb, ClimbAnalysis, ClimbCategory, ClimbOptions};
+pub use difficulty::{Difficulty, DifficultyRating, DifficultyScheme};
+pub use error::{HgtError, Result};
+pub use geodesy::Body;
+#[cfg(feature = "geo")]
+pub use geofence::GeofenceCheck;
+#[cfg(feature = "geoid")]
+pub use geoid::VerticalDatum;
+#[cfg(feature = "geo")]
+pub use geometry::AreaStats;
+#[cfg(not(target_arch = "wasm32"))]
+pub use import::ImportReport;
+#[cfg(feature = "manifest")]
+pub use manifest::{Manifest, ManifestReport};
+pub use metrics::Metrics;
+pub use missing::MissingPolicy;
+pub use point_cache::PointCacheConfig;
+pub use prefetch::{DownloadEvent, PrefetchReport};
+pub use profile::{Profile, ProfileSample};
+pub use provenance::Elevation;
+pub use qa::{Anomaly, AnomalyKind, QaReport};
+pub use snapshot::{Snapshot, TileSnapshot};
+pub use source::TileSource;
+pub use stream::StreamOptions;
+pub use subscription::{Subscription, TileUpdate, TileUpdates};
+pub use tile::{Resolution, TileId};
+#[cfg(feature = "gpx")]
+pub use track_export::TrackFormat;
+pub use versions::{Date, VersionedDem};
+pub use visibility::{VisibilityMatrix, VisibilityOptions};
+#[cfg(feature = "geo")]
+pub use zonal::ZonalStats;
 
-    #[error("Failed to decode gzip file: {0}")]
-    DecodeError(#[from] flate2::DecompressError),
+use in_flight::InFlight;
+use metrics::{trace, Counters, Instant, SystemTime};
+use overview::Overviews;
+use point_cache::PointCache;
+use profile_cache::ProfileCache;
+use tile::Tile;
 
-    #[error("Network error: {0}")]
-    ReqwestError(#[from] reqwest::Error),
-
-    #[error("Unexpected HGT resolution: {0}")]
-    InvalidResolution(u64),
+/// Elevation lookups from SRTM tiles, downloaded on demand and kept in a
+/// [`TileCache`].
+///
+/// `EarthEl::default()` caches tiles on disk with [`cache::FsCache`]; use
+/// [`EarthEl::builder`] to configure another cache.
+///
+/// # Sharing
+///
+/// `EarthEl` is `Send + Sync`, and cloning it is cheap: clones are handles
+/// to the same caches, counters and downloads, meant to be handed to every
+/// task of a server. It guarantees that:
+///
+/// - concurrent lookups missing the same tile download it once: one task
+///   downloads it while the others wait, then read it from the cache (see
+///   [`Metrics::coalesced_downloads`]). If the download fails, each waiting
+///   task tries in turn, so that one failure doesn't fail them all;
+/// - revalidations and [`refresh_tile`](Self::refresh_tile) calls of a tile
+///   are serialized the same way;
+/// - lookups never see a tile half stored by the built-in caches:
+///   [`cache::FsCache`] writes files under temporary names renamed into
+///   place, and [`cache::MemoryCache`] replaces tiles under a lock.
+///
+/// Separate instances built with [`EarthEl::builder`] share nothing but
+/// their cache. Over an [`cache::FsCache`] or
+/// [`cache::ContentAddressedCache`] directory, even in separate processes,
+/// they still download each tile once, through the locks of the cache (see
+/// [`TileCache::try_lock`]); over other caches, they may download the same
+/// tile concurrently.
+///
+/// ```
+/// use earthel::{cache::MemoryCache, EarthEl};
+///
+/// # async fn run() -> earthel::Result<()> {
+/// let earthel = EarthEl::builder().cache(MemoryCache::new()).build();
+/// let tasks: Vec<_> = (0..8)
+///     .map(|i| {
+///         let earthel = earthel.clone();
+///         tokio::spawn(async move { earthel.get_elevation(45.8, 6.8 + f64::from(i) * 0.01).await })
+///     })
+///     .collect();
+/// for task in tasks {
+///     println!("{} m", task.await.unwrap()?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct EarthEl {
+    cache: Arc<dyn TileCache>,
+    source: Arc<dyn TileSource>,
+    point_cache: Option<Arc<PointCache>>,
+    profile_cache: Option<Arc<ProfileCache>>,
+    overviews: Option<Arc<Overviews>>,
+    coverage: BoundingBox,
+    body: Body,
+    #[cfg(feature = "geoid")]
+    datum: VerticalDatum,
+    on_download: Option<DownloadCallback>,
+    missing: MissingPolicy,
+    max_age: Option<Duration>,
+    deadline: Option<Duration>,
+    resampling: raster::Resampling,
+    #[cfg(feature = "manifest")]
+    manifest: Option<Arc<Manifest>>,
+    /// Versions tiles are pinned to in strict mode.
+    pins: Option<Arc<HashMap<TileId, String>>>,
+    /// Bound on single allocations in bytes, in the low-memory profile.
+    max_allocation: Option<u64>,
+    in_flight: Arc<InFlight>,
+    counters: Arc<Counters>,
+    subscribers: Arc<subscription::Subscribers>,
 }
 
-struct HgtFile {
-    folder: String,
-    name: String,
-    path: PathBuf,
-}
+type DownloadCallback = Arc<dyn Fn(&DownloadEvent) + Send + Sync>;
 
-impl HgtFile {
-    async fn new(latitude: f64, longitude: f64) -> Self {
-        let lat_prefix = if latitude >= 0.0 { "N" } else { "S" };
-        let lon_prefix = if longitude >= 0.0 { "E" } else { "W" };
-        let lat_int = latitude.abs().floor() as i32;
-        let lon_int = longitude.abs().floor() as i32;
-        let name = format!(
-            "{}{:02}{}{:03}.hgt",
-            lat_prefix, lat_int, lon_prefix, lon_int
-        );
-        let folder = format!("{}{}", lat_prefix, lat_int);
-        let path = PathBuf::from(format!("/tmp/hgt/{}/{}", folder, name));
-        Self { folder, name, path }
+impl Default for EarthEl {
+    fn default() -> Self {
+        Self::builder().build()
     }
+}
 
-    async fn get_file(&self) -> std::result::Result<File, HgtError> {
-        if !self.path.exists() {
-            self.download_hgt().await?;
-        }
-        File::open(&self.path).map_err(HgtError::from)
+impl EarthEl {
+    pub fn builder() -> EarthElBuilder {
+        EarthElBuilder::default()
     }
 
-    async fn download_hgt(&self) -> Result<()> {
-        let path_s3 = format!(
-            "https://elevation-tiles-prod.s3.amazonaws.com/skadi/{}/{}.gz",
-            self.folder, self.name
-        );
-        let response = reqwest::get(&path_s3).await?;
-        if let Some(parent) = self.path.parent() {
-            fs::create_dir_all(parent).map_err(HgtError::from)?;
-        }
-        let tmp_path = PathBuf::from(format!("/tmp/tmp_{}.gz", self.name));
-        let mut file = File::create(tmp_path)?;
-        let mut content = Cursor::new(response.bytes().await?);
-        std::io::copy(&mut content, &mut file)?;
-        self.extract_gz_file()?;
-        Ok(())
-    }
-
-    fn extract_gz_file(&self) -> std::io::Result<()> {
-        let input_path = PathBuf::from(format!("/tmp/tmp_{}.gz", self.name));
-        let gz_file = File::open(&input_path)?;
-        let mut decoder = GzDecoder::new(gz_file);
-        let mut output_file = File::create(&self.path)?;
-        std::io::copy(&mut decoder, &mut output_file)?;
-        fs::remove_file(input_path)?;
-        Ok(())
-    }
-
-    pub fn get_resolution(&self) -> Option<usize> {
-        let from_metadata = |m: fs::Metadata| match m.len() {
-            25934402 => Some(3601), // SRTM1
-            2884802 => Some(1201),  // SRTM3
-            _ => None,              // Default to SRTM3
-        };
-        fs::metadata(&self.path).ok().and_then(from_metadata)
+    /// The cache holding downloaded tiles.
+    pub fn cache(&self) -> &dyn TileCache {
+        self.cache.as_ref()
     }
-}
 
-impl EarthEl {
-    // Generate doc string
     /// Retrieves the elevation data for the given latitude and longitude coordinates.
     ///
-    /// This function downloads the necessary HGT file if it is not already available locally,
+    /// This function downloads the necessary HGT file if it is not already in the cache,
     /// extracts the elevation data from the file, and returns the elevation in meters.
     ///
     /// # Arguments
@@ -103,55 +203,883 @@ impl EarthEl {
     /// # Returns
     ///
     /// A `Result` containing the elevation in meters as an i16, or an error if the operation fails.
+    /// Locations without data are handled according to the [`MissingPolicy`]; when it
+    /// reports no elevation, the HGT void value -32768 is returned (see
+    /// [`try_get_elevation`](Self::try_get_elevation)).
+    /// Longitudes outside of `[-180, 180)` are wrapped around (190° is -170°), while
+    /// non-finite values and latitudes beyond ±90° fail with [`HgtError::InvalidCoordinate`].
     ///
     /// # Example
     ///
-    /// ```
-    /// use earth_el::EarthEl;
+    /// ```no_run
+    /// use earthel::EarthEl;
     ///
     /// #[tokio::main]
     /// async fn main() {
     ///     let latitude = 47.0592;
     ///     let longitude = 5.7181;
-    ///     match EarthEl::get_elevation(latitude, longitude).await {
+    ///     match EarthEl::default().get_elevation(latitude, longitude).await {
     ///         Ok(elevation) => println!("Elevation: {} meters", elevation),
     ///         Err(e) => eprintln!("Error: {}", e),
     ///     }
     /// }
     /// ```
-    pub async fn get_elevation(latitude: f64, longitude: f64) -> Result<i16> {
-        let hgt_file = HgtFile::new(latitude, longitude).await;
-        let mut file = hgt_file.get_file().await?;
-        let grid_size: usize = hgt_file
-            .get_resolution()
-            .ok_or_else(|| HgtError::InvalidResolution(0))?;
-        let lat_seconds = ((latitude - latitude.floor()) * 3600.0) as usize;
-        let lon_seconds = ((longitude - longitude.floor()) * 3600.0) as usize;
-        let lat_pos = (grid_size - 1) - (lat_seconds * (grid_size - 1) / 3600);
-        let lon_pos = lon_seconds * (grid_size - 1) / 3600;
-        let pos = 2 * (lat_pos * grid_size + lon_pos);
-        file.seek(SeekFrom::Start(pos as u64))?;
-        let elevation = file.read_i16::<BigEndian>()?;
-        Ok(elevation)
+    pub async fn get_elevation(&self, latitude: f64, longitude: f64) -> Result<i16> {
+        Ok(match self.try_get_elevation(latitude, longitude).await? {
+            Some(elevation) => elevation.round() as i16,
+            None => tile::VOID,
+        })
+    }
+
+    /// Elevation in meters of a location, `None` where there is no data and
+    /// the [`MissingPolicy`] is [`MissingPolicy::None`].
+    ///
+    /// ```no_run
+    /// use earthel::{EarthEl, MissingPolicy};
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let earthel = EarthEl::builder().missing(MissingPolicy::None).build();
+    /// // In the middle of the Atlantic.
+    /// assert_eq!(earthel.try_get_elevation(35.0, -40.0).await?, None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn try_get_elevation(&self, latitude: f64, longitude: f64) -> Result<Option<f64>> {
+        let started = Instant::now();
+        let result = self
+            .within_deadline(async {
+                let (latitude, longitude) = geodesy::normalize(latitude, longitude)?;
+                match self.lookup(latitude, longitude).await? {
+                    Some(elevation) => Ok(Some(f64::from(elevation))),
+                    None => self.missing.resolve(latitude, longitude),
+                }
+            })
+            .await;
+        self.counters.query(started.elapsed());
+        result
+    }
+
+    /// [`try_get_elevation`](Self::try_get_elevation) for queries over many
+    /// points, which keep going across voids: `None` over voids whatever the
+    /// [`MissingPolicy`], and over missing tiles if it tolerates them.
+    #[cfg(any(feature = "gpx", feature = "geojson", feature = "geo"))]
+    pub(crate) async fn try_get_elevation_or_void(
+        &self,
+        latitude: f64,
+        longitude: f64,
+    ) -> Result<Option<f64>> {
+        match self.try_get_elevation(latitude, longitude).await {
+            Err(HgtError::NoData { .. }) => Ok(None),
+            result => result,
+        }
+    }
+
+    /// Runs a query, failing with [`HgtError::DeadlineExceeded`] if it takes
+    /// longer than the configured deadline.
+    pub(crate) async fn within_deadline<T>(
+        &self,
+        query: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        match self.deadline {
+            Some(deadline) => download::timeout(deadline, query)
+                .await
+                .unwrap_or(Err(HgtError::DeadlineExceeded(deadline))),
+            None => query.await,
+        }
+    }
+
+    /// Activity counters since the instance was built: queries, cache hits
+    /// and misses, downloads and decoding, with the time they took.
+    pub fn metrics(&self) -> Metrics {
+        self.counters.snapshot()
+    }
+
+    /// Elevation of a normalized location in the configured datum, `None` for
+    /// voids and, if the policy tolerates it, tiles missing at the source.
+    async fn lookup(&self, latitude: f64, longitude: f64) -> Result<Option<i16>> {
+        if let Some(cache) = &self.point_cache {
+            let cached = cache.get(latitude, longitude);
+            self.counters.point_cache(cached.is_some());
+            if let Some(elevation) = cached {
+                trace!("point cache hit");
+                return Ok((elevation != tile::VOID).then_some(elevation));
+            }
+        }
+        let mut elevation = match self.read_elevation(latitude, longitude).await {
+            Ok(elevation) => elevation,
+            Err(HgtError::TileNotFound(_)) if self.missing.tolerates_missing_tiles() => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        };
+        if elevation != tile::VOID {
+            elevation = self
+                .to_datum(latitude, longitude, f64::from(elevation))
+                .round() as i16;
+        }
+        if let Some(cache) = &self.point_cache {
+            cache.insert(latitude, longitude, elevation);
+        }
+        Ok((elevation != tile::VOID).then_some(elevation))
+    }
+
+    /// Sampling grid of the tile covering a location, downloading the tile if
+    /// needed. Caches may mix tiles of different resolutions.
+    pub async fn resolution_at(&self, latitude: f64, longitude: f64) -> Result<Resolution> {
+        self.within_deadline(async {
+            let (latitude, longitude) = geodesy::normalize(latitude, longitude)?;
+            let tile = TileId::containing(latitude, longitude);
+            let size = self.ensure_tile(&tile).await?;
+            let grid_size = tile::grid_size(size).ok_or(HgtError::InvalidResolution(size))?;
+            Ok(Resolution { grid_size })
+        })
+        .await
+    }
+
+    async fn read_elevation(&self, latitude: f64, longitude: f64) -> Result<i16> {
+        let tile = TileId::containing(latitude, longitude);
+        let size = self.ensure_tile(&tile).await?;
+        let grid_size = tile::grid_size(size).ok_or(HgtError::InvalidResolution(size))?;
+        let mut sample = [0; 2];
+        let offset = tile::sample_offset(grid_size, latitude, longitude);
+        self.cache.read_at(&tile, offset, &mut sample)?;
+        Ok((&sample[..]).read_i16::<BigEndian>()?)
+    }
+
+    /// Converts an EGM96 height to the configured vertical datum.
+    #[cfg_attr(not(feature = "geoid"), allow(unused_variables))]
+    pub(crate) fn to_datum(&self, latitude: f64, longitude: f64, height: f64) -> f64 {
+        #[cfg(feature = "geoid")]
+        return self.datum.convert(latitude, longitude, height);
+        #[cfg(not(feature = "geoid"))]
+        height
+    }
+
+    /// Converts a height in the configured vertical datum to EGM96.
+    #[cfg_attr(not(feature = "geoid"), allow(unused_variables))]
+    pub(crate) fn to_egm96(&self, latitude: f64, longitude: f64, height: f64) -> f64 {
+        #[cfg(feature = "geoid")]
+        return self.datum.revert(latitude, longitude, height);
+        #[cfg(not(feature = "geoid"))]
+        height
+    }
+
+    /// Loads and decodes a whole tile.
+    pub(crate) async fn load_tile(&self, id: &TileId) -> Result<Tile> {
+        self.ensure_tile(id).await?;
+        self.decode_tile(id)
+    }
+
+    /// Decodes a tile that is in the cache.
+    fn decode_tile(&self, id: &TileId) -> Result<Tile> {
+        if let Some(size) = self.cache.size(id)? {
+            self.check_allocation(size)?;
+        }
+        let data = self.cache.load(id)?.ok_or_else(|| cache::not_cached(id))?;
+        let started = Instant::now();
+        let tile = Tile::decode(*id, &data);
+        self.counters.decode(started.elapsed());
+        trace!(tile = %id, elapsed = ?started.elapsed(), "decoded tile");
+        tile
+    }
+
+    /// Loads and decodes a whole tile, `None` if it does not exist at the
+    /// source and the [`MissingPolicy`] tolerates it.
+    pub(crate) async fn load_tile_if_present(&self, id: &TileId) -> Result<Option<Tile>> {
+        match self.load_tile(id).await {
+            Ok(tile) => Ok(Some(tile)),
+            Err(HgtError::TileNotFound(_)) if self.missing.tolerates_missing_tiles() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Like [`load_tile_if_present`](Self::load_tile_if_present), but
+    /// downsampled to the coarsest overview whose samples are not further
+    /// apart than `cell_size` degrees.
+    pub(crate) async fn load_overview_if_present(
+        &self,
+        id: &TileId,
+        cell_size: f64,
+    ) -> Result<Option<Tile>> {
+        let Some(overviews) = &self.overviews else {
+            return self.load_tile_if_present(id).await;
+        };
+        let size = match self.ensure_tile(id).await {
+            Ok(size) => size,
+            Err(HgtError::TileNotFound(_)) if self.missing.tolerates_missing_tiles() => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        };
+        let grid_size = tile::grid_size(size).ok_or(HgtError::InvalidResolution(size))?;
+        let level = overview::level_for(grid_size, cell_size);
+        if level == 0 {
+            return self.decode_tile(id).map(Some);
+        }
+        if let Some(tile) = overviews.get(id, level) {
+            return Ok(Some(tile.as_ref().clone()));
+        }
+        let tile = Arc::new(overview::downsample(
+            &self.decode_tile(id)?,
+            level,
+            self.resampling,
+        ));
+        trace!(%id, level, "built overview");
+        overviews.insert(tile.clone(), level);
+        Ok(Some(tile.as_ref().clone()))
+    }
+
+    /// Checks a tile against its source, e.g. after the source published
+    /// corrections, and replaces the cached copy if it changed. Sources that
+    /// support it answer conditional requests without resending unchanged
+    /// tiles. Tiles that are not cached yet are downloaded.
+    ///
+    /// Returns whether the cached content changed.
+    ///
+    /// ```no_run
+    /// use earthel::{EarthEl, TileId};
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let earthel = EarthEl::default();
+    /// if earthel.refresh_tile(&TileId::new(47, 5)).await? {
+    ///     println!("N47E005 was updated");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn refresh_tile(&self, tile: &TileId) -> Result<bool> {
+        self.within_deadline(async {
+            let _lock = self.lock_tile(tile).await?;
+            let previous = match self.cache.size(tile)? {
+                Some(_) => Some(
+                    self.cache
+                        .metadata(tile)?
+                        .map(|metadata| metadata.validators)
+                        .unwrap_or_default(),
+                ),
+                None => None,
+            };
+            Ok(self.fetch_tile(tile, previous.as_ref()).await?.is_some())
+        })
+        .await
+    }
+
+    /// Makes sure a tile is cached, downloading it if needed, and returns its size.
+    /// Tiles older than the configured maximum age are revalidated first.
+    async fn ensure_tile(&self, tile: &TileId) -> Result<u64> {
+        let cached = self.cache.size(tile)?;
+        self.counters.tile_cache(cached.is_some());
+        if let Some(size) = cached {
+            if self
+                .pins
+                .as_ref()
+                .is_some_and(|pins| pins.contains_key(tile))
+            {
+                self.check_version(tile, None, self.tile_version(tile)?)?;
+            }
+            if self.stale_metadata(tile)?.is_none() {
+                return Ok(size);
+            }
+            let _lock = self.lock_tile(tile).await?;
+            // Another task may have revalidated it while we waited.
+            let Some(metadata) = self.stale_metadata(tile)? else {
+                return Ok(self.cache.size(tile)?.unwrap_or(size));
+            };
+            trace!(%tile, "revalidating stale tile");
+            // A stale tile is better than none while the source is unreachable,
+            // but not one that changed in strict mode.
+            return match self.fetch_tile(tile, Some(&metadata.validators)).await {
+                Err(e @ HgtError::VersionMismatch { .. }) => Err(e),
+                outcome => Ok(outcome.ok().flatten().unwrap_or(size)),
+            };
+        }
+        trace!(%tile, "tile cache miss");
+        let _lock = self.lock_tile(tile).await?;
+        if let Some(size) = self.cache.size(tile)? {
+            trace!(%tile, "downloaded by another task");
+            self.counters.coalesced();
+            return Ok(size);
+        }
+        self.fetch_tile(tile, None)
+            .await?
+            .ok_or_else(|| cache::not_cached(tile))
+    }
+
+    /// Metadata of a cached tile older than the maximum age. Tiles of unknown
+    /// age, e.g. in caches that don't keep metadata, are never stale.
+    fn stale_metadata(&self, tile: &TileId) -> Result<Option<TileMetadata>> {
+        let Some(max_age) = self.max_age else {
+            return Ok(None);
+        };
+        Ok(self
+            .cache
+            .metadata(tile)?
+            .filter(|metadata| metadata.fetched_at.elapsed().is_ok_and(|age| age > max_age)))
+    }
+
+    /// Downloads a tile, unless it is cached and the source confirms that it
+    /// is unchanged since it was served with the `previous` validators.
+    /// Returns the size of the new content, `None` if unchanged.
+    async fn fetch_tile(
+        &self,
+        tile: &TileId,
+        previous: Option<&Validators>,
+    ) -> Result<Option<u64>> {
+        let started = Instant::now();
+        let outcome = self.download_tile(tile, previous).await;
+        let elapsed = started.elapsed();
+        match &outcome {
+            Ok(Some(bytes)) => {
+                self.counters.download(Some(*bytes), elapsed);
+                self.report(DownloadEvent::Downloaded {
+                    tile: *tile,
+                    bytes: *bytes,
+                });
+            }
+            Ok(None) => self.counters.revalidated(elapsed),
+            Err(e) => {
+                self.counters.download(None, elapsed);
+                self.report(DownloadEvent::Failed {
+                    tile: *tile,
+                    reason: e.to_string(),
+                });
+            }
+        }
+        outcome
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(%tile)))]
+    async fn download_tile(
+        &self,
+        tile: &TileId,
+        previous: Option<&Validators>,
+    ) -> Result<Option<u64>> {
+        if !self.coverage.intersects(tile) {
+            return Err(HgtError::OutsideCoverage(*tile));
+        }
+        let none = Validators::default();
+        let (validators, size, updated) = match self
+            .source
+            .revalidate(tile, previous.unwrap_or(&none))
+            .await?
+        {
+            Revalidation::NotModified => (previous.cloned().unwrap_or_default(), None, false),
+            Revalidation::Modified { data, validators } => {
+                let pinned = previous.and_then(|previous| previous.etag.as_deref());
+                self.check_version(tile, pinned, validators.etag.clone())?;
+                let changed = match previous {
+                    Some(previous) => self.content_changed(tile, previous, &validators, &data)?,
+                    None => true,
+                };
+                if changed {
+                    self.cache.store(tile, &data)?;
+                    if previous.is_some() {
+                        self.forget_results();
+                    }
+                }
+                let size = changed.then_some(data.len() as u64);
+                (validators, size, changed && previous.is_some())
+            }
+        };
+        let version = updated.then(|| validators.etag.clone());
+        let metadata = TileMetadata {
+            validators,
+            fetched_at: SystemTime::now(),
+        };
+        self.cache.store_metadata(tile, &metadata)?;
+        if let (Some(version), Some(bytes)) = (version, size) {
+            self.subscribers.notify(&TileUpdate {
+                tile: *tile,
+                bytes,
+                version,
+            });
+        }
+        Ok(size)
+    }
+
+    /// Whether `data`, downloaded again, differs from the cached copy of
+    /// `tile`. Sources without validators resend unchanged tiles, which
+    /// are then compared byte for byte.
+    fn content_changed(
+        &self,
+        tile: &TileId,
+        previous: &Validators,
+        validators: &Validators,
+        data: &[u8],
+    ) -> Result<bool> {
+        if previous.etag.is_some() && validators.etag.is_some() && previous.etag != validators.etag
+        {
+            return Ok(true);
+        }
+        Ok(self.cache.load(tile)?.as_deref() != Some(data))
+    }
+
+    /// Entity tag of a cached tile as served by its source, if the cache
+    /// keeps [`TileMetadata`] and the source tags tiles. Results computed from
+    /// the tile can be reproduced by
+    /// [pinning](EarthElBuilder::pin_version) it to this version.
+    pub fn tile_version(&self, tile: &TileId) -> Result<Option<String>> {
+        Ok(self
+            .cache
+            .metadata(tile)?
+            .and_then(|metadata| metadata.validators.etag))
+    }
+
+    /// In strict mode, fails unless `actual` is the version `tile` is pinned
+    /// to, explicitly or else by the `cached` version.
+    fn check_version(
+        &self,
+        tile: &TileId,
+        cached: Option<&str>,
+        actual: Option<String>,
+    ) -> Result<()> {
+        let Some(pins) = &self.pins else {
+            return Ok(());
+        };
+        match pins.get(tile).map(String::as_str).or(cached) {
+            Some(expected) if actual.as_deref() != Some(expected) => {
+                Err(HgtError::VersionMismatch {
+                    tile: *tile,
+                    expected: expected.to_owned(),
+                    actual,
+                })
+            }
+            _ => Ok(()),
+        }
     }
-}
 
-type Result<T> = std::result::Result<T, HgtError>;
+    /// Drops memoized points, profiles and overviews, which may come from a
+    /// replaced tile.
+    fn forget_results(&self) {
+        if let Some(overviews) = &self.overviews {
+            overviews.clear();
+        }
+        if let Some(cache) = &self.point_cache {
+            cache.clear();
+        }
+        if let Some(cache) = &self.profile_cache {
+            cache.clear();
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cache::MemoryCache;
 
     #[tokio::test]
     async fn it_works() {
-        let el = EarthEl::get_elevation(47.0592, 5.7181)
-            .await
-            .expect("error");
+        let earthel = EarthEl::default();
+        let el = earthel.get_elevation(47.0592, 5.7181).await.expect("error");
         assert_eq!(el, 259);
-        let el = EarthEl::get_elevation(45.833641, 6.864594)
+        let el = earthel
+            .get_elevation(45.833641, 6.864594)
             .await
             .expect("error");
         assert_eq!(el, 4740);
         print!("Mont blanc {el}")
     }
+
+    #[tokio::test]
+    async fn reads_elevation_from_cache() {
+        let cache = Arc::new(MemoryCache::new());
+        let tile = TileId::new(47, 5);
+        let data = tile::synthetic_tile(1201, |row, col| (row * 10 + col) as i16);
+        cache.store(&tile, &data).unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        // 47.5°N is row 600, 5.25°E is column 300.
+        let el = earthel.get_elevation(47.5, 5.25).await.unwrap();
+        assert_eq!(el, 6300);
+    }
+
+    #[tokio::test]
+    async fn point_cache_skips_tile_access() {
+        let cache = Arc::new(MemoryCache::new());
+        let tile = TileId::new(47, 5);
+        cache
+            .store(&tile, &tile::synthetic_tile(1201, |_, _| 42))
+            .unwrap();
+        let earthel = EarthEl::builder()
+            .cache(cache.clone())
+            .point_cache(PointCacheConfig::default())
+            .build();
+        assert_eq!(earthel.get_elevation(47.5, 5.25).await.unwrap(), 42);
+        cache.remove(&tile).unwrap();
+        assert_eq!(earthel.get_elevation(47.5, 5.25).await.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn reads_tiles_of_any_resolution() {
+        let cache = MemoryCache::new();
+        cache
+            .store(&TileId::new(47, 5), &tile::synthetic_tile(3601, |_, _| 1))
+            .unwrap();
+        let mut odd = tile::synthetic_tile(1801, |row, col| (row + col) as i16);
+        odd.extend_from_slice(&[0; 7]);
+        cache.store(&TileId::new(47, 6), &odd).unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        assert_eq!(earthel.get_elevation(47.5, 5.5).await.unwrap(), 1);
+        // Row 900, column 450 of the 1801 grid.
+        assert_eq!(earthel.get_elevation(47.5, 6.25).await.unwrap(), 1350);
+        let resolution = earthel.resolution_at(47.5, 6.25).await.unwrap();
+        assert_eq!(resolution, Resolution { grid_size: 1801 });
+        assert!((resolution.arc_seconds() - 2.0).abs() < 1e-12);
+        assert_eq!(
+            earthel.resolution_at(47.5, 5.5).await.unwrap().grid_size,
+            3601
+        );
+    }
+
+    #[tokio::test]
+    async fn validates_and_wraps_coordinates() {
+        let cache = MemoryCache::new();
+        let data = tile::synthetic_tile(1201, |row, col| (row * 10 + col) as i16);
+        cache.store(&TileId::new(47, 5), &data).unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        assert_eq!(earthel.get_elevation(47.5, 365.25).await.unwrap(), 6300);
+        assert!(matches!(
+            earthel.get_elevation(91.0, 5.25).await,
+            Err(HgtError::InvalidCoordinate { lat, .. }) if lat == 91.0
+        ));
+        assert!(matches!(
+            earthel.get_elevation(47.5, f64::NAN).await,
+            Err(HgtError::InvalidCoordinate { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_tiles_outside_coverage() {
+        let earthel = EarthEl::builder()
+            .cache(MemoryCache::new())
+            .coverage(BoundingBox::new(-56.0, -180.0, 60.0, 180.0))
+            .build();
+        let error = earthel.get_elevation(78.2, 15.6).await.unwrap_err();
+        assert!(matches!(error, HgtError::OutsideCoverage(tile) if tile == TileId::new(78, 15)));
+        let error = EarthEl::builder()
+            .cache(MemoryCache::new())
+            .build()
+            .get_elevation(90.0, 0.0)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, HgtError::OutsideCoverage(_)));
+    }
+
+    #[tokio::test]
+    async fn records_metrics() {
+        let cache = MemoryCache::new();
+        cache
+            .store(&TileId::new(1, 1), &tile::synthetic_tile(1201, |_, _| 7))
+            .unwrap();
+        let earthel = EarthEl::builder()
+            .cache(cache)
+            .source(Ocean)
+            .point_cache(PointCacheConfig::default())
+            .missing(MissingPolicy::Zero)
+            .build();
+        earthel.get_elevation(1.5, 1.5).await.unwrap();
+        earthel.get_elevation(1.5, 1.5).await.unwrap();
+        earthel.get_elevation(2.5, 2.5).await.unwrap();
+        earthel
+            .profile(&[(1.2, 1.2), (1.3, 1.3)], 1_000.0)
+            .await
+            .unwrap();
+        let metrics = earthel.metrics();
+        assert_eq!(metrics.queries, 3);
+        assert_eq!(
+            (metrics.point_cache_hits, metrics.point_cache_misses),
+            (1, 2)
+        );
+        assert_eq!((metrics.tile_cache_hits, metrics.tile_cache_misses), (2, 1));
+        assert_eq!((metrics.downloads, metrics.failed_downloads), (0, 1));
+        assert_eq!(metrics.decoded_tiles, 1);
+        assert!(metrics.query_time >= metrics.download_time);
+    }
+
+    /// A source without any tile, like the Skadi bucket over the ocean.
+    struct Ocean;
+
+    impl TileSource for Ocean {
+        fn fetch<'a>(&'a self, tile: &'a TileId) -> source::SourceFuture<'a> {
+            Box::pin(async move { Err(HgtError::TileNotFound(*tile)) })
+        }
+    }
+
+    #[tokio::test]
+    async fn applies_the_missing_data_policy() {
+        let cache = MemoryCache::new();
+        let mut data = tile::synthetic_tile(1201, |_, _| 40);
+        // Void sample in the north-west corner of the second row.
+        data[2402..2404].copy_from_slice(&tile::VOID.to_be_bytes());
+        cache.store(&TileId::new(10, 10), &data).unwrap();
+        let cache = Arc::new(cache);
+        let earthel = |missing| {
+            EarthEl::builder()
+                .cache(Arc::clone(&cache))
+                .source(Ocean)
+                .missing(missing)
+                .build()
+        };
+        let strict = earthel(MissingPolicy::Error);
+        assert!(matches!(
+            strict.get_elevation(35.0, -40.0).await,
+            Err(HgtError::TileNotFound(_))
+        ));
+        assert!(matches!(
+            strict.get_elevation(10.9999, 10.0001).await,
+            Err(HgtError::NoData { .. })
+        ));
+        assert_eq!(strict.get_elevation(10.5, 10.5).await.unwrap(), 40);
+
+        let lenient = earthel(MissingPolicy::None);
+        assert_eq!(lenient.try_get_elevation(35.0, -40.0).await.unwrap(), None);
+        assert_eq!(
+            lenient.try_get_elevation(10.9999, 10.0001).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            lenient.get_elevation(35.0, -40.0).await.unwrap(),
+            tile::VOID
+        );
+        let sea = earthel(MissingPolicy::Zero);
+        assert_eq!(sea.get_elevation(35.0, -40.0).await.unwrap(), 0);
+        let filled = earthel(MissingPolicy::Value(-5.0));
+        assert_eq!(
+            filled.try_get_elevation(10.9999, 10.0001).await.unwrap(),
+            Some(-5.0)
+        );
+
+        // Profiles and rasters leave gaps or fill them.
+        let coast = [(10.5, 9.5), (10.5, 10.5)];
+        let profile = lenient.profile(&coast, 10_000.0).await.unwrap();
+        assert_eq!(profile.samples.first().unwrap().elevation, None);
+        assert_eq!(profile.samples.last().unwrap().elevation, Some(40.0));
+        let profile = sea.profile(&coast, 10_000.0).await.unwrap();
+        assert_eq!(profile.samples.first().unwrap().elevation, Some(0.0));
+        assert!(strict.profile(&coast, 10_000.0).await.is_err());
+        let dem = sea
+            .extract_dem(BoundingBox::new(10.4, 9.9, 10.6, 10.1), 0.1)
+            .await
+            .unwrap();
+        assert_eq!(dem.data, [0.0, 40.0, 0.0, 40.0]);
+    }
+
+    /// A source serving every tile filled with its current version number,
+    /// tagged with that version.
+    #[derive(Default)]
+    pub(crate) struct Versioned {
+        pub(crate) version: std::sync::atomic::AtomicI16,
+    }
+
+    impl TileSource for Versioned {
+        fn fetch<'a>(&'a self, _: &'a TileId) -> source::SourceFuture<'a> {
+            unreachable!("tiles are fetched through revalidate")
+        }
+
+        fn revalidate<'a>(
+            &'a self,
+            _: &'a TileId,
+            validators: &'a Validators,
+        ) -> source::RevalidateFuture<'a> {
+            let version = self.version.load(std::sync::atomic::Ordering::Relaxed);
+            let etag = version.to_string();
+            Box::pin(async move {
+                if validators.etag.as_deref() == Some(etag.as_str()) {
+                    return Ok(Revalidation::NotModified);
+                }
+                Ok(Revalidation::Modified {
+                    data: tile::synthetic_tile(1201, |_, _| version),
+                    validators: Validators {
+                        etag: Some(etag),
+                        last_modified: None,
+                    },
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn revalidates_stale_tiles() {
+        let source = Arc::new(Versioned::default());
+        let set_version = |version| {
+            source
+                .version
+                .store(version, std::sync::atomic::Ordering::Relaxed)
+        };
+        set_version(1);
+        let earthel = EarthEl::builder()
+            .cache(MemoryCache::new())
+            .source(Arc::clone(&source))
+            .max_age(Duration::ZERO)
+            .build();
+        assert_eq!(earthel.get_elevation(10.5, 10.5).await.unwrap(), 1);
+        assert_eq!(earthel.get_elevation(10.5, 10.5).await.unwrap(), 1);
+        set_version(2);
+        assert_eq!(earthel.get_elevation(10.5, 10.5).await.unwrap(), 2);
+        let metrics = earthel.metrics();
+        assert_eq!((metrics.downloads, metrics.revalidated_tiles), (2, 1));
+        let metadata = earthel.cache().metadata(&TileId::new(10, 10)).unwrap();
+        assert_eq!(metadata.unwrap().validators.etag.as_deref(), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn refreshes_tiles_on_demand() {
+        let source = Arc::new(Versioned::default());
+        let earthel = EarthEl::builder()
+            .cache(MemoryCache::new())
+            .source(Arc::clone(&source))
+            .point_cache(PointCacheConfig::default())
+            .build();
+        assert_eq!(earthel.get_elevation(10.5, 10.5).await.unwrap(), 0);
+        let tile = TileId::new(10, 10);
+        assert!(!earthel.refresh_tile(&tile).await.unwrap());
+        source
+            .version
+            .store(7, std::sync::atomic::Ordering::Relaxed);
+        // Without a maximum age the cached tile is served as is.
+        assert_eq!(earthel.get_elevation(10.4, 10.4).await.unwrap(), 0);
+        assert!(earthel.refresh_tile(&tile).await.unwrap());
+        // Memoized points of the old tile are dropped too.
+        assert_eq!(earthel.get_elevation(10.5, 10.5).await.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn pins_tile_versions_in_strict_mode() {
+        let source = Arc::new(Versioned::default());
+        let set_version = |version| {
+            source
+                .version
+                .store(version, std::sync::atomic::Ordering::Relaxed)
+        };
+        set_version(1);
+        let cache = Arc::new(MemoryCache::new());
+        let earthel = EarthEl::builder()
+            .cache(Arc::clone(&cache))
+            .source(Arc::clone(&source))
+            .max_age(Duration::ZERO)
+            .strict(true)
+            .build();
+        let tile = TileId::new(10, 10);
+        let elevation = earthel.get_elevation_detailed(10.5, 10.5).await.unwrap();
+        assert_eq!(elevation.value, Some(1.0));
+        assert_eq!(elevation.version.as_deref(), Some("1"));
+        assert_eq!(earthel.tile_version(&tile).unwrap().as_deref(), Some("1"));
+
+        // The upstream tile changes: revalidations fail loudly.
+        set_version(2);
+        fn mismatch<T>(result: Result<T>) -> Option<(TileId, String, Option<String>)> {
+            match result {
+                Err(HgtError::VersionMismatch {
+                    tile,
+                    expected,
+                    actual,
+                }) => Some((tile, expected, actual)),
+                _ => None,
+            }
+        }
+        let expected = Some((tile, "1".to_owned(), Some("2".to_owned())));
+        assert_eq!(mismatch(earthel.get_elevation(10.5, 10.5).await), expected);
+        assert_eq!(mismatch(earthel.refresh_tile(&tile).await), expected);
+        assert_eq!(earthel.tile_version(&tile).unwrap().as_deref(), Some("1"));
+
+        // Later runs pin the versions recorded by earlier ones, cached or not.
+        let pinned = |cache| {
+            EarthEl::builder()
+                .cache(cache)
+                .source(Arc::clone(&source))
+                .pin_version(tile, "1")
+                .build()
+        };
+        assert_eq!(
+            pinned(Arc::clone(&cache))
+                .get_elevation(10.5, 10.5)
+                .await
+                .unwrap(),
+            1
+        );
+        let fresh = pinned(Arc::new(MemoryCache::new()));
+        assert_eq!(mismatch(fresh.get_elevation(10.5, 10.5).await), expected);
+        set_version(1);
+        assert_eq!(fresh.get_elevation(10.5, 10.5).await.unwrap(), 1);
+        let other = EarthEl::builder()
+            .cache(cache)
+            .pin_version(tile, "2")
+            .build();
+        assert!(mismatch(other.get_elevation(10.5, 10.5).await).is_some());
+    }
+
+    /// Answers after a minute.
+    struct Hanging;
+
+    impl TileSource for Hanging {
+        fn fetch<'a>(&'a self, tile: &'a TileId) -> source::SourceFuture<'a> {
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Err(HgtError::TileUnavailable {
+                    tile: *tile,
+                    reason: "timed out".into(),
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn bounds_queries_by_their_deadline() {
+        use futures::StreamExt;
+
+        let cache = Arc::new(MemoryCache::new());
+        cache
+            .store(&TileId::new(10, 10), &tile::synthetic_tile(1201, |_, _| 40))
+            .unwrap();
+        let deadline = Duration::from_millis(100);
+        let earthel = EarthEl::builder()
+            .cache(Arc::clone(&cache))
+            .source(Hanging)
+            .deadline(deadline)
+            .build();
+
+        let started = std::time::Instant::now();
+        let (point, detailed, profile) = tokio::join!(
+            earthel.get_elevation(20.5, 20.5),
+            earthel.get_elevation_detailed(20.5, 20.5),
+            earthel.profile(&[(10.5, 10.5), (20.5, 20.5)], 10_000.0),
+        );
+        let cached = TileId::new(10, 10);
+        let (grid, streamed, refreshed, resolution) = tokio::join!(
+            earthel.export_ascii_grid(
+                BoundingBox::new(10.5, 10.5, 11.5, 11.5),
+                0.1,
+                Vec::new(),
+                |_| {}
+            ),
+            earthel
+                .elevation_stream_from_iter([(10.5, 10.5), (20.5, 20.5)])
+                .collect::<Vec<_>>(),
+            earthel.refresh_tile(&cached),
+            earthel.resolution_at(20.5, 20.5),
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+        for error in [point.err(), detailed.err(), profile.err()] {
+            assert!(matches!(error, Some(HgtError::DeadlineExceeded(d)) if d == deadline));
+        }
+        assert_eq!(grid.unwrap_err().code(), "deadline_exceeded");
+        // A stalled tile fails the whole batch of streamed points.
+        assert_eq!(streamed.len(), 2);
+        for result in streamed {
+            assert!(matches!(result, Err(HgtError::DeadlineExceeded(_))));
+        }
+        assert_eq!(refreshed.unwrap_err().code(), "deadline_exceeded");
+        assert_eq!(resolution.unwrap_err().code(), "deadline_exceeded");
+        let raster = earthel
+            .extract_dem(BoundingBox::new(10.5, 10.5, 11.5, 11.5), 0.1)
+            .await;
+        assert_eq!(raster.unwrap_err().code(), "deadline_exceeded");
+        // Cached tiles answer in time, and cancelled downloads don't block
+        // later queries.
+        assert_eq!(earthel.get_elevation(10.5, 10.5).await.unwrap(), 40);
+        let started = std::time::Instant::now();
+        assert!(earthel.get_elevation(20.5, 20.5).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
 }
@@ -0,0 +1,133 @@
+//! Sampling under a memory budget, for instances configured with
+//! [`EarthElBuilder::low_memory`](crate::EarthElBuilder::low_memory).
+
+use crate::tile::{self, VOID};
+use crate::{EarthEl, HgtError, Result, TileId};
+use byteorder::{BigEndian, ByteOrder};
+
+impl EarthEl {
+    /// Fails with [`HgtError::AllocationLimit`] if `bytes` exceed the
+    /// configured bound on single allocations.
+    pub(crate) fn check_allocation(&self, bytes: u64) -> Result<()> {
+        match self.max_allocation {
+            Some(limit) if bytes > limit => Err(HgtError::AllocationLimit { bytes, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Interpolated elevations of normalized locations, read sample by sample
+    /// from the cache instead of decoding whole tiles. `None` over voids and
+    /// over tiles missing at the source, if the
+    /// [`MissingPolicy`](crate::MissingPolicy) tolerates them.
+    pub(crate) async fn interpolate_windowed(
+        &self,
+        points: &[(f64, f64)],
+    ) -> Result<Vec<Option<f64>>> {
+        let mut elevations = Vec::with_capacity(points.len());
+        for &(latitude, longitude) in points {
+            let id = TileId::containing(latitude, longitude);
+            let size = match self.ensure_tile(&id).await {
+                Ok(size) => size,
+                Err(HgtError::TileNotFound(_)) if self.missing.tolerates_missing_tiles() => {
+                    elevations.push(None);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let grid_size = tile::grid_size(size).ok_or(HgtError::InvalidResolution(size))?;
+            let (row, col) = tile::grid_position(id, grid_size, latitude, longitude);
+            let (r0, c0) = (row.floor() as usize, col.floor() as usize);
+            let r1 = (r0 + 1).min(grid_size - 1);
+            let rows = [
+                self.read_pair(&id, grid_size, r0, c0)?,
+                self.read_pair(&id, grid_size, r1, c0)?,
+            ];
+            elevations.push(tile::bilinear(grid_size, row, col, |r, c| {
+                let value = rows[usize::from(r != r0)][usize::from(c != c0)];
+                (value != VOID).then_some(value)
+            }));
+        }
+        Ok(elevations)
+    }
+
+    /// The sample at (`row`, `col`) of a cached tile and the one east of it,
+    /// or the same sample twice on the last column.
+    fn read_pair(&self, id: &TileId, grid_size: usize, row: usize, col: usize) -> Result<[i16; 2]> {
+        let offset = ((row * grid_size + col) * 2) as u64;
+        if col + 1 < grid_size {
+            let mut buf = [0; 4];
+            self.cache.read_at(id, offset, &mut buf)?;
+            Ok([BigEndian::read_i16(&buf), BigEndian::read_i16(&buf[2..])])
+        } else {
+            let mut buf = [0; 2];
+            self.cache.read_at(id, offset, &mut buf)?;
+            let value = BigEndian::read_i16(&buf);
+            Ok([value, value])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::{BoundingBox, TileCache};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn samples_within_the_budget() {
+        let cache = Arc::new(MemoryCache::new());
+        let data = synthetic_tile(1201, |row, col| (row + col) as i16);
+        cache.store(&TileId::new(45, 6), &data).unwrap();
+        let low_memory = EarthEl::builder()
+            .cache(Arc::clone(&cache))
+            .coverage(BoundingBox::new(45.0, 6.0, 46.0, 7.0))
+            .low_memory(64 * 1024)
+            // Configured later, but disabled all the same.
+            .point_cache(Default::default())
+            .profile_cache(16)
+            .overviews(32)
+            .build();
+        assert!(low_memory.point_cache.is_none() && low_memory.profile_cache.is_none());
+        assert!(low_memory.overviews.is_none());
+        let unbounded = EarthEl::builder()
+            .cache(Arc::clone(&cache))
+            .overviews(0)
+            .build();
+
+        // Profiles match those sampled from decoded tiles, including over the
+        // last row.
+        let line = [(45.2, 6.1), (45.0, 6.999), (45.9, 6.9999)];
+        let expected = unbounded.profile(&line, 500.0).await.unwrap();
+        let profile = low_memory.profile(&line, 500.0).await.unwrap();
+        assert_eq!(profile.samples.len(), expected.samples.len());
+        for (sample, expected) in profile.samples.iter().zip(&expected.samples) {
+            let (a, b) = (sample.elevation.unwrap(), expected.elevation.unwrap());
+            assert!((a - b).abs() < 1e-6, "{a} != {b}");
+        }
+        assert_eq!(low_memory.metrics().decoded_tiles, 0);
+
+        // Whole tiles and large rasters exceed the budget.
+        let error = low_memory
+            .extract_dem(BoundingBox::new(45.0, 6.0, 46.0, 7.0), 0.001)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            HgtError::AllocationLimit { limit: 65536, .. }
+        ));
+        assert_eq!(error.code(), "allocation_limit");
+        let error = low_memory
+            .profile(&[(45.0, 6.0), (46.0, 7.0)], 0.1)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, HgtError::AllocationLimit { .. }));
+        let coarse = low_memory
+            .extract_dem(BoundingBox::new(45.0, 6.0, 45.1, 6.1), 0.01)
+            .await;
+        assert!(
+            matches!(coarse, Err(HgtError::AllocationLimit { bytes, .. }) if bytes == data.len() as u64)
+        );
+    }
+}
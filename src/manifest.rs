@@ -0,0 +1,608 @@
+//! Manifests pinning the exact tiles of a dataset, for byte-reproducible
+//! elevation inputs.
+//!
+//! A manifest lists, for each tile, the URL to download it from and the
+//! SHA-256 of its uncompressed HGT content. An [`EarthEl`] configured with a
+//! manifest only downloads the pinned tiles, from the pinned URLs, and
+//! rejects any download that doesn't match its checksum.
+//!
+//! Manifests are JSON or TOML documents with a list of `tiles`, whose `tile`
+//! name may be left out when the URL ends with it:
+//!
+//! ```toml
+//! [[tiles]]
+//! tile = "N47E005"
+//! url = "https://dem.example.com/v3/N47E005.hgt.gz"
+//! sha256 = "4f2b…"
+//! size = 25934402
+//! ```
+//!
+//! The optional `size` is that of the uncompressed tile in bytes.
+//! [`EarthEl::region_manifest`] generates manifests of the tiles of a region,
+//! for instance for mirroring them with a download manager.
+
+use crate::cache::sha256_hex;
+use crate::download::{self, RetryPolicy};
+use crate::source::{decode_tile, SourceFuture};
+use crate::{BoundingBox, EarthEl, HgtError, Result, TileCache, TileId, TileSource};
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::sync::Arc;
+
+/// Where to download a pinned tile, and what it must hash to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PinnedTile {
+    /// Where to download the tile from, in any format [`decode_tile`] reads.
+    pub url: String,
+    /// SHA-256 of the uncompressed tile, in lowercase hex.
+    pub sha256: String,
+    /// Size of the uncompressed tile in bytes, if known.
+    pub size: Option<u64>,
+}
+
+/// The tiles of a dataset with their URLs and checksums, see the
+/// [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    tiles: BTreeMap<TileId, PinnedTile>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pins `tile` to the content of `url` hashing to `sha256`.
+    pub fn pin(mut self, tile: TileId, url: impl Into<String>, sha256: &str) -> Result<Self> {
+        let sha256 = parse_sha256(sha256)
+            .ok_or_else(|| invalid(format!("{sha256:?} is not a SHA-256 for {tile}")))?;
+        let url = url.into();
+        let size = None;
+        self.tiles.insert(tile, PinnedTile { url, sha256, size });
+        Ok(self)
+    }
+
+    /// Records the uncompressed size of a pinned tile.
+    pub fn with_size(mut self, tile: &TileId, size: u64) -> Result<Self> {
+        let pinned = self
+            .tiles
+            .get_mut(tile)
+            .ok_or_else(|| invalid(format!("{tile} is not pinned")))?;
+        pinned.size = Some(size);
+        Ok(self)
+    }
+
+    /// Parses a JSON manifest.
+    pub fn from_json(text: &str) -> Result<Self> {
+        let value = serde_json::from_str(text).map_err(|e| invalid(e.to_string()))?;
+        Self::from_value(&value)
+    }
+
+    /// Parses a TOML manifest.
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| invalid(e.message().to_owned()))?;
+        let value = serde_json::to_value(table).map_err(|e| invalid(e.to_string()))?;
+        Self::from_value(&value)
+    }
+
+    /// Reads a manifest, as TOML if the file name ends with `.toml` and JSON
+    /// otherwise.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            Self::from_toml(&text)
+        } else {
+            Self::from_json(&text)
+        }
+    }
+
+    /// The manifest as a JSON document.
+    pub fn to_json(&self) -> String {
+        self.to_value().to_string()
+    }
+
+    /// The manifest as a TOML document.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(&self.to_value()).expect("manifests have no null values")
+    }
+
+    /// Writes the manifest, as TOML if the file name ends with `.toml` and
+    /// JSON otherwise.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let text = if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            self.to_toml()
+        } else {
+            self.to_json()
+        };
+        Ok(std::fs::write(path, text)?)
+    }
+
+    fn to_value(&self) -> Value {
+        let tiles: Vec<Value> = self
+            .tiles
+            .iter()
+            .map(|(tile, pinned)| {
+                let mut entry = json!({
+                    "tile": tile.name(),
+                    "url": pinned.url,
+                    "sha256": pinned.sha256,
+                });
+                if let Some(size) = pinned.size {
+                    entry["size"] = size.into();
+                }
+                entry
+            })
+            .collect();
+        json!({ "tiles": tiles })
+    }
+
+    fn from_value(value: &Value) -> Result<Self> {
+        let entries = value
+            .get("tiles")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("expected a list of `tiles`".into()))?;
+        let mut manifest = Self::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let field = |name: &str| {
+                let value = entry.get(name).and_then(Value::as_str);
+                value.ok_or_else(|| invalid(format!("tile #{i} has no `{name}`")))
+            };
+            let url = field("url")?;
+            let tile = match entry.get("tile") {
+                Some(_) => TileId::from_name(field("tile")?),
+                None => tile_of_url(url),
+            }
+            .ok_or_else(|| invalid(format!("no tile name for tile #{i}")))?;
+            if manifest.tiles.contains_key(&tile) {
+                return Err(invalid(format!("{tile} is pinned twice")));
+            }
+            manifest = manifest.pin(tile, url, field("sha256")?)?;
+            if let Some(size) = entry.get("size") {
+                let size = size
+                    .as_u64()
+                    .ok_or_else(|| invalid(format!("tile #{i} has an invalid `size`")))?;
+                manifest = manifest.with_size(&tile, size)?;
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// The pinned tiles, in order.
+    pub fn tiles(&self) -> impl Iterator<Item = (&TileId, &PinnedTile)> {
+        self.tiles.iter()
+    }
+
+    pub fn get(&self, tile: &TileId) -> Option<&PinnedTile> {
+        self.tiles.get(tile)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// Hashes the pinned tiles found in `cache`.
+    pub fn verify(&self, cache: &dyn TileCache) -> Result<ManifestReport> {
+        let mut report = ManifestReport::default();
+        for (tile, pinned) in &self.tiles {
+            match cache.load(tile)? {
+                None => report.missing.push(*tile),
+                Some(data) if sha256_hex(&data) == pinned.sha256 => report.intact.push(*tile),
+                Some(_) => report.mismatched.push(*tile),
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// State of the tiles of a [`Manifest`] in a cache.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ManifestReport {
+    /// Tiles matching their checksum.
+    pub intact: Vec<TileId>,
+    /// Tiles not in the cache.
+    pub missing: Vec<TileId>,
+    /// Cached tiles whose content doesn't match their checksum.
+    pub mismatched: Vec<TileId>,
+}
+
+impl ManifestReport {
+    /// Whether every pinned tile is cached and intact.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+impl EarthEl {
+    /// An instance limited to the tiles pinned in the manifest at `path` (see
+    /// [`Manifest::load`]), caching them in the default cache.
+    ///
+    /// ```no_run
+    /// use earthel::EarthEl;
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let earthel = EarthEl::from_manifest("alps-2024.toml")?;
+    /// let report = earthel.fetch_manifest().await?;
+    /// println!("downloaded {} tiles", report.missing.len() + report.mismatched.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_manifest(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::builder().manifest(Manifest::load(path)?).build())
+    }
+
+    /// The manifest the instance is limited to, if any.
+    pub fn manifest(&self) -> Option<&Manifest> {
+        self.manifest.as_deref()
+    }
+
+    /// Checks the cached tiles against the manifest, without downloading
+    /// anything.
+    pub fn verify_manifest(&self) -> Result<ManifestReport> {
+        self.pinned()?.verify(self.cache.as_ref())
+    }
+
+    /// Downloads the pinned tiles that are missing from the cache or don't
+    /// match their checksum, and returns the state found before downloading.
+    /// Fails on the first tile that can't be downloaded or doesn't match.
+    pub async fn fetch_manifest(&self) -> Result<ManifestReport> {
+        let report = self.verify_manifest()?;
+        for tile in &report.missing {
+            let _lock = self.lock_tile(tile).await?;
+            if self.cache.size(tile)?.is_none() {
+                self.fetch_tile(tile, None).await?;
+            }
+        }
+        for tile in &report.mismatched {
+            let _lock = self.lock_tile(tile).await?;
+            // As a replacement, so that results derived from it are dropped.
+            self.fetch_tile(tile, Some(&Default::default())).await?;
+        }
+        Ok(report)
+    }
+
+    /// A manifest of the tiles intersecting `bbox`, pinned to the URLs the
+    /// tiles are downloaded from and to their current content, with their
+    /// sizes. Missing tiles are downloaded first; tiles outside of the
+    /// coverage or missing at the source (e.g. over the ocean) are left out.
+    ///
+    /// Fails with [`HgtError::InvalidParameter`] if the
+    /// [source](TileSource::url) doesn't download tiles from URLs.
+    ///
+    /// ```no_run
+    /// use earthel::{BoundingBox, EarthEl};
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let alps = BoundingBox::new(45.0, 5.0, 48.0, 11.0);
+    /// EarthEl::default().region_manifest(alps).await?.save("alps.toml")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn region_manifest(&self, bbox: BoundingBox) -> Result<Manifest> {
+        bbox.validate()?;
+        self.manifest_of(bbox.tiles()).await
+    }
+
+    /// Like [`region_manifest`](Self::region_manifest), for the tiles
+    /// overlapping a polygon, holes included.
+    #[cfg(feature = "geo")]
+    pub async fn polygon_manifest(&self, polygon: &geo_types::Polygon<f64>) -> Result<Manifest> {
+        self.manifest_of(crate::geometry::polygon_tiles(polygon))
+            .await
+    }
+
+    async fn manifest_of(&self, tiles: Vec<TileId>) -> Result<Manifest> {
+        let mut manifest = Manifest::new();
+        for tile in tiles {
+            if !self.coverage.intersects(&tile) {
+                continue;
+            }
+            match self.ensure_tile(&tile).await {
+                Ok(_) => {}
+                Err(HgtError::TileNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            }
+            let url = self.source.url(&tile).ok_or_else(|| {
+                HgtError::InvalidParameter(format!("the tile source has no URL for {tile}"))
+            })?;
+            let data = self
+                .cache
+                .load(&tile)?
+                .ok_or_else(|| crate::cache::not_cached(&tile))?;
+            manifest = manifest
+                .pin(tile, url, &sha256_hex(&data))?
+                .with_size(&tile, data.len() as u64)?;
+        }
+        Ok(manifest)
+    }
+
+    fn pinned(&self) -> Result<&Manifest> {
+        self.manifest()
+            .ok_or_else(|| HgtError::InvalidParameter("no manifest configured".into()))
+    }
+}
+
+/// Downloads tiles from the URLs of a manifest, checking their checksums.
+pub(crate) struct PinnedSource {
+    manifest: Arc<Manifest>,
+    retry: RetryPolicy,
+    client: reqwest::Client,
+}
+
+impl PinnedSource {
+    pub fn new(manifest: Arc<Manifest>) -> Self {
+        Self {
+            manifest,
+            retry: RetryPolicy::default(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl TileSource for PinnedSource {
+    fn url(&self, tile: &TileId) -> Option<String> {
+        Some(self.manifest.get(tile)?.url.clone())
+    }
+
+    fn fetch<'a>(&'a self, tile: &'a TileId) -> SourceFuture<'a> {
+        Box::pin(async move {
+            let pinned = self
+                .manifest
+                .get(tile)
+                .ok_or(HgtError::TileNotFound(*tile))?;
+            let response =
+                download::get(&self.client, &pinned.url, HeaderMap::new(), &self.retry).await?;
+            let data = decode_tile(&response.bytes().await?)?;
+            let actual = sha256_hex(&data);
+            if actual != pinned.sha256 {
+                return Err(HgtError::ChecksumMismatch {
+                    tile: *tile,
+                    expected: pinned.sha256.clone(),
+                    actual,
+                });
+            }
+            Ok(data)
+        })
+    }
+}
+
+fn invalid(reason: String) -> HgtError {
+    HgtError::InvalidManifest(reason)
+}
+
+/// Lowercase hex of a SHA-256 written in either case.
+fn parse_sha256(hash: &str) -> Option<String> {
+    (hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| hash.to_ascii_lowercase())
+}
+
+/// Tile named by the last segment of `url`, e.g. `…/N47E005.hgt.gz`.
+fn tile_of_url(url: &str) -> Option<TileId> {
+    let name = url.rsplit('/').next()?;
+    TileId::from_name(name.strip_suffix(".gz").unwrap_or(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::source::SkadiSource;
+    use crate::tile::synthetic_tile;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves `files` gzipped at `/<name>`, and 404 for everything else.
+    async fn serve(files: Vec<(&'static str, Vec<u8>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0; 1024];
+                let n = socket.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..n]);
+                let file = files
+                    .iter()
+                    .find(|(name, _)| request.starts_with(&format!("GET /{name} ")));
+                let Some((_, data)) = file else {
+                    let reply = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
+                    socket.write_all(reply.as_bytes()).await.unwrap();
+                    continue;
+                };
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data).unwrap();
+                let body = encoder.finish().unwrap();
+                let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(&body).await.unwrap();
+            }
+        });
+        base
+    }
+
+    #[test]
+    fn parses_json_and_toml_manifests() {
+        let hash = "AB".repeat(32);
+        let json = format!(
+            r#"{{"tiles": [
+                {{"url": "https://dem.example.com/N47E005.hgt.gz", "sha256": "{hash}"}},
+                {{"tile": "S01W179", "url": "https://dem.example.com/a", "sha256": "{hash}"}}
+            ]}}"#
+        );
+        let manifest = Manifest::from_json(&json).unwrap();
+        assert_eq!(manifest.len(), 2);
+        let pinned = manifest.get(&TileId::new(47, 5)).unwrap();
+        assert_eq!(pinned.sha256, "ab".repeat(32));
+        assert_eq!(
+            manifest.get(&TileId::new(-1, -179)).unwrap().url,
+            "https://dem.example.com/a"
+        );
+
+        let toml = format!(
+            "[[tiles]]\nurl = \"https://dem.example.com/N47E005.hgt.gz\"\nsha256 = \"{hash}\"\n\
+             [[tiles]]\ntile = \"S01W179\"\nurl = \"https://dem.example.com/a\"\nsha256 = \"{hash}\"\n"
+        );
+        assert_eq!(Manifest::from_toml(&toml).unwrap(), manifest);
+
+        let short = r#"{"tiles": [{"tile": "N47E005", "url": "u", "sha256": "abc"}]}"#;
+        assert!(matches!(
+            Manifest::from_json(short),
+            Err(HgtError::InvalidManifest(_))
+        ));
+        let unnamed = format!(r#"{{"tiles": [{{"url": "https://a/b", "sha256": "{hash}"}}]}}"#);
+        assert!(Manifest::from_json(&unnamed).is_err());
+        let twice =
+            format!("{toml}[[tiles]]\ntile = \"N47E005\"\nurl = \"u\"\nsha256 = \"{hash}\"\n");
+        assert!(Manifest::from_toml(&twice).is_err());
+    }
+
+    #[tokio::test]
+    async fn fetches_and_verifies_pinned_tiles() {
+        let good = synthetic_tile(1201, |_, _| 300);
+        let tampered = synthetic_tile(1201, |_, _| 301);
+        let base = serve(vec![("good.hgt", good.clone()), ("tampered.hgt", tampered)]).await;
+        let manifest = Manifest::new()
+            .pin(
+                TileId::new(47, 5),
+                format!("{base}/good.hgt"),
+                &sha256_hex(&good),
+            )
+            .unwrap()
+            .pin(
+                TileId::new(47, 6),
+                format!("{base}/tampered.hgt"),
+                &sha256_hex(&good),
+            )
+            .unwrap();
+        let earthel = EarthEl::builder()
+            .cache(MemoryCache::new())
+            .manifest(manifest)
+            .build();
+        assert_eq!(earthel.get_elevation(47.5, 5.5).await.unwrap(), 300);
+        assert!(matches!(
+            earthel.get_elevation(47.5, 6.5).await,
+            Err(HgtError::ChecksumMismatch { .. })
+        ));
+        // Unpinned tiles don't exist as far as the instance is concerned.
+        assert!(matches!(
+            earthel.get_elevation(47.5, 7.5).await,
+            Err(HgtError::TileNotFound(_))
+        ));
+
+        let report = earthel.verify_manifest().unwrap();
+        assert_eq!(report.intact, [TileId::new(47, 5)]);
+        assert_eq!(report.missing, [TileId::new(47, 6)]);
+        assert!(earthel.fetch_manifest().await.is_err());
+        // A cached tile modified behind the manifest's back is detected.
+        earthel.cache().store(&TileId::new(47, 5), &[0; 4]).unwrap();
+        assert_eq!(
+            earthel.verify_manifest().unwrap().mismatched,
+            [TileId::new(47, 5)]
+        );
+    }
+
+    #[tokio::test]
+    async fn replaces_mismatched_tiles() {
+        let tile = synthetic_tile(1201, |_, _| 12);
+        let base = serve(vec![("N10E010.hgt", tile.clone())]).await;
+        let json = format!(
+            r#"{{"tiles": [{{"url": "{base}/N10E010.hgt", "sha256": "{}"}}]}}"#,
+            sha256_hex(&tile)
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dataset.json");
+        std::fs::write(&path, json).unwrap();
+        let cache = Arc::new(MemoryCache::new());
+        cache
+            .store(&TileId::new(10, 10), &synthetic_tile(1201, |_, _| 99))
+            .unwrap();
+        let earthel = EarthEl::builder()
+            .cache(Arc::clone(&cache))
+            .manifest(Manifest::load(&path).unwrap())
+            .build();
+        assert_eq!(earthel.get_elevation(10.5, 10.5).await.unwrap(), 99);
+        let report = earthel.fetch_manifest().await.unwrap();
+        assert_eq!(report.mismatched, [TileId::new(10, 10)]);
+        assert!(earthel.verify_manifest().unwrap().is_complete());
+        assert_eq!(earthel.get_elevation(10.5, 10.5).await.unwrap(), 12);
+        assert!(EarthEl::default().verify_manifest().is_err());
+    }
+
+    #[tokio::test]
+    async fn generates_region_manifests() {
+        let tile = synthetic_tile(1201, |_, _| 12);
+        let base = serve(vec![("N10/N10E010.hgt.gz", tile.clone())]).await;
+        let earthel = EarthEl::builder()
+            .cache(MemoryCache::new())
+            .source(SkadiSource::with_base_url(&base))
+            .coverage(BoundingBox::new(-10.0, 0.0, 11.0, 11.5))
+            .build();
+        // N10E011 is missing at the source and N11E010 outside of the coverage.
+        let bbox = BoundingBox::new(10.5, 10.5, 11.5, 11.5);
+        let manifest = earthel.region_manifest(bbox).await.unwrap();
+        assert_eq!(manifest.len(), 1);
+        assert_eq!(
+            manifest.get(&TileId::new(10, 10)),
+            Some(&PinnedTile {
+                url: format!("{base}/N10/N10E010.hgt.gz"),
+                sha256: sha256_hex(&tile),
+                size: Some(tile.len() as u64),
+            })
+        );
+        assert_eq!(Manifest::from_json(&manifest.to_json()).unwrap(), manifest);
+        assert_eq!(Manifest::from_toml(&manifest.to_toml()).unwrap(), manifest);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("region.toml");
+        manifest.save(&path).unwrap();
+        assert_eq!(Manifest::load(&path).unwrap(), manifest);
+
+        #[cfg(feature = "geo")]
+        {
+            use geo_types::polygon;
+
+            let triangle = polygon![
+                (x: 10.2, y: 10.2), (x: 10.8, y: 10.2), (x: 10.5, y: 10.8), (x: 10.2, y: 10.2),
+            ];
+            let polygon_manifest = earthel.polygon_manifest(&triangle).await.unwrap();
+            assert_eq!(polygon_manifest, manifest);
+        }
+
+        // Sources without URLs can't be pinned.
+        let local = EarthEl::builder()
+            .cache(MemoryCache::new())
+            .source(crate::source::DirectorySource::new(dir.path()))
+            .build();
+        let cached = synthetic_tile(1201, |_, _| 1);
+        local.cache().store(&TileId::new(10, 10), &cached).unwrap();
+        assert!(matches!(
+            local
+                .region_manifest(BoundingBox::new(10.2, 10.2, 10.8, 10.8))
+                .await,
+            Err(HgtError::InvalidParameter(_))
+        ));
+    }
+}
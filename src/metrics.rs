@@ -0,0 +1,200 @@
+//! Counters describing what lookups cost, for services that need to know why
+//! they are slow.
+//!
+//! With the `tracing` feature, the same operations are also instrumented with
+//! `tracing` spans and events.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::{Instant, SystemTime};
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::{Instant, SystemTime};
+
+/// Emits a `tracing` debug event when the `tracing` feature is enabled.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+pub(crate) use trace;
+
+/// A snapshot of the activity of an [`EarthEl`](crate::EarthEl) since it was
+/// built, from [`EarthEl::metrics`](crate::EarthEl::metrics).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metrics {
+    /// Point lookups answered, successfully or not.
+    pub queries: u64,
+    /// Total time spent answering point lookups.
+    pub query_time: Duration,
+    /// Point lookups answered by the point cache.
+    pub point_cache_hits: u64,
+    pub point_cache_misses: u64,
+    /// Tile accesses served by the tile cache.
+    pub tile_cache_hits: u64,
+    /// Tile accesses that required a download.
+    pub tile_cache_misses: u64,
+    /// Tile cache misses answered by the download of another task, see
+    /// [`EarthEl`](crate::EarthEl#sharing).
+    pub coalesced_downloads: u64,
+    /// Tiles downloaded and stored, and their total size in bytes.
+    pub downloads: u64,
+    pub downloaded_bytes: u64,
+    pub failed_downloads: u64,
+    /// Cached tiles the source confirmed unchanged when revalidating them.
+    pub revalidated_tiles: u64,
+    /// Total time spent downloading and revalidating tiles, failed attempts
+    /// included.
+    pub download_time: Duration,
+    /// Tiles decoded in memory and the time it took.
+    pub decoded_tiles: u64,
+    pub decode_time: Duration,
+}
+
+impl Metrics {
+    /// Mean time of a point lookup, zero before the first one.
+    pub fn average_query_time(&self) -> Duration {
+        match u32::try_from(self.queries) {
+            Ok(0) => Duration::ZERO,
+            Ok(queries) => self.query_time / queries,
+            Err(_) => Duration::from_secs_f64(self.query_time.as_secs_f64() / self.queries as f64),
+        }
+    }
+
+    /// Share of tile accesses served by the cache, between 0 and 1.
+    pub fn tile_cache_hit_rate(&self) -> f64 {
+        let total = self.tile_cache_hits + self.tile_cache_misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.tile_cache_hits as f64 / total as f64
+        }
+    }
+}
+
+/// Live counters behind [`Metrics`].
+#[derive(Default)]
+pub(crate) struct Counters {
+    queries: AtomicU64,
+    query_nanos: AtomicU64,
+    point_cache_hits: AtomicU64,
+    point_cache_misses: AtomicU64,
+    tile_cache_hits: AtomicU64,
+    tile_cache_misses: AtomicU64,
+    coalesced_downloads: AtomicU64,
+    downloads: AtomicU64,
+    downloaded_bytes: AtomicU64,
+    failed_downloads: AtomicU64,
+    revalidated_tiles: AtomicU64,
+    download_nanos: AtomicU64,
+    decoded_tiles: AtomicU64,
+    decode_nanos: AtomicU64,
+}
+
+impl Counters {
+    pub fn query(&self, elapsed: Duration) {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        add_nanos(&self.query_nanos, elapsed);
+    }
+
+    pub fn point_cache(&self, hit: bool) {
+        let counter = if hit {
+            &self.point_cache_hits
+        } else {
+            &self.point_cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn tile_cache(&self, hit: bool) {
+        let counter = if hit {
+            &self.tile_cache_hits
+        } else {
+            &self.tile_cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn coalesced(&self) {
+        self.coalesced_downloads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a download attempt and the size of the tile if it succeeded.
+    pub fn download(&self, bytes: Option<u64>, elapsed: Duration) {
+        match bytes {
+            Some(bytes) => {
+                self.downloads.fetch_add(1, Ordering::Relaxed);
+                self.downloaded_bytes.fetch_add(bytes, Ordering::Relaxed);
+            }
+            None => {
+                self.failed_downloads.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        add_nanos(&self.download_nanos, elapsed);
+    }
+
+    /// Records a revalidation answered with "not modified".
+    pub fn revalidated(&self, elapsed: Duration) {
+        self.revalidated_tiles.fetch_add(1, Ordering::Relaxed);
+        add_nanos(&self.download_nanos, elapsed);
+    }
+
+    pub fn decode(&self, elapsed: Duration) {
+        self.decoded_tiles.fetch_add(1, Ordering::Relaxed);
+        add_nanos(&self.decode_nanos, elapsed);
+    }
+
+    pub fn snapshot(&self) -> Metrics {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Metrics {
+            queries: load(&self.queries),
+            query_time: Duration::from_nanos(load(&self.query_nanos)),
+            point_cache_hits: load(&self.point_cache_hits),
+            point_cache_misses: load(&self.point_cache_misses),
+            tile_cache_hits: load(&self.tile_cache_hits),
+            tile_cache_misses: load(&self.tile_cache_misses),
+            coalesced_downloads: load(&self.coalesced_downloads),
+            downloads: load(&self.downloads),
+            downloaded_bytes: load(&self.downloaded_bytes),
+            failed_downloads: load(&self.failed_downloads),
+            revalidated_tiles: load(&self.revalidated_tiles),
+            download_time: Duration::from_nanos(load(&self.download_nanos)),
+            decoded_tiles: load(&self.decoded_tiles),
+            decode_time: Duration::from_nanos(load(&self.decode_nanos)),
+        }
+    }
+}
+
+fn add_nanos(counter: &AtomicU64, elapsed: Duration) {
+    let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+    counter.fetch_add(nanos, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_counters() {
+        let counters = Counters::default();
+        assert_eq!(counters.snapshot(), Metrics::default());
+        assert_eq!(Metrics::default().average_query_time(), Duration::ZERO);
+        counters.query(Duration::from_millis(3));
+        counters.query(Duration::from_millis(1));
+        counters.tile_cache(true);
+        counters.tile_cache(true);
+        counters.tile_cache(true);
+        counters.tile_cache(false);
+        counters.download(Some(100), Duration::from_millis(50));
+        counters.download(None, Duration::from_millis(10));
+        let metrics = counters.snapshot();
+        assert_eq!(metrics.average_query_time(), Duration::from_millis(2));
+        assert_eq!(metrics.tile_cache_hit_rate(), 0.75);
+        assert_eq!((metrics.downloads, metrics.failed_downloads), (1, 1));
+        assert_eq!(metrics.downloaded_bytes, 100);
+        assert_eq!(metrics.download_time, Duration::from_millis(60));
+    }
+}
@@ -0,0 +1,77 @@
+//! What lookups return where there is no elevation data.
+
+use crate::{HgtError, Result};
+
+/// How queries treat locations without data: tiles that do not exist at the
+/// source (the open ocean, mostly) and void samples.
+///
+/// Point lookups apply the policy to both. With the default
+/// [`Error`](Self::Error) policy,
+/// [`EarthEl::get_elevation`](crate::EarthEl::get_elevation) fails with
+/// [`HgtError::NoData`] over voids rather than returning the raw `-32768`
+/// sample.
+///
+/// Profiles and rasters leave voids as gaps, unless the policy provides a
+/// value to fill them with. They fail on missing tiles with
+/// [`Error`](Self::Error), and treat them like voids with the other policies.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MissingPolicy {
+    /// Fails: missing tiles with [`HgtError::TileNotFound`], voids with
+    /// [`HgtError::NoData`].
+    #[default]
+    Error,
+    /// Reports an elevation of 0 m, as for the sea.
+    Zero,
+    /// Reports no elevation: `None` from
+    /// [`EarthEl::try_get_elevation`](crate::EarthEl::try_get_elevation).
+    None,
+    /// Reports the given elevation.
+    Value(f64),
+}
+
+impl MissingPolicy {
+    /// Whether tiles missing at the source are treated as having no data
+    /// rather than failing.
+    pub(crate) fn tolerates_missing_tiles(self) -> bool {
+        self != Self::Error
+    }
+
+    /// The elevation standing in for missing data, if any.
+    pub(crate) fn fill(self) -> Option<f64> {
+        match self {
+            Self::Zero => Some(0.0),
+            Self::Value(value) => Some(value),
+            Self::Error | Self::None => None,
+        }
+    }
+
+    /// The result of a point lookup without data.
+    pub(crate) fn resolve(self, latitude: f64, longitude: f64) -> Result<Option<f64>> {
+        match self {
+            Self::Error => Err(HgtError::NoData {
+                lat: latitude,
+                lon: longitude,
+            }),
+            _ => Ok(self.fill()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_missing_data() {
+        assert!(matches!(
+            MissingPolicy::Error.resolve(1.0, 2.0),
+            Err(HgtError::NoData { .. })
+        ));
+        assert_eq!(MissingPolicy::Zero.resolve(1.0, 2.0).unwrap(), Some(0.0));
+        assert_eq!(MissingPolicy::None.resolve(1.0, 2.0).unwrap(), None);
+        assert_eq!(MissingPolicy::Value(-1.5).fill(), Some(-1.5));
+        assert!(!MissingPolicy::Error.tolerates_missing_tiles());
+        assert!(MissingPolicy::None.tolerates_missing_tiles());
+    }
+}
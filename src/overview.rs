@@ -0,0 +1,156 @@
+//! Downsampled versions of tiles, for operations over large regions.
+//!
+//! A continent-scale raster with kilometric cells needs one sample in
+//! hundreds of a full-resolution tile. Overviews average blocks of 2, 4 or 8
+//! samples each way, so such rasters are both faster to sample (the decoded
+//! tiles held in memory are up to 64 times smaller) and free from the
+//! aliasing of interpolating a few scattered full-resolution samples.
+
+use crate::lru::LruCache;
+use crate::raster::Resampling;
+use crate::tile::{Tile, VOID};
+use crate::TileId;
+use std::sync::{Arc, Mutex};
+
+/// Coarsest overview level: blocks of 2^3 = 8 samples.
+const MAX_LEVEL: u32 = 3;
+
+/// Overview tiles built so far, keyed by tile and level.
+pub(crate) struct Overviews {
+    tiles: Mutex<LruCache<(TileId, u32), Arc<Tile>>>,
+}
+
+impl Overviews {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            tiles: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    pub fn get(&self, id: &TileId, level: u32) -> Option<Arc<Tile>> {
+        self.tiles.lock().unwrap().get(&(*id, level))
+    }
+
+    pub fn insert(&self, tile: Arc<Tile>, level: u32) {
+        self.tiles.lock().unwrap().insert((tile.id, level), tile);
+    }
+
+    pub fn clear(&self) {
+        self.tiles.lock().unwrap().clear();
+    }
+}
+
+/// Coarsest overview level of a tile of `grid_size` samples whose samples
+/// are not further apart than `cell_size` degrees. 0 is the tile itself.
+pub(crate) fn level_for(grid_size: usize, cell_size: f64) -> u32 {
+    let intervals = grid_size - 1;
+    let spacing = 1.0 / intervals as f64;
+    (1..=MAX_LEVEL)
+        .take_while(|&level| {
+            let factor = 1 << level;
+            intervals.is_multiple_of(factor) && spacing * factor as f64 <= cell_size
+        })
+        .last()
+        .unwrap_or(0)
+}
+
+/// Keeps every 2^`level`-th sample of a tile, each averaged with the samples
+/// up to half a block away unless `method` is [`Resampling::Nearest`]. Voids
+/// are left out of the averages.
+pub(crate) fn downsample(tile: &Tile, level: u32, method: Resampling) -> Tile {
+    let factor = 1 << level;
+    let half = if method == Resampling::Nearest {
+        0
+    } else {
+        factor / 2
+    };
+    let last = tile.grid_size - 1;
+    let grid_size = last / factor + 1;
+    let mut samples = Vec::with_capacity(grid_size * grid_size);
+    for row in 0..grid_size {
+        let rows = (row * factor).saturating_sub(half)..=(row * factor + half).min(last);
+        for col in 0..grid_size {
+            let cols = (col * factor).saturating_sub(half)..=(col * factor + half).min(last);
+            let (mut sum, mut count) = (0i64, 0i64);
+            for r in rows.clone() {
+                for c in cols.clone() {
+                    if let Some(value) = tile.at(r, c) {
+                        sum += i64::from(value);
+                        count += 1;
+                    }
+                }
+            }
+            samples.push(if count == 0 {
+                VOID
+            } else {
+                (sum as f64 / count as f64).round() as i16
+            });
+        }
+    }
+    Tile {
+        id: tile.id,
+        grid_size,
+        samples,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile::synthetic_tile;
+
+    #[test]
+    fn picks_levels_not_coarser_than_cells() {
+        let srtm3 = 3.0 / 3600.0;
+        assert_eq!(level_for(1201, srtm3), 0);
+        assert_eq!(level_for(1201, 2.5 * srtm3), 1);
+        assert_eq!(level_for(1201, 0.1), 3);
+        assert_eq!(level_for(3601, 4.0 / 3600.0), 2);
+        // 10 intervals only split in two.
+        assert_eq!(level_for(11, 1.0), 1);
+    }
+
+    #[test]
+    fn averages_blocks_of_samples() {
+        let data = synthetic_tile(9, |row, col| match (row, col) {
+            (0, 1) => VOID,
+            _ => (col * 10) as i16,
+        });
+        let tile = Tile::decode(TileId::new(0, 0), &data).unwrap();
+        let overview = downsample(&tile, 1, Resampling::Average);
+        assert_eq!(overview.grid_size, 5);
+        // Columns 1, 2 and 3 around column 2, averaging 20.
+        assert_eq!(overview.at(2, 1), Some(20));
+        // The corner averages 0, 0 and 10, the void being left out.
+        assert_eq!(overview.at(0, 0), Some(3));
+        assert_eq!(overview.interpolate(0.5, 0.5), Some(40.0));
+        let decimated = downsample(&tile, 1, Resampling::Nearest);
+        assert_eq!(decimated.at(2, 1), Some(20));
+        assert_eq!(decimated.at(0, 0), Some(0));
+    }
+
+    #[tokio::test]
+    async fn samples_coarse_rasters_from_overviews() {
+        use crate::cache::MemoryCache;
+        use crate::{BoundingBox, EarthEl, TileCache};
+
+        let cache = std::sync::Arc::new(MemoryCache::new());
+        // Columns alternating between 0 and 100 m.
+        let data = synthetic_tile(1201, |_, col| (col % 2 * 100) as i16);
+        cache.store(&TileId::new(0, 0), &data).unwrap();
+        let bbox = BoundingBox::new(0.0, 0.0, 0.5, 0.5);
+        let earthel = EarthEl::builder().cache(cache.clone()).build();
+        let dem = earthel.extract_dem(bbox, 0.1).await.unwrap();
+        // Blocks of 8 columns average out the stripes.
+        assert!(dem.data.iter().all(|&v| (v - 50.0).abs() < 10.0), "{dem:?}");
+
+        let exact = EarthEl::builder()
+            .cache(cache)
+            .resampling(Resampling::Bilinear)
+            .overviews(0)
+            .build();
+        let dem = exact.extract_dem(bbox, 0.1).await.unwrap();
+        // Cell centers fall on even columns.
+        assert!(dem.data.iter().all(|&v| v < 1.0), "{dem:?}");
+    }
+}
@@ -0,0 +1,79 @@
+use crate::lru::LruCache;
+use std::sync::Mutex;
+
+/// Settings of the point-level memoization cache.
+///
+/// Coordinates are rounded to `precision` decimal places before lookup, so
+/// every query falling in the same quantization cell shares one entry (5
+/// decimals is about one meter). At most `capacity` entries are kept, the
+/// least recently used ones being evicted first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct PointCacheConfig {
+    pub precision: u8,
+    pub capacity: usize,
+}
+
+impl Default for PointCacheConfig {
+    fn default() -> Self {
+        Self {
+            precision: 5,
+            capacity: 100_000,
+        }
+    }
+}
+
+pub(crate) struct PointCache {
+    scale: f64,
+    entries: Mutex<LruCache<(i64, i64), i16>>,
+}
+
+impl PointCache {
+    pub fn new(config: PointCacheConfig) -> Self {
+        Self {
+            scale: 10f64.powi(i32::from(config.precision.min(12))),
+            entries: Mutex::new(LruCache::new(config.capacity)),
+        }
+    }
+
+    fn key(&self, latitude: f64, longitude: f64) -> (i64, i64) {
+        (
+            (latitude * self.scale).round() as i64,
+            (longitude * self.scale).round() as i64,
+        )
+    }
+
+    pub fn get(&self, latitude: f64, longitude: f64) -> Option<i16> {
+        let key = self.key(latitude, longitude);
+        self.entries.lock().unwrap().get(&key)
+    }
+
+    pub fn insert(&self, latitude: f64, longitude: f64, elevation: i16) {
+        let key = self.key(latitude, longitude);
+        self.entries.lock().unwrap().insert(key, elevation);
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantizes_coordinates() {
+        let cache = PointCache::new(PointCacheConfig {
+            precision: 3,
+            capacity: 10,
+        });
+        cache.insert(47.05921, 5.71812, 259);
+        assert_eq!(cache.get(47.0594, 5.7178), Some(259));
+        assert_eq!(cache.get(47.0606, 5.7181), None);
+    }
+}
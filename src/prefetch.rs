@@ -0,0 +1,149 @@
+//! Bulk tile downloads and download progress reporting.
+
+use crate::{BoundingBox, EarthEl, HgtError, Result, TileId};
+use futures::stream::{self, StreamExt};
+
+/// Download activity, reported to the callback registered with
+/// [`EarthElBuilder::on_download`](crate::EarthElBuilder::on_download).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum DownloadEvent {
+    /// `tiles` missing tiles are about to be downloaded by a prefetch or a
+    /// batch of streamed lookups.
+    Queued { tiles: usize },
+    /// A tile of `bytes` bytes was downloaded and stored in the cache.
+    Downloaded { tile: TileId, bytes: u64 },
+    /// A tile could not be downloaded.
+    Failed { tile: TileId, reason: String },
+}
+
+/// Outcome of [`EarthEl::prefetch`].
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PrefetchReport {
+    /// Tiles that were already cached.
+    pub cached: usize,
+    /// Tiles downloaded by the prefetch.
+    pub downloaded: usize,
+    /// Total size of the downloaded tiles.
+    pub bytes: u64,
+    /// Tiles that could not be downloaded, with the reason.
+    pub failed: Vec<(TileId, HgtError)>,
+}
+
+impl EarthEl {
+    /// Downloads every tile intersecting `bbox` that is not cached yet, with
+    /// up to `concurrency` downloads in flight.
+    ///
+    /// Failing tiles don't stop the prefetch; they are listed in the report.
+    /// Progress is reported as the downloads go to the
+    /// [`on_download`](crate::EarthElBuilder::on_download) callback.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn prefetch(&self, bbox: BoundingBox, concurrency: usize) -> Result<PrefetchReport> {
+        bbox.validate()?;
+        let mut report = PrefetchReport::default();
+        let mut missing = Vec::new();
+        for tile in bbox.tiles() {
+            match self.cache.size(&tile)? {
+                Some(_) => report.cached += 1,
+                None => missing.push(tile),
+            }
+        }
+        if missing.is_empty() {
+            return Ok(report);
+        }
+        self.report(DownloadEvent::Queued {
+            tiles: missing.len(),
+        });
+        let mut outcomes = stream::iter(missing)
+            .map(|tile| async move { (tile, self.ensure_tile(&tile).await) })
+            .buffer_unordered(concurrency.max(1));
+        while let Some((tile, outcome)) = outcomes.next().await {
+            match outcome {
+                Ok(size) => {
+                    report.downloaded += 1;
+                    report.bytes += size;
+                }
+                Err(e) => report.failed.push((tile, e)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Sends an event to the download callback, if any.
+    pub(crate) fn report(&self, event: DownloadEvent) {
+        if let Some(callback) = &self.on_download {
+            callback(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::TileCache;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn reports_prefetch_progress() {
+        let cache = MemoryCache::new();
+        cache
+            .store(&TileId::new(10, 20), &synthetic_tile(1201, |_, _| 1))
+            .unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        // Only the cached tile is covered, so the others fail offline.
+        let earthel = EarthEl::builder()
+            .cache(cache)
+            .coverage(BoundingBox::new(10.0, 20.0, 11.0, 21.0))
+            .on_download(move |event| recorded.lock().unwrap().push(event.clone()))
+            .build();
+        let bbox = BoundingBox::new(10.5, 20.5, 11.5, 21.5);
+        let report = earthel.prefetch(bbox, 2).await.unwrap();
+        assert_eq!(report.cached, 1);
+        assert_eq!(report.downloaded, 0);
+        assert_eq!(report.failed.len(), 3);
+        assert!(report
+            .failed
+            .iter()
+            .any(|(tile, e)| *tile == TileId::new(11, 21)
+                && matches!(e, HgtError::OutsideCoverage(_))));
+        let events = events.lock().unwrap();
+        assert_eq!(events[0], DownloadEvent::Queued { tiles: 3 });
+        assert_eq!(events.len(), 4);
+        assert!(events[1..]
+            .iter()
+            .all(|e| matches!(e, DownloadEvent::Failed { .. })));
+    }
+
+    #[tokio::test]
+    async fn prefetches_across_the_antimeridian() {
+        let cache = MemoryCache::new();
+        for lon in [179, -180] {
+            cache
+                .store(&TileId::new(59, lon), &synthetic_tile(1201, |_, _| 1))
+                .unwrap();
+        }
+        let earthel = EarthEl::builder()
+            .cache(cache)
+            .coverage(BoundingBox::SRTM)
+            .build();
+        // The Bering Sea, half north of the SRTM coverage.
+        let bbox = BoundingBox::new(59.5, 179.5, 60.5, -179.5);
+        let report = earthel.prefetch(bbox, 2).await.unwrap();
+        assert_eq!(report.cached, 2);
+        let mut failed: Vec<_> = report
+            .failed
+            .iter()
+            .map(|(tile, e)| {
+                assert!(matches!(e, HgtError::OutsideCoverage(_)), "{e}");
+                *tile
+            })
+            .collect();
+        failed.sort();
+        assert_eq!(failed, [TileId::new(60, -180), TileId::new(60, 179)]);
+    }
+}
@@ -0,0 +1,337 @@
+//! Elevation profiles along polylines.
+
+use crate::geodesy;
+use crate::profile_cache::ProfileCache;
+use crate::tile::TileSet;
+use crate::{Body, EarthEl, HgtError, Result, TileId};
+use std::sync::Arc;
+
+/// A point of an elevation profile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProfileSample {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Distance along the line from its first point, in meters.
+    pub distance: f64,
+    /// Interpolated elevation in meters, `None` over data voids.
+    pub elevation: Option<f64>,
+}
+
+/// Elevation samples along a polyline.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Profile {
+    pub samples: Vec<ProfileSample>,
+}
+
+impl Profile {
+    /// Horizontal length of the profile in meters.
+    pub fn length(&self) -> f64 {
+        self.samples.last().map_or(0.0, |s| s.distance)
+    }
+
+    /// Length of the profile in meters accounting for the elevation changes
+    /// between samples. Steps touching a data void count horizontally only.
+    pub fn length_3d(&self) -> f64 {
+        self.samples
+            .windows(2)
+            .map(|pair| {
+                let run = pair[1].distance - pair[0].distance;
+                match (pair[0].elevation, pair[1].elevation) {
+                    (Some(from), Some(to)) => run.hypot(to - from),
+                    _ => run,
+                }
+            })
+            .sum()
+    }
+}
+
+impl EarthEl {
+    /// Samples the elevation along a polyline of `(latitude, longitude)`
+    /// points, at most `spacing` meters apart.
+    pub async fn profile(&self, points: &[(f64, f64)], spacing: f64) -> Result<Profile> {
+        let mut profiles = self.segment_profiles(&[points], spacing).await?;
+        Ok(profiles.pop().unwrap_or_default())
+    }
+
+    /// True length in meters of a polyline of `(latitude, longitude)` points,
+    /// with the terrain sampled every `spacing` meters. This is what walkers
+    /// actually cover, and is always at least the horizontal length.
+    pub async fn route_length_3d(&self, points: &[(f64, f64)], spacing: f64) -> Result<f64> {
+        Ok(self.profile(points, spacing).await?.length_3d())
+    }
+
+    /// Samples many short polylines (e.g. every road segment of a city) in one
+    /// go. Each tile is decoded once for the whole batch, and the result holds
+    /// one profile per input segment, in input order.
+    ///
+    /// With a [profile cache](crate::EarthElBuilder::profile_cache), segments
+    /// sampled before are served from memory.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(segments = segments.len()))
+    )]
+    pub async fn segment_profiles<S: AsRef<[(f64, f64)]>>(
+        &self,
+        segments: &[S],
+        spacing: f64,
+    ) -> Result<Vec<Profile>> {
+        self.within_deadline(self.sample_segments(segments, spacing))
+            .await
+    }
+
+    async fn sample_segments<S: AsRef<[(f64, f64)]>>(
+        &self,
+        segments: &[S],
+        spacing: f64,
+    ) -> Result<Vec<Profile>> {
+        if !(spacing.is_finite() && spacing > 0.0) {
+            return Err(HgtError::InvalidParameter(format!(
+                "spacing must be a positive number of meters, got {spacing}"
+            )));
+        }
+        let vertices = segments
+            .iter()
+            .map(|segment| {
+                segment
+                    .as_ref()
+                    .iter()
+                    .map(|&(lat, lon)| geodesy::normalize(lat, lon))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let keys: Vec<_> = vertices
+            .iter()
+            .map(|vertices| ProfileCache::key(vertices, spacing))
+            .collect();
+        let mut profiles: Vec<Option<Profile>> = keys
+            .iter()
+            .map(|key| {
+                let cached = self.profile_cache.as_ref()?.get(key)?;
+                Some(cached.as_ref().clone())
+            })
+            .collect();
+        // Bound the samples of the longest segment before densifying it.
+        if self.max_allocation.is_some() {
+            let longest = vertices
+                .iter()
+                .map(|vertices| {
+                    let length: f64 = vertices
+                        .windows(2)
+                        .map(|pair| self.body.distance(pair[0], pair[1]))
+                        .sum();
+                    length / spacing + vertices.len() as f64
+                })
+                .fold(0.0, f64::max);
+            self.check_allocation((longest * size_of::<ProfileSample>() as f64) as u64)?;
+        }
+        let dense = vertices
+            .iter()
+            .zip(&profiles)
+            .filter(|(_, cached)| cached.is_none())
+            .map(|(vertices, _)| {
+                self.body
+                    .densify(vertices, spacing)
+                    .into_iter()
+                    .map(|(lat, lon)| geodesy::normalize(lat, lon))
+                    .collect()
+            })
+            .collect::<Result<Vec<Vec<(f64, f64)>>>>()?;
+        let mut elevations = Vec::with_capacity(dense.len());
+        if self.max_allocation.is_some() {
+            // Low-memory profile: no tile is decoded.
+            for points in &dense {
+                elevations.push(self.interpolate_windowed(points).await?);
+            }
+        } else {
+            // Coarse spacings sample overviews rather than full-resolution tiles.
+            let cell_size = spacing / self.body.meters_per_degree();
+            let tiles = self
+                .load_tiles_covering(dense.iter().flatten(), cell_size)
+                .await?;
+            elevations.extend(dense.iter().map(|points| tiles.interpolate_many(points)));
+        }
+        let mut computed = dense
+            .iter()
+            .zip(elevations)
+            .map(|(points, elevations)| sample_points(points, elevations, self.body));
+        for (profile, key) in profiles.iter_mut().zip(keys) {
+            if profile.is_some() {
+                continue;
+            }
+            let mut fresh = computed.next().expect("one profile per uncached segment");
+            for sample in &mut fresh.samples {
+                match &mut sample.elevation {
+                    Some(elevation) => {
+                        *elevation = self.to_datum(sample.latitude, sample.longitude, *elevation)
+                    }
+                    None => sample.elevation = self.missing.fill(),
+                }
+            }
+            if let Some(cache) = &self.profile_cache {
+                cache.insert(key, Arc::new(fresh.clone()));
+            }
+            *profile = Some(fresh);
+        }
+        Ok(profiles.into_iter().flatten().collect())
+    }
+
+    /// Loads the tiles containing at least one of `points`, downsampled to
+    /// the coarsest overview with samples at most `cell_size` degrees apart.
+    pub(crate) async fn load_tiles_covering<'a>(
+        &self,
+        points: impl Iterator<Item = &'a (f64, f64)>,
+        cell_size: f64,
+    ) -> Result<TileSet> {
+        let mut needed: Vec<TileId> = points
+            .map(|&(lat, lon)| TileId::containing(lat, lon))
+            .collect();
+        needed.sort();
+        needed.dedup();
+        let mut tiles = TileSet::default();
+        for id in needed {
+            tiles.extend(self.load_overview_if_present(&id, cell_size).await?);
+        }
+        Ok(tiles)
+    }
+}
+
+fn sample_points(points: &[(f64, f64)], elevations: Vec<Option<f64>>, body: Body) -> Profile {
+    let mut distance = 0.0;
+    let samples = points
+        .iter()
+        .zip(elevations)
+        .enumerate()
+        .map(|(i, (&(latitude, longitude), elevation))| {
+            if i > 0 {
+                distance += body.distance(points[i - 1], (latitude, longitude));
+            }
+            ProfileSample {
+                latitude,
+                longitude,
+                distance,
+                elevation,
+            }
+        })
+        .collect();
+    Profile { samples }
+}
+
+/// Builds a profile sampled every 100 m with the given elevations.
+#[cfg(test)]
+pub(crate) fn synthetic_profile(elevations: impl IntoIterator<Item = f64>) -> Profile {
+    let samples = elevations
+        .into_iter()
+        .enumerate()
+        .map(|(i, elevation)| ProfileSample {
+            latitude: 0.0,
+            longitude: 0.0,
+            distance: i as f64 * 100.0,
+            elevation: Some(elevation),
+        })
+        .collect();
+    Profile { samples }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::{BoundingBox, TileCache};
+
+    #[tokio::test]
+    async fn groups_samples_per_segment() {
+        let cache = MemoryCache::new();
+        // Elevation grows by one meter per column, eastwards.
+        cache
+            .store(
+                &TileId::new(0, 0),
+                &synthetic_tile(1201, |_, col| col as i16),
+            )
+            .unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        let segments = vec![
+            vec![(0.5, 0.25), (0.5, 0.26)],
+            vec![(0.1, 0.5), (0.2, 0.5), (0.2, 0.75)],
+        ];
+        let profiles = earthel.segment_profiles(&segments, 500.0).await.unwrap();
+        assert_eq!(profiles.len(), 2);
+        let first = &profiles[0].samples;
+        assert_eq!(first.len(), 4);
+        assert!((first[0].elevation.unwrap() - 300.0).abs() < 1e-6);
+        assert!((first[3].elevation.unwrap() - 312.0).abs() < 1e-6);
+        assert!((profiles[0].length() - 1111.95).abs() < 1.0);
+        let last = profiles[1].samples.last().unwrap();
+        assert!((last.elevation.unwrap() - 900.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn measures_slope_aware_length() {
+        let cache = MemoryCache::new();
+        // A ramp climbing 10 meters per column, about 926 m apart at the equator.
+        cache
+            .store(
+                &TileId::new(0, 0),
+                &synthetic_tile(1201, |_, col| (col * 10) as i16),
+            )
+            .unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        let route = [(0.5, 0.1), (0.5, 0.2)];
+        let flat = earthel.profile(&route, 50.0).await.unwrap().length();
+        let real = earthel.route_length_3d(&route, 50.0).await.unwrap();
+        // 120 columns climb 1200 m over the horizontal distance.
+        assert!((real - flat.hypot(1200.0)).abs() < 1.0, "{flat} {real}");
+    }
+
+    #[tokio::test]
+    async fn measures_distances_on_other_bodies() {
+        let cache = Arc::new(MemoryCache::new());
+        cache
+            .store(&TileId::new(0, 0), &synthetic_tile(1201, |_, _| 0))
+            .unwrap();
+        let route = [(0.5, 0.1), (0.5, 0.2)];
+        let on = |body| {
+            EarthEl::builder()
+                .cache(Arc::clone(&cache))
+                .body(body)
+                .build()
+        };
+        let earth = on(Body::EARTH).profile(&route, 100.0).await.unwrap();
+        let mars = on(Body::MARS).profile(&route, 100.0).await.unwrap();
+        let ratio = mars.length() / earth.length();
+        assert!((ratio - Body::MARS.radius / Body::EARTH.radius).abs() < 1e-9);
+        assert!(mars.samples.len() < earth.samples.len());
+    }
+
+    #[tokio::test]
+    async fn serves_repeated_routes_from_the_profile_cache() {
+        let cache = Arc::new(MemoryCache::new());
+        let tile = TileId::new(0, 0);
+        cache
+            .store(&tile, &synthetic_tile(1201, |_, col| col as i16))
+            .unwrap();
+        let earthel = EarthEl::builder()
+            .cache(Arc::clone(&cache))
+            .coverage(BoundingBox::new(10.0, 10.0, 11.0, 11.0))
+            .profile_cache(10)
+            .build();
+        let route = [(0.5, 0.25), (0.5, 0.26)];
+        let profile = earthel.profile(&route, 500.0).await.unwrap();
+        // Without the tile, only cached routes can still be sampled.
+        cache.remove(&tile).unwrap();
+        assert_eq!(earthel.profile(&route, 500.0).await.unwrap(), profile);
+        let segments = [&route[..], &[(0.5, 0.3), (0.5, 0.31)]];
+        assert!(earthel.segment_profiles(&segments, 500.0).await.is_err());
+        assert!(earthel.profile(&route, 100.0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn empty_segments_yield_empty_profiles() {
+        let earthel = EarthEl::builder().cache(MemoryCache::new()).build();
+        let segments: Vec<Vec<(f64, f64)>> = vec![vec![]];
+        let profiles = earthel.segment_profiles(&segments, 10.0).await.unwrap();
+        assert_eq!(profiles, vec![Profile::default()]);
+    }
+}
@@ -0,0 +1,69 @@
+//! Memoization of computed profiles, for servers answering the same popular
+//! routes over and over.
+
+use crate::lru::LruCache;
+use crate::Profile;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+
+/// Digest identifying a profile request.
+type RouteKey = [u8; 32];
+
+pub(crate) struct ProfileCache {
+    entries: Mutex<LruCache<RouteKey, Arc<Profile>>>,
+}
+
+impl ProfileCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// SHA-256 of the vertices of a route and the sampling spacing, so that
+    /// keys stay small however long the route is.
+    pub fn key(vertices: &[(f64, f64)], spacing: f64) -> RouteKey {
+        let mut hasher = Sha256::new();
+        hasher.update(spacing.to_bits().to_le_bytes());
+        for &(lat, lon) in vertices {
+            hasher.update(lat.to_bits().to_le_bytes());
+            hasher.update(lon.to_bits().to_le_bytes());
+        }
+        hasher.finalize().into()
+    }
+
+    pub fn get(&self, key: &RouteKey) -> Option<Arc<Profile>> {
+        self.entries.lock().unwrap().get(key)
+    }
+
+    pub fn insert(&self, key: RouteKey, profile: Arc<Profile>) {
+        self.entries.lock().unwrap().insert(key, profile);
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_routes_by_geometry_and_spacing() {
+        let route = [(45.0, 6.0), (45.1, 6.1)];
+        let key = ProfileCache::key(&route, 30.0);
+        assert_eq!(key, ProfileCache::key(&route, 30.0));
+        assert_ne!(key, ProfileCache::key(&route, 31.0));
+        assert_ne!(key, ProfileCache::key(&[(45.1, 6.1), (45.0, 6.0)], 30.0));
+
+        let cache = ProfileCache::new(1);
+        cache.insert(key, Arc::new(Profile::default()));
+        assert!(cache.get(&key).is_some());
+        cache.insert(
+            ProfileCache::key(&route, 10.0),
+            Arc::new(Profile::default()),
+        );
+        assert!(cache.get(&key).is_none());
+    }
+}
@@ -0,0 +1,164 @@
+//! Elevations along with where they come from, for consumers mixing data
+//! sources of different quality.
+
+use crate::geodesy;
+use crate::metrics::Instant;
+use crate::source::Dataset;
+use crate::{EarthEl, HgtError, Resolution, Result, TileId};
+
+/// An elevation and its provenance, see [`EarthEl::get_elevation_detailed`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Elevation {
+    /// Elevation in meters, as returned by
+    /// [`try_get_elevation`](EarthEl::try_get_elevation).
+    pub value: Option<f64>,
+    /// Whether the value stands in for missing data, as configured with the
+    /// [`MissingPolicy`](crate::MissingPolicy).
+    pub filled: bool,
+    /// Dataset of the [source](crate::TileSource) of the tiles.
+    pub dataset: Dataset,
+    /// Tile covering the location.
+    pub tile: TileId,
+    /// Version (entity tag) of the tile as served by the source, if known,
+    /// see [`EarthEl::tile_version`].
+    pub version: Option<String>,
+    /// Sampling grid of the tile, `None` when the tile is missing at the
+    /// source.
+    pub resolution: Option<Resolution>,
+    /// Whether the value is interpolated between samples. Point lookups read
+    /// the sample covering the location, without interpolating.
+    pub interpolated: bool,
+    /// Nominal absolute vertical accuracy of the value in meters, as a 90%
+    /// linear error: that of the dataset, `None` for filled values.
+    pub vertical_accuracy: Option<f64>,
+}
+
+impl EarthEl {
+    /// Elevation of a location like
+    /// [`try_get_elevation`](Self::try_get_elevation), along with the dataset
+    /// and tile it was read from, the version and resolution of the tile and a
+    /// nominal accuracy.
+    ///
+    /// ```no_run
+    /// use earthel::EarthEl;
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let elevation = EarthEl::default().get_elevation_detailed(45.83, 6.86).await?;
+    /// println!(
+    ///     "{:?} m ± {:?} m from {} ({}, {:?} arc seconds)",
+    ///     elevation.value,
+    ///     elevation.vertical_accuracy,
+    ///     elevation.dataset.name,
+    ///     elevation.tile,
+    ///     elevation.resolution.map(|r| r.arc_seconds()),
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_elevation_detailed(&self, latitude: f64, longitude: f64) -> Result<Elevation> {
+        let started = Instant::now();
+        let result = self
+            .within_deadline(async {
+                let (latitude, longitude) = geodesy::normalize(latitude, longitude)?;
+                let resolution = match self.resolution_at(latitude, longitude).await {
+                    Ok(resolution) => Some(resolution),
+                    Err(HgtError::TileNotFound(_)) if self.missing.tolerates_missing_tiles() => {
+                        None
+                    }
+                    Err(e) => return Err(e),
+                };
+                let sample = match resolution {
+                    Some(_) => self.lookup(latitude, longitude).await?,
+                    None => None,
+                };
+                let tile = TileId::containing(latitude, longitude);
+                let version = match resolution {
+                    Some(_) => self.tile_version(&tile)?,
+                    None => None,
+                };
+                let dataset = self.source.dataset();
+                let (value, filled, vertical_accuracy) = match sample {
+                    Some(elevation) => {
+                        (Some(f64::from(elevation)), false, dataset.vertical_accuracy)
+                    }
+                    None => {
+                        let value = self.missing.resolve(latitude, longitude)?;
+                        (value, value.is_some(), None)
+                    }
+                };
+                Ok(Elevation {
+                    value,
+                    filled,
+                    dataset,
+                    tile,
+                    version,
+                    resolution,
+                    interpolated: false,
+                    vertical_accuracy,
+                })
+            })
+            .await;
+        self.counters.query(started.elapsed());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::source::SourceFuture;
+    use crate::tile::{synthetic_tile, VOID};
+    use crate::{MissingPolicy, TileCache, TileSource};
+
+    /// Has no tile but reports a dataset.
+    struct Lidar;
+
+    impl TileSource for Lidar {
+        fn fetch<'a>(&'a self, tile: &'a TileId) -> SourceFuture<'a> {
+            Box::pin(async move { Err(HgtError::TileNotFound(*tile)) })
+        }
+
+        fn dataset(&self) -> Dataset {
+            Dataset::new("LiDAR survey", Some(0.5))
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_provenance() {
+        let cache = MemoryCache::new();
+        let data = synthetic_tile(3601, |_, col| if col == 0 { VOID } else { 812 });
+        cache.store(&TileId::new(45, 6), &data).unwrap();
+        let earthel = EarthEl::builder()
+            .cache(cache)
+            .source(Lidar)
+            .missing(MissingPolicy::Zero)
+            .build();
+
+        let elevation = earthel.get_elevation_detailed(45.5, 366.5).await.unwrap();
+        assert_eq!(
+            elevation,
+            Elevation {
+                value: Some(812.0),
+                filled: false,
+                dataset: Dataset::new("LiDAR survey", Some(0.5)),
+                tile: TileId::new(45, 6),
+                version: None,
+                resolution: Some(Resolution { grid_size: 3601 }),
+                interpolated: false,
+                vertical_accuracy: Some(0.5),
+            }
+        );
+        assert_eq!(earthel.metrics().queries, 1);
+
+        // A void, then a tile missing at the source.
+        let void = earthel.get_elevation_detailed(45.5, 6.0).await.unwrap();
+        assert_eq!((void.value, void.filled), (Some(0.0), true));
+        assert_eq!(void.vertical_accuracy, None);
+        assert!(void.resolution.is_some());
+        let missing = earthel.get_elevation_detailed(0.5, 0.5).await.unwrap();
+        assert_eq!((missing.value, missing.filled), (Some(0.0), true));
+        assert_eq!(missing.resolution, None);
+    }
+}
@@ -0,0 +1,188 @@
+//! Quality checks of the tiles, for spotting corrupted downloads and source
+//! artifacts.
+//!
+//! Real terrain rarely has a single sample standing far above or below all
+//! of its neighbours: at SRTM spacings even the sharpest peaks span several
+//! samples. Such samples are reported as spikes and pits.
+
+use crate::{BoundingBox, EarthEl, HgtError, Result, TileId};
+
+/// Direction of an [`Anomaly`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AnomalyKind {
+    /// Higher than all its neighbours.
+    Spike,
+    /// Lower than all its neighbours.
+    Pit,
+}
+
+/// A tile sample deviating from all its neighbours.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Anomaly {
+    pub tile: TileId,
+    /// Position of the sample.
+    pub latitude: f64,
+    pub longitude: f64,
+    pub elevation: i16,
+    /// Mean elevation of the neighbours with data.
+    pub expected: f64,
+    pub kind: AnomalyKind,
+}
+
+impl Anomaly {
+    /// Difference between the sample and its neighbours, in meters.
+    pub fn deviation(&self) -> f64 {
+        f64::from(self.elevation) - self.expected
+    }
+}
+
+/// Outcome of [`EarthEl::scan_anomalies`].
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QaReport {
+    /// Samples inside the scanned region, voids included.
+    pub samples: usize,
+    /// Void samples.
+    pub voids: usize,
+    /// Spikes and pits, by tile then from north to south.
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl EarthEl {
+    /// Scans the samples of the tiles inside `bbox`, at full resolution, for
+    /// spikes and pits: samples at least `threshold` meters above or below
+    /// every one of their neighbours. Samples on the edges of a tile are
+    /// compared with their neighbours within the tile. Tiles outside the
+    /// [coverage](crate::EarthElBuilder::coverage) are skipped.
+    ///
+    /// ```no_run
+    /// use earthel::{BoundingBox, EarthEl};
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let report = EarthEl::default()
+    ///     .scan_anomalies(BoundingBox::new(45.0, 6.0, 46.0, 7.0), 150.0)
+    ///     .await?;
+    /// for anomaly in &report.anomalies {
+    ///     println!("{:?} of {:.0} m in {}", anomaly.kind, anomaly.deviation(), anomaly.tile);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn scan_anomalies(&self, bbox: BoundingBox, threshold: f64) -> Result<QaReport> {
+        bbox.validate()?;
+        if !(threshold.is_finite() && threshold > 0.0) {
+            return Err(HgtError::InvalidParameter(format!(
+                "anomaly threshold must be a positive number of meters, got {threshold}"
+            )));
+        }
+        self.within_deadline(self.scan_tiles(bbox, threshold)).await
+    }
+
+    async fn scan_tiles(&self, bbox: BoundingBox, threshold: f64) -> Result<QaReport> {
+        let mut report = QaReport::default();
+        for id in bbox.tiles() {
+            let tile = match self.load_tile_if_present(&id).await {
+                Ok(Some(tile)) => tile,
+                Ok(None) | Err(HgtError::OutsideCoverage(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            let last = tile.grid_size - 1;
+            let position = |row: usize, col: usize| {
+                (
+                    f64::from(id.lat + 1) - row as f64 / last as f64,
+                    f64::from(id.lon) + col as f64 / last as f64,
+                )
+            };
+            for row in 0..=last {
+                for col in 0..=last {
+                    let (latitude, longitude) = position(row, col);
+                    if !bbox.contains(latitude, longitude) {
+                        continue;
+                    }
+                    report.samples += 1;
+                    let Some(elevation) = tile.at(row, col) else {
+                        report.voids += 1;
+                        continue;
+                    };
+                    let value = f64::from(elevation);
+                    let (mut sum, mut count) = (0.0, 0usize);
+                    let (mut low, mut high) = (f64::INFINITY, f64::NEG_INFINITY);
+                    for r in row.saturating_sub(1)..=(row + 1).min(last) {
+                        for c in col.saturating_sub(1)..=(col + 1).min(last) {
+                            if (r, c) == (row, col) {
+                                continue;
+                            }
+                            if let Some(neighbour) = tile.at(r, c).map(f64::from) {
+                                sum += neighbour;
+                                count += 1;
+                                low = low.min(neighbour);
+                                high = high.max(neighbour);
+                            }
+                        }
+                    }
+                    // Too few neighbours to tell a spike from a slope.
+                    if count < 3 {
+                        continue;
+                    }
+                    let kind = if value - high >= threshold {
+                        AnomalyKind::Spike
+                    } else if low - value >= threshold {
+                        AnomalyKind::Pit
+                    } else {
+                        continue;
+                    };
+                    report.anomalies.push(Anomaly {
+                        tile: id,
+                        latitude,
+                        longitude,
+                        elevation,
+                        expected: sum / count as f64,
+                        kind,
+                    });
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::{synthetic_tile, VOID};
+    use crate::TileCache;
+
+    #[tokio::test]
+    async fn reports_spikes_and_pits() {
+        let cache = MemoryCache::new();
+        // A steep but smooth slope, with one spike, one pit and a void.
+        let data = synthetic_tile(1201, |row, col| match (row, col) {
+            (100, 100) => 9000,
+            (600, 900) => -500,
+            (700, 700) => VOID,
+            _ => (col * 20) as i16,
+        });
+        cache.store(&TileId::new(45, 6), &data).unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        let bbox = BoundingBox::new(45.0, 6.0, 46.0, 7.0);
+        let report = earthel.scan_anomalies(bbox, 100.0).await.unwrap();
+        assert_eq!(report.samples, 1201 * 1201);
+        assert_eq!(report.voids, 1);
+        assert_eq!(report.anomalies.len(), 2);
+        let spike = report.anomalies[0];
+        assert_eq!(spike.kind, AnomalyKind::Spike);
+        assert_eq!((spike.elevation, spike.expected), (9000, 2000.0));
+        assert!((spike.latitude - (46.0 - 100.0 / 1200.0)).abs() < 1e-9);
+        assert_eq!(report.anomalies[1].kind, AnomalyKind::Pit);
+        assert_eq!(report.anomalies[1].deviation(), -18500.0);
+
+        // Scanning part of the tile skips the spike.
+        let south = BoundingBox::new(45.0, 6.0, 45.5, 7.0);
+        let report = earthel.scan_anomalies(south, 100.0).await.unwrap();
+        assert_eq!(report.anomalies.len(), 1);
+        assert!(earthel.scan_anomalies(south, 0.0).await.is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use super::DemRaster;
+use crate::Result;
+use std::io::Write;
+
+/// Value written for cells without data.
+const NODATA: f32 = -9999.0;
+
+/// Writes an ESRI ASCII grid row by row.
+pub(crate) struct AsciiGridWriter<W> {
+    writer: W,
+    width: usize,
+}
+
+impl<W: Write> AsciiGridWriter<W> {
+    /// Writes the header of a `width` x `height` grid of `cell_size` degrees
+    /// whose south-west corner is at (`south`, `west`).
+    pub fn new(
+        mut writer: W,
+        width: usize,
+        height: usize,
+        cell_size: f64,
+        south: f64,
+        west: f64,
+    ) -> Result<Self> {
+        writeln!(writer, "ncols {width}")?;
+        writeln!(writer, "nrows {height}")?;
+        writeln!(writer, "xllcorner {west}")?;
+        writeln!(writer, "yllcorner {south}")?;
+        writeln!(writer, "cellsize {cell_size}")?;
+        writeln!(writer, "NODATA_value {NODATA}")?;
+        Ok(Self { writer, width })
+    }
+
+    /// Appends whole rows of cells, north to south.
+    pub fn write_rows(&mut self, data: &[f32]) -> Result<()> {
+        for row in data.chunks(self.width.max(1)) {
+            let line: Vec<String> = row
+                .iter()
+                .map(|v| if v.is_nan() { NODATA } else { *v }.to_string())
+                .collect();
+            writeln!(self.writer, "{}", line.join(" "))?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl DemRaster {
+    /// Writes the raster in the ESRI ASCII grid format (`.asc`).
+    pub fn write_ascii_grid(&self, writer: impl Write) -> Result<()> {
+        let mut grid = AsciiGridWriter::new(
+            writer,
+            self.width,
+            self.height,
+            self.cell_size,
+            self.bbox.south,
+            self.bbox.west,
+        )?;
+        grid.write_rows(&self.data)?;
+        grid.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_header_and_rows() {
+        let mut raster = DemRaster::filled(47.0, 5.0, 0.5, 2, 2, 12.5);
+        raster.data[3] = f32::NAN;
+        let mut out = Vec::new();
+        raster.write_ascii_grid(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ncols 2\nnrows 2\nxllcorner 5\nyllcorner 46\ncellsize 0.5\n\
+             NODATA_value -9999\n12.5 12.5\n12.5 -9999\n"
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! Contour lines traced with marching squares.
+//!
+//! Elevations are taken at cell centers, and every square of four neighbouring
+//! centers is crossed by at most two segments per level. Squares with a cell
+//! without data are skipped, so lines stop at gaps in the data.
+
+use super::DemRaster;
+use crate::{BoundingBox, EarthEl, HgtError, Result};
+use std::collections::HashMap;
+
+#[cfg(feature = "geojson")]
+use serde_json::{json, Value};
+
+/// A line of constant elevation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContourLine {
+    /// Elevation of the line in meters.
+    pub elevation: f64,
+    /// Vertices as `(latitude, longitude)`. Closed lines end with their
+    /// first vertex.
+    pub points: Vec<(f64, f64)>,
+}
+
+impl ContourLine {
+    /// Whether the line is a ring rather than leaving the raster or a gap.
+    pub fn is_closed(&self) -> bool {
+        self.points.len() > 2 && self.points.first() == self.points.last()
+    }
+
+    /// The line as a GeoJSON `Feature` with a `LineString` geometry and an
+    /// `elevation` property.
+    #[cfg(feature = "geojson")]
+    pub fn to_geojson(&self) -> Value {
+        let coordinates: Vec<[f64; 2]> = self.points.iter().map(|&(lat, lon)| [lon, lat]).collect();
+        json!({
+            "type": "Feature",
+            "geometry": { "type": "LineString", "coordinates": coordinates },
+            "properties": { "elevation": self.elevation },
+        })
+    }
+}
+
+/// Contour lines as a GeoJSON `FeatureCollection` of
+/// [`ContourLine::to_geojson`] features.
+#[cfg(feature = "geojson")]
+pub fn contours_to_geojson(lines: &[ContourLine]) -> Value {
+    json!({
+        "type": "FeatureCollection",
+        "features": lines.iter().map(ContourLine::to_geojson).collect::<Vec<_>>(),
+    })
+}
+
+/// An edge between two neighbouring cell centers, identified by the center
+/// it starts from and whether it goes east (`false`) or south (`true`).
+type Edge = (usize, usize, bool);
+
+impl DemRaster {
+    /// Traces contour lines every `interval` meters, at the multiples of
+    /// `interval` between the lowest and highest elevations. Lines are
+    /// ordered by elevation.
+    pub fn contours(&self, interval: f64) -> Result<Vec<ContourLine>> {
+        if !(interval.is_finite() && interval > 0.0) {
+            return Err(HgtError::InvalidParameter(format!(
+                "contour interval must be positive, got {interval}"
+            )));
+        }
+        let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+        for &value in self.data.iter().filter(|v| !v.is_nan()) {
+            min = min.min(value);
+            max = max.max(value);
+        }
+        let mut lines = Vec::new();
+        if min > max {
+            return Ok(lines);
+        }
+        let first = (f64::from(min) / interval).ceil() as i64;
+        let last = (f64::from(max) / interval).floor() as i64;
+        for step in first..=last {
+            lines.extend(self.contours_at(step as f64 * interval));
+        }
+        Ok(lines)
+    }
+
+    fn contours_at(&self, level: f64) -> Vec<ContourLine> {
+        let mut segments = Vec::new();
+        for row in 0..self.height.saturating_sub(1) {
+            for col in 0..self.width.saturating_sub(1) {
+                self.march(row, col, level, &mut segments);
+            }
+        }
+        join_segments(&segments)
+            .into_iter()
+            .map(|edges| ContourLine {
+                elevation: level,
+                points: edges.iter().map(|&e| self.crossing(e, level)).collect(),
+            })
+            .collect()
+    }
+
+    /// Adds the segments crossing the square whose north-west corner is the
+    /// center of cell (`row`, `col`).
+    fn march(&self, row: usize, col: usize, level: f64, segments: &mut Vec<(Edge, Edge)>) {
+        let corners = [
+            self.get(row, col),
+            self.get(row, col + 1),
+            self.get(row + 1, col + 1),
+            self.get(row + 1, col),
+        ];
+        let Some(values) = corners
+            .into_iter()
+            .map(|v| v.map(f64::from))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+        let above = values.iter().map(|&v| v >= level);
+        let case = above
+            .enumerate()
+            .fold(0, |case, (i, above)| case | (usize::from(above) << i));
+        let (north, east) = ((row, col, false), (row, col + 1, true));
+        let (south, west) = ((row + 1, col, false), (row, col, true));
+        let mut push = |a, b| segments.push((a, b));
+        match case {
+            0 | 15 => {}
+            1 | 14 => push(west, north),
+            2 | 13 => push(north, east),
+            3 | 12 => push(west, east),
+            4 | 11 => push(east, south),
+            6 | 9 => push(north, south),
+            7 | 8 => push(west, south),
+            // Saddles: the average of the corners decides whether the high
+            // corners are connected through the middle of the square.
+            5 | 10 => {
+                let center_above = values.iter().sum::<f64>() / 4.0 >= level;
+                if (case == 5) == center_above {
+                    push(west, south);
+                    push(north, east);
+                } else {
+                    push(west, north);
+                    push(east, south);
+                }
+            }
+            _ => unreachable!("four corners make 16 cases"),
+        }
+    }
+
+    /// Where `level` crosses an edge, interpolated between its two ends.
+    fn crossing(&self, (row, col, south): Edge, level: f64) -> (f64, f64) {
+        let (end_row, end_col) = if south {
+            (row + 1, col)
+        } else {
+            (row, col + 1)
+        };
+        let a = f64::from(self.data[row * self.width + col]);
+        let b = f64::from(self.data[end_row * self.width + end_col]);
+        let t = if a == b { 0.5 } else { (level - a) / (b - a) };
+        let (lat0, lon0) = self.cell_center(row, col);
+        let (lat1, lon1) = self.cell_center(end_row, end_col);
+        (lat0 + t * (lat1 - lat0), lon0 + t * (lon1 - lon0))
+    }
+}
+
+/// Chains segments sharing edges into lines. Each edge belongs to at most
+/// two squares, so lines never branch.
+fn join_segments(segments: &[(Edge, Edge)]) -> Vec<Vec<Edge>> {
+    let mut ends: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (i, &(a, b)) in segments.iter().enumerate() {
+        ends.entry(a).or_default().push(i);
+        ends.entry(b).or_default().push(i);
+    }
+    let mut used = vec![false; segments.len()];
+    let mut lines = Vec::new();
+    // Open lines first, from their loose ends, then the remaining rings.
+    let mut starts: Vec<usize> = (0..segments.len())
+        .filter(|&i| ends[&segments[i].0].len() == 1 || ends[&segments[i].1].len() == 1)
+        .collect();
+    starts.extend(0..segments.len());
+    for start in starts {
+        if used[start] {
+            continue;
+        }
+        let (a, b) = segments[start];
+        let first = if ends[&b].len() == 1 { b } else { a };
+        let mut line = vec![first];
+        let (mut segment, mut edge) = (start, first);
+        loop {
+            used[segment] = true;
+            let (a, b) = segments[segment];
+            edge = if a == edge { b } else { a };
+            line.push(edge);
+            match ends[&edge].iter().find(|&&next| !used[next]) {
+                Some(&next) => segment = next,
+                None => break,
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+impl EarthEl {
+    /// Traces contour lines every `interval` meters in `bbox`, sampled with
+    /// cells of `target_resolution` degrees. See [`DemRaster::contours`].
+    pub async fn contours(
+        &self,
+        bbox: BoundingBox,
+        target_resolution: f64,
+        interval: f64,
+    ) -> Result<Vec<ContourLine>> {
+        self.extract_dem(bbox, target_resolution)
+            .await?
+            .contours(interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::{TileCache, TileId};
+
+    /// A 7x7 cone peaking at 65 m in the middle, 10 m lower per cell outwards.
+    fn cone() -> DemRaster {
+        let mut dem = DemRaster::filled(1.0, 0.0, 0.1, 7, 7, 0.0);
+        for (i, value) in dem.data.iter_mut().enumerate() {
+            let (row, col) = ((i / 7) as f32 - 3.0, (i % 7) as f32 - 3.0);
+            *value = 65.0 - 10.0 * row.abs().max(col.abs());
+        }
+        dem
+    }
+
+    #[test]
+    fn traces_closed_rings_around_peaks() {
+        let lines = cone().contours(20.0).unwrap();
+        let elevations: Vec<f64> = lines.iter().map(|l| l.elevation).collect();
+        assert_eq!(elevations, [40.0, 60.0]);
+        assert!(lines.iter().all(ContourLine::is_closed));
+        // The 60 m ring is a diamond halfway between the summit, at
+        // (0.65, 0.35), and its neighbours.
+        let ring = &lines[1];
+        assert_eq!(ring.points.len(), 5);
+        for &(lat, lon) in &ring.points {
+            let (dlat, dlon) = ((lat - 0.65).abs(), (lon - 0.35).abs());
+            assert!((dlat + dlon - 0.05).abs() < 1e-9, "{lat} {lon}");
+        }
+        assert!(lines[0].points.len() > ring.points.len());
+    }
+
+    #[test]
+    fn stops_lines_at_edges_and_gaps() {
+        // Terrain rising eastwards: each contour crosses the raster.
+        let mut dem = DemRaster::filled(1.0, 0.0, 0.1, 5, 6, 0.0);
+        for (i, value) in dem.data.iter_mut().enumerate() {
+            *value = (i % 5) as f32 * 10.0;
+        }
+        let lines = dem.contours(15.0).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|l| !l.is_closed() && l.points.len() == 6));
+        assert!(lines[0]
+            .points
+            .iter()
+            .all(|&(_, lon)| (lon - 0.2).abs() < 1e-9));
+
+        dem.data[2 * 5 + 1] = f32::NAN;
+        let lines = dem.contours(15.0).unwrap();
+        assert_eq!(lines.iter().filter(|l| l.elevation == 15.0).count(), 2);
+        assert!(dem.contours(0.0).is_err());
+    }
+
+    #[cfg(feature = "geojson")]
+    #[test]
+    fn exports_geojson_features() {
+        let lines = cone().contours(60.0).unwrap();
+        let collection = contours_to_geojson(&lines);
+        let feature = &collection["features"][0];
+        assert_eq!(feature["properties"]["elevation"], 60.0);
+        assert_eq!(feature["geometry"]["type"], "LineString");
+        assert_eq!(
+            feature["geometry"]["coordinates"][0][1],
+            lines[0].points[0].0
+        );
+    }
+
+    #[tokio::test]
+    async fn traces_contours_of_tiles() {
+        let cache = MemoryCache::new();
+        cache
+            .store(
+                &TileId::new(0, 0),
+                &synthetic_tile(1201, |_, col| (col / 10) as i16),
+            )
+            .unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        let bbox = BoundingBox::new(0.2, 0.2, 0.3, 0.3);
+        let lines = earthel.contours(bbox, 0.01, 10.0).await.unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].elevation, 30.0);
+    }
+}
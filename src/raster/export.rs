@@ -0,0 +1,266 @@
+//! Exports of extents too large to hold as a [`DemRaster`](super::DemRaster).
+//!
+//! The grid is sampled in bands of rows that never cross a tile boundary, and
+//! every band is written out before the next one is sampled. Memory use is
+//! bounded by two rows of tiles across the extent, for bands whose cells
+//! reach across a tile boundary, plus one band.
+//!
+//! Compressed formats, GeoTIFF and PNG, compress every band as it is written.
+
+use super::ascii::AsciiGridWriter;
+use super::geotiff::{GeoTiffOptions, GeoTiffWriter};
+use super::png::PngWriter;
+use super::{check_resolution, fill_from_tiles, grid_dimensions, DemRaster};
+use crate::tile::TileSet;
+use crate::{BoundingBox, EarthEl, Result};
+use std::io::{Seek, Write};
+
+/// Largest size of a band of rows, in bytes of `f32` cells.
+const BAND_BYTES: usize = 4 * 1024 * 1024;
+
+/// How far an export is, reported after every band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExportProgress {
+    /// Rows written so far.
+    pub rows_written: usize,
+    /// Rows of the whole grid.
+    pub rows: usize,
+}
+
+impl ExportProgress {
+    /// Share of the rows written, between 0 and 1.
+    pub fn fraction(&self) -> f64 {
+        if self.rows == 0 {
+            1.0
+        } else {
+            self.rows_written as f64 / self.rows as f64
+        }
+    }
+}
+
+/// Output format being streamed to.
+trait RowSink {
+    fn write_rows(&mut self, data: &[f32]) -> Result<()>;
+}
+
+impl<W: Write + Seek> RowSink for GeoTiffWriter<W> {
+    fn write_rows(&mut self, data: &[f32]) -> Result<()> {
+        GeoTiffWriter::write_rows(self, data)
+    }
+}
+
+impl<W: Write> RowSink for AsciiGridWriter<W> {
+    fn write_rows(&mut self, data: &[f32]) -> Result<()> {
+        AsciiGridWriter::write_rows(self, data)
+    }
+}
+
+impl<W: Write> RowSink for PngWriter<W> {
+    fn write_rows(&mut self, data: &[f32]) -> Result<()> {
+        PngWriter::write_rows(self, data)
+    }
+}
+
+impl EarthEl {
+    /// Writes the terrain inside `bbox` as a GeoTIFF with cells of
+    /// `target_resolution` degrees, as [`extract_dem`](Self::extract_dem)
+    /// followed by [`DemRaster::write_geotiff_with`] would, without holding
+    /// the whole grid in memory. `progress` is called after every band of rows.
+    ///
+    /// TIFF files are limited to 4 GiB, about 26 by 26 degrees of 3
+    /// arc-second cells uncompressed: exports growing past it fail with
+    /// [`HgtError::InvalidParameter`](crate::HgtError::InvalidParameter) as
+    /// soon as they reach it.
+    ///
+    /// ```no_run
+    /// use earthel::raster::GeoTiffOptions;
+    /// use earthel::{BoundingBox, EarthEl};
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let alps = BoundingBox::new(43.5, 5.0, 48.5, 16.5);
+    /// let file = std::io::BufWriter::new(std::fs::File::create("alps.tif")?);
+    /// let options = GeoTiffOptions::cloud_optimized();
+    /// EarthEl::default()
+    ///     .export_geotiff(alps, 1.0 / 3600.0, options, file, |p| {
+    ///         println!("{:.0}%", p.fraction() * 100.0)
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn export_geotiff<W: Write + Seek>(
+        &self,
+        bbox: BoundingBox,
+        target_resolution: f64,
+        options: GeoTiffOptions,
+        writer: W,
+        progress: impl FnMut(ExportProgress),
+    ) -> Result<()> {
+        bbox.validate()?;
+        check_resolution(target_resolution)?;
+        let (width, height) = grid_dimensions(&bbox, target_resolution);
+        let mut tiff = GeoTiffWriter::new(
+            writer,
+            options,
+            width,
+            height,
+            target_resolution,
+            bbox.north,
+            bbox.west,
+        )?;
+        self.within_deadline(self.stream_bands(bbox, target_resolution, &mut tiff, progress))
+            .await?;
+        tiff.finish()?;
+        Ok(())
+    }
+
+    /// Writes the terrain inside `bbox` as an ESRI ASCII grid, band by band
+    /// like [`export_geotiff`](Self::export_geotiff).
+    pub async fn export_ascii_grid<W: Write>(
+        &self,
+        bbox: BoundingBox,
+        target_resolution: f64,
+        writer: W,
+        progress: impl FnMut(ExportProgress),
+    ) -> Result<()> {
+        bbox.validate()?;
+        check_resolution(target_resolution)?;
+        let (width, height) = grid_dimensions(&bbox, target_resolution);
+        let south = bbox.north - height as f64 * target_resolution;
+        let mut grid =
+            AsciiGridWriter::new(writer, width, height, target_resolution, south, bbox.west)?;
+        self.within_deadline(self.stream_bands(bbox, target_resolution, &mut grid, progress))
+            .await?;
+        grid.finish()?;
+        Ok(())
+    }
+
+    /// Writes the terrain inside `bbox` as a 16-bit grayscale PNG, band by
+    /// band like [`export_geotiff`](Self::export_geotiff), encoded as
+    /// [`DemRaster::write_png`] does. PNG carries no georeferencing: the
+    /// image covers whole cells of `target_resolution` degrees from the north
+    /// west corner of `bbox`.
+    pub async fn export_png<W: Write>(
+        &self,
+        bbox: BoundingBox,
+        target_resolution: f64,
+        writer: W,
+        progress: impl FnMut(ExportProgress),
+    ) -> Result<()> {
+        bbox.validate()?;
+        check_resolution(target_resolution)?;
+        let (width, height) = grid_dimensions(&bbox, target_resolution);
+        let mut png = PngWriter::new(writer, width, height)?;
+        self.within_deadline(self.stream_bands(bbox, target_resolution, &mut png, progress))
+            .await?;
+        png.finish()?;
+        Ok(())
+    }
+
+    /// Samples the grid covering `bbox` band by band into `sink`. Tiles are
+    /// kept while consecutive bands fall in the same rows of tiles.
+    async fn stream_bands(
+        &self,
+        bbox: BoundingBox,
+        cell_size: f64,
+        sink: &mut impl RowSink,
+        mut progress: impl FnMut(ExportProgress),
+    ) -> Result<()> {
+        let (width, height) = grid_dimensions(&bbox, cell_size);
+        let east = bbox.west + width as f64 * cell_size;
+        let max_rows = (BAND_BYTES / (width * 4)).max(1);
+        let tile_row = |row: usize| (bbox.north - (row as f64 + 0.5) * cell_size).floor() as i32;
+        let (mut tiles, mut tiles_lat) = (TileSet::default(), None);
+        let mut row = 0;
+        while row < height {
+            let lat = tile_row(row);
+            let mut rows = 1;
+            while rows < max_rows && row + rows < height && tile_row(row + rows) == lat {
+                rows += 1;
+            }
+            let north = bbox.north - row as f64 * cell_size;
+            // Cells averaging samples may reach into the neighbouring rows of
+            // tiles, over the whole band as over the whole grid.
+            let south = (north - rows as f64 * cell_size).max(-90.0);
+            let lats = (south.floor() as i32, north.min(90.0).ceil() as i32);
+            if tiles_lat != Some(lats) {
+                // Release the previous rows of tiles before loading the next.
+                tiles = TileSet::default();
+                if lats.0 < lats.1 {
+                    let band = BoundingBox::new(south, bbox.west, north.min(90.0), east);
+                    tiles = self.load_tiles(&band, cell_size).await?.0;
+                }
+                tiles_lat = Some(lats);
+            }
+            let mut band = DemRaster::filled(north, bbox.west, cell_size, width, rows, f32::NAN);
+            fill_from_tiles(&mut band, &tiles, self.resampling, self.missing.fill());
+            sink.write_rows(&band.data)?;
+            row += rows;
+            progress(ExportProgress {
+                rows_written: row,
+                rows: height,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::{TileCache, TileId};
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn streams_the_same_output_as_whole_rasters() {
+        let cache = MemoryCache::new();
+        for lat in [0, 1] {
+            cache
+                .store(
+                    &TileId::new(lat, 0),
+                    &synthetic_tile(1201, |row, col| (row + col) as i16 + lat as i16 * 2000),
+                )
+                .unwrap();
+        }
+        let earthel = EarthEl::builder().cache(cache).build();
+        // Straddles the boundary between the two rows of tiles.
+        let bbox = BoundingBox::new(0.9, 0.2, 1.1, 0.3);
+        let cell = 1.0 / 600.0;
+        let dem = earthel.extract_dem(bbox, cell).await.unwrap();
+
+        let options = GeoTiffOptions::cloud_optimized();
+        let mut expected = Cursor::new(Vec::new());
+        dem.write_geotiff_with(&mut expected, options).unwrap();
+        let mut reports = Vec::new();
+        let mut streamed = Cursor::new(Vec::new());
+        earthel
+            .export_geotiff(bbox, cell, options, &mut streamed, |p| reports.push(p))
+            .await
+            .unwrap();
+        assert_eq!(streamed.into_inner(), expected.into_inner());
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].rows_written, 60);
+        assert_eq!(reports[1].fraction(), 1.0);
+
+        let mut expected = Vec::new();
+        dem.write_ascii_grid(&mut expected).unwrap();
+        let mut streamed = Vec::new();
+        earthel
+            .export_ascii_grid(bbox, cell, &mut streamed, |_| {})
+            .await
+            .unwrap();
+        assert_eq!(streamed, expected);
+
+        let mut expected = Vec::new();
+        dem.write_png(&mut expected).unwrap();
+        let mut streamed = Vec::new();
+        earthel
+            .export_png(bbox, cell, &mut streamed, |_| {})
+            .await
+            .unwrap();
+        assert_eq!(streamed, expected);
+    }
+}
@@ -0,0 +1,161 @@
+//! Detection of flat areas, such as lakes and reservoirs.
+//!
+//! Water surfaces come out of SRTM as patches of cells sharing exactly the
+//! same elevation, which natural terrain seldom produces over large areas.
+
+use super::DemRaster;
+use crate::{BoundingBox, EarthEl, Result};
+
+/// Largest elevation difference, in meters, between cells of a flat area.
+const FLAT_TOLERANCE: f32 = 0.01;
+
+/// A contiguous patch of cells at a constant elevation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatArea {
+    /// Surface elevation in meters.
+    pub elevation: f32,
+    /// Number of raster cells in the area.
+    pub cells: usize,
+    /// Surface in square meters.
+    pub area: f64,
+    /// Outline of the area as `(latitude, longitude)` vertices, following
+    /// cell edges. The ring is closed implicitly.
+    pub exterior: Vec<(f64, f64)>,
+    /// Outlines of the islands inside the area.
+    pub holes: Vec<Vec<(f64, f64)>>,
+}
+
+impl DemRaster {
+    /// Finds the patches of at least `min_cells` edge-connected cells sharing
+    /// the same elevation, largest first.
+    pub fn flat_areas(&self, min_cells: usize) -> Vec<FlatArea> {
+        let mut labels = vec![usize::MAX; self.data.len()];
+        let mut areas = Vec::new();
+        for seed in 0..self.data.len() {
+            if labels[seed] != usize::MAX || self.data[seed].is_nan() {
+                continue;
+            }
+            let cells = self.flood(seed, &mut labels);
+            if cells.len() >= min_cells.max(1) {
+                areas.push(self.flat_area(&cells, &labels));
+            }
+        }
+        areas.sort_by_key(|area| std::cmp::Reverse(area.cells));
+        areas
+    }
+
+    /// Labels the cells connected to `seed` at its elevation with `seed`.
+    fn flood(&self, seed: usize, labels: &mut [usize]) -> Vec<(usize, usize)> {
+        let elevation = self.data[seed];
+        let mut cells = Vec::new();
+        let mut stack = vec![seed];
+        labels[seed] = seed;
+        while let Some(index) = stack.pop() {
+            let (row, col) = (index / self.width, index % self.width);
+            cells.push((row, col));
+            let neighbours = [
+                (row > 0).then(|| index - self.width),
+                (row + 1 < self.height).then(|| index + self.width),
+                (col > 0).then(|| index - 1),
+                (col + 1 < self.width).then(|| index + 1),
+            ];
+            for next in neighbours.into_iter().flatten() {
+                if labels[next] == usize::MAX
+                    && (self.data[next] - elevation).abs() <= FLAT_TOLERANCE
+                {
+                    labels[next] = seed;
+                    stack.push(next);
+                }
+            }
+        }
+        cells
+    }
+
+    fn flat_area(&self, cells: &[(usize, usize)], labels: &[usize]) -> FlatArea {
+        let (first_row, first_col) = cells[0];
+        let label = labels[first_row * self.width + first_col];
+        let inside = |row: isize, col: isize| {
+            (0..self.height as isize).contains(&row)
+                && (0..self.width as isize).contains(&col)
+                && labels[row as usize * self.width + col as usize] == label
+        };
+        let (exterior, holes) = self.outline(cells, inside);
+        FlatArea {
+            elevation: self.data[label],
+            cells: cells.len(),
+            area: self.cells_area(cells),
+            exterior,
+            holes,
+        }
+    }
+}
+
+impl EarthEl {
+    /// Finds the flat areas (likely lakes or reservoirs) of at least
+    /// `min_cells` cells in `bbox`, sampled with cells of `target_resolution`
+    /// degrees. See [`DemRaster::flat_areas`].
+    pub async fn flat_areas(
+        &self,
+        bbox: BoundingBox,
+        target_resolution: f64,
+        min_cells: usize,
+    ) -> Result<Vec<FlatArea>> {
+        Ok(self
+            .extract_dem(bbox, target_resolution)
+            .await?
+            .flat_areas(min_cells))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::{TileCache, TileId};
+
+    #[test]
+    fn outlines_flat_patches_with_islands() {
+        // A 3x3 lake at 100 m with an island, in terrain sloping eastwards.
+        let mut dem = DemRaster::filled(1.0, 0.0, 0.1, 6, 5, 0.0);
+        for (i, value) in dem.data.iter_mut().enumerate() {
+            *value = (i % 6) as f32 * 7.0 + (i / 6) as f32 * 53.0;
+        }
+        for row in 1..4 {
+            for col in 1..4 {
+                dem.data[row * 6 + col] = 100.0;
+            }
+        }
+        dem.data[2 * 6 + 2] = 103.0;
+        let areas = dem.flat_areas(4);
+        assert_eq!(areas.len(), 1);
+        let lake = &areas[0];
+        assert_eq!((lake.elevation, lake.cells), (100.0, 8));
+        assert_eq!(lake.exterior.len(), 4);
+        assert_eq!(lake.holes.len(), 1);
+        assert!(lake.exterior.contains(&(0.9, 0.1)));
+        assert!((lake.area - 8.0 * 11_119.5f64.powi(2)).abs() < 1e6);
+    }
+
+    #[tokio::test]
+    async fn finds_lakes_in_tiles() {
+        let cache = MemoryCache::new();
+        cache
+            .store(
+                &TileId::new(0, 0),
+                &synthetic_tile(1201, |row, col| {
+                    if (100..200).contains(&row) && (100..200).contains(&col) {
+                        372
+                    } else {
+                        (row + col) as i16
+                    }
+                }),
+            )
+            .unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        let bbox = BoundingBox::new(0.8, 0.05, 0.95, 0.2);
+        let areas = earthel.flat_areas(bbox, 0.01, 10).await.unwrap();
+        assert_eq!(areas.len(), 1);
+        assert_eq!(areas[0].elevation, 372.0);
+    }
+}
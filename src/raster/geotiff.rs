@@ -0,0 +1,573 @@
+//! Minimal GeoTIFF encoder for single-band `f32` rasters, with optional
+//! compression and tiling.
+
+use super::DemRaster;
+use crate::{HgtError, Result};
+use flate2::write::ZlibEncoder;
+use std::io::{Seek, SeekFrom, Write};
+
+/// Target size of a strip in bytes.
+const STRIP_BYTES: usize = 64 * 1024;
+
+enum Value {
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Double(Vec<f64>),
+    Ascii(String),
+}
+
+impl Value {
+    fn field_type(&self) -> u16 {
+        match self {
+            Value::Ascii(_) => 2,
+            Value::Short(_) => 3,
+            Value::Long(_) => 4,
+            Value::Double(_) => 12,
+        }
+    }
+
+    fn count(&self) -> u32 {
+        match self {
+            Value::Ascii(s) => s.len() as u32 + 1,
+            Value::Short(v) => v.len() as u32,
+            Value::Long(v) => v.len() as u32,
+            Value::Double(v) => v.len() as u32,
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Value::Ascii(s) => s.bytes().chain([0]).collect(),
+            Value::Short(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Value::Long(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Value::Double(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+        }
+    }
+}
+
+/// Writes a little-endian TIFF: header, image data, then the IFD.
+struct TiffEncoder<W> {
+    writer: W,
+    /// Stream position of the header, which offsets are relative to.
+    start: u64,
+    /// Position relative to the header.
+    position: u64,
+}
+
+impl<W: Write + Seek> TiffEncoder<W> {
+    fn new(mut writer: W) -> Result<Self> {
+        let start = writer.stream_position()?;
+        writer.write_all(b"II")?;
+        writer.write_all(&42u16.to_le_bytes())?;
+        // IFD offset, patched once the IFD is written.
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            start,
+            position: 8,
+        })
+    }
+
+    /// Writes a block of data at the current (word aligned) position and returns its offset.
+    ///
+    /// Fails with [`HgtError::InvalidParameter`] before writing a block
+    /// ending past 4 GiB, which TIFF offsets can't point to.
+    fn write_block(&mut self, data: &[u8]) -> Result<u32> {
+        let offset = self.position + self.position % 2;
+        let end = offset + data.len() as u64;
+        if end > u64::from(u32::MAX) {
+            return Err(HgtError::InvalidParameter(format!(
+                "GeoTIFF of at least {end} bytes exceeds the 4 GiB limit of TIFF; \
+                 export smaller extents or compress them"
+            )));
+        }
+        if self.position % 2 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+        self.writer.write_all(data)?;
+        self.position = end;
+        Ok(offset as u32)
+    }
+
+    fn finish(mut self, mut entries: Vec<(u16, Value)>) -> Result<W> {
+        entries.sort_by_key(|(tag, _)| *tag);
+        let mut ifd = Vec::new();
+        ifd.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        let mut fields = Vec::new();
+        for (tag, value) in &entries {
+            let bytes = value.bytes();
+            let field = if bytes.len() <= 4 {
+                let mut inline = [0; 4];
+                inline[..bytes.len()].copy_from_slice(&bytes);
+                inline
+            } else {
+                self.write_block(&bytes)?.to_le_bytes()
+            };
+            fields.push((*tag, value.field_type(), value.count(), field));
+        }
+        for (tag, field_type, count, field) in fields {
+            ifd.extend_from_slice(&tag.to_le_bytes());
+            ifd.extend_from_slice(&field_type.to_le_bytes());
+            ifd.extend_from_slice(&count.to_le_bytes());
+            ifd.extend_from_slice(&field);
+        }
+        ifd.extend_from_slice(&0u32.to_le_bytes());
+        let ifd_offset = self.write_block(&ifd)?;
+        let end = self.start + self.position;
+        self.writer.seek(SeekFrom::Start(self.start + 4))?;
+        self.writer.write_all(&ifd_offset.to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Compression of the strips or tiles of a GeoTIFF.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TiffCompression {
+    #[default]
+    None,
+    /// Deflate (zlib), readable everywhere.
+    Deflate,
+    /// LZW, the most widely supported compression of older readers.
+    Lzw,
+    /// Zstandard, faster and smaller than Deflate in GDAL 2.3 and later.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl TiffCompression {
+    /// Value of the `Compression` tag.
+    fn code(self) -> u16 {
+        match self {
+            Self::None => 1,
+            Self::Lzw => 5,
+            Self::Deflate => 8,
+            #[cfg(feature = "zstd")]
+            Self::Zstd => 50000,
+        }
+    }
+
+    fn compress(self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data),
+            Self::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&data)?;
+                Ok(encoder.finish()?)
+            }
+            Self::Lzw => weezl::encode::Encoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+                .encode(&data)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e).into()),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(zstd::encode_all(&data[..], 0)?),
+        }
+    }
+}
+
+/// Layout and compression of a GeoTIFF, see [`DemRaster::write_geotiff_with`].
+///
+/// The default writes uncompressed strips, as [`DemRaster::write_geotiff`]
+/// does. Cloud-optimized readers expect tiles, typically of 256 or 512 cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct GeoTiffOptions {
+    pub compression: TiffCompression,
+    /// Applies the floating point predictor before compressing, which
+    /// usually makes elevation data much smaller. Ignored without
+    /// compression.
+    pub predictor: bool,
+    /// Writes square tiles of this many cells, a multiple of 16, instead of
+    /// strips of rows.
+    pub tile_size: Option<usize>,
+}
+
+impl Default for GeoTiffOptions {
+    fn default() -> Self {
+        Self {
+            compression: TiffCompression::None,
+            predictor: true,
+            tile_size: None,
+        }
+    }
+}
+
+impl GeoTiffOptions {
+    /// Deflate-compressed 256 x 256 tiles with the floating point predictor,
+    /// a reasonable layout for mosaics served from object storage.
+    pub fn cloud_optimized() -> Self {
+        Self {
+            compression: TiffCompression::Deflate,
+            predictor: true,
+            tile_size: Some(256),
+        }
+    }
+
+    fn uses_predictor(&self) -> bool {
+        self.predictor && self.compression != TiffCompression::None
+    }
+}
+
+/// Writes a GeoTIFF row by row, a band of strips or tiles at a time, so that
+/// large rasters never have to be held in memory.
+pub(crate) struct GeoTiffWriter<W> {
+    encoder: TiffEncoder<W>,
+    options: GeoTiffOptions,
+    width: usize,
+    height: usize,
+    cell_size: f64,
+    west: f64,
+    north: f64,
+    /// Size in cells of strips (the whole width) or tiles.
+    block_width: usize,
+    block_height: usize,
+    /// Rows of the band of blocks being filled.
+    band: Vec<f32>,
+    offsets: Vec<u32>,
+    counts: Vec<u32>,
+}
+
+impl<W: Write + Seek> GeoTiffWriter<W> {
+    /// Starts a `width` x `height` grid of `cell_size` degrees whose
+    /// north-west corner is at (`north`, `west`).
+    pub fn new(
+        writer: W,
+        options: GeoTiffOptions,
+        width: usize,
+        height: usize,
+        cell_size: f64,
+        north: f64,
+        west: f64,
+    ) -> Result<Self> {
+        let (block_width, block_height) = match options.tile_size {
+            Some(size) if size == 0 || size % 16 != 0 => {
+                return Err(HgtError::InvalidParameter(format!(
+                    "GeoTIFF tile size must be a positive multiple of 16, got {size}"
+                )));
+            }
+            Some(size) => (size, size),
+            None => {
+                let row_bytes = width * 4;
+                let rows = (STRIP_BYTES / row_bytes.max(1)).clamp(1, height.max(1));
+                (width, rows)
+            }
+        };
+        Ok(Self {
+            encoder: TiffEncoder::new(writer)?,
+            options,
+            width,
+            height,
+            cell_size,
+            west,
+            north,
+            block_width,
+            block_height,
+            band: Vec::new(),
+            offsets: Vec::new(),
+            counts: Vec::new(),
+        })
+    }
+
+    /// Appends whole rows of cells, north to south.
+    pub fn write_rows(&mut self, data: &[f32]) -> Result<()> {
+        let band_len = self.block_height * self.width.max(1);
+        for rows in data.chunks(band_len) {
+            let room = band_len - self.band.len();
+            let (now, later) = rows.split_at(rows.len().min(room));
+            self.band.extend_from_slice(now);
+            if self.band.len() == band_len {
+                self.flush_band()?;
+            }
+            self.band.extend_from_slice(later);
+        }
+        Ok(())
+    }
+
+    /// Writes the blocks of the buffered band. The last band of strips may
+    /// be short; tiles are always whole, padded with nodata.
+    fn flush_band(&mut self) -> Result<()> {
+        let band = std::mem::take(&mut self.band);
+        let rows = band.len() / self.width.max(1);
+        if self.options.tile_size.is_none() {
+            return self.write_block(band, self.width);
+        }
+        let size = self.block_width;
+        for first_col in (0..self.width).step_by(size) {
+            let mut tile = vec![f32::NAN; size * size];
+            let cols = size.min(self.width - first_col);
+            for row in 0..rows {
+                let start = row * self.width + first_col;
+                tile[row * size..row * size + cols].copy_from_slice(&band[start..start + cols]);
+            }
+            self.write_block(tile, size)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes and writes a strip or tile of rows of `width` cells.
+    fn write_block(&mut self, cells: Vec<f32>, width: usize) -> Result<()> {
+        let mut bytes: Vec<u8> = cells.iter().flat_map(|v| v.to_le_bytes()).collect();
+        if self.options.uses_predictor() {
+            for row in bytes.chunks_mut(width.max(1) * 4) {
+                predict(row);
+            }
+        }
+        let bytes = self.options.compression.compress(bytes)?;
+        self.offsets.push(self.encoder.write_block(&bytes)?);
+        // Blocks fit in 4 GiB, the encoder checked.
+        self.counts.push(bytes.len() as u32);
+        Ok(())
+    }
+
+    /// Writes the last band and the IFD, and returns the writer.
+    pub fn finish(mut self) -> Result<W> {
+        if !self.band.is_empty() {
+            self.flush_band()?;
+        }
+        let mut entries = vec![
+            (256, Value::Long(vec![self.width as u32])),
+            (257, Value::Long(vec![self.height as u32])),
+            (258, Value::Short(vec![32])),
+            (259, Value::Short(vec![self.options.compression.code()])),
+            (262, Value::Short(vec![1])), // min-is-black
+            (277, Value::Short(vec![1])),
+            (284, Value::Short(vec![1])), // chunky
+            (339, Value::Short(vec![3])), // IEEE float
+            (
+                33550,
+                Value::Double(vec![self.cell_size, self.cell_size, 0.0]),
+            ),
+            (
+                33922,
+                Value::Double(vec![0.0, 0.0, 0.0, self.west, self.north, 0.0]),
+            ),
+            (
+                34735,
+                Value::Short(vec![
+                    1, 1, 0, 3, // directory version, revision, minor revision, key count
+                    1024, 0, 1, 2, // GTModelTypeGeoKey: geographic
+                    1025, 0, 1, 1, // GTRasterTypeGeoKey: pixel is area
+                    2048, 0, 1, 4326, // GeographicTypeGeoKey: WGS84
+                ]),
+            ),
+            (42113, Value::Ascii("nan".into())), // GDAL_NODATA
+        ];
+        if self.options.uses_predictor() {
+            entries.push((317, Value::Short(vec![3]))); // floating point predictor
+        }
+        if self.options.tile_size.is_some() {
+            entries.extend([
+                (322, Value::Long(vec![self.block_width as u32])),
+                (323, Value::Long(vec![self.block_height as u32])),
+                (324, Value::Long(self.offsets)),
+                (325, Value::Long(self.counts)),
+            ]);
+        } else {
+            entries.extend([
+                (273, Value::Long(self.offsets)),
+                (278, Value::Long(vec![self.block_height as u32])),
+                (279, Value::Long(self.counts)),
+            ]);
+        }
+        self.encoder.finish(entries)
+    }
+}
+
+/// Floating point predictor (Adobe TIFF technical note 3) of a row of
+/// little-endian `f32`s: the bytes of the samples are regrouped by
+/// significance, most significant first, then replaced by their difference
+/// with the previous byte.
+fn predict(row: &mut [u8]) {
+    let samples = row.len() / 4;
+    let mut planes = vec![0; row.len()];
+    for (i, sample) in row.chunks_exact(4).enumerate() {
+        for (byte, &value) in sample.iter().rev().enumerate() {
+            planes[byte * samples + i] = value;
+        }
+    }
+    for i in (1..planes.len()).rev() {
+        planes[i] = planes[i].wrapping_sub(planes[i - 1]);
+    }
+    row.copy_from_slice(&planes);
+}
+
+impl DemRaster {
+    /// Writes the raster as a single-band 32-bit float GeoTIFF in WGS84
+    /// (EPSG:4326). Cells without data are written as NaN, which is also
+    /// declared as the nodata value.
+    pub fn write_geotiff<W: Write + Seek>(&self, writer: W) -> Result<()> {
+        self.write_geotiff_with(writer, GeoTiffOptions::default())
+    }
+
+    /// Writes the raster as a GeoTIFF like [`write_geotiff`](Self::write_geotiff),
+    /// compressed and tiled according to `options`.
+    pub fn write_geotiff_with<W: Write + Seek>(
+        &self,
+        writer: W,
+        options: GeoTiffOptions,
+    ) -> Result<()> {
+        let mut tiff = GeoTiffWriter::new(
+            writer,
+            options,
+            self.width,
+            self.height,
+            self.cell_size,
+            self.bbox.north,
+            self.bbox.west,
+        )?;
+        tiff.write_rows(&self.data)?;
+        tiff.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn u16_at(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([data[offset], data[offset + 1]])
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_a_readable_ifd() {
+        let raster = DemRaster::filled(47.0, 5.0, 0.25, 3, 2, 42.0);
+        let mut out = Cursor::new(Vec::new());
+        raster.write_geotiff(&mut out).unwrap();
+        let data = out.into_inner();
+        assert_eq!(&data[..4], b"II*\0");
+        let ifd = u32_at(&data, 4) as usize;
+        let entries = u16_at(&data, ifd) as usize;
+        let tags: Vec<u16> = (0..entries)
+            .map(|i| u16_at(&data, ifd + 2 + i * 12))
+            .collect();
+        assert!(tags.windows(2).all(|w| w[0] < w[1]));
+        // ImageWidth and ImageLength are the first two entries.
+        assert_eq!(u32_at(&data, ifd + 2 + 8), 3);
+        assert_eq!(u32_at(&data, ifd + 2 + 12 + 8), 2);
+        // Single strip holding the six cells right after the header.
+        assert_eq!(f32::from_le_bytes(data[8..12].try_into().unwrap()), 42.0);
+    }
+
+    /// A writer keeping nothing, counting the bytes written.
+    #[derive(Default)]
+    struct Counting {
+        position: u64,
+        len: u64,
+    }
+
+    impl Write for Counting {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.position += buf.len() as u64;
+            self.len = self.len.max(self.position);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Counting {
+        fn seek(&mut self, to: SeekFrom) -> std::io::Result<u64> {
+            self.position = match to {
+                SeekFrom::Start(position) => position,
+                SeekFrom::End(delta) => self.len.saturating_add_signed(delta),
+                SeekFrom::Current(delta) => self.position.saturating_add_signed(delta),
+            };
+            Ok(self.position)
+        }
+    }
+
+    #[test]
+    fn stops_at_the_tiff_size_limit() {
+        let mut encoder = TiffEncoder::new(Counting::default()).unwrap();
+        let block = vec![0; 256 * 1024 * 1024];
+        // 15 blocks of 256 MiB fit after the header, not 16.
+        for _ in 0..15 {
+            encoder.write_block(&block).unwrap();
+        }
+        let error = encoder.write_block(&block).unwrap_err();
+        assert!(matches!(error, HgtError::InvalidParameter(_)), "{error}");
+        assert_eq!(encoder.writer.len, 8 + 15 * 256 * 1024 * 1024);
+        encoder.write_block(&[0; 4]).unwrap();
+    }
+
+    /// A 40 x 30 slope with a gap, read back with the `tiff` crate.
+    fn round_trip(options: GeoTiffOptions) -> (Vec<f32>, usize) {
+        round_trip_after(options, &[])
+    }
+
+    /// Like [`round_trip`], the TIFF written after `prefix`.
+    fn round_trip_after(options: GeoTiffOptions, prefix: &[u8]) -> (Vec<f32>, usize) {
+        let mut raster = DemRaster::filled(47.0, 5.0, 0.01, 40, 30, 0.0);
+        for (i, value) in raster.data.iter_mut().enumerate() {
+            *value = 1000.0 + (i % 40) as f32 * 2.5 - (i / 40) as f32;
+        }
+        raster.data[7] = f32::NAN;
+        let mut out = Cursor::new(prefix.to_vec());
+        out.set_position(prefix.len() as u64);
+        raster.write_geotiff_with(&mut out, options).unwrap();
+        let data = out.into_inner();
+        assert_eq!(&data[..prefix.len()], prefix);
+        let out = Cursor::new(data[prefix.len()..].to_vec());
+        let len = out.get_ref().len();
+        let mut decoder = tiff::decoder::Decoder::new(out).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (40, 30));
+        let tiff::decoder::DecodingResult::F32(data) = decoder.read_image().unwrap() else {
+            panic!("not a float image");
+        };
+        assert_eq!(data.len(), raster.data.len());
+        for (read, written) in data.iter().zip(&raster.data) {
+            assert!(read == written || read.is_nan() && written.is_nan());
+        }
+        (data, len)
+    }
+
+    #[test]
+    fn compresses_and_tiles_images() {
+        let (_, plain) = round_trip(GeoTiffOptions::default());
+        for compression in [
+            TiffCompression::Deflate,
+            TiffCompression::Lzw,
+            #[cfg(feature = "zstd")]
+            TiffCompression::Zstd,
+        ] {
+            for tile_size in [None, Some(16)] {
+                let options = GeoTiffOptions {
+                    compression,
+                    tile_size,
+                    ..Default::default()
+                };
+                let (_, compressed) = round_trip(options);
+                assert!(compressed < plain, "{options:?}");
+                round_trip(GeoTiffOptions {
+                    predictor: false,
+                    ..options
+                });
+            }
+        }
+        round_trip(GeoTiffOptions::cloud_optimized());
+        // Offsets are relative to the header, wherever the writer starts.
+        round_trip_after(GeoTiffOptions::default(), b"abc");
+        round_trip_after(GeoTiffOptions::cloud_optimized(), b"prefix");
+        let odd = GeoTiffOptions {
+            tile_size: Some(100),
+            ..Default::default()
+        };
+        let raster = DemRaster::filled(47.0, 5.0, 0.01, 4, 3, 0.0);
+        assert!(raster
+            .write_geotiff_with(Cursor::new(Vec::new()), odd)
+            .is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Shaded relief, from a single light source, from several, or from slope
+//! alone.
+//!
+//! Shades are gray levels from 0 (black) to 255 (white), stored in a raster
+//! of the same grid as the elevations. Gradients are computed with Horn's
+//! method on cells measured in meters at their latitude.
+
+use super::DemRaster;
+
+/// Light azimuths of the multidirectional hillshade, in degrees.
+const AZIMUTHS: [f64; 4] = [225.0, 270.0, 315.0, 360.0];
+
+/// Lighting of a hillshade.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct HillshadeOptions {
+    /// Direction the light comes from, in degrees clockwise from north.
+    pub azimuth: f64,
+    /// Height of the light above the horizon, in degrees.
+    pub altitude: f64,
+    /// Vertical exaggeration.
+    pub z_factor: f64,
+}
+
+impl Default for HillshadeOptions {
+    fn default() -> Self {
+        Self {
+            azimuth: 315.0,
+            altitude: 45.0,
+            z_factor: 1.0,
+        }
+    }
+}
+
+impl DemRaster {
+    /// Classic hillshade lit from a single direction.
+    pub fn hillshade(&self, options: HillshadeOptions) -> DemRaster {
+        self.shade(options.z_factor, |gradient| {
+            illumination(gradient, options.azimuth, options.altitude)
+        })
+    }
+
+    /// Hillshade combining lights from the west to the north (225°, 270°,
+    /// 315° and 360°). Each light is weighted by the squared sine of the
+    /// angle between its azimuth and the direction the slope faces, so every
+    /// slope is lit from the side, which shows relief in all orientations
+    /// instead of flattening slopes parallel to a single light.
+    /// `options.azimuth` is ignored.
+    pub fn multidirectional_hillshade(&self, options: HillshadeOptions) -> DemRaster {
+        self.shade(options.z_factor, |gradient| {
+            let (east, north) = gradient;
+            if east == 0.0 && north == 0.0 {
+                return illumination(gradient, 0.0, options.altitude);
+            }
+            // Slopes face downhill.
+            let aspect = (-east).atan2(-north);
+            let (mut sum, mut weights) = (0.0, 0.0);
+            for azimuth in AZIMUTHS {
+                let weight = (aspect - azimuth.to_radians()).sin().powi(2);
+                sum += weight * illumination(gradient, azimuth, options.altitude);
+                weights += weight;
+            }
+            sum / weights
+        })
+    }
+
+    /// Slope shading, as used in Swiss-style relief: the steeper the darker,
+    /// regardless of orientation. Flat cells are white.
+    pub fn slope_shade(&self, z_factor: f64) -> DemRaster {
+        self.shade(z_factor, |(east, north)| {
+            1.0 / (1.0 + east * east + north * north).sqrt()
+        })
+    }
+
+    /// Applies `shade`, mapping a gradient (rise per meter eastwards and
+    /// northwards) to a brightness in `[0, 1]`, to every cell with data.
+    fn shade(&self, z_factor: f64, shade: impl Fn((f64, f64)) -> f64) -> DemRaster {
+        let mut shaded = self.clone();
+        let dy = self.cell_size * self.body.meters_per_degree();
+        for row in 0..self.height {
+            let latitude = self.cell_center(row, 0).0;
+            let dx = dy * latitude.to_radians().cos().max(1e-6);
+            for col in 0..self.width {
+                let Some(center) = self.get(row, col) else {
+                    continue;
+                };
+                // Neighbours outside the raster or without data take the
+                // elevation of the center cell.
+                let z = |dr: isize, dc: isize| {
+                    let r = row.checked_add_signed(dr).filter(|&r| r < self.height);
+                    let c = col.checked_add_signed(dc).filter(|&c| c < self.width);
+                    let value = r.zip(c).and_then(|(r, c)| self.get(r, c));
+                    f64::from(value.unwrap_or(center)) * z_factor
+                };
+                let east = ((z(-1, 1) + 2.0 * z(0, 1) + z(1, 1))
+                    - (z(-1, -1) + 2.0 * z(0, -1) + z(1, -1)))
+                    / (8.0 * dx);
+                let north = ((z(-1, -1) + 2.0 * z(-1, 0) + z(-1, 1))
+                    - (z(1, -1) + 2.0 * z(1, 0) + z(1, 1)))
+                    / (8.0 * dy);
+                let brightness = shade((east, north)).clamp(0.0, 1.0);
+                shaded.data[row * self.width + col] = (brightness * 255.0).round() as f32;
+            }
+        }
+        shaded
+    }
+}
+
+/// Lambertian reflectance of a surface with the given gradient, for a light
+/// at `azimuth` and `altitude` degrees.
+fn illumination((east, north): (f64, f64), azimuth: f64, altitude: f64) -> f64 {
+    let (azimuth, altitude) = (azimuth.to_radians(), altitude.to_radians());
+    let light = (
+        azimuth.sin() * altitude.cos(),
+        azimuth.cos() * altitude.cos(),
+        altitude.sin(),
+    );
+    let normal = (-east, -north, 1.0);
+    let length = (east * east + north * north + 1.0).sqrt();
+    ((normal.0 * light.0 + normal.1 * light.1 + normal.2 * light.2) / length).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A raster near the equator rising eastwards by `rise` meters per cell
+    /// of about 92.6 m.
+    fn ramp(rise: f32) -> DemRaster {
+        let mut dem = DemRaster::filled(0.01, 0.0, 1.0 / 1200.0, 5, 5, 0.0);
+        for (i, value) in dem.data.iter_mut().enumerate() {
+            *value = (i % 5) as f32 * rise;
+        }
+        dem
+    }
+
+    #[test]
+    fn lights_slopes_facing_the_sun() {
+        let flat = ramp(0.0).hillshade(HillshadeOptions::default());
+        // sin(45°) * 255
+        assert_eq!(flat.get(2, 2), Some(180.0));
+        // A 45° slope facing west, lit from the west at 45°, faces the light.
+        let dem = ramp(92.66);
+        let lit = dem.hillshade(HillshadeOptions {
+            azimuth: 270.0,
+            ..Default::default()
+        });
+        assert!(lit.get(2, 2).unwrap() > 254.0);
+        let dark = dem.hillshade(HillshadeOptions {
+            azimuth: 90.0,
+            ..Default::default()
+        });
+        assert_eq!(dark.get(2, 2), Some(0.0));
+    }
+
+    #[test]
+    fn shades_all_orientations() {
+        let dem = ramp(50.0);
+        let single = dem.hillshade(HillshadeOptions {
+            azimuth: 360.0,
+            ..Default::default()
+        });
+        let multi = dem.multidirectional_hillshade(HillshadeOptions::default());
+        // A slope parallel to the northern light looks flat under it, but
+        // gets lit by the lights from the west.
+        let (single, multi) = (single.get(2, 2).unwrap(), multi.get(2, 2).unwrap());
+        assert!(single > 150.0 && multi > single, "{single} {multi}");
+        let flat = ramp(0.0).multidirectional_hillshade(HillshadeOptions::default());
+        assert_eq!(flat.get(0, 0), Some(180.0));
+    }
+
+    #[test]
+    fn darkens_steep_slopes() {
+        assert_eq!(ramp(0.0).slope_shade(1.0).get(1, 1), Some(255.0));
+        let gentle = ramp(10.0).slope_shade(1.0).get(2, 2).unwrap();
+        let steep = ramp(92.66).slope_shade(1.0).get(2, 2).unwrap();
+        assert!(steep < gentle && gentle < 255.0);
+        assert!((steep - 255.0 * 45f32.to_radians().cos()).abs() < 1.5);
+    }
+}
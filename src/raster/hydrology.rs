@@ -0,0 +1,284 @@
+//! Depression filling and D8 flow routing.
+//!
+//! Sinks are filled with the priority-flood algorithm (Barnes et al., 2014):
+//! cells are flooded inwards from the raster edges and from cells without
+//! data, lowest first, so that every cell ends up draining out of the raster.
+
+use super::DemRaster;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// Offsets (row, col) of the eight neighbours of a cell, clockwise from north.
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (-1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+];
+
+/// Settings of [`DemRaster::flow`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct FlowOptions {
+    /// Fills depressions first, so that water is routed across them instead
+    /// of stopping at their bottom. Enabled by default.
+    pub fill_sinks: bool,
+}
+
+impl Default for FlowOptions {
+    fn default() -> Self {
+        Self { fill_sinks: true }
+    }
+}
+
+/// D8 flow routing over a raster: each cell drains to one of its eight
+/// neighbours.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowGrid {
+    pub width: usize,
+    pub height: usize,
+    /// Index in clockwise order from north of the neighbour each cell drains
+    /// to, `None` for outlets, pits and cells without data.
+    directions: Vec<Option<u8>>,
+    /// Number of cells draining through each cell, itself included.
+    accumulation: Vec<u32>,
+}
+
+impl FlowGrid {
+    /// The cell a cell drains to, `None` if water leaves the raster or
+    /// stays there.
+    pub fn downstream(&self, row: usize, col: usize) -> Option<(usize, usize)> {
+        let (dr, dc) = NEIGHBOURS[usize::from(self.directions[row * self.width + col]?)];
+        Some((row.checked_add_signed(dr)?, col.checked_add_signed(dc)?))
+    }
+
+    /// Number of cells whose water goes through a cell, the cell included.
+    pub fn accumulation(&self, row: usize, col: usize) -> u32 {
+        self.accumulation[row * self.width + col]
+    }
+}
+
+impl DemRaster {
+    /// Raises the cells of every depression to the level of its spill point,
+    /// leaving flat areas where the depressions were. Cells without data
+    /// count as outlets, like the raster edges.
+    pub fn fill_sinks(&self) -> DemRaster {
+        let mut filled = self.clone();
+        self.priority_flood(|index, _, level| {
+            filled.data[index] = filled.data[index].max(level);
+        });
+        filled
+    }
+
+    /// Routes water over the raster with the D8 method, each cell draining to
+    /// its steepest downhill neighbour. Cells are assumed square.
+    ///
+    /// With [`FlowOptions::fill_sinks`], depressions and the flats left by
+    /// filling them drain towards their spill point.
+    pub fn flow(&self, options: FlowOptions) -> FlowGrid {
+        let mut directions = vec![None; self.data.len()];
+        if options.fill_sinks {
+            // Each cell drains to the cell it was flooded from.
+            self.priority_flood(|index, from, _| {
+                if let Some(from) = from {
+                    directions[index] = Some(self.direction_between(index, from));
+                }
+            });
+        } else {
+            for (index, direction) in directions.iter_mut().enumerate() {
+                *direction = self.steepest_descent(index);
+            }
+        }
+        let accumulation = self.accumulate(&directions);
+        FlowGrid {
+            width: self.width,
+            height: self.height,
+            directions,
+            accumulation,
+        }
+    }
+
+    fn neighbours(&self, index: usize) -> impl Iterator<Item = (u8, usize)> + '_ {
+        let (row, col) = (index / self.width, index % self.width);
+        NEIGHBOURS
+            .iter()
+            .enumerate()
+            .filter_map(move |(direction, &(dr, dc))| {
+                let row = row.checked_add_signed(dr).filter(|&r| r < self.height)?;
+                let col = col.checked_add_signed(dc).filter(|&c| c < self.width)?;
+                Some((direction as u8, row * self.width + col))
+            })
+    }
+
+    fn direction_between(&self, from: usize, to: usize) -> u8 {
+        self.neighbours(from)
+            .find(|&(_, index)| index == to)
+            .map(|(direction, _)| direction)
+            .expect("cells are neighbours")
+    }
+
+    /// Visits every cell with data once, in flooding order, with the cell it
+    /// was reached from (`None` for outlets) and its water level.
+    fn priority_flood(&self, mut visit: impl FnMut(usize, Option<usize>, f32)) {
+        let mut queue = BinaryHeap::new();
+        let mut seen = vec![false; self.data.len()];
+        // Pushes are numbered so that ties drain first-in first-out, which
+        // routes water across flats in straight lines.
+        let mut order = 0u64;
+        for (index, seen) in seen.iter_mut().enumerate() {
+            let (row, col) = (index / self.width, index % self.width);
+            let edge = row == 0 || col == 0 || row + 1 == self.height || col + 1 == self.width;
+            if self.data[index].is_nan() {
+                *seen = true;
+            } else if edge || self.neighbours(index).any(|(_, n)| self.data[n].is_nan()) {
+                *seen = true;
+                queue.push(Reverse(Flooded(self.data[index], order, index)));
+                order += 1;
+                visit(index, None, self.data[index]);
+            }
+        }
+        while let Some(Reverse(Flooded(level, _, index))) = queue.pop() {
+            for (_, next) in self.neighbours(index) {
+                if seen[next] {
+                    continue;
+                }
+                seen[next] = true;
+                let next_level = self.data[next].max(level);
+                visit(next, Some(index), next_level);
+                queue.push(Reverse(Flooded(next_level, order, next)));
+                order += 1;
+            }
+        }
+    }
+
+    fn steepest_descent(&self, index: usize) -> Option<u8> {
+        let here = self.data[index];
+        let mut best = None;
+        let mut steepest = 0.0;
+        for (direction, next) in self.neighbours(index) {
+            let distance = if direction % 2 == 0 { 1.0 } else { 2f32.sqrt() };
+            let slope = (here - self.data[next]) / distance;
+            if slope > steepest {
+                steepest = slope;
+                best = Some(direction);
+            }
+        }
+        best
+    }
+
+    fn accumulate(&self, directions: &[Option<u8>]) -> Vec<u32> {
+        let downstream = |index: usize| {
+            let direction = directions[index]?;
+            self.neighbours(index)
+                .find(|&(d, _)| d == direction)
+                .map(|(_, next)| next)
+        };
+        let mut accumulation: Vec<u32> = self.data.iter().map(|v| u32::from(!v.is_nan())).collect();
+        let mut inflows = vec![0u32; self.data.len()];
+        for index in 0..self.data.len() {
+            if let Some(next) = downstream(index) {
+                inflows[next] += 1;
+            }
+        }
+        // Walk from the sources down, adding each cell to its receiver once
+        // everything upstream of it has been counted.
+        let mut ready: Vec<usize> = (0..self.data.len()).filter(|&i| inflows[i] == 0).collect();
+        while let Some(index) = ready.pop() {
+            if let Some(next) = downstream(index) {
+                accumulation[next] += accumulation[index];
+                inflows[next] -= 1;
+                if inflows[next] == 0 {
+                    ready.push(next);
+                }
+            }
+        }
+        accumulation
+    }
+}
+
+/// Queue entry of the priority flood: water level, push order and cell.
+struct Flooded(f32, u64, usize);
+
+impl PartialEq for Flooded {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Flooded {}
+
+impl PartialOrd for Flooded {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Flooded {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 5x5 bowl sloping down to the east edge, with a pit in the middle.
+    fn bowl() -> DemRaster {
+        let mut dem = DemRaster::filled(1.0, 0.0, 0.1, 5, 5, 0.0);
+        let heights = [
+            [9.0, 9.0, 9.0, 9.0, 9.0],
+            [9.0, 6.0, 6.0, 6.0, 9.0],
+            [9.0, 6.0, 2.0, 6.0, 5.0],
+            [9.0, 6.0, 6.0, 6.0, 9.0],
+            [9.0, 9.0, 9.0, 9.0, 9.0],
+        ];
+        dem.data = heights.into_iter().flatten().collect();
+        dem
+    }
+
+    #[test]
+    fn fills_depressions_to_their_spill_point() {
+        let filled = bowl().fill_sinks();
+        // The pit can only spill over the ring of 6 m around it.
+        assert_eq!(filled.get(2, 2), Some(6.0));
+        assert_eq!(filled.get(1, 1), Some(6.0));
+        assert_eq!(filled.get(2, 4), Some(5.0));
+        assert_eq!(filled.get(0, 0), Some(9.0));
+    }
+
+    #[test]
+    fn treats_missing_cells_as_outlets() {
+        let mut dem = bowl();
+        dem.data[2 * 5 + 4] = 9.0;
+        dem.data[2 * 5 + 2] = f32::NAN;
+        let filled = dem.fill_sinks();
+        assert_eq!(filled.get(1, 2), Some(6.0));
+        assert_eq!(filled.get(2, 2), None);
+    }
+
+    #[test]
+    fn routes_water_out_of_depressions() {
+        let dem = bowl();
+        let stuck = dem.flow(FlowOptions { fill_sinks: false });
+        assert_eq!(stuck.downstream(2, 2), None);
+        assert_eq!(stuck.downstream(2, 3), Some((2, 2)));
+        // Everything but the three cells of the east outlet ends in the pit.
+        assert_eq!(stuck.accumulation(2, 2), 22);
+
+        let flow = dem.flow(FlowOptions::default());
+        assert_eq!(flow.downstream(2, 3), Some((2, 4)));
+        assert_eq!(flow.downstream(2, 4), None);
+        // The whole inside of the bowl drains through the spill point.
+        assert_eq!(flow.accumulation(2, 4), 10);
+        assert_eq!(flow.accumulation(0, 0), 1);
+    }
+}
@@ -0,0 +1,374 @@
+//! Gridded elevation data stitched from several tiles.
+
+mod ascii;
+mod contours;
+mod export;
+mod flats;
+mod geotiff;
+mod hillshade;
+mod hydrology;
+mod png;
+mod polygonize;
+mod region;
+mod resample;
+mod roughness;
+
+#[cfg(feature = "geojson")]
+pub use contours::contours_to_geojson;
+pub use contours::ContourLine;
+pub use export::ExportProgress;
+pub use flats::FlatArea;
+pub use geotiff::{GeoTiffOptions, TiffCompression};
+pub use hillshade::HillshadeOptions;
+pub use hydrology::{FlowGrid, FlowOptions};
+pub use region::Region;
+pub use resample::Resampling;
+
+use crate::tile::TileSet;
+use crate::{Body, BoundingBox, EarthEl, HgtError, Result, TileId};
+
+/// A north-up elevation grid in geographic coordinates (WGS84).
+///
+/// Cells are square in degrees and stored row by row from north to south.
+/// Cells without data hold `f32::NAN`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemRaster {
+    /// Area covered by the grid; its north-west corner is the corner of the first cell.
+    pub bbox: BoundingBox,
+    pub width: usize,
+    pub height: usize,
+    /// Size of a cell in degrees.
+    pub cell_size: f64,
+    pub data: Vec<f32>,
+    /// Body the grid covers, on which cell sizes and areas are measured.
+    pub body: Body,
+}
+
+/// Outcome of [`EarthEl::extract_dem_report`].
+#[derive(Debug)]
+pub struct DemReport {
+    pub raster: DemRaster,
+    /// Tiles left without data, outside of the coverage or missing at the
+    /// source, with the reason.
+    pub skipped: Vec<(TileId, HgtError)>,
+}
+
+impl DemRaster {
+    /// Creates a raster of `width` x `height` cells whose north-west corner is
+    /// at (`north`, `west`), filled with `value`.
+    pub fn filled(
+        north: f64,
+        west: f64,
+        cell_size: f64,
+        width: usize,
+        height: usize,
+        value: f32,
+    ) -> Self {
+        Self {
+            bbox: BoundingBox::new(
+                north - height as f64 * cell_size,
+                west,
+                north,
+                west + width as f64 * cell_size,
+            ),
+            width,
+            height,
+            cell_size,
+            data: vec![value; width * height],
+            body: Body::EARTH,
+        }
+    }
+
+    /// Elevation of a cell, `None` when the cell has no data.
+    pub fn get(&self, row: usize, col: usize) -> Option<f32> {
+        let value = self.data[row * self.width + col];
+        (!value.is_nan()).then_some(value)
+    }
+
+    /// Latitude and longitude of the center of a cell.
+    pub fn cell_center(&self, row: usize, col: usize) -> (f64, f64) {
+        (
+            self.bbox.north - (row as f64 + 0.5) * self.cell_size,
+            self.bbox.west + (col as f64 + 0.5) * self.cell_size,
+        )
+    }
+
+    /// Row and column of the cell containing a point, `None` outside the grid.
+    pub fn cell_at(&self, lat: f64, lon: f64) -> Option<(usize, usize)> {
+        let row = (self.bbox.north - lat) / self.cell_size;
+        // Points east of the antimeridian, for grids extending past 180°.
+        let col = (lon - self.bbox.west).rem_euclid(360.0) / self.cell_size;
+        ((0.0..self.height as f64).contains(&row) && (0.0..self.width as f64).contains(&col))
+            .then_some((row as usize, col as usize))
+    }
+
+    /// The `width` x `height` cells starting at (`row`, `col`), as a new raster.
+    pub(crate) fn crop(&self, row: usize, col: usize, width: usize, height: usize) -> Self {
+        let (north, west) = (
+            self.bbox.north - row as f64 * self.cell_size,
+            self.bbox.west + col as f64 * self.cell_size,
+        );
+        let mut cropped = Self::filled(north, west, self.cell_size, width, height, f32::NAN);
+        cropped.body = self.body;
+        for r in 0..height {
+            let start = (row + r) * self.width + col;
+            cropped.data[r * width..(r + 1) * width]
+                .copy_from_slice(&self.data[start..start + width]);
+        }
+        cropped
+    }
+
+    /// Copies the grid into a `(height, width)` array.
+    #[cfg(feature = "ndarray")]
+    pub fn to_array(&self) -> ndarray::Array2<f32> {
+        ndarray::Array2::from_shape_vec((self.height, self.width), self.data.clone())
+            .expect("raster data matches its dimensions")
+    }
+
+    /// Borrows the grid as a `(height, width)` array view.
+    #[cfg(feature = "ndarray")]
+    pub fn view(&self) -> ndarray::ArrayView2<'_, f32> {
+        ndarray::ArrayView2::from_shape((self.height, self.width), &self.data)
+            .expect("raster data matches its dimensions")
+    }
+}
+
+impl EarthEl {
+    /// Extracts the terrain inside `bbox` as a raster with cells of
+    /// `target_resolution` degrees, stitching and resampling the underlying
+    /// tiles as configured with
+    /// [`EarthElBuilder::resampling`](crate::EarthElBuilder::resampling)
+    /// (area-weighted averages by default, see [`Resampling`]).
+    ///
+    /// The raster starts at the north-west corner of `bbox` and is extended
+    /// south and east to a whole number of cells. Boxes crossing the
+    /// antimeridian give rasters extending east of 180°. Cells over tiles
+    /// outside the [coverage](crate::EarthElBuilder::coverage) have no data;
+    /// [`extract_dem_report`](Self::extract_dem_report) lists them.
+    ///
+    /// ```no_run
+    /// use earthel::{BoundingBox, EarthEl};
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let bbox = BoundingBox::new(45.7, 6.7, 46.0, 7.1);
+    /// let dem = EarthEl::default().extract_dem(bbox, 1.0 / 1200.0).await?;
+    /// dem.write_geotiff(std::fs::File::create("mont-blanc.tif")?)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn extract_dem(
+        &self,
+        bbox: BoundingBox,
+        target_resolution: f64,
+    ) -> Result<DemRaster> {
+        Ok(self
+            .extract_dem_report(bbox, target_resolution)
+            .await?
+            .raster)
+    }
+
+    /// Like [`extract_dem`](Self::extract_dem), also reporting the tiles left
+    /// without data.
+    pub async fn extract_dem_report(
+        &self,
+        bbox: BoundingBox,
+        target_resolution: f64,
+    ) -> Result<DemReport> {
+        bbox.validate()?;
+        check_resolution(target_resolution)?;
+        let (width, height) = grid_dimensions(&bbox, target_resolution);
+        self.check_allocation(width as u64 * height as u64 * size_of::<f32>() as u64)?;
+        let mut raster = DemRaster::filled(
+            bbox.north,
+            bbox.west,
+            target_resolution,
+            width,
+            height,
+            f32::NAN,
+        );
+        let skipped = self.fill_raster(&mut raster).await?;
+        Ok(DemReport { raster, skipped })
+    }
+
+    /// Samples the terrain of every cell of `raster`, returning the tiles
+    /// left without data. Cells beyond the poles are left untouched.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(width = raster.width, height = raster.height))
+    )]
+    pub(crate) async fn fill_raster(
+        &self,
+        raster: &mut DemRaster,
+    ) -> Result<Vec<(TileId, HgtError)>> {
+        raster.body = self.body;
+        let mut bbox = raster.bbox;
+        bbox.south = bbox.south.max(-90.0);
+        bbox.north = bbox.north.min(90.0);
+        let (tiles, skipped) = self
+            .within_deadline(self.load_tiles(&bbox, raster.cell_size))
+            .await?;
+        fill_from_tiles(raster, &tiles, self.resampling, self.missing.fill());
+        Ok(skipped)
+    }
+
+    /// Downloads (if needed) and decodes every tile intersecting `bbox`, at
+    /// the coarsest overview that still has a sample per cell of `cell_size`
+    /// degrees. Tiles outside the coverage, or missing at the source where
+    /// the missing data policy tolerates it, are left out and returned with
+    /// the reason.
+    pub(crate) async fn load_tiles(
+        &self,
+        bbox: &BoundingBox,
+        cell_size: f64,
+    ) -> Result<(TileSet, Vec<(TileId, HgtError)>)> {
+        let mut tiles = TileSet::default();
+        let mut skipped = Vec::new();
+        for id in bbox.tiles() {
+            match self.load_overview_if_present(&id, cell_size).await {
+                Ok(Some(tile)) => tiles.extend([tile]),
+                Ok(None) => skipped.push((id, HgtError::TileNotFound(id))),
+                Err(e @ HgtError::OutsideCoverage(_)) => skipped.push((id, e)),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok((tiles, skipped))
+    }
+}
+
+/// Number of cells of `cell_size` degrees needed to cover `bbox`, as (width,
+/// height). Rounding errors don't add a sliver of a cell to exact fits.
+fn grid_dimensions(bbox: &BoundingBox, cell_size: f64) -> (usize, usize) {
+    let bbox = bbox.unwrapped();
+    let cells = |extent: f64| ((extent / cell_size - 1e-9).ceil() as usize).max(1);
+    (cells(bbox.east - bbox.west), cells(bbox.north - bbox.south))
+}
+
+fn check_resolution(target_resolution: f64) -> Result<()> {
+    if target_resolution.is_finite() && target_resolution > 0.0 {
+        Ok(())
+    } else {
+        Err(HgtError::InvalidParameter(format!(
+            "target resolution must be a positive number of degrees, got {target_resolution}"
+        )))
+    }
+}
+
+fn fill_from_tiles(
+    raster: &mut DemRaster,
+    tiles: &TileSet,
+    method: Resampling,
+    missing: Option<f64>,
+) {
+    let missing = missing.map_or(f32::NAN, |v| v as f32);
+    let half = raster.cell_size / 2.0;
+    for row in 0..raster.height {
+        for col in 0..raster.width {
+            let (lat, lon) = raster.cell_center(row, col);
+            if lat.abs() > 90.0 {
+                continue;
+            }
+            let cell = BoundingBox::new(
+                (lat - half).max(-90.0),
+                lon - half,
+                (lat + half).min(90.0),
+                lon + half,
+            );
+            raster.data[row * raster.width + col] =
+                resample::sample_cell(tiles, method, &cell).map_or(missing, |v| v as f32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::{TileCache, TileId};
+
+    #[tokio::test]
+    async fn stitches_neighbouring_tiles() {
+        let cache = MemoryCache::new();
+        cache
+            .store(&TileId::new(10, 20), &synthetic_tile(1201, |_, _| 100))
+            .unwrap();
+        cache
+            .store(&TileId::new(10, 21), &synthetic_tile(1201, |_, _| 200))
+            .unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        let bbox = BoundingBox::new(10.5, 20.5, 10.75, 21.5);
+        let dem = earthel.extract_dem(bbox, 0.25).await.unwrap();
+        assert_eq!((dem.width, dem.height), (4, 1));
+        assert_eq!(dem.data, vec![100.0, 100.0, 200.0, 200.0]);
+        assert_eq!(dem.cell_center(0, 0), (10.625, 20.625));
+    }
+
+    #[tokio::test]
+    async fn reports_skipped_tiles() {
+        let cache = MemoryCache::new();
+        cache
+            .store(&TileId::new(10, 20), &synthetic_tile(1201, |_, _| 100))
+            .unwrap();
+        let earthel = EarthEl::builder()
+            .cache(cache)
+            .coverage(BoundingBox::new(10.0, 20.0, 11.0, 21.0))
+            .build();
+        let bbox = BoundingBox::new(10.5, 20.5, 10.75, 21.5);
+        let report = earthel.extract_dem_report(bbox, 0.25).await.unwrap();
+        assert_eq!(report.raster.data[..2], [100.0, 100.0]);
+        assert!(report.raster.data[2..].iter().all(|v| v.is_nan()));
+        assert_eq!(report.skipped.len(), 1);
+        let tile = TileId::new(10, 21);
+        assert!(
+            matches!(report.skipped[0], (t, HgtError::OutsideCoverage(e)) if t == tile && e == tile)
+        );
+    }
+
+    #[tokio::test]
+    async fn stitches_across_the_antimeridian() {
+        let cache = std::sync::Arc::new(MemoryCache::new());
+        // Fiji, on both sides of 180°.
+        cache
+            .store(&TileId::new(-18, 179), &synthetic_tile(1201, |_, _| 100))
+            .unwrap();
+        cache
+            .store(&TileId::new(-18, -180), &synthetic_tile(1201, |_, _| 200))
+            .unwrap();
+        let earthel = |resampling| {
+            EarthEl::builder()
+                .cache(std::sync::Arc::clone(&cache))
+                .coverage(BoundingBox::SRTM)
+                .resampling(resampling)
+                .build()
+        };
+        for resampling in [
+            Resampling::Nearest,
+            Resampling::Bilinear,
+            Resampling::Average,
+        ] {
+            let earthel = earthel(resampling);
+            let bbox = BoundingBox::new(-17.75, 179.5, -17.5, -179.5);
+            let dem = earthel.extract_dem(bbox, 0.25).await.unwrap();
+            assert_eq!((dem.width, dem.height), (4, 1));
+            assert_eq!(dem.data, vec![100.0, 100.0, 200.0, 200.0], "{resampling:?}");
+            assert_eq!(dem.bbox.east, 180.5);
+            assert_eq!(dem.cell_at(-17.6, -179.6), Some((0, 3)));
+        }
+
+        // North of the coverage, cells are left without data.
+        let bbox = BoundingBox::new(60.5, 179.5, 61.5, -179.5);
+        let dem = earthel(Resampling::default())
+            .extract_dem(bbox, 0.5)
+            .await
+            .unwrap();
+        assert_eq!(dem.data.len(), 4);
+        assert!(dem.data.iter().all(|v| v.is_nan()));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_resolution() {
+        let earthel = EarthEl::builder().cache(MemoryCache::new()).build();
+        let bbox = BoundingBox::new(10.0, 20.0, 11.0, 21.0);
+        assert!(earthel.extract_dem(bbox, 0.0).await.is_err());
+    }
+}
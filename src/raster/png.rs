@@ -0,0 +1,177 @@
+use super::DemRaster;
+use crate::{HgtError, Result};
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use std::io::Write;
+
+/// Size of the chunks of compressed rows.
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Writes a 16-bit grayscale PNG row by row, the compressed rows going out
+/// a chunk at a time.
+///
+/// Samples are elevations rounded to the meter and offset by 32768, so that
+/// 32768 is sea level, and 0, transparent, is no data.
+pub(crate) struct PngWriter<W> {
+    writer: W,
+    width: usize,
+    encoder: ZlibEncoder<Vec<u8>>,
+}
+
+impl<W: Write> PngWriter<W> {
+    /// Writes the header of a `width` x `height` image.
+    ///
+    /// Fails with [`HgtError::InvalidParameter`] for images PNG can't hold,
+    /// empty or larger than 2³¹ - 1 pixels on a side.
+    pub fn new(mut writer: W, width: usize, height: usize) -> Result<Self> {
+        let side = |side: usize| {
+            u32::try_from(side)
+                .ok()
+                .filter(|&side| side > 0 && side <= i32::MAX as u32)
+                .ok_or_else(|| {
+                    HgtError::InvalidParameter(format!(
+                        "PNG images must be 1 to 2^31 - 1 pixels on a side, got {width} x {height}"
+                    ))
+                })
+        };
+        let (columns, rows) = (side(width)?, side(height)?);
+        writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&columns.to_be_bytes());
+        header.extend_from_slice(&rows.to_be_bytes());
+        // 16-bit grayscale, deflate, adaptive filtering, not interlaced.
+        header.extend_from_slice(&[16, 0, 0, 0, 0]);
+        write_chunk(&mut writer, b"IHDR", &header)?;
+        // Samples without data are transparent.
+        write_chunk(&mut writer, b"tRNS", &0u16.to_be_bytes())?;
+        Ok(Self {
+            writer,
+            width,
+            encoder: ZlibEncoder::new(Vec::new(), Compression::default()),
+        })
+    }
+
+    /// Appends whole rows of cells, north to south.
+    pub fn write_rows(&mut self, data: &[f32]) -> Result<()> {
+        for row in data.chunks(self.width) {
+            let mut line = Vec::with_capacity(1 + row.len() * 2);
+            // No filter.
+            line.push(0);
+            line.extend(row.iter().flat_map(|&v| sample(v).to_be_bytes()));
+            self.encoder.write_all(&line)?;
+            let data = self.encoder.get_mut();
+            if data.len() >= CHUNK_BYTES {
+                // Whole chunks only, so that chunks don't depend on how
+                // rows are split between calls.
+                let whole = data.len() - data.len() % CHUNK_BYTES;
+                write_data(&mut self.writer, &data[..whole])?;
+                data.drain(..whole);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        let data = self.encoder.finish()?;
+        write_data(&mut self.writer, &data)?;
+        write_chunk(&mut self.writer, b"IEND", &[])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Sample of an elevation, 0 without data.
+fn sample(elevation: f32) -> u16 {
+    if elevation.is_nan() {
+        return 0;
+    }
+    (elevation.round() + 32768.0).clamp(1.0, 65535.0) as u16
+}
+
+/// Writes compressed rows in chunks of [`CHUNK_BYTES`].
+fn write_data(writer: &mut impl Write, data: &[u8]) -> Result<()> {
+    for chunk in data.chunks(CHUNK_BYTES) {
+        write_chunk(writer, b"IDAT", chunk)?;
+    }
+    Ok(())
+}
+
+fn write_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc.sum().to_be_bytes())?;
+    Ok(())
+}
+
+impl DemRaster {
+    /// Writes the raster as a 16-bit grayscale PNG, e.g. for height maps of
+    /// game engines and 3D printing: elevations are rounded to the meter and
+    /// offset by 32768, so that 32768 is sea level, and cells without data
+    /// are 0, which is transparent. PNG carries no georeferencing.
+    pub fn write_png(&self, writer: impl Write) -> Result<()> {
+        let mut png = PngWriter::new(writer, self.width, self.height)?;
+        png.write_rows(&self.data)?;
+        png.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    /// The chunks of a PNG, their checksums checked.
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut at = 8;
+        while at < png.len() {
+            let len = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = png[at + 4..at + 8].try_into().unwrap();
+            let data = png[at + 8..at + 8 + len].to_vec();
+            let mut crc = Crc::new();
+            crc.update(&png[at + 4..at + 8 + len]);
+            let sum = u32::from_be_bytes(png[at + 8 + len..at + 12 + len].try_into().unwrap());
+            assert_eq!(crc.sum(), sum);
+            chunks.push((kind, data));
+            at += 12 + len;
+        }
+        chunks
+    }
+
+    #[test]
+    fn writes_16_bit_grayscale() {
+        let mut raster = DemRaster::filled(47.0, 5.0, 0.5, 3, 2, 12.4);
+        raster.data[1] = -10.0;
+        raster.data[5] = f32::NAN;
+        let mut out = Vec::new();
+        raster.write_png(&mut out).unwrap();
+
+        let chunks = chunks(&out);
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| &kind[..]).collect();
+        assert_eq!(kinds, [&b"IHDR"[..], b"tRNS", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 3, 0, 0, 0, 2, 16, 0, 0, 0, 0]);
+        let mut rows = Vec::new();
+        ZlibDecoder::new(&chunks[2].1[..])
+            .read_to_end(&mut rows)
+            .unwrap();
+        let samples = |row: &[u8]| -> Vec<u16> {
+            assert_eq!(row[0], 0);
+            row[1..]
+                .chunks(2)
+                .map(|v| u16::from_be_bytes([v[0], v[1]]))
+                .collect()
+        };
+        assert_eq!(samples(&rows[..7]), [32780, 32758, 32780]);
+        assert_eq!(samples(&rows[7..]), [32780, 32780, 0]);
+
+        let empty = DemRaster::filled(47.0, 5.0, 0.5, 0, 2, 0.0);
+        assert!(empty.write_png(Vec::new()).is_err());
+    }
+}
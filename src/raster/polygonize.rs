@@ -0,0 +1,208 @@
+//! Outlines of groups of raster cells, as polygons following the cell edges.
+//!
+//! Rings are made of cell corners given as `(row, col)`, the top-left corner
+//! of the raster being `(0, 0)`. Cells touching only by a corner are kept in
+//! separate rings.
+
+use super::DemRaster;
+use std::collections::BTreeMap;
+
+/// A closed ring of cell corners; the first vertex is not repeated at the end.
+pub(crate) type Ring = Vec<(usize, usize)>;
+
+/// Exterior ring and holes of a group of cells, as `(latitude, longitude)`
+/// vertices.
+pub(crate) type Outline = (Vec<(f64, f64)>, Vec<Vec<(f64, f64)>>);
+
+/// Traces the boundaries of a group of `cells`, `inside` telling which cells
+/// belong to the group.
+///
+/// Exterior rings have a positive [`signed_area`], holes a negative one.
+pub(crate) fn trace_rings(
+    cells: &[(usize, usize)],
+    inside: impl Fn(isize, isize) -> bool,
+) -> Vec<Ring> {
+    // Directed edges going clockwise around each cell (rows growing
+    // downwards), kept only where the neighbouring cell is outside.
+    let mut edges: BTreeMap<(usize, usize), Vec<(usize, usize)>> = BTreeMap::new();
+    for &(row, col) in cells {
+        let (r, c) = (row as isize, col as isize);
+        let sides = [
+            ((r - 1, c), (row, col), (row, col + 1)),
+            ((r, c + 1), (row, col + 1), (row + 1, col + 1)),
+            ((r + 1, c), (row + 1, col + 1), (row + 1, col)),
+            ((r, c - 1), (row + 1, col), (row, col)),
+        ];
+        for ((nr, nc), from, to) in sides {
+            if !inside(nr, nc) {
+                edges.entry(from).or_default().push(to);
+            }
+        }
+    }
+    let mut rings = Vec::new();
+    // Rings are started where there is no choice of direction, so they are
+    // closed as soon as they come back to their first vertex.
+    while let Some(start) = edges
+        .iter()
+        .find(|(_, ends)| ends.len() == 1)
+        .map(|(&vertex, _)| vertex)
+    {
+        let mut ring = vec![start];
+        let mut current = take_edge(&mut edges, start, None);
+        let mut previous = start;
+        while current != start {
+            ring.push(current);
+            let next = take_edge(&mut edges, current, Some(previous));
+            previous = current;
+            current = next;
+        }
+        rings.push(simplify(ring));
+    }
+    rings
+}
+
+/// Removes the edge leaving `vertex`, turning right when the boundary touches
+/// itself there, which keeps diagonal neighbours apart.
+fn take_edge(
+    edges: &mut BTreeMap<(usize, usize), Vec<(usize, usize)>>,
+    vertex: (usize, usize),
+    from: Option<(usize, usize)>,
+) -> (usize, usize) {
+    let ends = edges.get_mut(&vertex).expect("boundaries are closed");
+    let index = match (from, ends.len()) {
+        (Some(from), 2) => {
+            let heading = direction(from, vertex);
+            let right = (heading.1, -heading.0);
+            ends.iter()
+                .position(|&end| direction(vertex, end) == right)
+                .unwrap_or(0)
+        }
+        _ => 0,
+    };
+    let end = ends.swap_remove(index);
+    if ends.is_empty() {
+        edges.remove(&vertex);
+    }
+    end
+}
+
+fn direction(from: (usize, usize), to: (usize, usize)) -> (isize, isize) {
+    (
+        to.0 as isize - from.0 as isize,
+        to.1 as isize - from.1 as isize,
+    )
+}
+
+/// Drops the vertices in the middle of straight runs.
+fn simplify(ring: Ring) -> Ring {
+    let n = ring.len();
+    (0..n)
+        .filter(|&i| {
+            let (prev, next) = (ring[(i + n - 1) % n], ring[(i + 1) % n]);
+            direction(prev, ring[i]) != direction(ring[i], next)
+        })
+        .map(|i| ring[i])
+        .collect()
+}
+
+/// Twice the area enclosed by a ring, in cells, positive for exterior rings.
+pub(crate) fn signed_area(ring: &[(usize, usize)]) -> f64 {
+    let n = ring.len();
+    (0..n)
+        .map(|i| {
+            let ((y0, x0), (y1, x1)) = (ring[i], ring[(i + 1) % n]);
+            x0 as f64 * y1 as f64 - x1 as f64 * y0 as f64
+        })
+        .sum()
+}
+
+impl DemRaster {
+    /// The outline of a connected group of `cells`.
+    pub(crate) fn outline(
+        &self,
+        cells: &[(usize, usize)],
+        inside: impl Fn(isize, isize) -> bool,
+    ) -> Outline {
+        let to_coordinates = |ring: &[(usize, usize)]| {
+            ring.iter()
+                .map(|&(row, col)| {
+                    (
+                        self.bbox.north - row as f64 * self.cell_size,
+                        self.bbox.west + col as f64 * self.cell_size,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut exterior = Vec::new();
+        let mut holes = Vec::new();
+        for ring in trace_rings(cells, inside) {
+            if signed_area(&ring) > 0.0 {
+                exterior = to_coordinates(&ring);
+            } else {
+                holes.push(to_coordinates(&ring));
+            }
+        }
+        (exterior, holes)
+    }
+
+    /// Surface of `cells` in square meters.
+    pub(crate) fn cells_area(&self, cells: &[(usize, usize)]) -> f64 {
+        let side = (self.cell_size * self.body.meters_per_degree()).powi(2);
+        cells
+            .iter()
+            .map(|&(row, col)| side * self.cell_center(row, col).0.to_radians().cos())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rings_of(mask: &[&str]) -> Vec<Ring> {
+        let inside = |r: isize, c: isize| {
+            r >= 0
+                && c >= 0
+                && mask
+                    .get(r as usize)
+                    .and_then(|line| line.as_bytes().get(c as usize))
+                    == Some(&b'#')
+        };
+        let cells: Vec<(usize, usize)> = (0..mask.len())
+            .flat_map(|r| (0..mask[r].len()).map(move |c| (r, c)))
+            .filter(|&(r, c)| inside(r as isize, c as isize))
+            .collect();
+        trace_rings(&cells, inside)
+    }
+
+    #[test]
+    fn outlines_cells_with_holes() {
+        let rings = rings_of(&["###", "#.#", "###"]);
+        assert_eq!(rings.len(), 2);
+        let (mut exterior, mut holes) = (0, 0);
+        for ring in &rings {
+            assert_eq!(ring.len(), 4);
+            match signed_area(ring) {
+                18.0 => exterior += 1,
+                -2.0 => holes += 1,
+                a => panic!("unexpected area {a}"),
+            }
+        }
+        assert_eq!((exterior, holes), (1, 1));
+    }
+
+    #[test]
+    fn separates_diagonal_neighbours() {
+        let rings = rings_of(&["#.", ".#"]);
+        assert_eq!(rings.len(), 2);
+        assert!(rings.iter().all(|ring| signed_area(ring) == 2.0));
+    }
+
+    #[test]
+    fn outlines_concave_shapes() {
+        let rings = rings_of(&["#..", "###"]);
+        assert_eq!(rings.len(), 1);
+        assert_eq!(rings[0].len(), 6);
+        assert_eq!(signed_area(&rings[0]), 8.0);
+    }
+}
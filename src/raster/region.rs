@@ -0,0 +1,171 @@
+//! Region growing: the terrain connected to a point within an elevation band,
+//! such as a plateau or a valley floor.
+
+use super::DemRaster;
+use crate::{BoundingBox, EarthEl, HgtError, Result};
+
+/// Cells grown from a seed point by [`DemRaster::grow_region`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    /// Elevation of the seed cell in meters; the region holds the cells
+    /// within the tolerance of it.
+    pub seed_elevation: f32,
+    /// Lowest and highest elevations in the region.
+    pub min_elevation: f32,
+    pub max_elevation: f32,
+    /// Number of raster cells in the region.
+    pub cells: usize,
+    /// Surface in square meters.
+    pub area: f64,
+    /// Outline of the region as `(latitude, longitude)` vertices, following
+    /// cell edges. The ring is closed implicitly.
+    pub exterior: Vec<(f64, f64)>,
+    /// Outlines of the enclaves outside the band, e.g. hills on a plateau.
+    pub holes: Vec<Vec<(f64, f64)>>,
+}
+
+impl DemRaster {
+    /// Grows a region from the cell containing `seed` (`(latitude,
+    /// longitude)`), adding every edge-connected cell whose elevation is
+    /// within `tolerance` meters of the seed cell.
+    ///
+    /// Returns `None` when the seed is outside the raster or its cell has
+    /// no data.
+    pub fn grow_region(&self, seed: (f64, f64), tolerance: f32) -> Option<Region> {
+        let (row, col) = self.cell_at(seed.0, seed.1)?;
+        let seed_elevation = self.get(row, col)?;
+        let band = seed_elevation - tolerance..=seed_elevation + tolerance;
+        let mut inside = vec![false; self.data.len()];
+        let mut cells = Vec::new();
+        let mut stack = vec![row * self.width + col];
+        inside[row * self.width + col] = true;
+        while let Some(index) = stack.pop() {
+            let (row, col) = (index / self.width, index % self.width);
+            cells.push((row, col));
+            let neighbours = [
+                (row > 0).then(|| index - self.width),
+                (row + 1 < self.height).then(|| index + self.width),
+                (col > 0).then(|| index - 1),
+                (col + 1 < self.width).then(|| index + 1),
+            ];
+            for next in neighbours.into_iter().flatten() {
+                if !inside[next] && band.contains(&self.data[next]) {
+                    inside[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+        let (min_elevation, max_elevation) = cells
+            .iter()
+            .map(|&(row, col)| self.data[row * self.width + col])
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+                (min.min(v), max.max(v))
+            });
+        let (exterior, holes) = self.outline(&cells, |row, col| {
+            (0..self.height as isize).contains(&row)
+                && (0..self.width as isize).contains(&col)
+                && inside[row as usize * self.width + col as usize]
+        });
+        Some(Region {
+            seed_elevation,
+            min_elevation,
+            max_elevation,
+            cells: cells.len(),
+            area: self.cells_area(&cells),
+            exterior,
+            holes,
+        })
+    }
+}
+
+impl EarthEl {
+    /// Grows a region from `seed` within `bbox`, sampled with cells of
+    /// `target_resolution` degrees. See [`DemRaster::grow_region`].
+    ///
+    /// The region is clipped to `bbox`, which must contain the seed.
+    pub async fn grow_region(
+        &self,
+        seed: (f64, f64),
+        bbox: BoundingBox,
+        target_resolution: f64,
+        tolerance: f32,
+    ) -> Result<Region> {
+        let dem = self.extract_dem(bbox, target_resolution).await?;
+        if dem.cell_at(seed.0, seed.1).is_none() {
+            return Err(HgtError::InvalidParameter(format!(
+                "seed ({}, {}) is outside the bounding box",
+                seed.0, seed.1
+            )));
+        }
+        dem.grow_region(seed, tolerance)
+            .ok_or(HgtError::InvalidCoordinate {
+                lat: seed.0,
+                lon: seed.1,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::{TileCache, TileId};
+
+    /// A 6x6 raster at 1000 m with a plateau at 1500-1520 m in the middle
+    /// and a 1600 m hill on it.
+    fn plateau() -> DemRaster {
+        let mut dem = DemRaster::filled(1.0, 0.0, 0.1, 6, 6, 1000.0);
+        for row in 1..5 {
+            for col in 1..5 {
+                dem.data[row * 6 + col] = 1500.0 + (row + col) as f32;
+            }
+        }
+        dem.data[2 * 6 + 2] = 1600.0;
+        dem
+    }
+
+    #[test]
+    fn grows_regions_within_the_band() {
+        let dem = plateau();
+        let region = dem.grow_region((0.55, 0.35), 50.0).unwrap();
+        assert_eq!(region.seed_elevation, 1507.0);
+        assert_eq!(
+            (region.min_elevation, region.max_elevation),
+            (1502.0, 1508.0)
+        );
+        assert_eq!(region.cells, 15);
+        assert_eq!(region.exterior.len(), 4);
+        assert!(region.exterior.contains(&(0.9, 0.1)));
+        assert_eq!(region.holes.len(), 1);
+
+        // A tight band follows the cells at about the seed elevation.
+        assert_eq!(dem.grow_region((0.55, 0.35), 1.0).unwrap().cells, 6);
+        let lowlands = dem.grow_region((0.95, 0.05), 100.0).unwrap();
+        assert_eq!(lowlands.cells, 20);
+        assert!(dem.grow_region((2.0, 0.0), 10.0).is_none());
+    }
+
+    #[tokio::test]
+    async fn grows_regions_from_tiles() {
+        let cache = MemoryCache::new();
+        cache
+            .store(
+                &TileId::new(0, 0),
+                &synthetic_tile(1201, |row, _| if row < 600 { 800 } else { 200 }),
+            )
+            .unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        let bbox = BoundingBox::new(0.3, 0.3, 0.7, 0.7);
+        let region = earthel
+            .grow_region((0.65, 0.5), bbox, 0.05, 10.0)
+            .await
+            .unwrap();
+        assert_eq!(region.seed_elevation, 800.0);
+        assert!(region.exterior.iter().all(|&(lat, _)| lat >= 0.5 - 1e-9));
+        assert!(earthel
+            .grow_region((0.9, 0.5), bbox, 0.05, 10.0)
+            .await
+            .is_err());
+    }
+}
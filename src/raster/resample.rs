@@ -0,0 +1,193 @@
+//! Sampling of raster cells from tiles.
+//!
+//! Cells larger than the samples of a tile cover many of them. Reading the
+//! one sample under the cell center aliases: ridges and valleys narrower than
+//! a cell flicker in and out, and stripes appear where the cells beat against
+//! the samples. Averaging the samples under each cell keeps the mean terrain.
+
+use crate::tile::TileSet;
+use crate::{BoundingBox, TileId};
+
+/// How raster operations such as [`EarthEl::extract_dem`](crate::EarthEl::extract_dem)
+/// sample cells from the tiles, see
+/// [`EarthElBuilder::resampling`](crate::EarthElBuilder::resampling).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Resampling {
+    /// The sample nearest to the cell center. Overviews keep every n-th
+    /// sample. The fastest, but coarse cells alias.
+    Nearest,
+    /// Bilinear interpolation at the cell center. Overviews average blocks
+    /// of samples, which smooths cells up to 8 times coarser than the tiles.
+    Bilinear,
+    /// Mean of the samples under the cell, weighted by the area they share
+    /// with it, from overviews averaging blocks of samples. Cells finer than
+    /// the samples are interpolated bilinearly.
+    #[default]
+    Average,
+}
+
+/// Value of the cell covering `cell`, `None` without data.
+pub(crate) fn sample_cell(tiles: &TileSet, method: Resampling, cell: &BoundingBox) -> Option<f64> {
+    let lat = (cell.south + cell.north) / 2.0;
+    let lon = (cell.west + cell.east) / 2.0;
+    match method {
+        Resampling::Nearest => tiles.nearest(lat, lon),
+        Resampling::Bilinear => tiles.interpolate(lat, lon),
+        Resampling::Average => {
+            let spacing = tiles
+                .get(&TileId::containing(lat, lon))
+                .map(|tile| 1.0 / (tile.grid_size - 1) as f64);
+            if spacing.is_some_and(|spacing| spacing >= cell.north - cell.south) {
+                tiles.interpolate(lat, lon)
+            } else {
+                area_average(tiles, cell)
+            }
+        }
+    }
+}
+
+/// Overlaps thinner than this many degrees (about 0.1 mm) are rounding
+/// errors of cells meeting tile or sample boundaries.
+const SLIVER: f64 = 1e-9;
+
+/// Mean of the samples overlapping `cell`, each sample standing for the
+/// square half a spacing around it, cut at the edges of its tile so that the
+/// edge samples shared by neighbouring tiles aren't counted twice. Voids are
+/// left out.
+fn area_average(tiles: &TileSet, cell: &BoundingBox) -> Option<f64> {
+    let (mut sum, mut weights) = (0.0, 0.0);
+    for id in cell.tiles() {
+        let Some(tile) = tiles.get(&id) else {
+            continue;
+        };
+        // The tile edge on the side of the antimeridian the cell is on.
+        let lon = f64::from(id.lon);
+        let left = lon + 360.0 * ((cell.west - lon) / 360.0).round();
+        let top = f64::from(id.lat + 1);
+        let south = cell.south.max(top - 1.0);
+        let north = cell.north.min(top);
+        let west = cell.west.max(left);
+        let east = cell.east.min(left + 1.0);
+        if north - south < SLIVER || east - west < SLIVER {
+            continue;
+        }
+        let last = tile.grid_size - 1;
+        let spacing = 1.0 / last as f64;
+        // Samples within half a spacing of the overlap.
+        let index = |offset: f64| (offset / spacing).clamp(0.0, last as f64);
+        let rows = (index(top - north) - 0.5).floor().max(0.0) as usize
+            ..=((index(top - south) + 0.5).ceil() as usize).min(last);
+        let cols = (index(west - left) - 0.5).floor().max(0.0) as usize
+            ..=((index(east - left) + 0.5).ceil() as usize).min(last);
+        for row in rows {
+            let center = top - row as f64 * spacing;
+            let height = (center + spacing / 2.0).min(north) - (center - spacing / 2.0).max(south);
+            if height < SLIVER {
+                continue;
+            }
+            for col in cols.clone() {
+                let center = left + col as f64 * spacing;
+                let width = (center + spacing / 2.0).min(east) - (center - spacing / 2.0).max(west);
+                if width < SLIVER {
+                    continue;
+                }
+                if let Some(value) = tile.at(row, col) {
+                    sum += f64::from(value) * height * width;
+                    weights += height * width;
+                }
+            }
+        }
+    }
+    (weights > 0.0).then(|| sum / weights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::{synthetic_tile, Tile, VOID};
+    use crate::{EarthEl, TileCache};
+
+    fn tiles(tiles: &[(TileId, Vec<u8>)]) -> TileSet {
+        let mut set = TileSet::default();
+        for (id, data) in tiles {
+            set.insert(Tile::decode(*id, data).unwrap());
+        }
+        set
+    }
+
+    #[test]
+    fn weights_samples_by_shared_area() {
+        // 11 samples a side, 0.1° apart: columns of 0 and 100 m alternate,
+        // and the sample at row 1, column 1 is void.
+        let set = tiles(&[(
+            TileId::new(0, 0),
+            synthetic_tile(11, |row, col| match (row, col) {
+                (1, 1) => VOID,
+                _ => (col % 2 * 100) as i16,
+            }),
+        )]);
+        let cell = |south, west, size| BoundingBox::new(south, west, south + size, west + size);
+        let average = |cell| sample_cell(&set, Resampling::Average, &cell).unwrap();
+        let assert_close = |value: f64, expected: f64| {
+            assert!((value - expected).abs() < 1e-9, "{value} != {expected}");
+        };
+        // Columns 1 to 5 in full, of which 1, 3 and 5 at 100 m.
+        assert_close(average(cell(0.05, 0.05, 0.5)), 60.0);
+        // Halves of columns 1 and 3 at 100 m around column 2 at 0 m.
+        assert_close(average(cell(0.1, 0.1, 0.2)), 50.0);
+        // Half of column 0, cut at the edge of the tile, then columns 1 and
+        // half of 2, over rows 1 and 2. The void at row 1, column 1 is left
+        // out, leaving 100 m over a third of the area.
+        assert_close(average(cell(0.75, 0.0, 0.2)), 100.0 / 3.0);
+        // Cells finer than the samples fall back to interpolation.
+        assert_close(average(cell(0.45, 0.0, 0.05)), 25.0);
+        let center_on_column_1 = cell(0.0, 0.0, 0.2);
+        assert_eq!(
+            sample_cell(&set, Resampling::Nearest, &center_on_column_1),
+            Some(100.0)
+        );
+        assert_eq!(
+            sample_cell(&set, Resampling::Bilinear, &center_on_column_1),
+            Some(100.0)
+        );
+    }
+
+    #[test]
+    fn averages_across_tiles() {
+        let set = tiles(&[
+            (TileId::new(0, 0), synthetic_tile(11, |_, _| 100)),
+            (TileId::new(0, 1), synthetic_tile(11, |_, _| 200)),
+        ]);
+        // A quarter in the western tile.
+        let cell = BoundingBox::new(0.4, 0.9, 0.8, 1.3);
+        let value = sample_cell(&set, Resampling::Average, &cell).unwrap();
+        assert!((value - 175.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn selects_the_resampling_method() {
+        let cache = std::sync::Arc::new(MemoryCache::new());
+        // Stripes 2 columns wide: 0, 0, 100, 100, 0, ...
+        let data = synthetic_tile(1201, |_, col| (col / 2 % 2 * 100) as i16);
+        cache.store(&TileId::new(0, 0), &data).unwrap();
+        let bbox = BoundingBox::new(0.2, 0.2, 0.3, 0.3);
+        let cell = 0.01;
+        let dem = |method| {
+            let earthel = EarthEl::builder()
+                .cache(std::sync::Arc::clone(&cache))
+                .resampling(method)
+                .build();
+            async move { earthel.extract_dem(bbox, cell).await.unwrap() }
+        };
+        let average = dem(Resampling::Average).await;
+        assert!(
+            average.data.iter().all(|&v| (v - 50.0).abs() < 10.0),
+            "{average:?}"
+        );
+        // Overviews keep every 8th sample, all in the 0 m stripes.
+        let nearest = dem(Resampling::Nearest).await;
+        assert!(nearest.data.iter().all(|&v| v == 0.0), "{nearest:?}");
+    }
+}
@@ -0,0 +1,156 @@
+//! Terrain roughness, the standard deviation of elevation in a moving window.
+
+use super::{check_resolution, grid_dimensions, DemRaster};
+use crate::{BoundingBox, EarthEl, HgtError, Result};
+
+impl DemRaster {
+    /// Standard deviation of the elevation in the `window` x `window` cells
+    /// centered on every cell, in meters. `window` must be odd.
+    ///
+    /// Cells without data are left out of the windows they fall in, and stay
+    /// without data in the result. Windows are truncated at the raster edges.
+    pub fn roughness(&self, window: usize) -> Result<DemRaster> {
+        check_window(window)?;
+        let radius = window / 2;
+        let sums = SummedArea::new(self);
+        let mut rough = self.clone();
+        for row in 0..self.height {
+            for col in 0..self.width {
+                if self.get(row, col).is_none() {
+                    continue;
+                }
+                let rows = row.saturating_sub(radius)..(row + radius + 1).min(self.height);
+                let cols = col.saturating_sub(radius)..(col + radius + 1).min(self.width);
+                let [count, sum, squares] = sums.window(rows, cols);
+                let mean = sum / count;
+                let variance = (squares / count - mean * mean).max(0.0);
+                rough.data[row * self.width + col] = variance.sqrt() as f32;
+            }
+        }
+        Ok(rough)
+    }
+}
+
+impl EarthEl {
+    /// Extracts a roughness raster of `bbox` (see [`DemRaster::roughness`])
+    /// with cells of `target_resolution` degrees. The terrain is sampled past
+    /// the edges of `bbox` so that border cells get full windows.
+    pub async fn extract_roughness(
+        &self,
+        bbox: BoundingBox,
+        target_resolution: f64,
+        window: usize,
+    ) -> Result<DemRaster> {
+        bbox.validate()?;
+        check_resolution(target_resolution)?;
+        check_window(window)?;
+        let radius = window / 2;
+        let (width, height) = grid_dimensions(&bbox, target_resolution);
+        let margin = radius as f64 * target_resolution;
+        let mut padded = DemRaster::filled(
+            bbox.north + margin,
+            bbox.west - margin,
+            target_resolution,
+            width + 2 * radius,
+            height + 2 * radius,
+            f32::NAN,
+        );
+        self.fill_raster(&mut padded).await?;
+        Ok(padded
+            .roughness(window)?
+            .crop(radius, radius, width, height))
+    }
+}
+
+fn check_window(window: usize) -> Result<()> {
+    if window % 2 == 1 {
+        Ok(())
+    } else {
+        Err(HgtError::InvalidParameter(format!(
+            "window must be an odd number of cells, got {window}"
+        )))
+    }
+}
+
+/// Summed-area tables of the count, sum and sum of squares of the valid cells.
+struct SummedArea {
+    width: usize,
+    tables: Vec<[f64; 3]>,
+}
+
+impl SummedArea {
+    fn new(raster: &DemRaster) -> Self {
+        let width = raster.width + 1;
+        let mut tables = vec![[0.0; 3]; width * (raster.height + 1)];
+        for row in 0..raster.height {
+            let mut line = [0.0; 3];
+            for col in 0..raster.width {
+                if let Some(value) = raster.get(row, col) {
+                    let value = f64::from(value);
+                    line[0] += 1.0;
+                    line[1] += value;
+                    line[2] += value * value;
+                }
+                let above = tables[row * width + col + 1];
+                tables[(row + 1) * width + col + 1] = [0, 1, 2].map(|i| line[i] + above[i]);
+            }
+        }
+        Self { width, tables }
+    }
+
+    /// Totals over a block of cells.
+    fn window(&self, rows: std::ops::Range<usize>, cols: std::ops::Range<usize>) -> [f64; 3] {
+        let at = |row: usize, col: usize| self.tables[row * self.width + col];
+        let (a, b, c, d) = (
+            at(rows.end, cols.end),
+            at(rows.start, cols.end),
+            at(rows.end, cols.start),
+            at(rows.start, cols.start),
+        );
+        [0, 1, 2].map(|i| a[i] - b[i] - c[i] + d[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::{TileCache, TileId};
+
+    #[test]
+    fn measures_local_variation() {
+        let mut dem = DemRaster::filled(1.0, 0.0, 0.1, 4, 3, 10.0);
+        dem.data[5] = 20.0;
+        dem.data[3] = f32::NAN;
+        let rough = dem.roughness(3).unwrap();
+        // The window around the spike holds eight 10s and one 20.
+        assert!((rough.get(1, 1).unwrap() - 3.1427).abs() < 1e-3);
+        // Only the spike and three 10s around the top left corner.
+        assert!((rough.get(0, 0).unwrap() - 4.3301).abs() < 1e-3);
+        assert_eq!(rough.get(0, 3), None);
+        assert!(dem.roughness(2).is_err());
+    }
+
+    #[tokio::test]
+    async fn pads_the_extracted_area() {
+        let cache = MemoryCache::new();
+        // Alternating 0 and 10 m columns.
+        cache
+            .store(
+                &TileId::new(0, 0),
+                &synthetic_tile(1201, |_, col| (col % 2 * 10) as i16),
+            )
+            .unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        let cell = 1.0 / 1200.0;
+        // Cell centers fall on the samples of the tile.
+        let west = 0.5 - cell / 2.0;
+        let bbox = BoundingBox::new(0.5, west, 0.5 + 2.0 * cell, west + 3.0 * cell);
+        let rough = earthel.extract_roughness(bbox, cell, 3).await.unwrap();
+        assert_eq!((rough.width, rough.height), (3, 2));
+        assert!((rough.bbox.north - bbox.north).abs() < 1e-9);
+        // Border cells see both neighbouring columns, as inner ones do.
+        assert!(rough.data.iter().all(|&v| v > 4.0 && v < 5.0), "{rough:?}");
+    }
+}
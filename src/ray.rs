@@ -0,0 +1,267 @@
+//! Intersection of 3D rays with the terrain, for drones, flight simulators
+//! and augmented reality.
+//!
+//! Rays are straight lines in space: as they go, the earth curves away below
+//! them. They are marched in steps of half a tile sample, loading tiles as
+//! they are entered, and each crossing is refined by bisection.
+
+use crate::geodesy;
+use crate::tile::TileSet;
+use crate::{EarthEl, HgtError, Result, TileId};
+use std::collections::HashSet;
+
+/// Sampling grid assumed over missing tiles, which sets the marching step.
+const ABSENT_GRID_SIZE: usize = 1201;
+/// Bisection steps refining a crossing, enough for centimeter precision.
+const REFINEMENTS: usize = 20;
+
+impl EarthEl {
+    /// Finds where a ray leaving `origin` (`(latitude, longitude, height)`,
+    /// the height in the configured vertical datum) first meets the ground.
+    ///
+    /// `direction` is `(azimuth, pitch)` in degrees: the azimuth clockwise
+    /// from north, the pitch above the horizon (negative downwards, -90
+    /// pointing straight down, which snaps the origin to the ground below
+    /// it). The ray is followed for at most `max_distance` meters along the
+    /// ground.
+    ///
+    /// Returns `(latitude, longitude, elevation)` of the intersection, or
+    /// `None` when the ray leaves the range or rises above any terrain. An
+    /// origin below ground is its own intersection. Voids and missing tiles
+    /// only stop rays if the [`MissingPolicy`](crate::MissingPolicy) gives them
+    /// an elevation.
+    pub async fn ray_terrain_intersection(
+        &self,
+        origin: (f64, f64, f64),
+        direction: (f64, f64),
+        max_distance: f64,
+    ) -> Result<Option<(f64, f64, f64)>> {
+        self.within_deadline(self.march_ray(origin, direction, max_distance))
+            .await
+    }
+
+    async fn march_ray(
+        &self,
+        origin: (f64, f64, f64),
+        direction: (f64, f64),
+        max_distance: f64,
+    ) -> Result<Option<(f64, f64, f64)>> {
+        let (azimuth, pitch) = direction;
+        if !(azimuth.is_finite() && (-90.0..=90.0).contains(&pitch)) {
+            return Err(HgtError::InvalidParameter(format!(
+                "invalid ray direction ({azimuth}, {pitch}), the pitch must be within ±90°"
+            )));
+        }
+        if !origin.2.is_finite() {
+            return Err(HgtError::InvalidParameter(format!(
+                "ray origin height must be finite, got {}",
+                origin.2
+            )));
+        }
+        if !(max_distance.is_finite() && max_distance >= 0.0) {
+            return Err(HgtError::InvalidParameter(format!(
+                "max_distance must be a non-negative number of meters, got {max_distance}"
+            )));
+        }
+        let start = geodesy::normalize(origin.0, origin.1)?;
+        let mut march = Marcher {
+            earthel: self,
+            tiles: TileSet::default(),
+            absent: HashSet::new(),
+            start,
+            azimuth,
+            height: origin.2,
+            pitch: pitch.to_radians(),
+        };
+        let Some((mut step, ground)) = march.terrain(0.0).await? else {
+            return Ok(None);
+        };
+        if ground >= origin.2 || pitch == -90.0 {
+            return Ok(ground.is_finite().then_some((start.0, start.1, ground)));
+        }
+        let mut before = 0.0;
+        while before < max_distance {
+            let after = (before + step).min(max_distance);
+            let ray = march.ray_height(after);
+            if ray > self.body.max_elevation && ray > march.ray_height(before) {
+                break;
+            }
+            let Some((next_step, ground)) = march.terrain(after).await? else {
+                return Ok(None);
+            };
+            step = next_step;
+            if ray.is_nan() || ground >= ray {
+                return march.refine(before, after).await.map(Some);
+            }
+            before = after;
+        }
+        Ok(None)
+    }
+}
+
+/// State of a ray being marched.
+struct Marcher<'a> {
+    earthel: &'a EarthEl,
+    tiles: TileSet,
+    /// Tiles that do not exist at the source.
+    absent: HashSet<TileId>,
+    start: (f64, f64),
+    azimuth: f64,
+    height: f64,
+    /// Pitch in radians.
+    pitch: f64,
+}
+
+impl Marcher<'_> {
+    fn position(&self, distance: f64) -> (f64, f64) {
+        let (lat, lon) = self
+            .earthel
+            .body
+            .destination(self.start, self.azimuth, distance);
+        (lat, (lon + 180.0).rem_euclid(360.0) - 180.0)
+    }
+
+    /// Height of the ray above the datum `distance` meters along the ground
+    /// from the origin, NaN once it has gone through the earth.
+    fn ray_height(&self, distance: f64) -> f64 {
+        let radius = self.earthel.body.radius;
+        let angle = distance / radius;
+        let cos = (angle + self.pitch).cos();
+        if cos <= 0.0 {
+            return f64::NAN;
+        }
+        (radius + self.height) * self.pitch.cos() / cos - radius
+    }
+
+    /// Ground elevation `distance` meters along the ground, together with
+    /// the marching step suited to the tile there. Voids are at `-inf`,
+    /// so that they never stop the ray.
+    async fn terrain(&mut self, distance: f64) -> Result<Option<(f64, f64)>> {
+        let (lat, lon) = self.position(distance);
+        if lat.abs() > 90.0 {
+            return Ok(None);
+        }
+        let id = TileId::containing(lat, lon);
+        if self.tiles.get(&id).is_none() && !self.absent.contains(&id) {
+            match self.earthel.load_tile_if_present(&id).await? {
+                Some(tile) => self.tiles.insert(tile),
+                None => {
+                    self.absent.insert(id);
+                }
+            }
+        }
+        let missing = self.earthel.missing.fill().unwrap_or(f64::NEG_INFINITY);
+        let (grid_size, ground) = match self.tiles.get(&id) {
+            Some(tile) => (
+                tile.grid_size,
+                tile.interpolate(lat, lon)
+                    .map_or(missing, |h| self.earthel.to_datum(lat, lon, h)),
+            ),
+            None => (ABSENT_GRID_SIZE, missing),
+        };
+        let sample = self.earthel.body.meters_per_degree() / (grid_size - 1) as f64;
+        let step = sample * lat.to_radians().cos().max(0.01) / 2.0;
+        Ok(Some((step, ground)))
+    }
+
+    /// Bisects the span where the ray went below the ground.
+    async fn refine(&mut self, mut above: f64, mut below: f64) -> Result<(f64, f64, f64)> {
+        for _ in 0..REFINEMENTS {
+            let middle = (above + below) / 2.0;
+            let ray = self.ray_height(middle);
+            match self.terrain(middle).await? {
+                Some((_, ground)) if !ray.is_nan() && ground < ray => above = middle,
+                _ => below = middle,
+            }
+        }
+        let (lat, lon) = self.position(below);
+        let ground = match self.terrain(below).await? {
+            Some((_, ground)) if ground.is_finite() => ground,
+            _ => self.ray_height(below),
+        };
+        Ok((lat, lon, ground))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::{Body, TileCache};
+
+    /// Flat ground at 100 m with a 1000 m cliff east of 0.5° E.
+    fn cliff() -> EarthEl {
+        let cache = MemoryCache::new();
+        cache
+            .store(
+                &TileId::new(0, 0),
+                &synthetic_tile(1201, |_, col| if col >= 600 { 1000 } else { 100 }),
+            )
+            .unwrap();
+        EarthEl::builder().cache(cache).build()
+    }
+
+    #[tokio::test]
+    async fn hits_the_ground_ahead() {
+        let earthel = cliff();
+        // A 45° descent from 1100 m reaches the 100 m ground 1 km ahead.
+        let (lat, lon, elevation) = earthel
+            .ray_terrain_intersection((0.2, 0.2, 1100.0), (0.0, -45.0), 5_000.0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!((Body::EARTH.distance((0.2, 0.2), (lat, lon)) - 1000.0).abs() < 1.0);
+        assert!((lon - 0.2).abs() < 1e-9);
+        assert!((elevation - 100.0).abs() < 1e-6);
+
+        // Flying level eastwards at 500 m ends in the cliff.
+        let (_, lon, elevation) = earthel
+            .ray_terrain_intersection((0.2, 0.45, 500.0), (90.0, 0.0), 20_000.0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!((0.499..=0.5).contains(&lon), "{lon}");
+        assert!((100.0..=1000.0).contains(&elevation));
+    }
+
+    #[tokio::test]
+    async fn misses_when_rising_or_out_of_range() {
+        let earthel = cliff();
+        let missed = earthel
+            .ray_terrain_intersection((0.2, 0.45, 500.0), (90.0, 0.0), 1_000.0)
+            .await
+            .unwrap();
+        assert_eq!(missed, None);
+        let rising = earthel
+            .ray_terrain_intersection((0.2, 0.2, 500.0), (270.0, 60.0), 1e9)
+            .await
+            .unwrap();
+        assert_eq!(rising, None);
+        assert!(earthel
+            .ray_terrain_intersection((0.2, 0.2, 500.0), (0.0, 100.0), 1_000.0)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn snaps_points_to_the_ground() {
+        let earthel = cliff();
+        let underground = (0.2, 0.7, 300.0);
+        let (lat, lon, elevation) = earthel
+            .ray_terrain_intersection(underground, (0.0, 0.0), 1_000.0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!((lat - 0.2).abs() < 1e-9 && (lon - 0.7).abs() < 1e-9);
+        assert_eq!(elevation, 1000.0);
+        let drone = (0.2, 0.2, 350.0);
+        let (lat, lon, elevation) = earthel
+            .ray_terrain_intersection(drone, (0.0, -90.0), 0.0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!((lat - 0.2).abs() < 1e-9 && (lon - 0.2).abs() < 1e-9);
+        assert_eq!(elevation, 100.0);
+    }
+}
@@ -0,0 +1,113 @@
+//! `serde` support for the types with a textual form: tiles by name, dates
+//! as `YYYY-MM-DD` and errors by their message.
+//!
+//! Other public types derive `Serialize` and `Deserialize`, option structs
+//! filling missing fields with their defaults so that configuration files
+//! only need to list what they change.
+
+use crate::{Date, HgtError, TileId};
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// Serializes a tile as its name, e.g. `"N47E005"`.
+impl Serialize for TileId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for TileId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        TileId::from_name(&name)
+            .ok_or_else(|| de::Error::custom(format!("{name:?} is not a tile name")))
+    }
+}
+
+/// Serializes a date as `YYYY-MM-DD`.
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Serializes an error as its message, for services reporting failures to
+/// their clients.
+impl Serialize for HgtError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MissingPolicy, PointCacheConfig, ProfileSample, VisibilityOptions};
+    use serde_json::json;
+
+    #[test]
+    fn serializes_tiles_dates_and_errors_as_strings() {
+        let tile = TileId::new(-1, -179);
+        assert_eq!(serde_json::to_value(tile).unwrap(), json!("S01W179"));
+        assert_eq!(
+            serde_json::from_value::<TileId>(json!("S01W179")).unwrap(),
+            tile
+        );
+        assert!(serde_json::from_value::<TileId>(json!("X01W179")).is_err());
+
+        let date = Date::new(2024, 2, 29).unwrap();
+        assert_eq!(serde_json::to_value(date).unwrap(), json!("2024-02-29"));
+        assert_eq!(
+            serde_json::from_value::<Date>(json!("2024-02-29")).unwrap(),
+            date
+        );
+        assert!(serde_json::from_value::<Date>(json!("2023-02-29")).is_err());
+
+        let error = HgtError::TileNotFound(tile);
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!("Tile S01W179 does not exist at the source")
+        );
+    }
+
+    #[test]
+    fn round_trips_results_and_fills_options_with_defaults() {
+        let sample = ProfileSample {
+            latitude: 45.83,
+            longitude: 6.86,
+            distance: 120.0,
+            elevation: None,
+        };
+        let value = serde_json::to_value(sample).unwrap();
+        assert_eq!(
+            value,
+            json!({"latitude": 45.83, "longitude": 6.86, "distance": 120.0, "elevation": null})
+        );
+        assert_eq!(
+            serde_json::from_value::<ProfileSample>(value).unwrap(),
+            sample
+        );
+
+        let options: VisibilityOptions =
+            serde_json::from_value(json!({"observer_height": 10.0})).unwrap();
+        assert_eq!(
+            options,
+            VisibilityOptions {
+                observer_height: 10.0,
+                ..Default::default()
+            }
+        );
+        let config: PointCacheConfig = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(config, PointCacheConfig::default());
+        let policy: MissingPolicy = serde_json::from_value(json!({"Value": -5.0})).unwrap();
+        assert_eq!(policy, MissingPolicy::Value(-5.0));
+    }
+}
@@ -0,0 +1,80 @@
+//! Web Mercator ("slippy map") tile coordinates.
+
+use crate::{EarthEl, HgtError, Result};
+use std::f64::consts::PI;
+
+/// Width and height of a map tile in pixels.
+pub const TILE_SIZE: f64 = 256.0;
+
+/// Converts a pixel (`px`, `py`) of map tile `z/x/y` to latitude and longitude.
+///
+/// Pixels are counted from the top-left corner of a 256 x 256 tile and may be
+/// fractional (e.g. clicks on high-DPI displays).
+pub fn tile_pixel_to_lat_lon(z: u8, x: u32, y: u32, px: f64, py: f64) -> Result<(f64, f64)> {
+    let tiles = 2f64.powi(i32::from(z));
+    let valid_pixel = |p: f64| (0.0..TILE_SIZE).contains(&p);
+    if z > 30
+        || f64::from(x) >= tiles
+        || f64::from(y) >= tiles
+        || !valid_pixel(px)
+        || !valid_pixel(py)
+    {
+        return Err(HgtError::InvalidParameter(format!(
+            "no pixel ({px}, {py}) in tile {z}/{x}/{y}"
+        )));
+    }
+    let fx = (f64::from(x) + px / TILE_SIZE) / tiles;
+    let fy = (f64::from(y) + py / TILE_SIZE) / tiles;
+    let longitude = fx * 360.0 - 180.0;
+    let latitude = (PI * (1.0 - 2.0 * fy)).sinh().atan().to_degrees();
+    Ok((latitude, longitude))
+}
+
+impl EarthEl {
+    /// Elevation under a pixel of Web Mercator tile `z/x/y`, typically the
+    /// point a user clicked on a web map, see
+    /// [`try_get_elevation`](Self::try_get_elevation).
+    ///
+    /// ```no_run
+    /// # async fn run() -> earthel::Result<()> {
+    /// let earthel = earthel::EarthEl::default();
+    /// let elevation = earthel.elevation_at_tile_pixel(12, 2126, 1459, 128.0, 64.0).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn elevation_at_tile_pixel(
+        &self,
+        z: u8,
+        x: u32,
+        y: u32,
+        px: f64,
+        py: f64,
+    ) -> Result<Option<f64>> {
+        let (latitude, longitude) = tile_pixel_to_lat_lon(z, x, y, px, py)?;
+        self.try_get_elevation(latitude, longitude).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_tile_pixels() {
+        let (lat, lon) = tile_pixel_to_lat_lon(0, 0, 0, 128.0, 128.0).unwrap();
+        assert!(lat.abs() < 1e-9 && lon.abs() < 1e-9);
+        let (lat, lon) = tile_pixel_to_lat_lon(1, 0, 0, 0.0, 0.0).unwrap();
+        assert!((lat - 85.0511287798).abs() < 1e-9);
+        assert_eq!(lon, -180.0);
+        // Tile 12/2126/1459 covers Mont Blanc (45.8326°N, 6.8646°E).
+        let (lat, lon) = tile_pixel_to_lat_lon(12, 2126, 1459, 0.0, 0.0).unwrap();
+        assert!((lat - 45.8900).abs() < 1e-4 && (lon - 6.8555).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rejects_pixels_outside_the_tile() {
+        assert!(tile_pixel_to_lat_lon(1, 2, 0, 0.0, 0.0).is_err());
+        assert!(tile_pixel_to_lat_lon(1, 0, 0, 256.0, 0.0).is_err());
+        assert!(tile_pixel_to_lat_lon(1, 0, 0, f64::NAN, 0.0).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! Descriptions of the configuration and cache of an instance, for bug
+//! reports and for setting up an identical instance elsewhere.
+
+use crate::cache::sha256_hex;
+#[cfg(feature = "manifest")]
+use crate::manifest::Manifest;
+use crate::raster::Resampling;
+use crate::source::Dataset;
+#[cfg(feature = "geoid")]
+use crate::VerticalDatum;
+use crate::{
+    tile, Body, BoundingBox, EarthEl, EarthElBuilder, MissingPolicy, Resolution, Result, TileId,
+};
+use std::time::Duration;
+
+/// The configuration and cache state of an [`EarthEl`], see
+/// [`EarthEl::snapshot`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    /// Version of the crate that took the snapshot.
+    pub crate_version: String,
+    /// Dataset of the [source](crate::TileSource) of the tiles.
+    pub dataset: Dataset,
+    pub coverage: BoundingBox,
+    pub body: Body,
+    #[cfg(feature = "geoid")]
+    pub vertical_datum: VerticalDatum,
+    pub missing: MissingPolicy,
+    pub resampling: Resampling,
+    pub max_age: Option<Duration>,
+    pub deadline: Option<Duration>,
+    /// Whether tiles are pinned to their versions, see
+    /// [`EarthElBuilder::strict`].
+    pub strict: bool,
+    /// The cached tiles, sorted.
+    pub tiles: Vec<TileSnapshot>,
+}
+
+/// A cached tile, as described in a [`Snapshot`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TileSnapshot {
+    pub tile: TileId,
+    /// Size of the raw tile in bytes.
+    pub size: u64,
+    /// Sampling grid of the tile, `None` for tiles that aren't square grids.
+    pub resolution: Option<Resolution>,
+    /// SHA-256 of the raw tile, in lowercase hex.
+    pub sha256: String,
+    /// URL the source downloads the tile from, if it downloads from URLs.
+    pub url: Option<String>,
+    /// Version (entity tag) of the tile, see [`EarthEl::tile_version`].
+    pub version: Option<String>,
+}
+
+impl EarthEl {
+    /// Describes the configuration of the instance and the tiles in its
+    /// cache with their checksums and versions, e.g. to attach to a bug
+    /// report. Every cached tile is read to compute its checksum.
+    ///
+    /// The source itself, the caches in memory and the callbacks are left
+    /// out; [`Snapshot::configure`] applies the rest to a builder.
+    ///
+    /// ```no_run
+    /// use earthel::EarthEl;
+    ///
+    /// # fn run() -> earthel::Result<()> {
+    /// let snapshot = EarthEl::default().snapshot()?;
+    /// println!("{} cached tiles of {}", snapshot.tiles.len(), snapshot.dataset.name);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let mut tiles = self.cache.tiles()?;
+        tiles.sort();
+        let tiles = tiles
+            .into_iter()
+            .filter_map(|tile| self.tile_snapshot(tile).transpose())
+            .collect::<Result<_>>()?;
+        Ok(Snapshot {
+            crate_version: env!("CARGO_PKG_VERSION").into(),
+            dataset: self.source.dataset(),
+            coverage: self.coverage,
+            body: self.body,
+            #[cfg(feature = "geoid")]
+            vertical_datum: self.datum,
+            missing: self.missing,
+            resampling: self.resampling,
+            max_age: self.max_age,
+            deadline: self.deadline,
+            strict: self.pins.is_some(),
+            tiles,
+        })
+    }
+
+    /// `None` if the tile was removed since the cache was listed.
+    fn tile_snapshot(&self, tile: TileId) -> Result<Option<TileSnapshot>> {
+        let Some(data) = self.cache.load(&tile)? else {
+            return Ok(None);
+        };
+        let size = data.len() as u64;
+        Ok(Some(TileSnapshot {
+            tile,
+            size,
+            resolution: tile::grid_size(size)
+                .filter(|&grid_size| (grid_size * grid_size * 2) as u64 == size)
+                .map(|grid_size| Resolution { grid_size }),
+            sha256: sha256_hex(&data),
+            url: self.source.url(&tile),
+            version: self.tile_version(&tile)?,
+        }))
+    }
+}
+
+impl Snapshot {
+    /// Applies the configuration of the snapshot to `builder`, in strict mode
+    /// pinning the tiles to their versions in the snapshot.
+    ///
+    /// ```no_run
+    /// # use earthel::{EarthEl, Snapshot};
+    /// # fn run(snapshot: Snapshot) {
+    /// let earthel = snapshot.configure(EarthEl::builder()).build();
+    /// # }
+    /// ```
+    pub fn configure(&self, builder: EarthElBuilder) -> EarthElBuilder {
+        let mut builder = builder
+            .coverage(self.coverage)
+            .body(self.body)
+            .missing(self.missing)
+            .resampling(self.resampling)
+            .strict(self.strict);
+        #[cfg(feature = "geoid")]
+        {
+            builder = builder.vertical_datum(self.vertical_datum);
+        }
+        if let Some(max_age) = self.max_age {
+            builder = builder.max_age(max_age);
+        }
+        if let Some(deadline) = self.deadline {
+            builder = builder.deadline(deadline);
+        }
+        if self.strict {
+            for tile in &self.tiles {
+                if let Some(version) = &tile.version {
+                    builder = builder.pin_version(tile.tile, version);
+                }
+            }
+        }
+        builder
+    }
+
+    /// A manifest pinning the tiles of the snapshot to their URLs and
+    /// checksums, to download the same tiles elsewhere with
+    /// [`EarthElBuilder::manifest`].
+    ///
+    /// Fails with
+    /// [`HgtError::InvalidParameter`](crate::HgtError::InvalidParameter) if a
+    /// tile has no URL.
+    #[cfg(feature = "manifest")]
+    pub fn manifest(&self) -> Result<Manifest> {
+        self.tiles
+            .iter()
+            .try_fold(Manifest::new(), |manifest, tile| {
+                let url = tile.url.as_ref().ok_or_else(|| {
+                    crate::HgtError::InvalidParameter(format!(
+                        "the snapshot has no URL for {}",
+                        tile.tile
+                    ))
+                })?;
+                manifest
+                    .pin(tile.tile, url, &tile.sha256)?
+                    .with_size(&tile.tile, tile.size)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{MemoryCache, TileMetadata};
+    use crate::metrics::SystemTime;
+    use crate::source::{SkadiSource, Validators};
+    use crate::tile::synthetic_tile;
+    use crate::{HgtError, TileCache};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn describes_the_instance() {
+        let cache = Arc::new(MemoryCache::new());
+        let data = synthetic_tile(1201, |_, _| 40);
+        let tile = TileId::new(45, 6);
+        cache.store(&tile, &data).unwrap();
+        let metadata = TileMetadata {
+            validators: Validators {
+                etag: Some("\"v1\"".into()),
+                ..Default::default()
+            },
+            fetched_at: SystemTime::now(),
+        };
+        cache.store_metadata(&tile, &metadata).unwrap();
+        cache.store(&TileId::new(-10, 20), &[1, 2, 3]).unwrap();
+        let earthel = EarthEl::builder()
+            .cache(Arc::clone(&cache))
+            .source(SkadiSource::with_base_url("https://dem.example.com"))
+            .coverage(BoundingBox::SRTM)
+            .missing(MissingPolicy::Zero)
+            .deadline(Duration::from_secs(2))
+            .strict(true)
+            .build();
+
+        let snapshot = earthel.snapshot().unwrap();
+        assert_eq!(snapshot.crate_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(snapshot.coverage, BoundingBox::SRTM);
+        assert_eq!(snapshot.deadline, Some(Duration::from_secs(2)));
+        assert!(snapshot.strict);
+        let tiles: Vec<_> = snapshot.tiles.iter().map(|tile| tile.tile).collect();
+        assert_eq!(tiles, [TileId::new(-10, 20), tile]);
+        assert_eq!(
+            snapshot.tiles[1],
+            TileSnapshot {
+                tile,
+                size: data.len() as u64,
+                resolution: Some(Resolution { grid_size: 1201 }),
+                sha256: sha256_hex(&data),
+                url: Some("https://dem.example.com/N45/N45E006.hgt.gz".into()),
+                version: Some("\"v1\"".into()),
+            }
+        );
+        assert_eq!(snapshot.tiles[0].resolution, None);
+
+        // Instances configured from the snapshot pin the same versions.
+        let restored = EarthEl::builder()
+            .cache(Arc::clone(&cache))
+            .source(SkadiSource::with_base_url("https://dem.example.com"));
+        let restored = snapshot.configure(restored).build();
+        assert_eq!(restored.snapshot().unwrap(), snapshot);
+        let other = MemoryCache::new();
+        other.store(&tile, &data).unwrap();
+        let metadata = TileMetadata {
+            validators: Validators {
+                etag: Some("\"v2\"".into()),
+                ..Default::default()
+            },
+            ..metadata
+        };
+        other.store_metadata(&tile, &metadata).unwrap();
+        let elsewhere = snapshot.configure(EarthEl::builder().cache(other)).build();
+        assert!(matches!(
+            elsewhere.get_elevation(45.5, 6.5).await,
+            Err(HgtError::VersionMismatch { .. })
+        ));
+
+        #[cfg(feature = "manifest")]
+        {
+            let manifest = snapshot.manifest().unwrap();
+            assert_eq!(manifest.len(), 2);
+            assert_eq!(manifest.get(&tile).unwrap().size, Some(data.len() as u64));
+        }
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&snapshot).unwrap();
+            assert_eq!(serde_json::from_str::<Snapshot>(&json).unwrap(), snapshot);
+        }
+    }
+}
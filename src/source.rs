@@ -0,0 +1,555 @@
+//! Where tiles come from.
+//!
+//! [`EarthEl`](crate::EarthEl) downloads missing tiles from a [`TileSource`],
+//! by default the Skadi tiles of the AWS Terrain Tiles dataset. Sources only
+//! need an HTTP client (or no network at all), so the crate runs both natively
+//! and in the browser, where requests go through `fetch`.
+//!
+//! Datasets downloaded by hand, in the formats SRTM archives are distributed
+//! in, are served by a [`DirectorySource`]; custom sources can read these
+//! formats with [`decode_tile`] and [`decode_bil`].
+
+use crate::download;
+use crate::{HgtError, Result, TileId};
+use flate2::read::GzDecoder;
+use reqwest::header::{
+    HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::StatusCode;
+use std::io::Read;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+
+pub use crate::download::RetryPolicy;
+pub use crate::formats::{decode_bil, decode_tile};
+
+/// Future returned by [`TileSource::fetch`]. It is `Send` except on
+/// WebAssembly, where browser futures are bound to their thread.
+#[cfg(not(target_arch = "wasm32"))]
+pub type SourceFuture<'a> = futures::future::BoxFuture<'a, Result<Vec<u8>>>;
+#[cfg(target_arch = "wasm32")]
+pub type SourceFuture<'a> = futures::future::LocalBoxFuture<'a, Result<Vec<u8>>>;
+
+/// Future returned by [`TileSource::revalidate`], `Send` like [`SourceFuture`].
+#[cfg(not(target_arch = "wasm32"))]
+pub type RevalidateFuture<'a> = futures::future::BoxFuture<'a, Result<Revalidation>>;
+#[cfg(target_arch = "wasm32")]
+pub type RevalidateFuture<'a> = futures::future::LocalBoxFuture<'a, Result<Revalidation>>;
+
+/// HTTP validators of a downloaded tile, sent back to the source to ask
+/// whether the tile changed since.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    /// Value of the `ETag` header.
+    pub etag: Option<String>,
+    /// Value of the `Last-Modified` header.
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Whether there is nothing to validate against.
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Outcome of [`TileSource::revalidate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Revalidation {
+    /// The cached tile is still current.
+    NotModified,
+    /// The tile changed, or could not be validated: here is its content.
+    Modified {
+        data: Vec<u8>,
+        validators: Validators,
+    },
+}
+
+/// The dataset a [`TileSource`] serves, reported along elevations by
+/// [`EarthEl::get_elevation_detailed`](crate::EarthEl::get_elevation_detailed).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dataset {
+    pub name: String,
+    /// Nominal absolute vertical accuracy in meters, as a 90% linear error,
+    /// if known.
+    pub vertical_accuracy: Option<f64>,
+}
+
+impl Dataset {
+    pub fn new(name: impl Into<String>, vertical_accuracy: Option<f64>) -> Self {
+        Self {
+            name: name.into(),
+            vertical_accuracy,
+        }
+    }
+
+    /// A dataset nothing is known about.
+    pub fn unknown() -> Self {
+        Self::new("unknown", None)
+    }
+
+    /// The void-filled SRTM tiles of the AWS Terrain Tiles, with the 16 m
+    /// absolute vertical accuracy specified for the SRTM mission.
+    pub fn srtm() -> Self {
+        Self::new("SRTM (AWS Terrain Tiles)", Some(16.0))
+    }
+}
+
+/// A provider of raw (uncompressed) HGT tiles.
+///
+/// Sources should fail with [`HgtError::TileNotFound`] for tiles they don't
+/// have, e.g. over the oceans.
+pub trait TileSource: Send + Sync {
+    fn fetch<'a>(&'a self, tile: &'a TileId) -> SourceFuture<'a>;
+
+    /// The dataset the tiles come from, [`Dataset::unknown`] by default.
+    fn dataset(&self) -> Dataset {
+        Dataset::unknown()
+    }
+
+    /// The URL `tile` is downloaded from, for pinning it in a
+    /// [manifest](crate::EarthEl::region_manifest). `None` by default, for
+    /// sources that don't download tiles from URLs.
+    fn url(&self, tile: &TileId) -> Option<String> {
+        let _ = tile;
+        None
+    }
+
+    /// Downloads a tile unless it is unchanged since it was served with
+    /// `validators`. Empty validators always download the tile.
+    ///
+    /// The default implementation cannot tell and downloads the tile again;
+    /// HTTP sources should override it with conditional requests.
+    fn revalidate<'a>(
+        &'a self,
+        tile: &'a TileId,
+        validators: &'a Validators,
+    ) -> RevalidateFuture<'a> {
+        let _ = validators;
+        Box::pin(async move {
+            Ok(Revalidation::Modified {
+                data: self.fetch(tile).await?,
+                validators: Validators::default(),
+            })
+        })
+    }
+}
+
+impl<S: TileSource + ?Sized> TileSource for std::sync::Arc<S> {
+    fn fetch<'a>(&'a self, tile: &'a TileId) -> SourceFuture<'a> {
+        (**self).fetch(tile)
+    }
+
+    fn dataset(&self) -> Dataset {
+        (**self).dataset()
+    }
+
+    fn url(&self, tile: &TileId) -> Option<String> {
+        (**self).url(tile)
+    }
+
+    fn revalidate<'a>(
+        &'a self,
+        tile: &'a TileId,
+        validators: &'a Validators,
+    ) -> RevalidateFuture<'a> {
+        (**self).revalidate(tile, validators)
+    }
+}
+
+/// Gzipped tiles laid out as `<base>/N47/N47E005.hgt.gz`, as in the Skadi
+/// bucket of the AWS Terrain Tiles dataset.
+#[derive(Debug, Clone)]
+pub struct SkadiSource {
+    base_url: String,
+    retry: RetryPolicy,
+    client: reqwest::Client,
+}
+
+impl SkadiSource {
+    /// The Skadi tiles at `https://elevation-tiles-prod.s3.amazonaws.com/skadi`.
+    pub fn new() -> Self {
+        Self::with_base_url("https://elevation-tiles-prod.s3.amazonaws.com/skadi")
+    }
+
+    /// A mirror of the Skadi layout at `base_url`.
+    pub fn with_base_url(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_owned(),
+            retry: RetryPolicy::default(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Retries rate-limited requests with `retry` instead of the default
+    /// policy, 5 retries waiting at most a minute each.
+    ///
+    /// ```
+    /// use earthel::source::{RetryPolicy, SkadiSource};
+    /// use earthel::EarthEl;
+    ///
+    /// let source = SkadiSource::new().with_retry_policy(RetryPolicy {
+    ///     max_retries: 2,
+    ///     ..RetryPolicy::default()
+    /// });
+    /// let builder = EarthEl::builder().source(source);
+    /// ```
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn tile_url(&self, tile: &TileId) -> String {
+        format!("{}/{}/{}.hgt.gz", self.base_url, tile.folder(), tile.name())
+    }
+
+    async fn download(&self, tile: &TileId, validators: &Validators) -> Result<Revalidation> {
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = validators.etag.as_deref().and_then(header_value) {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(date) = validators.last_modified.as_deref().and_then(header_value) {
+            headers.insert(IF_MODIFIED_SINCE, date);
+        }
+        let response =
+            match download::get(&self.client, &self.tile_url(tile), headers, &self.retry).await {
+                // S3 answers 403 rather than 404 for missing keys of a bucket that
+                // cannot be listed anonymously; there is no tile there in both cases.
+                Err(HgtError::ReqwestError(e))
+                    if matches!(
+                        e.status(),
+                        Some(StatusCode::NOT_FOUND | StatusCode::FORBIDDEN)
+                    ) =>
+                {
+                    return Err(HgtError::TileNotFound(*tile));
+                }
+                response => response?,
+            };
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Revalidation::NotModified);
+        }
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        let validators = Validators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        let compressed = response.bytes().await?;
+        let mut data = Vec::new();
+        GzDecoder::new(&compressed[..]).read_to_end(&mut data)?;
+        Ok(Revalidation::Modified { data, validators })
+    }
+}
+
+fn header_value(value: &str) -> Option<HeaderValue> {
+    HeaderValue::from_str(value).ok()
+}
+
+impl Default for SkadiSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TileSource for SkadiSource {
+    fn fetch<'a>(&'a self, tile: &'a TileId) -> SourceFuture<'a> {
+        Box::pin(async move {
+            match self.download(tile, &Validators::default()).await? {
+                Revalidation::Modified { data, .. } => Ok(data),
+                Revalidation::NotModified => Err(HgtError::TileUnavailable {
+                    tile: *tile,
+                    reason: "unconditional request answered 304 Not Modified".into(),
+                }),
+            }
+        })
+    }
+
+    fn revalidate<'a>(
+        &'a self,
+        tile: &'a TileId,
+        validators: &'a Validators,
+    ) -> RevalidateFuture<'a> {
+        Box::pin(self.download(tile, validators))
+    }
+
+    fn url(&self, tile: &TileId) -> Option<String> {
+        Some(self.tile_url(tile))
+    }
+
+    fn dataset(&self) -> Dataset {
+        Dataset::srtm()
+    }
+}
+
+/// Tiles provided as local files under a directory, in any folder layout:
+/// plain or gzipped `.hgt` files, `.zip` archives holding a `.hgt` file or a
+/// BIL raster (e.g. `N47E005.SRTMGL1.hgt.zip`), and BIL rasters next to their
+/// `.hdr` header (e.g. `n47_e005_1arc_v3.bil`).
+///
+/// Files are found by the tile name they start with, in either case, with an
+/// optional underscore between the latitude and the longitude. When a tile
+/// is there in several formats, plain `.hgt` files are preferred, then
+/// gzipped files, zip archives and BIL rasters.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct DirectorySource {
+    root: PathBuf,
+    dataset: Dataset,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DirectorySource {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            dataset: Dataset::unknown(),
+        }
+    }
+
+    /// Reports the tiles as coming from `dataset`.
+    pub fn with_dataset(mut self, dataset: Dataset) -> Self {
+        self.dataset = dataset;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The preferred file holding `tile` under the root, and its format
+    /// rank.
+    fn find(&self, tile: &TileId) -> Result<Option<(usize, PathBuf)>> {
+        let mut best: Option<(usize, PathBuf)> = None;
+        let mut folders = vec![self.root.clone()];
+        while let Some(folder) = folders.pop() {
+            for entry in std::fs::read_dir(&folder)? {
+                let entry = entry?;
+                let path = entry.path();
+                if entry.file_type()?.is_dir() {
+                    folders.push(path);
+                    continue;
+                }
+                let name = entry.file_name().to_string_lossy().to_ascii_lowercase();
+                let Some(rank) = file_rank(&name) else {
+                    continue;
+                };
+                if file_tile(&name) == Some(*tile)
+                    && best
+                        .as_ref()
+                        .is_none_or(|best| (rank, &path) < (best.0, &best.1))
+                {
+                    best = Some((rank, path));
+                }
+            }
+        }
+        Ok(best)
+    }
+
+    fn read(&self, tile: &TileId) -> Result<Vec<u8>> {
+        let Some((rank, path)) = self.find(tile)? else {
+            return Err(HgtError::TileNotFound(*tile));
+        };
+        read_tile_file(&path, rank)
+    }
+}
+
+/// Raw HGT samples of a tile file of the given [rank](file_rank).
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn read_tile_file(path: &Path, rank: usize) -> Result<Vec<u8>> {
+    let data = std::fs::read(path)?;
+    if rank < BIL {
+        return decode_tile(&data);
+    }
+    let header = ["hdr", "HDR"]
+        .iter()
+        .map(|extension| path.with_extension(extension))
+        .find(|header| header.is_file())
+        .ok_or_else(|| {
+            HgtError::UnsupportedFormat(format!("{} has no .hdr header", path.display()))
+        })?;
+    decode_bil(&data, &std::fs::read_to_string(header)?)
+}
+
+/// Rank of [`DirectorySource`] files with zip archives.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const ZIP: usize = 2;
+/// Rank of [`DirectorySource`] files with BIL rasters.
+#[cfg(not(target_arch = "wasm32"))]
+const BIL: usize = 3;
+
+/// Preference of a tile file of [`DirectorySource`] by its lowercase name,
+/// lowest first, `None` for other files.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn file_rank(name: &str) -> Option<usize> {
+    [".hgt", ".gz", ".zip", ".bil"]
+        .iter()
+        .position(|extension| name.ends_with(extension))
+}
+
+/// Tile named at the start of a file name such as `N47E005.SRTMGL1.hgt.zip`
+/// or `n47_e005_1arc_v3.bil`.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn file_tile(name: &str) -> Option<TileId> {
+    let name = match name.as_bytes().get(3) {
+        Some(b'_') => format!("{}{}", &name[..3], name.get(4..8)?),
+        _ => name.get(..7)?.to_owned(),
+    };
+    TileId::from_name(&name)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TileSource for DirectorySource {
+    fn fetch<'a>(&'a self, tile: &'a TileId) -> SourceFuture<'a> {
+        Box::pin(async move { self.read(tile) })
+    }
+
+    fn dataset(&self) -> Dataset {
+        self.dataset.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile::Tile;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves `tile` gzipped at its Skadi path with the `"v1"` entity tag, and
+    /// 404 for everything else.
+    async fn serve(tile: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/skadi/", listener.local_addr().unwrap());
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(tile).unwrap();
+        let body = encoder.finish().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0; 1024];
+                let n = socket.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..n]);
+                if !request.starts_with("GET /skadi/N47/N47E005.hgt.gz ") {
+                    let reply = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
+                    socket.write_all(reply.as_bytes()).await.unwrap();
+                } else if request.to_lowercase().contains("if-none-match: \"v1\"") {
+                    let reply = "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\n\r\n";
+                    socket.write_all(reply.as_bytes()).await.unwrap();
+                } else {
+                    let head = format!(
+                        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\n\r\n",
+                        body.len()
+                    );
+                    socket.write_all(head.as_bytes()).await.unwrap();
+                    socket.write_all(&body).await.unwrap();
+                }
+            }
+        });
+        base
+    }
+
+    #[tokio::test]
+    async fn reads_local_archives() {
+        use crate::formats::tests::{bil, zip};
+        use crate::tile::synthetic_tile;
+
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, data: &[u8]| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        };
+        let tile = |value| synthetic_tile(1201, move |_, _| value);
+        write(
+            "srtm/N47E005.SRTMGL3.hgt.zip",
+            &zip(&[("N47E005.hgt", &tile(1)[..])]),
+        );
+        let (data, header) = bil(&tile(2));
+        write("usgs/n46_e005_3arc_v2.bil", &data);
+        write("usgs/n46_e005_3arc_v2.hdr", header.as_bytes());
+        write("usgs/n45_e005_3arc_v2.bil", &data);
+        // Plain files win over archives of the same tile.
+        write("S01W179.hgt", &tile(3));
+        write("s01w179.hgt.zip", &zip(&[("S01W179.hgt", &tile(4)[..])]));
+        write("notes.txt", b"N44E005");
+
+        let source = DirectorySource::new(dir.path());
+        let elevation = |tile: &TileId| {
+            let source = source.clone();
+            let tile = *tile;
+            async move {
+                source
+                    .fetch(&tile)
+                    .await
+                    .map(|data| Tile::decode(tile, &data).unwrap().at(0, 0))
+            }
+        };
+        assert_eq!(elevation(&TileId::new(47, 5)).await.unwrap(), Some(1));
+        assert_eq!(elevation(&TileId::new(46, 5)).await.unwrap(), Some(2));
+        assert_eq!(elevation(&TileId::new(-1, -179)).await.unwrap(), Some(3));
+        assert!(matches!(
+            elevation(&TileId::new(45, 5)).await,
+            Err(HgtError::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            elevation(&TileId::new(44, 5)).await,
+            Err(HgtError::TileNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn builds_skadi_urls() {
+        let source = SkadiSource::new();
+        assert_eq!(
+            source.url(&TileId::new(-1, -179)).as_deref(),
+            Some("https://elevation-tiles-prod.s3.amazonaws.com/skadi/S01/S01W179.hgt.gz")
+        );
+    }
+
+    #[tokio::test]
+    async fn downloads_and_decompresses_tiles() {
+        let source = SkadiSource::with_base_url(serve(&[1, 2, 3, 4]).await);
+        assert_eq!(
+            source.fetch(&TileId::new(47, 5)).await.unwrap(),
+            [1, 2, 3, 4]
+        );
+        let missing = source.fetch(&TileId::new(0, -30)).await.unwrap_err();
+        assert!(matches!(missing, HgtError::TileNotFound(tile) if tile == TileId::new(0, -30)));
+    }
+
+    #[tokio::test]
+    async fn sends_conditional_requests() {
+        let source = SkadiSource::with_base_url(serve(&[1, 2, 3, 4]).await);
+        let tile = TileId::new(47, 5);
+        let Revalidation::Modified { data, validators } = source
+            .revalidate(&tile, &Validators::default())
+            .await
+            .unwrap()
+        else {
+            panic!("unconditional requests download the tile");
+        };
+        assert_eq!(data, [1, 2, 3, 4]);
+        assert_eq!(validators.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(
+            source.revalidate(&tile, &validators).await.unwrap(),
+            Revalidation::NotModified
+        );
+        let outdated = Validators {
+            etag: Some("\"v0\"".into()),
+            last_modified: None,
+        };
+        assert!(matches!(
+            source.revalidate(&tile, &outdated).await.unwrap(),
+            Revalidation::Modified { .. }
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+//! Streaming elevation lookups for continuous feeds of coordinates.
+
+use crate::geodesy;
+use crate::{DownloadEvent, EarthEl, HgtError, Result, TileId};
+use futures::stream::{self, Stream, StreamExt};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Tuning of [`EarthEl::elevation_stream_with`].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct StreamOptions {
+    /// Largest number of points looked up together. Smaller batches are
+    /// processed as soon as no more input is immediately available, so a slow
+    /// feed is never held back waiting for a batch to fill up.
+    pub batch_size: usize,
+    /// Number of tiles downloaded concurrently within a batch.
+    pub concurrency: usize,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 256,
+            concurrency: 4,
+        }
+    }
+}
+
+impl EarthEl {
+    /// Looks up the elevation of every `(latitude, longitude)` of a stream,
+    /// yielding results in input order, like
+    /// [`try_get_elevation`](Self::try_get_elevation).
+    ///
+    /// Points are grouped into batches; the tiles a batch needs are fetched
+    /// once, concurrently, before its points are looked up. Input is only
+    /// pulled when the output is polled, so backpressure propagates upstream.
+    ///
+    /// ```no_run
+    /// use futures::{stream, StreamExt};
+    ///
+    /// # async fn run() {
+    /// let earthel = earthel::EarthEl::default();
+    /// let feed = stream::iter(vec![(47.0592, 5.7181), (45.8326, 6.8646)]);
+    /// let mut elevations = std::pin::pin!(earthel.elevation_stream(feed));
+    /// while let Some(elevation) = elevations.next().await {
+    ///     println!("{:?}", elevation);
+    /// }
+    /// # }
+    /// ```
+    pub fn elevation_stream<'a>(
+        &'a self,
+        points: impl Stream<Item = (f64, f64)> + 'a,
+    ) -> impl Stream<Item = Result<Option<f64>>> + 'a {
+        self.elevation_stream_with(points, StreamOptions::default())
+    }
+
+    /// Same as [`elevation_stream`](Self::elevation_stream) for points coming
+    /// from an iterator.
+    pub fn elevation_stream_from_iter<'a>(
+        &'a self,
+        points: impl IntoIterator<Item = (f64, f64)> + 'a,
+    ) -> impl Stream<Item = Result<Option<f64>>> + 'a {
+        self.elevation_stream(stream::iter(points))
+    }
+
+    /// [`elevation_stream`](Self::elevation_stream) with explicit batching and
+    /// concurrency settings.
+    pub fn elevation_stream_with<'a>(
+        &'a self,
+        points: impl Stream<Item = (f64, f64)> + 'a,
+        options: StreamOptions,
+    ) -> impl Stream<Item = Result<Option<f64>>> + 'a {
+        points
+            .ready_chunks(options.batch_size.max(1))
+            .then(move |batch| self.lookup_batch(batch, options.concurrency.max(1)))
+            .flat_map(stream::iter)
+    }
+
+    /// Looks up a batch within the deadline, the points of a batch running
+    /// past it all failing with [`HgtError::DeadlineExceeded`].
+    async fn lookup_batch(
+        &self,
+        batch: Vec<(f64, f64)>,
+        concurrency: usize,
+    ) -> Vec<Result<Option<f64>>> {
+        let points = batch.len();
+        let results = self
+            .within_deadline(async { Ok(self.lookup_points(batch, concurrency).await) })
+            .await;
+        match results {
+            Ok(results) => results,
+            Err(HgtError::DeadlineExceeded(deadline)) => (0..points)
+                .map(|_| Err(HgtError::DeadlineExceeded(deadline)))
+                .collect(),
+            Err(_) => unreachable!("batches only fail as a whole past the deadline"),
+        }
+    }
+
+    async fn lookup_points(
+        &self,
+        batch: Vec<(f64, f64)>,
+        concurrency: usize,
+    ) -> Vec<Result<Option<f64>>> {
+        let mut failed: BTreeMap<TileId, HgtError> = BTreeMap::new();
+        let mut tiles: Vec<TileId> = batch
+            .iter()
+            .filter_map(|&(lat, lon)| geodesy::normalize(lat, lon).ok())
+            .map(|(lat, lon)| TileId::containing(lat, lon))
+            .collect();
+        tiles.sort();
+        tiles.dedup();
+        let missing = tiles
+            .iter()
+            .filter(|tile| !matches!(self.cache.size(tile), Ok(Some(_))))
+            .count();
+        if missing > 0 {
+            self.report(DownloadEvent::Queued { tiles: missing });
+        }
+        let fetched: Vec<(TileId, Result<u64>)> = stream::iter(tiles)
+            .map(|tile| async move { (tile, self.ensure_tile(&tile).await) })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+        let mut absent = BTreeSet::new();
+        for (tile, outcome) in fetched {
+            match outcome {
+                Err(HgtError::TileNotFound(_)) if self.missing.tolerates_missing_tiles() => {
+                    absent.insert(tile);
+                }
+                Err(e) => {
+                    failed.insert(tile, e);
+                }
+                Ok(_) => {}
+            }
+        }
+        let mut results = Vec::with_capacity(batch.len());
+        for (lat, lon) in batch {
+            let (lat, lon) = match geodesy::normalize(lat, lon) {
+                Ok(point) => point,
+                Err(e) => {
+                    results.push(Err(e));
+                    continue;
+                }
+            };
+            let tile = TileId::containing(lat, lon);
+            results.push(match failed.remove(&tile) {
+                // Every point of a failed tile fails with its error. Errors
+                // that can't be copied are reported once, the lookups of the
+                // other points of the tile reporting their own.
+                Some(error) => {
+                    if let Some(copy) = error.duplicate() {
+                        failed.insert(tile, copy);
+                    }
+                    Err(error)
+                }
+                // Missing tiles are not looked up again for every point.
+                None if absent.contains(&tile) => self.missing.resolve(lat, lon),
+                None => self.try_get_elevation(lat, lon).await,
+            });
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::TileCache;
+
+    #[tokio::test]
+    async fn yields_results_in_input_order() {
+        let cache = MemoryCache::new();
+        cache
+            .store(&TileId::new(1, 1), &synthetic_tile(1201, |_, _| 11))
+            .unwrap();
+        cache
+            .store(&TileId::new(2, 2), &synthetic_tile(1201, |_, _| 22))
+            .unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+        let points = vec![(1.5, 1.5), (2.5, 2.5), (1.1, 1.9), (2.9, 2.1), (1.0, 1.0)];
+        let options = StreamOptions {
+            batch_size: 2,
+            concurrency: 2,
+        };
+        let elevations: Vec<Option<f64>> = earthel
+            .elevation_stream_with(stream::iter(points), options)
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
+        let expected = [11.0, 22.0, 11.0, 22.0, 11.0].map(Some);
+        assert_eq!(elevations, expected);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_errors_of_failed_tiles() {
+        let cache = MemoryCache::new();
+        cache
+            .store(&TileId::new(1, 1), &synthetic_tile(1201, |_, _| 11))
+            .unwrap();
+        let earthel = EarthEl::builder()
+            .cache(cache)
+            .coverage(crate::BoundingBox::new(1.0, 1.0, 2.0, 2.0))
+            .build();
+        let points = [(5.5, 5.5), (1.5, 1.5), (5.1, 5.9)];
+        let results: Vec<_> = earthel.elevation_stream_from_iter(points).collect().await;
+        assert_eq!(results[1].as_ref().unwrap(), &Some(11.0));
+        for result in [&results[0], &results[2]] {
+            let tile = TileId::new(5, 5);
+            assert!(matches!(result, Err(HgtError::OutsideCoverage(t)) if *t == tile));
+        }
+    }
+}
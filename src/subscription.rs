@@ -0,0 +1,263 @@
+//! Notifications of upstream changes to cached tiles, for services that keep
+//! products derived from them, e.g. hillshade caches, up to date.
+
+use crate::{EarthEl, TileId};
+use futures::channel::mpsc;
+use futures::Stream;
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+
+/// A cached tile that changed upstream and was downloaded again, see
+/// [`EarthEl::subscribe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TileUpdate {
+    pub tile: TileId,
+    /// Size of the new content in bytes.
+    pub bytes: u64,
+    /// Version (entity tag) of the new content, if the source tags tiles.
+    pub version: Option<String>,
+}
+
+type Notify = Arc<dyn Fn(&TileUpdate) -> bool + Send + Sync>;
+
+struct Subscriber {
+    id: u64,
+    tiles: HashSet<TileId>,
+    /// Delivers an update, `false` once the subscriber is gone.
+    notify: Notify,
+}
+
+/// Subscribers of an instance and of its clones.
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    next_id: AtomicU64,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl Subscribers {
+    fn add(
+        self: &Arc<Self>,
+        tiles: impl IntoIterator<Item = TileId>,
+        notify: Notify,
+    ) -> Subscription {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let subscriber = Subscriber {
+            id,
+            tiles: tiles.into_iter().collect(),
+            notify,
+        };
+        self.subscribers.lock().unwrap().push(subscriber);
+        Subscription {
+            id,
+            subscribers: Arc::downgrade(self),
+        }
+    }
+
+    /// Delivers `update` to the subscribers of its tile. Callbacks run
+    /// outside the lock, so that they may subscribe or unsubscribe.
+    pub(crate) fn notify(&self, update: &TileUpdate) {
+        let notified: Vec<(u64, Notify)> = self
+            .subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|subscriber| subscriber.tiles.contains(&update.tile))
+            .map(|subscriber| (subscriber.id, Arc::clone(&subscriber.notify)))
+            .collect();
+        let gone: Vec<u64> = notified
+            .into_iter()
+            .filter(|(_, notify)| !notify(update))
+            .map(|(id, _)| id)
+            .collect();
+        if !gone.is_empty() {
+            self.subscribers
+                .lock()
+                .unwrap()
+                .retain(|subscriber| !gone.contains(&subscriber.id));
+        }
+    }
+}
+
+/// Registration of an [`on_tile_update`](EarthEl::on_tile_update) callback;
+/// dropping it unsubscribes.
+#[must_use = "dropping the subscription unsubscribes"]
+pub struct Subscription {
+    id: u64,
+    subscribers: Weak<Subscribers>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(subscribers) = self.subscribers.upgrade() {
+            subscribers
+                .subscribers
+                .lock()
+                .unwrap()
+                .retain(|subscriber| subscriber.id != self.id);
+        }
+    }
+}
+
+/// Stream of the [`TileUpdate`]s of the tiles passed to
+/// [`EarthEl::subscribe`]; dropping it unsubscribes. The stream never ends
+/// while the instance or one of its clones is alive.
+#[must_use = "streams do nothing unless polled"]
+pub struct TileUpdates {
+    receiver: mpsc::UnboundedReceiver<TileUpdate>,
+    _subscription: Subscription,
+}
+
+impl Stream for TileUpdates {
+    type Item = TileUpdate;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<TileUpdate>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+impl EarthEl {
+    /// Subscribes to upstream changes of `tiles`: whenever a
+    /// [refresh](Self::refresh_tile), or the revalidation of a tile older
+    /// than the [maximum age](crate::EarthElBuilder::max_age), finds that a
+    /// cached tile changed and downloads it again, the returned stream yields
+    /// a [`TileUpdate`]. Tiles downloaded for the first time are not updates.
+    ///
+    /// Subscriptions are shared by the clones of the instance. Updates are
+    /// buffered until the stream is polled.
+    ///
+    /// ```no_run
+    /// use earthel::{BoundingBox, EarthEl};
+    /// use futures::StreamExt;
+    ///
+    /// # async fn run(earthel: EarthEl) {
+    /// let region = BoundingBox::new(45.0, 6.0, 47.0, 8.0);
+    /// let mut updates = earthel.subscribe(region.tiles());
+    /// while let Some(update) = updates.next().await {
+    ///     println!("{} changed, rendering its hillshade again", update.tile);
+    /// }
+    /// # }
+    /// ```
+    pub fn subscribe(&self, tiles: impl IntoIterator<Item = TileId>) -> TileUpdates {
+        let (sender, receiver) = mpsc::unbounded();
+        let notify =
+            Arc::new(move |update: &TileUpdate| sender.unbounded_send(update.clone()).is_ok());
+        TileUpdates {
+            receiver,
+            _subscription: self.subscribers.add(tiles, notify),
+        }
+    }
+
+    /// Like [`subscribe`](Self::subscribe), calling `callback` on the task
+    /// that refreshed the tile instead, until the returned [`Subscription`]
+    /// is dropped.
+    pub fn on_tile_update(
+        &self,
+        tiles: impl IntoIterator<Item = TileId>,
+        callback: impl Fn(&TileUpdate) + Send + Sync + 'static,
+    ) -> Subscription {
+        self.subscribers.add(
+            tiles,
+            Arc::new(move |update: &TileUpdate| {
+                callback(update);
+                true
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::source::{SourceFuture, TileSource};
+    use crate::tests::Versioned;
+    use crate::tile::synthetic_tile;
+    use futures::StreamExt;
+    use std::sync::atomic::AtomicI16;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn notifies_upstream_changes() {
+        let source = Arc::new(Versioned::default());
+        let set_version = |version| source.version.store(version, Ordering::Relaxed);
+        let earthel = EarthEl::builder()
+            .cache(MemoryCache::new())
+            .source(Arc::clone(&source))
+            .max_age(Duration::ZERO)
+            .build();
+        let (tile, other) = (TileId::new(10, 10), TileId::new(11, 10));
+        let mut updates = earthel.clone().subscribe([tile]);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&seen);
+        let subscription = earthel.on_tile_update([tile, other], move |update| {
+            recorded.lock().unwrap().push(update.tile)
+        });
+
+        // First downloads and unchanged revalidations are not updates.
+        set_version(1);
+        earthel.get_elevation(10.5, 10.5).await.unwrap();
+        earthel.get_elevation(11.5, 10.5).await.unwrap();
+        assert!(!earthel.refresh_tile(&tile).await.unwrap());
+        set_version(2);
+        assert!(earthel.refresh_tile(&tile).await.unwrap());
+        // Revalidations of stale tiles notify too.
+        assert_eq!(earthel.get_elevation(11.5, 10.5).await.unwrap(), 2);
+
+        let update = updates.next().await.unwrap();
+        assert_eq!(
+            update,
+            TileUpdate {
+                tile,
+                bytes: 1201 * 1201 * 2,
+                version: Some("2".into()),
+            }
+        );
+        assert_eq!(*seen.lock().unwrap(), [tile, other]);
+
+        drop(subscription);
+        drop(updates);
+        set_version(3);
+        assert!(earthel.refresh_tile(&tile).await.unwrap());
+        assert_eq!(seen.lock().unwrap().len(), 2);
+        assert!(earthel.subscribers.subscribers.lock().unwrap().is_empty());
+    }
+
+    /// A source without validators, serving every tile filled with its
+    /// current value.
+    #[derive(Default)]
+    struct Untagged {
+        value: AtomicI16,
+    }
+
+    impl TileSource for Untagged {
+        fn fetch<'a>(&'a self, _: &'a TileId) -> SourceFuture<'a> {
+            let value = self.value.load(Ordering::Relaxed);
+            Box::pin(async move { Ok(synthetic_tile(1201, |_, _| value)) })
+        }
+    }
+
+    #[tokio::test]
+    async fn ignores_tiles_resent_unchanged() {
+        let source = Arc::new(Untagged::default());
+        let earthel = EarthEl::builder()
+            .cache(MemoryCache::new())
+            .source(Arc::clone(&source))
+            .build();
+        let tile = TileId::new(10, 10);
+        let mut updates = earthel.subscribe([tile]);
+        source.value.store(1, Ordering::Relaxed);
+        assert_eq!(earthel.get_elevation(10.5, 10.5).await.unwrap(), 1);
+        assert!(!earthel.refresh_tile(&tile).await.unwrap());
+        source.value.store(2, Ordering::Relaxed);
+        assert!(earthel.refresh_tile(&tile).await.unwrap());
+
+        let update = updates.next().await.unwrap();
+        assert_eq!(update.version, None);
+        drop(earthel);
+        assert_eq!(updates.next().await, None);
+    }
+}
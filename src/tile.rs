@@ -0,0 +1,340 @@
+use crate::{HgtError, Result};
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Value of void (missing) samples in HGT files.
+pub(crate) const VOID: i16 = -32768;
+
+/// Wraps a tile longitude into `[-180, 180)`.
+pub(crate) fn wrap_lon(lon: i32) -> i32 {
+    (lon + 180).rem_euclid(360) - 180
+}
+
+/// Identifies the one-degree tile whose south-west corner is at (`lat`, `lon`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TileId {
+    pub lat: i32,
+    pub lon: i32,
+}
+
+impl TileId {
+    pub fn new(lat: i32, lon: i32) -> Self {
+        Self { lat, lon }
+    }
+
+    /// The tile containing the given coordinates. Longitudes beyond ±180°
+    /// wrap around, e.g. 180.5° is in the tile at -180°.
+    pub fn containing(latitude: f64, longitude: f64) -> Self {
+        Self::new(latitude.floor() as i32, wrap_lon(longitude.floor() as i32))
+    }
+
+    /// HGT name of the tile, e.g. `N47E005`.
+    pub fn name(&self) -> String {
+        let lat_prefix = if self.lat >= 0 { "N" } else { "S" };
+        let lon_prefix = if self.lon >= 0 { "E" } else { "W" };
+        format!(
+            "{}{:02}{}{:03}",
+            lat_prefix,
+            self.lat.unsigned_abs(),
+            lon_prefix,
+            self.lon.unsigned_abs()
+        )
+    }
+
+    /// Folder grouping tiles of the same latitude band, e.g. `N47`.
+    pub fn folder(&self) -> String {
+        let lat_prefix = if self.lat >= 0 { "N" } else { "S" };
+        format!("{}{:02}", lat_prefix, self.lat.unsigned_abs())
+    }
+
+    /// Parses an HGT name such as `N47E005` (a `.hgt` suffix is accepted).
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.strip_suffix(".hgt").unwrap_or(name);
+        let (lat_part, lon_part) = name.split_at_checked(3)?;
+        let sign = |prefix: u8, positive: u8, negative: u8| match prefix.to_ascii_uppercase() {
+            p if p == positive => Some(1),
+            p if p == negative => Some(-1),
+            _ => None,
+        };
+        let lat_sign = sign(*lat_part.as_bytes().first()?, b'N', b'S')?;
+        let lon_sign = sign(*lon_part.as_bytes().first()?, b'E', b'W')?;
+        if lon_part.len() != 4 {
+            return None;
+        }
+        let lat: i32 = lat_part[1..].parse().ok()?;
+        let lon: i32 = lon_part[1..].parse().ok()?;
+        Some(Self::new(lat_sign * lat, lon_sign * lon))
+    }
+}
+
+impl fmt::Display for TileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name())
+    }
+}
+
+/// Sampling grid of a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Resolution {
+    /// Number of samples along each side of the tile, e.g. 3601 for SRTM1.
+    pub grid_size: usize,
+}
+
+impl Resolution {
+    /// Spacing between samples, in arc seconds.
+    pub fn arc_seconds(&self) -> f64 {
+        3600.0 / (self.grid_size - 1) as f64
+    }
+}
+
+/// Grid size of an HGT tile from its length in bytes.
+///
+/// Tiles are square grids of 16-bit samples, so the size is the square root
+/// of half the length. Up to one row of trailing bytes, as left by some
+/// producers, is ignored.
+pub(crate) fn grid_size(len: u64) -> Option<usize> {
+    let samples = len / 2;
+    let mut size = (samples as f64).sqrt() as u64;
+    // Correct the float square root around perfect squares.
+    while size * size > samples {
+        size -= 1;
+    }
+    while (size + 1) * (size + 1) <= samples {
+        size += 1;
+    }
+    let trailing = len - 2 * size * size;
+    (size >= 2 && trailing < 2 * size).then_some(size as usize)
+}
+
+/// Byte offset of the sample covering the given coordinates in a tile.
+pub(crate) fn sample_offset(grid_size: usize, latitude: f64, longitude: f64) -> u64 {
+    let lat_seconds = ((latitude - latitude.floor()) * 3600.0) as usize;
+    let lon_seconds = ((longitude - longitude.floor()) * 3600.0) as usize;
+    let lat_pos = (grid_size - 1) - (lat_seconds * (grid_size - 1) / 3600);
+    let lon_pos = lon_seconds * (grid_size - 1) / 3600;
+    2 * (lat_pos * grid_size + lon_pos) as u64
+}
+
+/// A tile decoded in memory, samples stored row by row from north to south.
+#[derive(Clone)]
+pub(crate) struct Tile {
+    pub id: TileId,
+    pub grid_size: usize,
+    pub samples: Vec<i16>,
+}
+
+impl Tile {
+    pub fn decode(id: TileId, data: &[u8]) -> Result<Self> {
+        let grid_size =
+            grid_size(data.len() as u64).ok_or(HgtError::InvalidResolution(data.len() as u64))?;
+        let mut samples = vec![0; grid_size * grid_size];
+        BigEndian::read_i16_into(&data[..samples.len() * 2], &mut samples);
+        Ok(Self {
+            id,
+            grid_size,
+            samples,
+        })
+    }
+
+    /// Sample at a grid position, `None` for voids.
+    pub fn at(&self, row: usize, col: usize) -> Option<i16> {
+        let value = self.samples[row * self.grid_size + col];
+        (value != VOID).then_some(value)
+    }
+
+    /// Fractional grid position (row, column) of a coordinate inside the tile.
+    pub fn position(&self, latitude: f64, longitude: f64) -> (f64, f64) {
+        grid_position(self.id, self.grid_size, latitude, longitude)
+    }
+
+    /// Sample nearest to a coordinate, `None` for voids.
+    pub fn nearest(&self, latitude: f64, longitude: f64) -> Option<i16> {
+        let (row, col) = self.position(latitude, longitude);
+        self.at(row.round() as usize, col.round() as usize)
+    }
+
+    /// Bilinear interpolation of the four samples around a coordinate. Void
+    /// samples are left out of the average; `None` if all four are void.
+    pub fn interpolate(&self, latitude: f64, longitude: f64) -> Option<f64> {
+        let (row, col) = self.position(latitude, longitude);
+        bilinear(self.grid_size, row, col, |r, c| self.at(r, c))
+    }
+}
+
+/// Fractional grid position (row, column) of a coordinate inside a tile of
+/// `grid_size` samples.
+pub(crate) fn grid_position(
+    id: TileId,
+    grid_size: usize,
+    latitude: f64,
+    longitude: f64,
+) -> (f64, f64) {
+    let last = (grid_size - 1) as f64;
+    let row = (f64::from(id.lat + 1) - latitude) * last;
+    // Longitudes given on the other side of the antimeridian, e.g. 180.5°
+    // for the tile at -180°.
+    let mut offset = longitude - f64::from(id.lon);
+    if offset > 180.0 {
+        offset -= 360.0;
+    } else if offset < -180.0 {
+        offset += 360.0;
+    }
+    let col = offset * last;
+    (row.clamp(0.0, last), col.clamp(0.0, last))
+}
+
+/// Bilinear interpolation at a grid position of the four samples around it,
+/// given by `at(row, col)`, see [`Tile::interpolate`].
+pub(crate) fn bilinear(
+    grid_size: usize,
+    row: f64,
+    col: f64,
+    at: impl Fn(usize, usize) -> Option<i16>,
+) -> Option<f64> {
+    let (r0, c0) = (row.floor() as usize, col.floor() as usize);
+    let (r1, c1) = ((r0 + 1).min(grid_size - 1), (c0 + 1).min(grid_size - 1));
+    let (dr, dc) = (row - r0 as f64, col - c0 as f64);
+    let neighbours = [
+        (r0, c0, (1.0 - dr) * (1.0 - dc)),
+        (r0, c1, (1.0 - dr) * dc),
+        (r1, c0, dr * (1.0 - dc)),
+        (r1, c1, dr * dc),
+    ];
+    let (mut sum, mut weights) = (0.0, 0.0);
+    for (r, c, weight) in neighbours {
+        if let Some(value) = at(r, c) {
+            sum += f64::from(value) * weight;
+            weights += weight;
+        }
+    }
+    if weights > 0.0 {
+        Some(sum / weights)
+    } else {
+        // All weight is on void samples, fall back to any valid neighbour.
+        neighbours
+            .iter()
+            .find_map(|&(r, c, _)| at(r, c))
+            .map(f64::from)
+    }
+}
+
+/// Decoded tiles covering a region.
+#[derive(Default)]
+pub(crate) struct TileSet {
+    tiles: HashMap<TileId, Tile>,
+}
+
+impl TileSet {
+    pub fn insert(&mut self, tile: Tile) {
+        self.tiles.insert(tile.id, tile);
+    }
+
+    pub fn extend(&mut self, tiles: impl IntoIterator<Item = Tile>) {
+        for tile in tiles {
+            self.insert(tile);
+        }
+    }
+
+    /// Interpolates a batch of coordinates, resolving the tile only when it
+    /// changes from one point to the next.
+    pub fn interpolate_many(&self, points: &[(f64, f64)]) -> Vec<Option<f64>> {
+        let mut current: Option<&Tile> = None;
+        points
+            .iter()
+            .map(|&(lat, lon)| {
+                let id = TileId::containing(lat, lon);
+                if current.is_none_or(|tile| tile.id != id) {
+                    current = self.tiles.get(&id);
+                }
+                current.and_then(|tile| tile.interpolate(lat, lon))
+            })
+            .collect()
+    }
+
+    pub fn get(&self, id: &TileId) -> Option<&Tile> {
+        self.tiles.get(id)
+    }
+
+    /// Nearest sample, `None` outside the loaded tiles or over voids.
+    pub fn nearest(&self, latitude: f64, longitude: f64) -> Option<f64> {
+        self.tiles
+            .get(&TileId::containing(latitude, longitude))
+            .and_then(|tile| tile.nearest(latitude, longitude))
+            .map(f64::from)
+    }
+
+    /// Interpolated elevation, `None` outside the loaded tiles or over voids.
+    pub fn interpolate(&self, latitude: f64, longitude: f64) -> Option<f64> {
+        self.tiles
+            .get(&TileId::containing(latitude, longitude))
+            .and_then(|tile| tile.interpolate(latitude, longitude))
+    }
+}
+
+/// Builds an HGT tile whose samples are given by `sample(row, col)`, rows
+/// running from north to south.
+#[cfg(test)]
+pub(crate) fn synthetic_tile(grid_size: usize, sample: impl Fn(usize, usize) -> i16) -> Vec<u8> {
+    let mut data = Vec::with_capacity(grid_size * grid_size * 2);
+    for row in 0..grid_size {
+        for col in 0..grid_size {
+            data.extend_from_slice(&sample(row, col).to_be_bytes());
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_tiles_by_south_west_corner() {
+        assert_eq!(TileId::containing(47.0592, 5.7181).name(), "N47E005");
+        assert_eq!(TileId::containing(-0.5, -0.5).name(), "S01W001");
+        assert_eq!(TileId::containing(-33.9, 151.2).folder(), "S34");
+    }
+
+    #[test]
+    fn parses_tile_names() {
+        assert_eq!(TileId::from_name("N47E005.hgt"), Some(TileId::new(47, 5)));
+        assert_eq!(TileId::from_name("s01w179"), Some(TileId::new(-1, -179)));
+        assert_eq!(TileId::from_name("X47E005"), None);
+        assert_eq!(TileId::from_name("N47E05"), None);
+    }
+
+    #[test]
+    fn infers_grid_sizes() {
+        assert_eq!(grid_size(25934402), Some(3601));
+        assert_eq!(grid_size(2884802), Some(1201));
+        assert_eq!(grid_size(2 * 1801 * 1801), Some(1801));
+        assert_eq!(grid_size(2884802 + 512), Some(1201));
+        assert_eq!(grid_size(2884802 + 2402), None);
+        assert_eq!(grid_size(3), None);
+        let resolution = Resolution { grid_size: 3601 };
+        assert!((resolution.arc_seconds() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn interpolates_between_samples() {
+        let data = synthetic_tile(1201, |row, col| match (row, col) {
+            (0, 1) => VOID,
+            _ => (col * 10) as i16,
+        });
+        let tile = Tile::decode(TileId::new(0, 0), &data).unwrap();
+        let half_cell = 0.5 / 1200.0;
+        let value = tile.interpolate(0.5, 0.5 + half_cell).unwrap();
+        assert!((value - 6005.0).abs() < 1e-6);
+        // The void north-east neighbour of the corner is ignored.
+        assert_eq!(tile.interpolate(1.0, half_cell), Some(0.0));
+    }
+
+    #[test]
+    fn locates_samples() {
+        // Rows are stored from north to south.
+        assert_eq!(sample_offset(1201, 47.9999, 5.0), 2 * 1201);
+        assert_eq!(sample_offset(1201, 47.0, 5.9999), 2 * (1201 * 1201 - 2));
+    }
+}
@@ -0,0 +1,362 @@
+//! Re-export of GPX tracks with the elevation, grade and cumulative gain of
+//! every point, for fitness platforms that import them.
+//!
+//! Neither the Garmin GPX nor the TCX track point extensions have fields for
+//! grades and gains, so they are written as extensions in the namespace
+//! [`EXTENSION_NAMESPACE`]: a `TrackPointExtension` holding the `Grade` in
+//! percent from the previous point and the cumulative `Gain` in meters from
+//! the start of the track.
+
+use crate::{EarthEl, HgtError, Result};
+use std::io::{Read, Write};
+
+/// XML namespace of the track point extensions, bound to the `ee` prefix.
+pub const EXTENSION_NAMESPACE: &str = "urn:earthel:track-point-extension:1";
+
+/// Document format of [`EarthEl::export_track`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrackFormat {
+    /// GPX 1.1.
+    #[default]
+    Gpx,
+    /// Garmin Training Center XML, as a course per track. Track points
+    /// must be timed, as the schema requires.
+    Tcx,
+}
+
+/// A track point with the values derived from the terrain.
+struct Point {
+    latitude: f64,
+    longitude: f64,
+    time: Option<String>,
+    /// `None` where there is no elevation data.
+    elevation: Option<f64>,
+    /// Distance from the start of the track in meters.
+    distance: f64,
+    grade: f64,
+    gain: f64,
+}
+
+struct Track {
+    name: Option<String>,
+    segments: Vec<Vec<Point>>,
+}
+
+impl EarthEl {
+    /// Reads the tracks of a GPX document and writes them to `writer` in
+    /// `format`, each point with its elevation (replacing any elevation
+    /// already present), its grade from the previous point and the elevation
+    /// gained since the start of the track, see [`EXTENSION_NAMESPACE`].
+    /// Times are kept; waypoints and routes are left out. Points without
+    /// elevation data are written without elevation, and grades and gains
+    /// skip them.
+    ///
+    /// Fails with [`HgtError::InvalidParameter`] if a point has no time in
+    /// [`TrackFormat::Tcx`].
+    ///
+    /// The writer is handed back once the document has been written.
+    ///
+    /// ```no_run
+    /// use earthel::{EarthEl, TrackFormat};
+    /// use std::fs::File;
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let input = File::open("ride.gpx")?;
+    /// let output = File::create("ride.tcx")?;
+    /// EarthEl::default().export_track(input, output, TrackFormat::Tcx).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn export_track<R: Read, W: Write>(
+        &self,
+        reader: R,
+        writer: W,
+        format: TrackFormat,
+    ) -> Result<W> {
+        self.within_deadline(self.write_track(reader, writer, format))
+            .await
+    }
+
+    async fn write_track<R: Read, W: Write>(
+        &self,
+        reader: R,
+        mut writer: W,
+        format: TrackFormat,
+    ) -> Result<W> {
+        let gpx = gpx::read(reader)?;
+        let mut tracks = Vec::with_capacity(gpx.tracks.len());
+        for track in &gpx.tracks {
+            // Distances and gains carry on across the gaps between segments.
+            let mut previous: Option<(f64, f64)> = None;
+            // Distance and elevation of the last point with data.
+            let mut last: Option<(f64, f64)> = None;
+            let (mut distance, mut gain) = (0.0, 0.0);
+            let mut segments = Vec::with_capacity(track.segments.len());
+            for segment in &track.segments {
+                let mut points = Vec::with_capacity(segment.points.len());
+                for waypoint in &segment.points {
+                    let (latitude, longitude) = (waypoint.point().y(), waypoint.point().x());
+                    if format == TrackFormat::Tcx && waypoint.time.is_none() {
+                        return Err(HgtError::InvalidParameter(format!(
+                            "TCX track points must be timed, point at lat {latitude}, lon {longitude} is not"
+                        )));
+                    }
+                    let elevation = self.try_get_elevation_or_void(latitude, longitude).await?;
+                    if let Some(position) = previous {
+                        distance += self.body.distance(position, (latitude, longitude));
+                    }
+                    previous = Some((latitude, longitude));
+                    let mut grade = 0.0;
+                    if let Some(elevation) = elevation {
+                        if let Some((last_distance, last_elevation)) = last {
+                            let (run, rise) =
+                                (distance - last_distance, elevation - last_elevation);
+                            gain += rise.max(0.0);
+                            if run > 0.0 {
+                                grade = 100.0 * rise / run;
+                            }
+                        }
+                        last = Some((distance, elevation));
+                    }
+                    points.push(Point {
+                        latitude,
+                        longitude,
+                        time: waypoint.time.map(|time| time.format()).transpose()?,
+                        elevation,
+                        distance,
+                        grade,
+                        gain,
+                    });
+                }
+                segments.push(points);
+            }
+            tracks.push(Track {
+                name: track.name.clone(),
+                segments,
+            });
+        }
+        match format {
+            TrackFormat::Gpx => write_gpx(&tracks, &mut writer)?,
+            TrackFormat::Tcx => write_tcx(&tracks, &mut writer)?,
+        }
+        Ok(writer)
+    }
+}
+
+fn write_gpx(tracks: &[Track], w: &mut impl Write) -> Result<()> {
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<gpx version="1.1" creator="earthel" xmlns="http://www.topografix.com/GPX/1/1" xmlns:ee="{EXTENSION_NAMESPACE}">"#
+    )?;
+    for track in tracks {
+        writeln!(w, "  <trk>")?;
+        if let Some(name) = &track.name {
+            writeln!(w, "    <name>{}</name>", escape(name))?;
+        }
+        for segment in &track.segments {
+            writeln!(w, "    <trkseg>")?;
+            for point in segment {
+                write!(
+                    w,
+                    r#"      <trkpt lat="{}" lon="{}">"#,
+                    point.latitude, point.longitude
+                )?;
+                if let Some(elevation) = point.elevation {
+                    write!(w, "<ele>{elevation}</ele>")?;
+                }
+                if let Some(time) = &point.time {
+                    write!(w, "<time>{time}</time>")?;
+                }
+                writeln!(w, "<extensions>{}</extensions></trkpt>", extension(point))?;
+            }
+            writeln!(w, "    </trkseg>")?;
+        }
+        writeln!(w, "  </trk>")?;
+    }
+    writeln!(w, "</gpx>")?;
+    Ok(())
+}
+
+fn write_tcx(tracks: &[Track], w: &mut impl Write) -> Result<()> {
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2" xmlns:ee="{EXTENSION_NAMESPACE}">"#
+    )?;
+    writeln!(w, "  <Courses>")?;
+    for (i, track) in tracks.iter().enumerate() {
+        // Course names are limited to 15 characters.
+        let name = match &track.name {
+            Some(name) => name.chars().take(15).collect(),
+            None => format!("Track {}", i + 1),
+        };
+        writeln!(w, "    <Course>")?;
+        writeln!(w, "      <Name>{}</Name>", escape(&name))?;
+        for segment in &track.segments {
+            writeln!(w, "      <Track>")?;
+            for point in segment {
+                // Points are timed, checked before writing.
+                let time = point.time.as_deref().unwrap_or_default();
+                write!(
+                    w,
+                    "        <Trackpoint><Time>{time}</Time><Position><LatitudeDegrees>{}</LatitudeDegrees><LongitudeDegrees>{}</LongitudeDegrees></Position>",
+                    point.latitude, point.longitude
+                )?;
+                if let Some(elevation) = point.elevation {
+                    write!(w, "<AltitudeMeters>{elevation}</AltitudeMeters>")?;
+                }
+                writeln!(
+                    w,
+                    "<DistanceMeters>{:.1}</DistanceMeters><Extensions>{}</Extensions></Trackpoint>",
+                    point.distance,
+                    extension(point)
+                )?;
+            }
+            writeln!(w, "      </Track>")?;
+        }
+        writeln!(w, "    </Course>")?;
+    }
+    writeln!(w, "  </Courses>")?;
+    writeln!(w, "</TrainingCenterDatabase>")?;
+    Ok(())
+}
+
+fn extension(point: &Point) -> String {
+    format!(
+        "<ee:TrackPointExtension><ee:Grade>{:.1}</ee:Grade><ee:Gain>{:.1}</ee:Gain></ee:TrackPointExtension>",
+        point.grade, point.gain
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::{synthetic_tile, VOID};
+    use crate::{TileCache, TileId};
+
+    const DOCUMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+          <wpt lat="45.5" lon="6.5"/>
+          <trk><name>Col &amp; back</name>
+            <trkseg>
+              <trkpt lat="45.5" lon="6.125"><ele>3</ele><time>2024-06-01T08:00:00Z</time></trkpt>
+              <trkpt lat="45.5" lon="6.25"/>
+            </trkseg>
+            <trkseg>
+              <trkpt lat="45.5" lon="6.375"/>
+              <trkpt lat="45.5" lon="6.25"/>
+            </trkseg>
+          </trk>
+        </gpx>"#;
+
+    const TIMED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+          <trk><name>Col &amp; back</name>
+            <trkseg>
+              <trkpt lat="45.5" lon="6.125"><time>2024-06-01T08:00:00Z</time></trkpt>
+              <trkpt lat="45.5" lon="6.25"><time>2024-06-01T08:20:00Z</time></trkpt>
+              <trkpt lat="45.5" lon="6.375"><time>2024-06-01T08:40:00Z</time></trkpt>
+            </trkseg>
+          </trk>
+        </gpx>"#;
+
+    #[tokio::test]
+    async fn exports_grades_and_gains() {
+        let cache = MemoryCache::new();
+        // Rising 1 m per column, 150 m per 0.125° of longitude.
+        let data = synthetic_tile(1201, |_, col| col as i16);
+        cache.store(&TileId::new(45, 6), &data).unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+
+        let gpx = earthel
+            .export_track(DOCUMENT.as_bytes(), Vec::new(), TrackFormat::Gpx)
+            .await
+            .unwrap();
+        let gpx = String::from_utf8(gpx).unwrap();
+        // Readable by GPX parsers, elevations included.
+        let parsed = gpx::read(gpx.as_bytes()).unwrap();
+        assert!(parsed.waypoints.is_empty());
+        assert_eq!(parsed.tracks[0].name.as_deref(), Some("Col & back"));
+        let elevations: Vec<_> = parsed.tracks[0]
+            .segments
+            .iter()
+            .flat_map(|segment| &segment.points)
+            .map(|point| point.elevation.unwrap())
+            .collect();
+        assert_eq!(elevations, [150.0, 300.0, 450.0, 300.0]);
+        assert!(gpx.contains("<time>2024-06-01T08:00:00.000000000Z</time>"));
+        let gains: Vec<_> = gpx
+            .match_indices("<ee:Gain>")
+            .map(|(i, _)| &gpx[i + 9..i + 14])
+            .collect();
+        assert_eq!(gains, ["0.0</", "150.0", "300.0", "300.0"]);
+        // 150 m over 0.125° of longitude, about 9.7 km at 45.5°N.
+        assert!(gpx.contains("<ee:Grade>1.5</ee:Grade>"), "{gpx}");
+        assert!(gpx.contains("<ee:Grade>-1.5</ee:Grade>"), "{gpx}");
+
+        let tcx = earthel
+            .export_track(TIMED.as_bytes(), Vec::new(), TrackFormat::Tcx)
+            .await
+            .unwrap();
+        let tcx = String::from_utf8(tcx).unwrap();
+        assert!(tcx.contains("<Name>Col &amp; back</Name>"));
+        assert_eq!(tcx.matches("<Trackpoint><Time>").count(), 3);
+        assert!(tcx.contains("<AltitudeMeters>450</AltitudeMeters><DistanceMeters>19"));
+        // TCX requires times, which untimed tracks don't have.
+        let untimed = earthel
+            .export_track(DOCUMENT.as_bytes(), Vec::new(), TrackFormat::Tcx)
+            .await;
+        assert!(matches!(untimed, Err(HgtError::InvalidParameter(_))));
+    }
+
+    #[tokio::test]
+    async fn skips_voids() {
+        let cache = MemoryCache::new();
+        // The third point, at column 450, falls in a void.
+        let data = synthetic_tile(1201, |_, col| if col < 400 { col as i16 } else { VOID });
+        cache.store(&TileId::new(45, 6), &data).unwrap();
+        let earthel = EarthEl::builder().cache(cache).build();
+
+        let gpx = earthel
+            .export_track(DOCUMENT.as_bytes(), Vec::new(), TrackFormat::Gpx)
+            .await
+            .unwrap();
+        let gpx = String::from_utf8(gpx).unwrap();
+        let parsed = gpx::read(gpx.as_bytes()).unwrap();
+        let elevations: Vec<_> = parsed.tracks[0]
+            .segments
+            .iter()
+            .flat_map(|segment| &segment.points)
+            .map(|point| point.elevation)
+            .collect();
+        assert_eq!(elevations, [Some(150.0), Some(300.0), None, Some(300.0)]);
+        let gains: Vec<_> = gpx
+            .match_indices("<ee:Gain>")
+            .map(|(i, _)| &gpx[i + 9..i + 14])
+            .collect();
+        assert_eq!(gains, ["0.0</", "150.0", "150.0", "150.0"]);
+        // The last point is level with the last one with data.
+        assert_eq!(gpx.matches("<ee:Grade>0.0</ee:Grade>").count(), 3, "{gpx}");
+
+        let tcx = earthel
+            .export_track(TIMED.as_bytes(), Vec::new(), TrackFormat::Tcx)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(tcx)
+                .unwrap()
+                .matches("<AltitudeMeters>")
+                .count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,331 @@
+//! Dated versions of a DEM, e.g. surveys before and after an eruption.
+//!
+//! Each version is a separate [`EarthEl`] with its own source and cache, so
+//! tiles of different surveys never mix. Queries pick the latest version
+//! published on or before the requested date.
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::cache::FsCache;
+use crate::raster::DemRaster;
+use crate::Profile;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::TileSource;
+use crate::{BoundingBox, EarthEl, HgtError, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::str::FromStr;
+
+/// A calendar day, ordered chronologically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    year: i32,
+    month: u8,
+    day: u8,
+}
+
+impl Date {
+    /// The given day of the proleptic Gregorian calendar, failing with
+    /// [`HgtError::InvalidParameter`] for days that don't exist.
+    pub fn new(year: i32, month: u8, day: u8) -> Result<Self> {
+        let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+        let days = match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => 0,
+        };
+        if !(1..=days).contains(&day) {
+            return Err(HgtError::InvalidParameter(format!(
+                "{year:04}-{month:02}-{day:02} is not a valid date"
+            )));
+        }
+        Ok(Self { year, month, day })
+    }
+
+    pub fn year(&self) -> i32 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+}
+
+impl fmt::Display for Date {
+    /// Formats the date as `YYYY-MM-DD`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl FromStr for Date {
+    type Err = HgtError;
+
+    /// Parses a `YYYY-MM-DD` date.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid =
+            || HgtError::InvalidParameter(format!("expected a YYYY-MM-DD date, got {s:?}"));
+        let mut parts = s.splitn(3, '-');
+        let mut next = || parts.next().ok_or_else(invalid);
+        let (year, month, day) = (next()?, next()?, next()?);
+        Self::new(
+            year.parse().map_err(|_| invalid())?,
+            month.parse().map_err(|_| invalid())?,
+            day.parse().map_err(|_| invalid())?,
+        )
+    }
+}
+
+/// Several dated versions of a DEM, queried "as of" a date.
+///
+/// ```no_run
+/// use earthel::source::SkadiSource;
+/// use earthel::{Date, VersionedDem};
+///
+/// # async fn run() -> earthel::Result<()> {
+/// let dem = VersionedDem::new()
+///     .version_from(
+///         "2021-03-01".parse()?,
+///         SkadiSource::with_base_url("https://dem.example.com/2021"),
+///         "/var/cache/earthel",
+///     )
+///     .version_from(
+///         "2022-06-01".parse()?,
+///         SkadiSource::with_base_url("https://dem.example.com/2022"),
+///         "/var/cache/earthel",
+///     );
+/// let date = Date::new(2021, 12, 25)?;
+/// let elevation = dem.elevation_as_of(28.61, -17.87, date).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct VersionedDem {
+    versions: BTreeMap<Date, EarthEl>,
+}
+
+impl VersionedDem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the version published on `date`, replacing any version of the
+    /// same date. `earthel` should have a cache of its own.
+    pub fn version(mut self, date: Date, earthel: EarthEl) -> Self {
+        self.versions.insert(date, earthel);
+        self
+    }
+
+    /// Adds the version published on `date` by `source`, caching its tiles
+    /// in a [`FsCache`] under `<cache_root>/<date>`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn version_from(
+        self,
+        date: Date,
+        source: impl TileSource + 'static,
+        cache_root: impl AsRef<Path>,
+    ) -> Self {
+        let cache = FsCache::new(cache_root.as_ref().join(date.to_string()));
+        let earthel = EarthEl::builder().cache(cache).source(source).build();
+        self.version(date, earthel)
+    }
+
+    /// Dates of the versions, oldest first.
+    pub fn dates(&self) -> impl Iterator<Item = Date> + '_ {
+        self.versions.keys().copied()
+    }
+
+    /// The version published exactly on `date`.
+    pub fn get(&self, date: Date) -> Option<&EarthEl> {
+        self.versions.get(&date)
+    }
+
+    /// The latest version published on or before `date`, with its date.
+    /// Fails with [`HgtError::NoVersion`] if all versions are more recent.
+    pub fn as_of(&self, date: Date) -> Result<(Date, &EarthEl)> {
+        self.versions
+            .range(..=date)
+            .next_back()
+            .map(|(&date, earthel)| (date, earthel))
+            .ok_or(HgtError::NoVersion(date))
+    }
+
+    /// Elevation of a location in the version current on `date`, see
+    /// [`EarthEl::try_get_elevation`].
+    pub async fn elevation_as_of(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        date: Date,
+    ) -> Result<Option<f64>> {
+        let (_, earthel) = self.as_of(date)?;
+        earthel.try_get_elevation(latitude, longitude).await
+    }
+
+    /// Elevation change of a location between the versions current on `from`
+    /// and on `to`, positive where the terrain rose. `None` where either
+    /// version has no data.
+    pub async fn elevation_change(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        from: Date,
+        to: Date,
+    ) -> Result<Option<f64>> {
+        let before = self.elevation_as_of(latitude, longitude, from).await?;
+        let after = self.elevation_as_of(latitude, longitude, to).await?;
+        Ok(before.zip(after).map(|(before, after)| after - before))
+    }
+
+    /// Elevation change inside `bbox` between the versions current on `from`
+    /// and on `to`, as a raster with cells of `target_resolution` degrees (see
+    /// [`EarthEl::extract_dem`]). Cells without data in either version hold
+    /// `f32::NAN`.
+    pub async fn diff(
+        &self,
+        from: Date,
+        to: Date,
+        bbox: BoundingBox,
+        target_resolution: f64,
+    ) -> Result<DemRaster> {
+        let (_, before) = self.as_of(from)?;
+        let (_, after) = self.as_of(to)?;
+        let before = before.extract_dem(bbox, target_resolution).await?;
+        let mut diff = after.extract_dem(bbox, target_resolution).await?;
+        for (after, before) in diff.data.iter_mut().zip(&before.data) {
+            // NaN propagates through the subtraction.
+            *after -= before;
+        }
+        Ok(diff)
+    }
+
+    /// Elevation change along a transect between the versions current on
+    /// `from` and on `to`, e.g. the thinning of a glacier: a profile sampled
+    /// every `spacing` meters as with [`EarthEl::profile`], whose elevations
+    /// are the changes, positive where the terrain rose. `None` where either
+    /// version has no data, unless its [`MissingPolicy`](crate::MissingPolicy)
+    /// fills it.
+    ///
+    /// ```no_run
+    /// # use earthel::{Date, VersionedDem};
+    /// # async fn run(dem: VersionedDem) -> earthel::Result<()> {
+    /// let transect = [(45.93, 7.87), (45.95, 7.83)];
+    /// let change = dem
+    ///     .transect_change("2000-02-11".parse()?, "2021-08-01".parse()?, &transect, 30.0)
+    ///     .await?;
+    /// for sample in &change.samples {
+    ///     println!("{:.0} m: {:?} m", sample.distance, sample.elevation);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn transect_change(
+        &self,
+        from: Date,
+        to: Date,
+        line: &[(f64, f64)],
+        spacing: f64,
+    ) -> Result<Profile> {
+        let (_, before) = self.as_of(from)?;
+        let (_, after) = self.as_of(to)?;
+        let before = before.profile(line, spacing).await?;
+        let mut change = after.profile(line, spacing).await?;
+        for (after, before) in change.samples.iter_mut().zip(&before.samples) {
+            after.elevation = after
+                .elevation
+                .zip(before.elevation)
+                .map(|(after, before)| after - before);
+        }
+        Ok(change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::{TileCache, TileId};
+
+    fn survey(sample: impl Fn(usize, usize) -> i16) -> EarthEl {
+        let cache = MemoryCache::new();
+        cache
+            .store(&TileId::new(28, -18), &synthetic_tile(1201, sample))
+            .unwrap();
+        EarthEl::builder().cache(cache).build()
+    }
+
+    fn date(s: &str) -> Date {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_validates_dates() {
+        assert_eq!(date("2024-02-29"), Date::new(2024, 2, 29).unwrap());
+        assert_eq!(date("2021-09-19").to_string(), "2021-09-19");
+        assert!("2023-02-29".parse::<Date>().is_err());
+        assert!("2023-13-01".parse::<Date>().is_err());
+        assert!("19/09/2021".parse::<Date>().is_err());
+        assert!(date("2021-09-19") < date("2021-12-13"));
+    }
+
+    #[tokio::test]
+    async fn queries_versions_as_of_dates() {
+        // A new cone grew in the eastern half of the tile.
+        let dem = VersionedDem::new()
+            .version(date("2021-06-01"), survey(|_, _| 500))
+            .version(
+                date("2022-01-15"),
+                survey(|_, col| if col >= 600 { 700 } else { 500 }),
+            );
+        assert_eq!(dem.dates().count(), 2);
+        assert!(matches!(
+            dem.as_of(date("2020-01-01")),
+            Err(HgtError::NoVersion(_))
+        ));
+        let (current, _) = dem.as_of(date("2021-12-31")).unwrap();
+        assert_eq!(current, date("2021-06-01"));
+        let (lat, lon) = (28.5, -17.25);
+        assert_eq!(
+            dem.elevation_as_of(lat, lon, date("2022-03-01"))
+                .await
+                .unwrap(),
+            Some(700.0)
+        );
+        assert_eq!(
+            dem.elevation_change(lat, lon, date("2021-07-01"), date("2023-01-01"))
+                .await
+                .unwrap(),
+            Some(200.0)
+        );
+
+        let bbox = BoundingBox::new(28.4, -17.8, 28.5, -17.2);
+        let diff = dem
+            .diff(date("2021-06-01"), date("2022-01-15"), bbox, 0.1)
+            .await
+            .unwrap();
+        assert_eq!(diff.width, 6);
+        assert_eq!(diff.data[..2], [0.0, 0.0]);
+        assert_eq!(diff.data[4..], [200.0, 200.0]);
+        // The cells on either side of the edge of the cone average a few
+        // samples of the other side.
+        assert!(diff.data[2] < 10.0 && diff.data[3] > 190.0, "{diff:?}");
+
+        let transect = [(28.5, -17.9), (28.5, -17.1)];
+        let change = dem
+            .transect_change(date("2021-06-01"), date("2022-01-15"), &transect, 5000.0)
+            .await
+            .unwrap();
+        let first = change.samples.first().unwrap();
+        let last = change.samples.last().unwrap();
+        assert_eq!((first.elevation, last.elevation), (Some(0.0), Some(200.0)));
+        assert!(change.samples.len() > 10);
+    }
+}
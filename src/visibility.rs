@@ -0,0 +1,230 @@
+//! Line-of-sight between sets of observers and targets.
+//!
+//! Sight lines are straight in a frame where the earth is flattened by the
+//! usual atmospheric refraction correction: terrain between two points is
+//! raised by `x (d - x) / 2R'` at `x` meters from the observer, `d` being the
+//! distance between them and `R'` the radius of the [`Body`](crate::Body)
+//! divided by `1 - k`.
+
+use crate::geodesy;
+use crate::tile::TileSet;
+use crate::{EarthEl, HgtError, Result, TileId};
+use std::collections::BTreeSet;
+
+/// Settings of [`EarthEl::visibility_matrix`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct VisibilityOptions {
+    /// Height of the observers above the ground, in meters.
+    pub observer_height: f64,
+    /// Height of the targets above the ground, in meters.
+    pub target_height: f64,
+    /// Distance between terrain samples along sight lines, in meters.
+    pub spacing: f64,
+    /// Atmospheric refraction coefficient `k`; 0 ignores refraction, as on
+    /// bodies without an atmosphere.
+    pub refraction: f64,
+}
+
+impl Default for VisibilityOptions {
+    fn default() -> Self {
+        Self {
+            observer_height: 2.0,
+            target_height: 0.0,
+            spacing: 30.0,
+            refraction: 0.13,
+        }
+    }
+}
+
+/// Which targets each observer sees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VisibilityMatrix {
+    pub observers: usize,
+    pub targets: usize,
+    /// Row-major visibility, one row of `targets` per observer.
+    visible: Vec<bool>,
+}
+
+impl VisibilityMatrix {
+    /// Whether observer `observer` sees target `target`.
+    pub fn is_visible(&self, observer: usize, target: usize) -> bool {
+        self.visible[observer * self.targets + target]
+    }
+
+    /// Indices of the targets seen by an observer.
+    pub fn visible_targets(&self, observer: usize) -> impl Iterator<Item = usize> + '_ {
+        let row = &self.visible[observer * self.targets..(observer + 1) * self.targets];
+        row.iter()
+            .enumerate()
+            .filter(|(_, &visible)| visible)
+            .map(|(target, _)| target)
+    }
+
+    /// Number of observers seeing a target.
+    pub fn coverage(&self, target: usize) -> usize {
+        (0..self.observers)
+            .filter(|&observer| self.is_visible(observer, target))
+            .count()
+    }
+}
+
+impl EarthEl {
+    /// Computes whether every observer sees every target, as a
+    /// `observers.len()` x `targets.len()` matrix.
+    ///
+    /// Tiles are decoded once for all the sight lines, and the ground
+    /// elevation of each observer and target is looked up once. Terrain
+    /// without data does not block sight lines, unless the
+    /// [`MissingPolicy`](crate::MissingPolicy) gives it an elevation.
+    pub async fn visibility_matrix(
+        &self,
+        observers: &[(f64, f64)],
+        targets: &[(f64, f64)],
+        options: VisibilityOptions,
+    ) -> Result<VisibilityMatrix> {
+        if !(options.spacing.is_finite() && options.spacing > 0.0) {
+            return Err(HgtError::InvalidParameter(format!(
+                "spacing must be a positive number of meters, got {}",
+                options.spacing
+            )));
+        }
+        let observers = normalize_all(observers)?;
+        let targets = normalize_all(targets)?;
+        let mut needed = BTreeSet::new();
+        for &observer in &observers {
+            for &target in &targets {
+                let line = self.body.densify(&[observer, target], options.spacing);
+                needed.extend(line.iter().map(|&(lat, lon)| TileId::containing(lat, lon)));
+            }
+        }
+        let tiles = self
+            .within_deadline(async {
+                let mut tiles = TileSet::default();
+                for id in needed {
+                    tiles.extend(self.load_tile_if_present(&id).await?);
+                }
+                Ok(tiles)
+            })
+            .await?;
+        let ground = |points: &[(f64, f64)], height: f64| -> Vec<f64> {
+            tiles
+                .interpolate_many(points)
+                .into_iter()
+                .map(|elevation| elevation.or(self.missing.fill()).unwrap_or(0.0) + height)
+                .collect()
+        };
+        let observer_heights = ground(&observers, options.observer_height);
+        let target_heights = ground(&targets, options.target_height);
+        let radius = self.body.radius / (1.0 - options.refraction);
+        let mut visible = Vec::with_capacity(observers.len() * targets.len());
+        for (&observer, &from) in observers.iter().zip(&observer_heights) {
+            for (&target, &to) in targets.iter().zip(&target_heights) {
+                let line = self.body.densify(&[observer, target], options.spacing);
+                let distance = self.body.distance(observer, target);
+                let terrain = tiles.interpolate_many(&line);
+                visible.push(line.iter().zip(terrain).all(|(&point, elevation)| {
+                    let Some(elevation) = elevation.or(self.missing.fill()) else {
+                        return true;
+                    };
+                    let x = self.body.distance(observer, point);
+                    if x <= 0.0 || x >= distance {
+                        return true;
+                    }
+                    let sight = from + (to - from) * x / distance;
+                    elevation + x * (distance - x) / (2.0 * radius) <= sight
+                }));
+            }
+        }
+        Ok(VisibilityMatrix {
+            observers: observers.len(),
+            targets: targets.len(),
+            visible,
+        })
+    }
+}
+
+fn normalize_all(points: &[(f64, f64)]) -> Result<Vec<(f64, f64)>> {
+    points
+        .iter()
+        .map(|&(lat, lon)| geodesy::normalize(lat, lon))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::synthetic_tile;
+    use crate::TileCache;
+
+    /// Flat terrain at sea level with a 500 m ridge along 0.5° E.
+    fn ridge() -> EarthEl {
+        let cache = MemoryCache::new();
+        cache
+            .store(
+                &TileId::new(0, 0),
+                &synthetic_tile(1201, |_, col| if col == 600 { 500 } else { 0 }),
+            )
+            .unwrap();
+        EarthEl::builder().cache(cache).build()
+    }
+
+    #[tokio::test]
+    async fn blocks_sight_lines_behind_ridges() {
+        let earthel = ridge();
+        let observers = [(0.5, 0.45), (0.5, 0.5)];
+        let targets = [(0.5, 0.48), (0.5, 0.55), (0.52, 0.44)];
+        let matrix = earthel
+            .visibility_matrix(&observers, &targets, VisibilityOptions::default())
+            .await
+            .unwrap();
+        assert_eq!((matrix.observers, matrix.targets), (2, 3));
+        assert_eq!(matrix.visible_targets(0).collect::<Vec<_>>(), [0, 2]);
+        // From the ridge both sides are in view.
+        assert_eq!(matrix.visible_targets(1).collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(matrix.coverage(1), 1);
+
+        let towers = VisibilityOptions {
+            observer_height: 1500.0,
+            ..Default::default()
+        };
+        let matrix = earthel
+            .visibility_matrix(&observers, &targets, towers)
+            .await
+            .unwrap();
+        assert!(matrix.is_visible(0, 1));
+    }
+
+    #[tokio::test]
+    async fn hides_targets_below_the_horizon() {
+        let earthel = ridge();
+        // About 33 km apart over flat ground, beyond the 5 km horizon of a
+        // 2 m observer.
+        let (observer, target) = ((0.1, 0.05), (0.1, 0.35));
+        let options = VisibilityOptions::default();
+        let matrix = earthel
+            .visibility_matrix(&[observer], &[target], options)
+            .await
+            .unwrap();
+        assert!(!matrix.is_visible(0, 0));
+        let masts = VisibilityOptions {
+            observer_height: 30.0,
+            target_height: 30.0,
+            ..options
+        };
+        let matrix = earthel
+            .visibility_matrix(&[observer], &[target], masts)
+            .await
+            .unwrap();
+        assert!(matrix.is_visible(0, 0));
+        assert!(earthel
+            .visibility_matrix(&[(91.0, 0.0)], &[target], options)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,455 @@
+//! Elevation statistics of many zones, e.g. administrative boundaries, in a
+//! single pass over the tiles.
+
+use crate::geometry::polygon_contains;
+use crate::{BoundingBox, EarthEl, Result, TileId};
+use geo_types::{MultiPolygon, Point};
+use std::collections::BTreeSet;
+
+/// Area-weighted elevation statistics of a zone, see
+/// [`EarthEl::zonal_stats`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ZonalStats {
+    /// Number of samples with data inside the zone.
+    pub samples: usize,
+    /// Area in square meters covered by these samples.
+    pub area: f64,
+    /// Mean elevation, each sample weighted by the area of its cell, which
+    /// shrinks towards the poles.
+    pub mean: f64,
+    /// Lowest elevation of the samples inside the zone.
+    pub min: f64,
+    /// Highest elevation of the samples inside the zone.
+    pub max: f64,
+}
+
+/// Samples per side of the tiles filled in for missing ones, those of a 3
+/// arc-second grid.
+const FILLED_GRID_SIZE: usize = 1201;
+
+/// Accumulates the samples of a zone.
+#[derive(Clone, Copy)]
+struct Accumulator {
+    samples: usize,
+    area: f64,
+    weighted: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self {
+            samples: 0,
+            area: 0.0,
+            weighted: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Accumulator {
+    fn add(&mut self, value: f64, area: f64) {
+        self.samples += 1;
+        self.area += area;
+        self.weighted += value * area;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn stats(self) -> Option<ZonalStats> {
+        (self.samples > 0).then(|| ZonalStats {
+            samples: self.samples,
+            area: self.area,
+            mean: self.weighted / self.area,
+            min: self.min,
+            max: self.max,
+        })
+    }
+}
+
+impl EarthEl {
+    /// Area-weighted elevation statistics of each zone (holes excluded), in
+    /// input order, from every sample of the tiles inside it: `None` for
+    /// zones without data, e.g. over the ocean or smaller than a sample.
+    ///
+    /// Tiles are decoded one at a time, once for all the zones. Voids and
+    /// tiles outside of the coverage follow the
+    /// [missing data policy](crate::EarthElBuilder::missing), like tiles
+    /// missing at the source: left out unless the policy fills them, the
+    /// samples of filled tiles being those of a 3 arc-second grid.
+    ///
+    /// ```no_run
+    /// use earthel::EarthEl;
+    /// use geo_types::{polygon, MultiPolygon};
+    ///
+    /// # async fn run() -> earthel::Result<()> {
+    /// let district: MultiPolygon<f64> = polygon![
+    ///     (x: 6.8, y: 45.8), (x: 7.0, y: 45.8), (x: 7.0, y: 46.0), (x: 6.8, y: 46.0),
+    /// ]
+    /// .into();
+    /// let stats = EarthEl::default().zonal_stats(&[district]).await?;
+    /// if let Some(stats) = stats[0] {
+    ///     println!("{:.0} m on average, up to {} m", stats.mean, stats.max);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn zonal_stats(
+        &self,
+        zones: &[MultiPolygon<f64>],
+    ) -> Result<Vec<Option<ZonalStats>>> {
+        self.within_deadline(self.accumulate_zones(zones)).await
+    }
+
+    async fn accumulate_zones(
+        &self,
+        zones: &[MultiPolygon<f64>],
+    ) -> Result<Vec<Option<ZonalStats>>> {
+        let bounds: Vec<_> = zones.iter().map(zone_bounds).collect();
+        let tiles: BTreeSet<TileId> = bounds
+            .iter()
+            .flatten()
+            .flat_map(BoundingBox::tiles)
+            .collect();
+        let fill = self.missing.fill();
+        let mut accumulators = vec![Accumulator::default(); zones.len()];
+        for id in tiles {
+            let tile = match self.coverage.intersects(&id) {
+                true => self.load_tile_if_present(&id).await?,
+                false => None,
+            };
+            let grid_size = match (&tile, fill) {
+                (Some(tile), _) => tile.grid_size,
+                (None, Some(_)) => FILLED_GRID_SIZE,
+                (None, None) => continue,
+            };
+            let last = (grid_size - 1) as f64;
+            let step = 1.0 / last;
+            let cell_side = self.body.meters_per_degree() * step;
+            // Row 0 and the last column are those of the neighbouring
+            // tiles, so that every sample counts once.
+            let rows = 1..grid_size;
+            let cols = 0..grid_size - 1;
+            for ((zone, bounds), accumulator) in zones.iter().zip(&bounds).zip(&mut accumulators) {
+                let Some(bounds) = bounds else { continue };
+                // Samples of the tile inside the bounds of the zone.
+                let north = f64::from(id.lat + 1);
+                let first_row = ((north - bounds.north) * last)
+                    .floor()
+                    .max(rows.start as f64);
+                let last_row = ((north - bounds.south) * last)
+                    .ceil()
+                    .min(rows.end as f64 - 1.0);
+                let west = f64::from(id.lon);
+                let first_col = ((bounds.west - west) * last).floor().max(cols.start as f64);
+                let last_col = ((bounds.east - west) * last)
+                    .ceil()
+                    .min(cols.end as f64 - 1.0);
+                if first_row > last_row || first_col > last_col {
+                    continue;
+                }
+                for row in first_row as usize..=last_row as usize {
+                    let lat = north - row as f64 * step;
+                    let area = cell_side * cell_side * lat.to_radians().cos();
+                    for col in first_col as usize..=last_col as usize {
+                        let value = match &tile {
+                            Some(tile) => tile.at(row, col).map(f64::from).or(fill),
+                            None => fill,
+                        };
+                        let Some(value) = value else {
+                            continue;
+                        };
+                        let point = Point::new(west + col as f64 * step, lat);
+                        if zone.iter().any(|polygon| polygon_contains(polygon, &point)) {
+                            accumulator.add(value, area);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(accumulators.into_iter().map(Accumulator::stats).collect())
+    }
+
+    /// Adds the elevation statistics of every `Polygon` and `MultiPolygon`
+    /// feature of a GeoJSON `FeatureCollection` or `Feature` to its properties,
+    /// see [`zonal_stats`](Self::zonal_stats): `elevation_mean`,
+    /// `elevation_min`, `elevation_max` in meters and `elevation_area` in
+    /// square meters, `null` without data. Other features are left as they
+    /// are.
+    ///
+    /// ```no_run
+    /// use earthel::EarthEl;
+    ///
+    /// # async fn run(districts: serde_json::Value) -> earthel::Result<()> {
+    /// let annotated = EarthEl::default().annotate_zones_geojson(districts).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "geojson")]
+    pub async fn annotate_zones_geojson(
+        &self,
+        mut value: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        use serde_json::{Map, Value};
+
+        let features: Vec<&mut Value> = match value.get("type").and_then(Value::as_str) {
+            Some("FeatureCollection") => match value.get_mut("features") {
+                Some(Value::Array(features)) => features.iter_mut().collect(),
+                _ => return Err(invalid_geojson("\"FeatureCollection\" without features")),
+            },
+            Some("Feature") => vec![&mut value],
+            _ => return Err(invalid_geojson("expected a Feature or a FeatureCollection")),
+        };
+        let mut zones = Vec::new();
+        let mut annotated = Vec::new();
+        for feature in features {
+            if let Some(zone) = feature
+                .get("geometry")
+                .map(geojson_zone)
+                .transpose()?
+                .flatten()
+            {
+                zones.push(zone);
+                annotated.push(feature);
+            }
+        }
+        let stats = self.zonal_stats(&zones).await?;
+        for (feature, stats) in annotated.into_iter().zip(stats) {
+            let properties = match feature.get_mut("properties") {
+                Some(Value::Object(properties)) => properties,
+                _ => {
+                    feature["properties"] = Value::Object(Map::new());
+                    feature["properties"]
+                        .as_object_mut()
+                        .expect("just inserted")
+                }
+            };
+            let values = [
+                ("elevation_mean", stats.map(|stats| stats.mean)),
+                ("elevation_min", stats.map(|stats| stats.min)),
+                ("elevation_max", stats.map(|stats| stats.max)),
+                ("elevation_area", stats.map(|stats| stats.area)),
+            ];
+            for (key, value) in values {
+                properties.insert(key.into(), value.into());
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Bounds of the exteriors of a zone, `None` if it has no vertices.
+fn zone_bounds(zone: &MultiPolygon<f64>) -> Option<BoundingBox> {
+    let mut coords = zone
+        .iter()
+        .flat_map(|polygon| polygon.exterior().coords())
+        .peekable();
+    coords.peek()?;
+    let mut bounds = BoundingBox::new(
+        f64::INFINITY,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::NEG_INFINITY,
+    );
+    for c in coords {
+        bounds.west = bounds.west.min(c.x);
+        bounds.east = bounds.east.max(c.x);
+        bounds.south = bounds.south.min(c.y);
+        bounds.north = bounds.north.max(c.y);
+    }
+    Some(bounds)
+}
+
+#[cfg(feature = "geojson")]
+fn invalid_geojson(reason: &str) -> crate::HgtError {
+    crate::HgtError::InvalidGeoJson(reason.into())
+}
+
+/// The zone of a `Polygon` or `MultiPolygon` geometry, `None` for other
+/// geometries.
+#[cfg(feature = "geojson")]
+fn geojson_zone(geometry: &serde_json::Value) -> Result<Option<MultiPolygon<f64>>> {
+    use geo_types::{Coord, LineString, Polygon};
+    use serde_json::Value;
+
+    let ring = |ring: &Value| -> Result<LineString<f64>> {
+        let positions = ring
+            .as_array()
+            .ok_or_else(|| invalid_geojson("rings must be arrays"))?;
+        positions
+            .iter()
+            .map(|position| match position.as_array().map(Vec::as_slice) {
+                Some([x, y, ..]) => match (x.as_f64(), y.as_f64()) {
+                    (Some(x), Some(y)) => Ok(Coord { x, y }),
+                    _ => Err(invalid_geojson("non-numeric position")),
+                },
+                _ => Err(invalid_geojson("position with less than two values")),
+            })
+            .collect()
+    };
+    let polygon = |rings: &Value| -> Result<Polygon<f64>> {
+        let rings = rings
+            .as_array()
+            .ok_or_else(|| invalid_geojson("polygons must be arrays of rings"))?;
+        let (exterior, interiors) = rings
+            .split_first()
+            .ok_or_else(|| invalid_geojson("polygon without rings"))?;
+        Ok(Polygon::new(
+            ring(exterior)?,
+            interiors.iter().map(ring).collect::<Result<_>>()?,
+        ))
+    };
+    let coordinates = geometry.get("coordinates");
+    match (geometry.get("type").and_then(Value::as_str), coordinates) {
+        (Some("Polygon"), Some(rings)) => Ok(Some(polygon(rings)?.into())),
+        (Some("MultiPolygon"), Some(Value::Array(polygons))) => Ok(Some(
+            polygons
+                .iter()
+                .map(polygon)
+                .collect::<Result<Vec<_>>>()?
+                .into(),
+        )),
+        (Some("Polygon" | "MultiPolygon"), _) => {
+            Err(invalid_geojson("polygon without valid coordinates"))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::tile::{synthetic_tile, VOID};
+    use crate::{Body, HgtError, MissingPolicy, TileCache};
+    use geo_types::polygon;
+
+    fn earthel() -> EarthEl {
+        let cache = MemoryCache::new();
+        // 100 m in the west half of N45E006, 300 m in the east half.
+        let data = synthetic_tile(1201, |_, col| if col < 600 { 100 } else { 300 });
+        cache.store(&TileId::new(45, 6), &data).unwrap();
+        // N45E007 rises with the latitude, with voids in its south-west corner.
+        let data = synthetic_tile(1201, |row, col| {
+            if row > 1100 && col < 100 {
+                VOID
+            } else {
+                (1200 - row) as i16
+            }
+        });
+        cache.store(&TileId::new(45, 7), &data).unwrap();
+        EarthEl::builder()
+            .cache(cache)
+            .coverage(BoundingBox::new(45.0, 6.0, 46.0, 8.0))
+            .missing(MissingPolicy::None)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn aggregates_zones_in_one_pass() {
+        let earthel = earthel();
+        let halves: MultiPolygon<f64> = polygon![
+            (x: 6.25, y: 45.25), (x: 6.75, y: 45.25), (x: 6.75, y: 45.75), (x: 6.25, y: 45.75),
+        ]
+        .into();
+        let across = polygon![
+            (x: 6.9, y: 45.0), (x: 7.1, y: 45.0), (x: 7.1, y: 45.2), (x: 6.9, y: 45.2),
+        ];
+        let outside = polygon![(x: 8.5, y: 45.5), (x: 8.6, y: 45.5), (x: 8.6, y: 45.6)];
+        let tiny =
+            polygon![(x: 6.1001, y: 45.1001), (x: 6.1002, y: 45.1001), (x: 6.1002, y: 45.1002)];
+        let stats = earthel
+            .zonal_stats(&[halves, across.into(), outside.into(), tiny.into()])
+            .await
+            .unwrap();
+
+        let halves = stats[0].unwrap();
+        assert_eq!((halves.min, halves.max), (100.0, 300.0));
+        assert!((halves.mean - 200.0).abs() < 1.0, "{halves:?}");
+        // About 0.5° × 0.5° at 45.5°N.
+        let expected = (0.5 * Body::EARTH.meters_per_degree()).powi(2) * 45.5f64.to_radians().cos();
+        assert!((halves.area / expected - 1.0).abs() < 0.01, "{halves:?}");
+        // The voids of N45E007 are left out.
+        let across = stats[1].unwrap();
+        assert_eq!((across.min, across.max), (0.0, 300.0));
+        assert!(across.samples < 241 * 240);
+        assert_eq!(&stats[2..], [None, None]);
+    }
+
+    #[tokio::test]
+    async fn follows_the_missing_policy() {
+        // N45E008 is missing at the source.
+        let dir = tempfile::tempdir().unwrap();
+        let cache = std::sync::Arc::new(MemoryCache::new());
+        let data = synthetic_tile(1201, |_, _| VOID);
+        cache.store(&TileId::new(45, 7), &data).unwrap();
+        let earthel = |missing| {
+            EarthEl::builder()
+                .cache(std::sync::Arc::clone(&cache))
+                .source(crate::source::DirectorySource::new(dir.path()))
+                .coverage(BoundingBox::new(45.0, 7.0, 46.0, 9.0))
+                .missing(missing)
+                .build()
+        };
+        let voids: MultiPolygon<f64> = polygon![
+            (x: 7.25, y: 45.25), (x: 7.75, y: 45.25), (x: 7.75, y: 45.75), (x: 7.25, y: 45.75),
+        ]
+        .into();
+        let missing: MultiPolygon<f64> = polygon![
+            (x: 8.25, y: 45.25), (x: 8.75, y: 45.25), (x: 8.75, y: 45.75), (x: 8.25, y: 45.75),
+        ]
+        .into();
+        let zones = [voids, missing];
+
+        let strict = earthel(MissingPolicy::Error).zonal_stats(&zones).await;
+        assert!(matches!(strict, Err(HgtError::TileNotFound(_))));
+        let left_out = earthel(MissingPolicy::None).zonal_stats(&zones).await;
+        assert_eq!(left_out.unwrap(), [None, None]);
+        let filled = earthel(MissingPolicy::Value(-1.0))
+            .zonal_stats(&zones)
+            .await
+            .unwrap();
+        for stats in filled {
+            let stats = stats.unwrap();
+            assert_eq!((stats.min, stats.max), (-1.0, -1.0));
+            assert!((stats.mean + 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[cfg(feature = "geojson")]
+    #[tokio::test]
+    async fn annotates_geojson_features() {
+        let districts = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [
+                { "type": "Feature", "properties": { "name": "west" }, "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[6.1, 45.1], [6.4, 45.1], [6.4, 45.9], [6.1, 45.1]]]
+                } },
+                { "type": "Feature", "geometry": {
+                    "type": "MultiPolygon",
+                    "coordinates": [[[[8.5, 45.5], [8.6, 45.5], [8.6, 45.6], [8.5, 45.5]]]]
+                } },
+                { "type": "Feature", "properties": {}, "geometry": { "type": "Point", "coordinates": [6.5, 45.5] } }
+            ]
+        });
+        let annotated = earthel().annotate_zones_geojson(districts).await.unwrap();
+        let features = annotated["features"].as_array().unwrap();
+        let west = &features[0]["properties"];
+        assert_eq!(west["name"], "west");
+        assert!((west["elevation_mean"].as_f64().unwrap() - 100.0).abs() < 1e-6);
+        assert_eq!(west["elevation_max"], 100.0);
+        assert!(west["elevation_area"].as_f64().unwrap() > 0.0);
+        assert_eq!(
+            features[1]["properties"]["elevation_mean"],
+            serde_json::Value::Null
+        );
+        assert_eq!(features[2]["properties"], serde_json::json!({}));
+
+        let point = serde_json::json!({ "type": "Point", "coordinates": [6.5, 45.5] });
+        assert!(earthel().annotate_zones_geojson(point).await.is_err());
+    }
+}